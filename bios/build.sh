@@ -0,0 +1,14 @@
+#!/bin/sh
+# Assembles the replacement BIOS into `replacement.bin`,
+# which gets embedded into the emulator binary.
+#
+# Needs `llvm-mc`, `llvm-readelf` and `llvm-objcopy`.
+set -e
+cd "$(dirname "$0")"
+llvm-mc -triple=armv4t-none-eabi -filetype=obj -o replacement.o replacement.s
+# The image is position dependent, so nothing may be left to a linker.
+if llvm-readelf -r replacement.o | grep -q 'R_ARM'; then
+    echo "replacement.s needs relocations" >&2; rm replacement.o; exit 1
+fi
+llvm-objcopy -O binary --only-section=.text replacement.o replacement.bin
+rm replacement.o
@@ -0,0 +1,1057 @@
+@ License below.
+@
+@ A freely licensed replacement for the GBA BIOS ROM.
+@
+@ It provides the exception vectors, boots straight into the
+@ GamePak, forwards IRQs to the game's handler stored at
+@ 0x03007FFC, and implements the commonly used SWIs. The sound
+@ driver SWIs return without any effect. All other unimplemented
+@ SWIs execute an undefined instruction, which the emulator logs,
+@ and return without any effect.
+@
+@ Build with `bios/build.sh`, which updates `replacement.bin`.
+
+    .syntax unified
+    .arm
+    .text
+
+    .equ MODE_IRQ,          0x12
+    .equ MODE_SVC,          0x13
+    .equ MODE_SYS,          0x1F
+    .equ NO_IRQ,            0x80
+    .equ NO_FIQ,            0x40
+
+    .equ SP_SVC,            0x03007FE0
+    .equ SP_IRQ,            0x03007FA0
+    .equ SP_USR,            0x03007F00
+    .equ BIOS_WRAM,         0x03007E00
+    .equ BIOS_RESET_FLAG,   0x03007FFA
+    .equ IO,                0x04000000
+    .equ GAME_PAK,          0x08000000
+    .equ WRAM_ON_BOARD,     0x02000000
+
+    .equ REG_SOUNDCNT_X,    0x084
+    .equ REG_SOUNDBIAS,     0x088
+    .equ REG_RCNT,          0x134
+    .equ REG_IME,           0x208
+    .equ REG_POSTFLG,       0x300
+    .equ REG_HALTCNT,       0x301
+
+    .equ SWI_COUNT,         0x2B
+
+
+@ Exception vectors.
+    .global _start
+_start:
+    b       reset_handler           @ 0x00 Reset
+    b       undef_handler           @ 0x04 Undefined instruction
+    b       swi_handler             @ 0x08 Software interrupt
+    b       abort_handler           @ 0x0C Prefetch abort
+    b       abort_handler           @ 0x10 Data abort
+    b       reset_handler           @ 0x14 Reserved
+    b       irq_handler             @ 0x18 IRQ
+    b       fiq_handler             @ 0x1C FIQ
+
+undef_handler:
+    movs    pc, lr
+
+abort_handler:
+    @ Nothing on a GBA aborts, so something went badly wrong.
+    b       abort_handler
+
+fiq_handler:
+    subs    pc, lr, #4
+
+
+@ Power-on. Skips the intro and starts the game.
+reset_handler:
+    mov     r0, #1
+    mov     r12, #IO
+    strb    r0, [r12, #REG_POSTFLG]
+    mov     r0, #0x200
+    strh    r0, [r12, #REG_SOUNDBIAS]
+    mov     r0, #GAME_PAK
+    b       boot
+
+
+@ Resets the stacks and registers, clears the BIOS area
+@ of on-chip WRAM, and jumps to r0 in system mode.
+boot:
+    mov     r1, #0
+    mov     r2, #(MODE_SVC | NO_IRQ | NO_FIQ)
+    msr     cpsr_fc, r2
+    ldr     sp, =SP_SVC
+    mov     lr, #0
+    msr     spsr_fc, r1
+    mov     r2, #(MODE_IRQ | NO_IRQ | NO_FIQ)
+    msr     cpsr_fc, r2
+    ldr     sp, =SP_IRQ
+    mov     lr, #0
+    msr     spsr_fc, r1
+    mov     r2, #(MODE_SYS | NO_IRQ | NO_FIQ)
+    msr     cpsr_fc, r2
+    ldr     sp, =SP_USR
+
+    ldr     r2, =BIOS_WRAM
+    mov     r3, #0x200
+1:  subs    r3, r3, #4
+    str     r1, [r2, r3]
+    bne     1b
+
+    mov     lr, r0
+    mov     r12, #MODE_SYS
+    msr     cpsr_fc, r12
+    mov     r0, #0
+    mov     r1, #0
+    mov     r2, #0
+    mov     r3, #0
+    mov     r4, #0
+    mov     r5, #0
+    mov     r6, #0
+    mov     r7, #0
+    mov     r8, #0
+    mov     r9, #0
+    mov     r10, #0
+    mov     r11, #0
+    mov     r12, #0
+    bx      lr
+
+
+@ Calls the game's IRQ handler. The address 0x03FFFFFC
+@ mirrors 0x03007FFC, which is reachable from IO base.
+irq_handler:
+    stmfd   sp!, {r0-r3, r12, lr}
+    mov     r0, #IO
+    add     lr, pc, #0
+    ldr     pc, [r0, #-4]
+    ldmfd   sp!, {r0-r3, r12, lr}
+    subs    pc, lr, #4
+
+
+@ Dispatches SWIs. The comment field's low byte is at LR-2
+@ for both ARM and THUMB callers. Functions run in system
+@ mode with the caller's IRQ state and return via `bx lr`.
+@ R2, R11 and R12 are preserved for the caller.
+swi_handler:
+    stmfd   sp!, {r11, r12, lr}
+    ldrb    r12, [lr, #-2]
+    cmp     r12, #SWI_COUNT
+    movhs   r12, #SWI_COUNT
+    adr     r11, swi_table
+    ldr     r12, [r11, r12, lsl #2]
+    mrs     r11, spsr
+    stmfd   sp!, {r11}
+    and     r11, r11, #NO_IRQ
+    orr     r11, r11, #MODE_SYS
+    msr     cpsr_fc, r11
+    stmfd   sp!, {r2, lr}
+    mov     lr, pc
+    bx      r12
+    ldmfd   sp!, {r2, lr}
+    mov     r12, #(MODE_SVC | NO_IRQ)
+    msr     cpsr_fc, r12
+    ldmfd   sp!, {r11}
+    msr     spsr_fc, r11
+    ldmfd   sp!, {r11, r12, lr}
+    movs    pc, lr
+
+swi_table:
+    .word   swi_soft_reset          - _start    @ 0x00
+    .word   swi_register_ram_reset  - _start    @ 0x01
+    .word   swi_halt                - _start    @ 0x02
+    .word   swi_stop                - _start    @ 0x03
+    .word   swi_intr_wait           - _start    @ 0x04
+    .word   swi_vblank_intr_wait    - _start    @ 0x05
+    .word   swi_div                 - _start    @ 0x06
+    .word   swi_div_arm             - _start    @ 0x07
+    .word   swi_sqrt                - _start    @ 0x08
+    .word   swi_arctan              - _start    @ 0x09
+    .word   swi_arctan2             - _start    @ 0x0A
+    .word   swi_cpu_set             - _start    @ 0x0B
+    .word   swi_cpu_fast_set        - _start    @ 0x0C
+    .word   swi_get_bios_checksum   - _start    @ 0x0D
+    .word   swi_bg_affine_set       - _start    @ 0x0E
+    .word   swi_obj_affine_set      - _start    @ 0x0F
+    .word   swi_bit_unpack          - _start    @ 0x10
+    .word   swi_lz77_uncomp_wram    - _start    @ 0x11
+    .word   swi_lz77_uncomp_vram    - _start    @ 0x12
+    .word   swi_huff_uncomp         - _start    @ 0x13
+    .word   swi_rl_uncomp_wram      - _start    @ 0x14
+    .word   swi_rl_uncomp_vram      - _start    @ 0x15
+    .word   swi_diff8_unfilter_wram - _start    @ 0x16
+    .word   swi_diff8_unfilter_vram - _start    @ 0x17
+    .word   swi_diff16_unfilter     - _start    @ 0x18
+    .rept   0x25 - 0x19
+    .word   swi_none                - _start    @ 0x19..0x24 Sound driver
+    .endr
+    .word   swi_unimplemented       - _start    @ 0x25 MultiBoot
+    .word   swi_unimplemented       - _start    @ 0x26 HardReset
+    .word   swi_custom_halt         - _start    @ 0x27
+    .rept   SWI_COUNT - 0x28
+    .word   swi_none                - _start    @ 0x28..0x2A Sound driver
+    .endr
+    .word   swi_unimplemented       - _start    @ Out of range.
+
+
+swi_none:
+    bx      lr
+
+swi_unimplemented:
+    .word   0xE7F000F0                          @ Undefined instruction.
+    bx      lr
+
+
+@ SWI 0x00: Restarts the game in ROM or, if the byte
+@ at 0x03007FFA is set, in on-board WRAM.
+swi_soft_reset:
+    ldr     r0, =BIOS_RESET_FLAG
+    ldrb    r0, [r0]
+    cmp     r0, #0
+    moveq   r0, #GAME_PAK
+    movne   r0, #WRAM_ON_BOARD
+    b       boot
+
+
+@ SWI 0x01: Clears the memory areas selected in r0.
+@ Of the IO reset flags, only the SIO and sound
+@ flags are honoured.
+swi_register_ram_reset:
+    stmfd   sp!, {r4, lr}
+    mov     r4, r0
+    mov     r12, #IO
+    mov     r0, #0x80
+    strh    r0, [r12]                           @ DISPCNT: forced blank.
+
+    tst     r4, #0x01
+    movne   r0, #0x02000000
+    movne   r1, #0x40000
+    movne   lr, pc
+    bne     clear_words
+    tst     r4, #0x02
+    movne   r0, #0x03000000
+    movne   r1, #0x7E00
+    movne   lr, pc
+    bne     clear_words
+    tst     r4, #0x04
+    movne   r0, #0x05000000
+    movne   r1, #0x400
+    movne   lr, pc
+    bne     clear_words
+    tst     r4, #0x08
+    movne   r0, #0x06000000
+    movne   r1, #0x18000
+    movne   lr, pc
+    bne     clear_words
+    tst     r4, #0x10
+    movne   r0, #0x07000000
+    movne   r1, #0x400
+    movne   lr, pc
+    bne     clear_words
+
+    mov     r12, #IO
+    tst     r4, #0x20
+    movne   r0, #0x8000
+    addne   r1, r12, #(REG_RCNT & 0xF00)
+    strhne  r0, [r1, #(REG_RCNT & 0xFF)]
+    tst     r4, #0x40
+    movne   r0, #0
+    strhne  r0, [r12, #REG_SOUNDCNT_X]
+    movne   r0, #0x200
+    strhne  r0, [r12, #REG_SOUNDBIAS]
+
+    ldmfd   sp!, {r4, lr}
+    bx      lr
+
+@ Zero-fills r1 bytes at r0. r1 must be a non-zero
+@ multiple of 4. Clobbers r1 and r2.
+clear_words:
+    mov     r2, #0
+1:  subs    r1, r1, #4
+    str     r2, [r0, r1]
+    bne     1b
+    bx      lr
+
+
+@ SWI 0x02: Halts the CPU until an interrupt is requested.
+swi_halt:
+    mov     r12, #IO
+    mov     r2, #0
+    strb    r2, [r12, #REG_HALTCNT]
+    bx      lr
+
+
+@ SWI 0x03: Stops the CPU until a keypad, serial or
+@ GamePak interrupt is requested.
+swi_stop:
+    mov     r12, #IO
+    mov     r2, #0x80
+    strb    r2, [r12, #REG_HALTCNT]
+    bx      lr
+
+
+@ SWI 0x04: Halts until one of the interrupts in r1 has
+@ been handled. If r0 is set, old flags are discarded.
+@ The game's IRQ handler sets handled flags at 0x03007FF8,
+@ i.e. at IO-8 through the on-chip WRAM mirror.
+swi_intr_wait:
+    mov     r12, #IO
+    mov     r3, #0
+    strb    r3, [r12, #REG_IME]
+    cmp     r0, #0
+    ldrhne  r2, [r12, #-8]
+    bicne   r2, r2, r1
+    strhne  r2, [r12, #-8]
+    bne     2f
+1:  strb    r3, [r12, #REG_IME]
+    ldrh    r2, [r12, #-8]
+    ands    r0, r1, r2
+    eorne   r2, r2, r0
+    strhne  r2, [r12, #-8]
+    mov     r0, #1
+    strb    r0, [r12, #REG_IME]
+    bxne    lr
+2:  mov     r0, #1
+    strb    r0, [r12, #REG_IME]
+    strb    r3, [r12, #REG_HALTCNT]
+    b       1b
+
+
+@ SWI 0x05: `IntrWait` for VBlank, discarding old flags.
+swi_vblank_intr_wait:
+    mov     r0, #1
+    mov     r1, #1
+    b       swi_intr_wait
+
+
+@ SWI 0x07: `Div` with swapped parameters.
+swi_div_arm:
+    eor     r0, r0, r1
+    eor     r1, r0, r1
+    eor     r0, r0, r1
+    @ Fall through.
+
+@ SWI 0x06: Signed division r0 / r1.
+@ Returns the quotient in r0, the remainder in r1, and
+@ the absolute quotient in r3. Division by zero returns
+@ without changing any registers.
+swi_div:
+    cmp     r1, #0
+    bxeq    lr
+    stmfd   sp!, {r4, r5}
+    mov     r4, r0
+    eor     r5, r0, r1
+    cmp     r0, #0
+    rsblt   r0, r0, #0
+    cmp     r1, #0
+    rsblt   r1, r1, #0
+
+    mov     r2, r1
+    cmp     r2, r0, lsr #1
+1:  movls   r2, r2, lsl #1
+    cmpls   r2, r0, lsr #1
+    bls     1b
+    mov     r3, #0
+2:  cmp     r0, r2
+    subhs   r0, r0, r2
+    adc     r3, r3, r3
+    mov     r2, r2, lsr #1
+    cmp     r2, r1
+    bhs     2b
+
+    mov     r1, r0
+    cmp     r4, #0
+    rsblt   r1, r1, #0
+    mov     r0, r3
+    cmp     r5, #0
+    rsblt   r0, r0, #0
+    ldmfd   sp!, {r4, r5}
+    bx      lr
+
+
+@ SWI 0x08: Unsigned square root of r0, rounded down.
+swi_sqrt:
+    mov     r1, #0
+    mov     r2, #0x40000000
+1:  cmp     r2, r0
+    movhi   r2, r2, lsr #2
+    bhi     1b
+2:  cmp     r2, #0
+    beq     3f
+    add     r3, r1, r2
+    cmp     r0, r3
+    subhs   r0, r0, r3
+    mov     r1, r1, lsr #1
+    addhs   r1, r1, r2
+    mov     r2, r2, lsr #2
+    b       2b
+3:  mov     r0, r1
+    bx      lr
+
+
+@ SWI 0x09: Arc tangent of the 1.14 fixed point tangent
+@ in r0. Returns the angle in r0, -tan^2 in r1 and the
+@ last polynomial term in r3.
+swi_arctan:
+    stmfd   sp!, {r4}
+    mov     r12, r0
+    mul     r1, r12, r12
+    mov     r1, r1, asr #14
+    rsb     r1, r1, #0
+    mov     r3, #0xA9
+    adr     r2, arctan_terms
+    mov     r4, #7
+1:  mul     r12, r1, r3
+    ldr     r3, [r2], #4
+    add     r3, r3, r12, asr #14
+    subs    r4, r4, #1
+    bne     1b
+    mul     r12, r3, r0
+    mov     r0, r12, asr #16
+    ldmfd   sp!, {r4}
+    bx      lr
+
+arctan_terms:
+    .word   0x0390, 0x091C, 0x0FB6, 0x16AA, 0x2081, 0x3651, 0xA2F9
+
+
+@ SWI 0x0A: Angle of the vector (r0, r1), in 0..0xFFFF.
+@ Divides the smaller by the larger coordinate, such that
+@ `ArcTan` only sees tangents within -1..1.
+swi_arctan2:
+    stmfd   sp!, {r4, r5, lr}
+    cmp     r1, #0
+    bne     1f
+    cmp     r0, #0
+    movge   r0, #0
+    movlt   r0, #0x8000
+    b       9f
+1:  cmp     r0, #0
+    bne     2f
+    cmp     r1, #0
+    movge   r0, #0x4000
+    movlt   r0, #0xC000
+    b       9f
+
+2:  cmp     r1, #0
+    blt     4f
+    cmp     r0, #0
+    blt     3f
+    cmp     r0, r1                  @ 0 < x, y and x >= y
+    mov     r4, #0
+    bge     5f
+    b       6f
+3:  rsb     r2, r0, #0              @ x < 0 < y and -x >= y
+    cmp     r2, r1
+    mov     r4, #0x8000
+    bge     5f
+    b       6f
+4:  cmp     r0, #0
+    bgt     41f
+    rsb     r2, r0, #0              @ x, y < 0 and -x > -y
+    rsb     r3, r1, #0
+    cmp     r2, r3
+    mov     r4, #0x8000
+    bgt     5f
+    b       7f
+41: rsb     r3, r1, #0              @ y < 0 < x and x >= -y
+    cmp     r0, r3
+    mov     r4, #0x10000
+    bge     5f
+    b       7f
+
+5:  mov     r5, #0                  @ angle = r4 + arctan(y / x)
+    mov     r2, r0
+    mov     r0, r1
+    mov     r1, r2
+    b       8f
+6:  mov     r4, #0x4000             @ angle = r4 - arctan(x / y)
+    mov     r5, #1
+    b       8f
+7:  mov     r4, #0xC000
+    mov     r5, #1
+8:  mov     r0, r0, lsl #14
+    mov     lr, pc
+    b       swi_div
+    mov     lr, pc
+    b       swi_arctan
+    cmp     r5, #0
+    addeq   r0, r4, r0
+    subne   r0, r4, r0
+    mov     r0, r0, lsl #16
+    mov     r0, r0, lsr #16
+9:  mov     r3, #0x170
+    ldmfd   sp!, {r4, r5, lr}
+    bx      lr
+
+
+@ SWI 0x0B: Copies or fills r2[20:0] halfwords or words
+@ from r0 to r1. Sources in the BIOS area are ignored.
+swi_cpu_set:
+    tst     r0, #0x0E000000
+    bxeq    lr
+    stmfd   sp!, {r4, r5}
+    mov     r4, r2, lsl #11
+    mov     r4, r4, lsr #11
+    tst     r2, #0x04000000
+    bne     3f
+
+    tst     r2, #0x01000000
+    bne     2f
+1:  subs    r4, r4, #1
+    ldrhpl  r5, [r0], #2
+    strhpl  r5, [r1], #2
+    bpl     1b
+    b       6f
+2:  ldrh    r5, [r0]
+1:  subs    r4, r4, #1
+    strhpl  r5, [r1], #2
+    bpl     1b
+    b       6f
+
+3:  tst     r2, #0x01000000
+    bne     5f
+4:  subs    r4, r4, #1
+    ldrpl   r5, [r0], #4
+    strpl   r5, [r1], #4
+    bpl     4b
+    b       6f
+5:  ldr     r5, [r0]
+4:  subs    r4, r4, #1
+    strpl   r5, [r1], #4
+    bpl     4b
+
+6:  ldmfd   sp!, {r4, r5}
+    bx      lr
+
+
+@ SWI 0x0C: Copies or fills r2[20:0] words from r0 to r1
+@ in blocks of 8 words. Sources in the BIOS area are ignored.
+swi_cpu_fast_set:
+    tst     r0, #0x0E000000
+    bxeq    lr
+    stmfd   sp!, {r4-r10}
+    mov     r12, r2, lsl #11
+    mov     r12, r12, lsr #11
+    add     r12, r12, #7
+    mov     r12, r12, lsr #3
+    tst     r2, #0x01000000
+    bne     2f
+1:  subs    r12, r12, #1
+    ldmiapl r0!, {r3-r10}
+    stmiapl r1!, {r3-r10}
+    bpl     1b
+    b       3f
+2:  ldr     r3, [r0]
+    mov     r4, r3
+    mov     r5, r3
+    mov     r6, r3
+    mov     r7, r3
+    mov     r8, r3
+    mov     r9, r3
+    mov     r10, r3
+1:  subs    r12, r12, #1
+    stmiapl r1!, {r3-r10}
+    bpl     1b
+3:  ldmfd   sp!, {r4-r10}
+    bx      lr
+
+
+@ SWI 0x0D: Returns the checksum of the original BIOS,
+@ which is what games check for.
+swi_get_bios_checksum:
+    ldr     r0, =0xBAAE187F
+    bx      lr
+
+
+@ SWI 0x0E: Calculates r2 sets of BG affine parameters
+@ from the 20 byte entries at r0 into the 16 byte
+@ entries at r1.
+swi_bg_affine_set:
+    stmfd   sp!, {r4-r10, lr}
+    ldr     r12, =(sine_table - _start)
+1:  subs    r2, r2, #1
+    bmi     2f
+    ldrh    r3, [r0, #16]           @ Angle.
+    mov     r3, r3, lsr #8
+    mov     r3, r3, lsl #1
+    ldrsh   r4, [r12, r3]           @ Sine.
+    add     r3, r3, #128
+    bic     r3, r3, #0x200
+    ldrsh   r5, [r12, r3]           @ Cosine.
+    ldrsh   r6, [r0, #12]           @ Scale X.
+    ldrsh   r7, [r0, #14]           @ Scale Y.
+    mul     r8, r6, r5              @ PA
+    mov     r8, r8, lsl #2
+    mov     r8, r8, asr #16
+    rsb     r6, r6, #0
+    mul     r9, r6, r4              @ PB
+    mov     r9, r9, lsl #2
+    mov     r9, r9, asr #16
+    mul     r10, r7, r4             @ PC
+    mov     r10, r10, lsl #2
+    mov     r10, r10, asr #16
+    mul     lr, r7, r5              @ PD
+    mov     lr, lr, lsl #2
+    mov     lr, lr, asr #16
+    strh    r8, [r1]
+    strh    r9, [r1, #2]
+    strh    r10, [r1, #4]
+    strh    lr, [r1, #6]
+
+    ldrsh   r3, [r0, #8]            @ Screen X.
+    ldrsh   r4, [r0, #10]           @ Screen Y.
+    mul     r5, r8, r3
+    mla     r5, r9, r4, r5
+    ldr     r6, [r0]
+    sub     r6, r6, r5
+    str     r6, [r1, #8]
+    mul     r5, r10, r3
+    mla     r5, lr, r4, r5
+    ldr     r6, [r0, #4]
+    sub     r6, r6, r5
+    str     r6, [r1, #12]
+    add     r0, r0, #20
+    add     r1, r1, #16
+    b       1b
+2:  ldmfd   sp!, {r4-r10, lr}
+    bx      lr
+
+
+@ SWI 0x0F: Calculates r2 affine matrices from the 8 byte
+@ entries at r0. PA, PB, PC and PD are stored r3 bytes
+@ apart, starting at r1.
+swi_obj_affine_set:
+    stmfd   sp!, {r4-r9}
+    ldr     r12, =(sine_table - _start)
+1:  subs    r2, r2, #1
+    bmi     2f
+    ldrh    r4, [r0, #4]            @ Angle.
+    mov     r4, r4, lsr #8
+    mov     r4, r4, lsl #1
+    ldrsh   r5, [r12, r4]           @ Sine.
+    add     r4, r4, #128
+    bic     r4, r4, #0x200
+    ldrsh   r6, [r12, r4]           @ Cosine.
+    ldrsh   r7, [r0]                @ Scale X.
+    ldrsh   r8, [r0, #2]            @ Scale Y.
+    mul     r9, r7, r6              @ PA
+    mov     r9, r9, asr #14
+    strh    r9, [r1], r3
+    rsb     r7, r7, #0
+    mul     r9, r7, r5              @ PB
+    mov     r9, r9, asr #14
+    strh    r9, [r1], r3
+    mul     r9, r8, r5              @ PC
+    mov     r9, r9, asr #14
+    strh    r9, [r1], r3
+    mul     r9, r8, r6              @ PD
+    mov     r9, r9, asr #14
+    strh    r9, [r1], r3
+    add     r0, r0, #8
+    b       1b
+2:  ldmfd   sp!, {r4-r9}
+    bx      lr
+
+
+@ SWI 0x10: Widens each unit of the packed data at r0 and
+@ stores the result as words at r1. r2 points to the length,
+@ unit widths and offset. Invalid widths store nothing.
+swi_bit_unpack:
+    tst     r0, #0x0E000000
+    bxeq    lr
+    stmfd   sp!, {r4-r10, lr}
+    ldrb    r7, [r2]                @ Length.
+    ldrb    r3, [r2, #1]
+    orr     r7, r7, r3, lsl #8
+    ldrb    r5, [r2, #2]            @ Source width.
+    ldrb    r6, [r2, #3]            @ Destination width.
+    ldrb    r12, [r2, #4]           @ Offset and zero flag.
+    ldrb    r3, [r2, #5]
+    orr     r12, r12, r3, lsl #8
+    ldrb    r3, [r2, #6]
+    orr     r12, r12, r3, lsl #16
+    ldrb    r3, [r2, #7]
+    orr     r12, r12, r3, lsl #24
+    mov     r2, r12, lsr #31
+    bic     r12, r12, #0x80000000
+
+    sub     r3, r5, #1              @ Widths must be powers of two,
+    tst     r5, r3                  @ with 1 <= r5 <= 8, r5 <= r6
+    subeq   r3, r6, #1              @ and r6 <= 32.
+    tsteq   r6, r3
+    bne     4f
+    cmp     r5, #0
+    beq     4f
+    cmp     r5, #8
+    bhi     4f
+    cmp     r6, #32
+    bhi     4f
+    cmp     r5, r6
+    bhi     4f
+
+    bic     r1, r1, #3
+    mov     r11, #1
+    rsb     r11, r11, r11, lsl r5   @ Source unit mask.
+    mov     r10, #1
+    rsb     r10, r10, r10, lsl r6   @ Destination unit mask.
+    mov     r8, #0
+    mov     r9, #0
+1:  subs    r7, r7, #1
+    bmi     3f
+    ldrb    r3, [r0], #1
+    mov     r4, #8
+2:  and     lr, r3, r11
+    mov     r3, r3, lsr r5
+    cmp     lr, #0
+    cmpeq   r2, #0
+    addne   lr, lr, r12
+    and     lr, lr, r10
+    orr     r8, r8, lr, lsl r9
+    add     r9, r9, r6
+    cmp     r9, #32
+    streq   r8, [r1], #4
+    moveq   r8, #0
+    moveq   r9, #0
+    subs    r4, r4, r5
+    bne     2b
+    b       1b
+3:  cmp     r9, #0
+    strne   r8, [r1], #4
+4:  ldmfd   sp!, {r4-r10, lr}
+    bx      lr
+
+
+@ The byte-wise decompression functions share these registers:
+@
+@ r0   Source address.
+@ r1   Destination address of the next byte.
+@ r2   Number of bytes left to store.
+@ r7   Destination address of the first byte.
+@ r8   Pending low byte of a halfword.
+@ r10  Zero to store bytes, non-zero to store halfwords.
+
+@ Starts a decompression function. Reads the header at r0
+@ and returns the size in r2 and the parameter in r3.
+@ Clobbers r4.
+uncomp_start:
+    ldrb    r3, [r0], #1
+    ldrb    r2, [r0], #1
+    ldrb    r4, [r0], #1
+    orr     r2, r2, r4, lsl #8
+    ldrb    r4, [r0], #1
+    orr     r2, r2, r4, lsl #16
+    and     r3, r3, #0x0F
+    cmp     r10, #0
+    bicne   r1, r1, #1
+    mov     r7, r1
+    mov     r8, #0
+    bx      lr
+
+@ Stores the byte in r3, unless all bytes have been stored.
+@ Halfwords are stored once their upper byte is known.
+put_byte:
+    cmp     r2, #0
+    bxeq    lr
+    sub     r2, r2, #1
+    cmp     r10, #0
+    strbeq  r3, [r1], #1
+    bxeq    lr
+    tst     r1, #1
+    andeq   r8, r3, #0xFF
+    orrne   r8, r8, r3, lsl #8
+    strhne  r8, [r1, #-1]
+    add     r1, r1, #1
+    bx      lr
+
+@ Pads a pending halfword with a zero byte, and returns from
+@ a decompression function.
+uncomp_finish:
+    tst     r1, #1
+    cmpne   r10, #0
+    strhne  r8, [r1, #-1]
+    ldmfd   sp!, {r4-r10, lr}
+    bx      lr
+
+
+@ SWI 0x11 and 0x12: Decompresses LZ77 data from r0 to r1.
+@ References before the destination's start read zeros.
+swi_lz77_uncomp_wram:
+    mov     r12, #0
+    b       lz77_uncomp
+swi_lz77_uncomp_vram:
+    mov     r12, #1
+lz77_uncomp:
+    tst     r0, #0x0E000000
+    bxeq    lr
+    stmfd   sp!, {r4-r10, lr}
+    mov     r10, r12
+    mov     lr, pc
+    b       uncomp_start
+1:  cmp     r2, #0
+    beq     uncomp_finish
+    ldrb    r5, [r0], #1            @ Block flags.
+    mov     r5, r5, lsl #24
+    mov     r6, #8
+2:  cmp     r2, #0
+    beq     uncomp_finish
+    subs    r6, r6, #1
+    bmi     1b
+    movs    r5, r5, lsl #1
+    bcs     3f
+    ldrb    r3, [r0], #1            @ Raw byte.
+    mov     lr, pc
+    b       put_byte
+    b       2b
+3:  ldrb    r4, [r0], #1            @ Back-reference.
+    ldrb    r3, [r0], #1
+    and     r9, r4, #0x0F
+    orr     r9, r3, r9, lsl #8
+    add     r9, r9, #1              @ Distance.
+    mov     r4, r4, lsr #4
+    add     r4, r4, #3              @ Length.
+4:  sub     r3, r1, r7
+    cmp     r9, r3
+    movhi   r3, #0
+    bhi     6f
+    sub     r3, r1, r9
+    cmp     r10, #0
+    beq     5f
+    bic     r12, r1, #1             @ The pending byte isn't stored yet.
+    cmp     r3, r12
+    andhs   r3, r8, #0xFF
+    bhs     6f
+5:  ldrb    r3, [r3]
+6:  mov     lr, pc
+    b       put_byte
+    subs    r4, r4, #1
+    bne     4b
+    b       2b
+
+
+@ SWI 0x13: Decompresses Huffman coded data from r0 to r1,
+@ storing words. Unit widths other than 1, 2, 4 and 8 bits
+@ store nothing.
+swi_huff_uncomp:
+    tst     r0, #0x0E000000
+    bxeq    lr
+    stmfd   sp!, {r4-r10, lr}
+    mov     r10, #0
+    mov     r11, r0                 @ Offsets within the data are
+    mov     lr, pc                  @ relative to r11.
+    b       uncomp_start
+    sub     r4, r3, #1
+    tst     r3, r4
+    bne     uncomp_finish
+    cmp     r3, #0
+    beq     uncomp_finish
+
+    bic     r1, r1, #3
+    mov     r12, r3                 @ Unit width.
+    mov     lr, #1
+    rsb     lr, lr, lr, lsl r12     @ Unit mask.
+    ldrb    r4, [r11, #4]           @ Tree size.
+    add     r4, r4, #1
+    add     r0, r11, #4
+    add     r0, r0, r4, lsl #1      @ Bitstream.
+    mov     r5, #5                  @ Offset of the current node.
+    ldrb    r6, [r11, #5]           @ Current node.
+    mov     r8, #0
+    mov     r9, #0
+1:  cmp     r2, #0
+    ble     uncomp_finish
+    ldrb    r3, [r0], #1
+    ldrb    r4, [r0], #1
+    orr     r3, r3, r4, lsl #8
+    ldrb    r4, [r0], #1
+    orr     r3, r3, r4, lsl #16
+    ldrb    r4, [r0], #1
+    orr     r3, r3, r4, lsl #24
+    mov     r7, #32
+2:  subs    r7, r7, #1
+    bmi     1b
+    bic     r4, r5, #1
+    and     r10, r6, #0x3F
+    add     r4, r4, r10, lsl #1
+    add     r4, r4, #2
+    movs    r3, r3, lsl #1
+    addcs   r4, r4, #1
+    movcs   r10, #0x40
+    movcc   r10, #0x80
+    tst     r6, r10
+    ldrb    r6, [r11, r4]
+    moveq   r5, r4                  @ Inner node.
+    beq     2b
+    and     r6, r6, lr              @ Data node.
+    orr     r8, r8, r6, lsl r9
+    add     r9, r9, r12
+    cmp     r9, #32
+    bne     3f
+    cmp     r2, #4                  @ Clear bytes beyond the size.
+    rsblt   r9, r2, #4
+    movlt   r9, r9, lsl #3
+    movlt   r8, r8, lsl r9
+    movlt   r8, r8, lsr r9
+    str     r8, [r1], #4
+    mov     r8, #0
+    mov     r9, #0
+    subs    r2, r2, #4
+    ble     uncomp_finish
+3:  mov     r5, #5
+    ldrb    r6, [r11, #5]
+    b       2b
+
+
+@ SWI 0x14 and 0x15: Decompresses run-length encoded
+@ data from r0 to r1.
+swi_rl_uncomp_wram:
+    mov     r12, #0
+    b       rl_uncomp
+swi_rl_uncomp_vram:
+    mov     r12, #1
+rl_uncomp:
+    tst     r0, #0x0E000000
+    bxeq    lr
+    stmfd   sp!, {r4-r10, lr}
+    mov     r10, r12
+    mov     lr, pc
+    b       uncomp_start
+1:  cmp     r2, #0
+    beq     uncomp_finish
+    ldrb    r4, [r0], #1            @ Flag.
+    tst     r4, #0x80
+    and     r4, r4, #0x7F
+    beq     3f
+    add     r4, r4, #3              @ Run.
+    ldrb    r5, [r0], #1
+2:  mov     r3, r5
+    mov     lr, pc
+    b       put_byte
+    subs    r4, r4, #1
+    bne     2b
+    b       1b
+3:  add     r4, r4, #1              @ Raw bytes.
+4:  ldrb    r3, [r0], #1
+    mov     lr, pc
+    b       put_byte
+    subs    r4, r4, #1
+    bne     4b
+    b       1b
+
+
+@ SWI 0x16 and 0x17: Undoes 8-bit delta filtering of
+@ the data at r0, storing the result at r1.
+swi_diff8_unfilter_wram:
+    mov     r12, #0
+    b       diff8_unfilter
+swi_diff8_unfilter_vram:
+    mov     r12, #1
+diff8_unfilter:
+    tst     r0, #0x0E000000
+    bxeq    lr
+    stmfd   sp!, {r4-r10, lr}
+    mov     r10, r12
+    mov     lr, pc
+    b       uncomp_start
+    mov     r5, #0
+1:  cmp     r2, #0
+    beq     uncomp_finish
+    ldrb    r3, [r0], #1
+    add     r5, r5, r3
+    and     r3, r5, #0xFF
+    mov     lr, pc
+    b       put_byte
+    b       1b
+
+
+@ SWI 0x18: Undoes 16-bit delta filtering of the data
+@ at r0, storing the result at r1.
+swi_diff16_unfilter:
+    tst     r0, #0x0E000000
+    bxeq    lr
+    stmfd   sp!, {r4-r10, lr}
+    mov     r10, #1
+    mov     lr, pc
+    b       uncomp_start
+    mov     r5, #0
+1:  cmp     r2, #0
+    beq     uncomp_finish
+    ldrb    r3, [r0], #1
+    ldrb    r4, [r0], #1
+    orr     r3, r3, r4, lsl #8
+    add     r5, r5, r3
+    and     r3, r5, #0xFF
+    mov     lr, pc
+    b       put_byte
+    mov     r3, r5, lsr #8
+    and     r3, r3, #0xFF
+    mov     lr, pc
+    b       put_byte
+    b       1b
+
+
+@ SWI 0x27: Writes r2 to HALTCNT.
+swi_custom_halt:
+    mov     r12, #IO
+    strb    r2, [r12, #REG_HALTCNT]
+    bx      lr
+
+
+    .ltorg
+
+
+@ The sine of a full turn in 256 steps, as signed 1.14 fixed
+@ point numbers rounded towards zero.
+    .balign 4
+sine_table:
+    .hword  0x0000, 0x0192, 0x0323, 0x04B5, 0x0645, 0x07D5, 0x0964, 0x0AF1
+    .hword  0x0C7C, 0x0E05, 0x0F8C, 0x1111, 0x1294, 0x1413, 0x158F, 0x1708
+    .hword  0x187D, 0x19EF, 0x1B5D, 0x1CC6, 0x1E2B, 0x1F8B, 0x20E7, 0x223D
+    .hword  0x238E, 0x24DA, 0x261F, 0x275F, 0x2899, 0x29CD, 0x2AFA, 0x2C21
+    .hword  0x2D41, 0x2E5A, 0x2F6B, 0x3076, 0x3179, 0x3274, 0x3367, 0x3453
+    .hword  0x3536, 0x3612, 0x36E5, 0x37AF, 0x3871, 0x392A, 0x39DA, 0x3A82
+    .hword  0x3B20, 0x3BB6, 0x3C42, 0x3CC5, 0x3D3E, 0x3DAE, 0x3E14, 0x3E71
+    .hword  0x3EC5, 0x3F0E, 0x3F4E, 0x3F84, 0x3FB1, 0x3FD3, 0x3FEC, 0x3FFB
+    .hword  0x4000, 0x3FFB, 0x3FEC, 0x3FD3, 0x3FB1, 0x3F84, 0x3F4E, 0x3F0E
+    .hword  0x3EC5, 0x3E71, 0x3E14, 0x3DAE, 0x3D3E, 0x3CC5, 0x3C42, 0x3BB6
+    .hword  0x3B20, 0x3A82, 0x39DA, 0x392A, 0x3871, 0x37AF, 0x36E5, 0x3612
+    .hword  0x3536, 0x3453, 0x3367, 0x3274, 0x3179, 0x3076, 0x2F6B, 0x2E5A
+    .hword  0x2D41, 0x2C21, 0x2AFA, 0x29CD, 0x2899, 0x275F, 0x261F, 0x24DA
+    .hword  0x238E, 0x223D, 0x20E7, 0x1F8B, 0x1E2B, 0x1CC6, 0x1B5D, 0x19EF
+    .hword  0x187D, 0x1708, 0x158F, 0x1413, 0x1294, 0x1111, 0x0F8C, 0x0E05
+    .hword  0x0C7C, 0x0AF1, 0x0964, 0x07D5, 0x0645, 0x04B5, 0x0323, 0x0192
+    .hword  0x0000, 0xFE6E, 0xFCDD, 0xFB4B, 0xF9BB, 0xF82B, 0xF69C, 0xF50F
+    .hword  0xF384, 0xF1FB, 0xF074, 0xEEEF, 0xED6C, 0xEBED, 0xEA71, 0xE8F8
+    .hword  0xE783, 0xE611, 0xE4A3, 0xE33A, 0xE1D5, 0xE075, 0xDF19, 0xDDC3
+    .hword  0xDC72, 0xDB26, 0xD9E1, 0xD8A1, 0xD767, 0xD633, 0xD506, 0xD3DF
+    .hword  0xD2BF, 0xD1A6, 0xD095, 0xCF8A, 0xCE87, 0xCD8C, 0xCC99, 0xCBAD
+    .hword  0xCACA, 0xC9EE, 0xC91B, 0xC851, 0xC78F, 0xC6D6, 0xC626, 0xC57E
+    .hword  0xC4E0, 0xC44A, 0xC3BE, 0xC33B, 0xC2C2, 0xC252, 0xC1EC, 0xC18F
+    .hword  0xC13B, 0xC0F2, 0xC0B2, 0xC07C, 0xC04F, 0xC02D, 0xC014, 0xC005
+    .hword  0xC000, 0xC005, 0xC014, 0xC02D, 0xC04F, 0xC07C, 0xC0B2, 0xC0F2
+    .hword  0xC13B, 0xC18F, 0xC1EC, 0xC252, 0xC2C2, 0xC33B, 0xC3BE, 0xC44A
+    .hword  0xC4E0, 0xC57E, 0xC626, 0xC6D6, 0xC78F, 0xC851, 0xC91B, 0xC9EE
+    .hword  0xCACA, 0xCBAD, 0xCC99, 0xCD8C, 0xCE87, 0xCF8A, 0xD095, 0xD1A6
+    .hword  0xD2BF, 0xD3DF, 0xD506, 0xD633, 0xD767, 0xD8A1, 0xD9E1, 0xDB26
+    .hword  0xDC72, 0xDDC3, 0xDF19, 0xE075, 0xE1D5, 0xE33A, 0xE4A3, 0xE611
+    .hword  0xE783, 0xE8F8, 0xEA71, 0xEBED, 0xED6C, 0xEEEF, 0xF074, 0xF1FB
+    .hword  0xF384, 0xF50F, 0xF69C, 0xF82B, 0xF9BB, 0xFB4B, 0xFCDD, 0xFE6E
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,93 @@
+// License below.
+//! Implements ways of outputting the emulated APU's audio.
+//!
+//! The APU mixes samples at a native rate that depends on
+//! `SOUNDBIAS`. Before reaching an `AudioSink`, the samples
+//! are converted to the sink's fixed sample rate.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::io;
+use super::hardware::apu::{AudioChunk, StereoSample};
+
+pub use self::resampler::*;
+pub use self::wav::*;
+
+pub mod resampler;
+pub mod wav;
+
+#[cfg(test)]
+mod test;
+
+
+/// Something consuming 16-bit stereo samples at a fixed rate.
+pub trait AudioSink {
+    /// Get the sample rate in Hz the sink expects.
+    fn sample_rate(&self) -> u32;
+
+    /// Consumes a batch of samples.
+    fn write_samples(&mut self, samples: &[StereoSample]) -> io::Result<()>;
+
+    /// Flushes all samples, after which no more samples may be written.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+
+/// Feeds the APU's output into an audio sink.
+///
+/// The native sample rate of the APU is converted to the
+/// sink's sample rate on the way.
+pub struct AudioPipeline<S: AudioSink> {
+    resampler: Resampler,
+    sink: S,
+    buffer: Vec<StereoSample>,
+}
+
+impl<S: AudioSink> AudioPipeline<S> {
+    /// Creates a new pipeline feeding a given sink.
+    pub fn new(sink: S) -> AudioPipeline<S> {
+        AudioPipeline {
+            resampler: Resampler::new(sink.sample_rate()),
+            sink: sink,
+            buffer: Vec::with_capacity(4096),
+        }
+    }
+
+    /// Resamples chunks of APU output and writes them to the sink.
+    pub fn push(&mut self, chunks: &[AudioChunk]) -> io::Result<()> {
+        self.buffer.clear();
+        for c in chunks { self.resampler.process(c, &mut self.buffer); }
+        self.sink.write_samples(&self.buffer[..])
+    }
+
+    /// Finishes the sink and returns it.
+    pub fn finish(mut self) -> io::Result<S> {
+        try!(self.sink.finish());
+        Ok(self.sink)
+    }
+
+    /// Get the sink.
+    pub fn sink(&self) -> &S { &self.sink }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,105 @@
+// License below.
+//! Implements a sample rate converter for the APU's output.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::super::hardware::apu::{AudioChunk, StereoSample};
+
+/// The usual sample rate of CD audio.
+pub const SAMPLE_RATE_44100: u32 = 44100;
+
+/// The usual sample rate of video and most sound cards.
+pub const SAMPLE_RATE_48000: u32 = 48000;
+
+/// The lowest supported output sample rate in Hz.
+pub const SAMPLE_RATE_MIN: u32 = 8000;
+
+/// The highest supported output sample rate in Hz.
+pub const SAMPLE_RATE_MAX: u32 = 192000;
+
+
+/// Checks whether an output sample rate is supported.
+pub fn is_valid_sample_rate(rate: u32) -> bool {
+    (SAMPLE_RATE_MIN <= rate) & (rate <= SAMPLE_RATE_MAX)
+}
+
+
+/// Converts a stream of samples with varying rates to a fixed rate.
+///
+/// Output samples are linearly interpolated between the two
+/// nearest input samples. The conversion only depends on the
+/// input, such that the same input always results in exactly
+/// the same output.
+pub struct Resampler {
+    out_rate: u32,
+    // Position of the next output sample between `prev` and
+    // `cur`, in units of input samples.
+    phase: f64,
+    prev: StereoSample,
+    cur: StereoSample,
+}
+
+impl Resampler {
+    /// Creates a new resampler starting in silence.
+    ///
+    /// # Params
+    /// - `out_rate`: The output sample rate in Hz.
+    ///   See `is_valid_sample_rate`.
+    pub fn new(out_rate: u32) -> Resampler {
+        debug_assert!(is_valid_sample_rate(out_rate));
+        Resampler {
+            out_rate: out_rate,
+            phase: 0.0,
+            prev: (0, 0),
+            cur: (0, 0),
+        }
+    }
+
+    /// Get the output sample rate in Hz.
+    pub fn out_rate(&self) -> u32 { self.out_rate }
+
+    /// Resamples a chunk of input samples.
+    ///
+    /// # Params
+    /// - `chunk`: The input samples and their rate.
+    /// - `out`: Receives the output samples.
+    pub fn process(&mut self, chunk: &AudioChunk, out: &mut Vec<StereoSample>) {
+        let step = (chunk.rate as f64) / (self.out_rate as f64);
+        for &s in &chunk.samples {
+            self.prev = self.cur;
+            self.cur = s;
+            while self.phase < 1.0 {
+                out.push((lerp(self.prev.0, self.cur.0, self.phase), lerp(self.prev.1, self.cur.1, self.phase)));
+                self.phase += step;
+            }
+            self.phase -= 1.0;
+        }
+    }
+}
+
+fn lerp(a: i16, b: i16, t: f64) -> i16 {
+    let x = (a as f64) + ((b as f64) - (a as f64)) * t;
+    x.round() as i16
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,91 @@
+// License below.
+#![allow(missing_docs)]
+
+use std::io::Cursor;
+use super::*;
+use super::super::hardware::apu::AudioChunk;
+
+// Two frames (1, -1) and (0x1234, -0x8000) at 44100Hz.
+const WAV_FILE: &'static [u8] = b"RIFF\x2C\x00\x00\x00WAVE\
+    fmt \x10\x00\x00\x00\x01\x00\x02\x00\x44\xAC\x00\x00\x10\xB1\x02\x00\x04\x00\x10\x00\
+    data\x08\x00\x00\x00\x01\x00\xFF\xFF\x34\x12\x00\x80";
+
+fn ramp(rate: u32, from: i16, len: i16) -> AudioChunk {
+    AudioChunk { rate: rate, samples: (from..(from + len)).map(|i| (i * 100, i * -100)).collect() }
+}
+
+#[test]
+fn wav_writer_patches_header() {
+    let mut w = WavWriter::new(Cursor::new(Vec::new()), 44100).unwrap();
+    assert_eq!(w.get_ref().get_ref().len(), 44);
+    w.write_samples(&[(1, -1)]).unwrap();
+    w.write_samples(&[(0x1234, -0x8000)]).unwrap();
+    w.finish().unwrap();
+    w.finish().unwrap();
+    assert_eq!(w.frames(), 2);
+    assert_eq!(&w.get_ref().get_ref()[..], WAV_FILE);
+}
+
+#[test]
+fn resampler_interpolates_between_rates() {
+    let mut r = Resampler::new(65536);
+    let mut out = vec![];
+    r.process(&ramp(32768, 0, 3), &mut out);
+    assert_eq!(out, vec![(0, 0), (0, 0), (0, 0), (50, -50), (100, -100), (150, -150)]);
+
+    out.clear();
+    r.process(&ramp(262144, 3, 5), &mut out);
+    assert_eq!(out, vec![(200, -200), (600, -600)]);
+}
+
+#[test]
+fn resampler_ignores_chunk_boundaries() {
+    let mut whole = vec![];
+    Resampler::new(SAMPLE_RATE_44100).process(&ramp(32768, 0, 100), &mut whole);
+    assert_eq!(whole.len(), 135);
+
+    let mut split = vec![];
+    let mut r = Resampler::new(SAMPLE_RATE_44100);
+    r.process(&ramp(32768, 0, 37), &mut split);
+    r.process(&ramp(32768, 37, 63), &mut split);
+    assert_eq!(split, whole);
+}
+
+#[test]
+fn pipeline_resamples_into_sink() {
+    let mut p = AudioPipeline::new(WavWriter::new(Cursor::new(Vec::new()), 16384).unwrap());
+    p.push(&[ramp(32768, 0, 4), ramp(65536, 4, 4)]).unwrap();
+    let w = p.finish().unwrap();
+    assert_eq!(w.frames(), 3);
+    assert_eq!(&w.get_ref().get_ref()[44..], &[
+        0x00, 0x00, 0x00, 0x00, 0x64, 0x00, 0x9C, 0xFF, 0x2C, 0x01, 0xD4, 0xFE,
+    ][..]);
+}
+
+#[test]
+fn sample_rates_are_validated() {
+    assert!(!is_valid_sample_rate(0));
+    assert!(!is_valid_sample_rate(1000));
+    assert!(is_valid_sample_rate(SAMPLE_RATE_44100));
+    assert!(is_valid_sample_rate(SAMPLE_RATE_48000));
+    assert!(!is_valid_sample_rate(1 << 30));
+}
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
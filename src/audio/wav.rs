@@ -0,0 +1,124 @@
+// License below.
+//! Implements an audio sink writing RIFF WAVE files.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::io::{self, Write, Seek, SeekFrom, BufWriter};
+use std::fs::File;
+use std::path::Path;
+use byteorder::{LittleEndian, WriteBytesExt};
+use super::AudioSink;
+use super::super::hardware::apu::StereoSample;
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const BYTES_PER_FRAME: u32 = (CHANNELS as u32) * (BITS_PER_SAMPLE as u32) / 8;
+const HEADER_LEN: u32 = 44;
+
+
+/// Writes 16-bit stereo PCM samples into a WAVE file.
+///
+/// The header's size fields are patched when finishing,
+/// so the output must be seekable.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    rate: u32,
+    frames: u32,
+    finished: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Creates a new WAVE file, overwriting any existing one.
+    ///
+    /// # Params
+    /// - `fp`: Path to the file to create.
+    /// - `rate`: The sample rate in Hz.
+    pub fn create(fp: &Path, rate: u32) -> io::Result<WavWriter<BufWriter<File>>> {
+        trace!("Recording audio into `{}`.", fp.display());
+        WavWriter::new(BufWriter::new(try!(File::create(fp))), rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Starts writing a WAVE file into some output.
+    ///
+    /// # Params
+    /// - `out`: Where to write the file.
+    /// - `rate`: The sample rate in Hz.
+    pub fn new(out: W, rate: u32) -> io::Result<WavWriter<W>> {
+        let mut w = WavWriter { out: out, rate: rate, frames: 0, finished: false };
+        try!(w.write_header());
+        Ok(w)
+    }
+
+    /// Get the number of stereo samples written so far.
+    pub fn frames(&self) -> u32 { self.frames }
+
+    /// Gets the underlying output.
+    pub fn get_ref(&self) -> &W { &self.out }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let data_len = self.frames * BYTES_PER_FRAME;
+        try!(self.out.write_all(b"RIFF"));
+        try!(self.out.write_u32::<LittleEndian>(HEADER_LEN - 8 + data_len));
+        try!(self.out.write_all(b"WAVE"));
+
+        try!(self.out.write_all(b"fmt "));
+        try!(self.out.write_u32::<LittleEndian>(16));
+        try!(self.out.write_u16::<LittleEndian>(1)); // PCM.
+        try!(self.out.write_u16::<LittleEndian>(CHANNELS));
+        try!(self.out.write_u32::<LittleEndian>(self.rate));
+        try!(self.out.write_u32::<LittleEndian>(self.rate * BYTES_PER_FRAME));
+        try!(self.out.write_u16::<LittleEndian>(BYTES_PER_FRAME as u16));
+        try!(self.out.write_u16::<LittleEndian>(BITS_PER_SAMPLE));
+
+        try!(self.out.write_all(b"data"));
+        try!(self.out.write_u32::<LittleEndian>(data_len));
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavWriter<W> {
+    fn sample_rate(&self) -> u32 { self.rate }
+
+    fn write_samples(&mut self, samples: &[StereoSample]) -> io::Result<()> {
+        debug_assert!(!self.finished);
+        for &(l, r) in samples {
+            try!(self.out.write_i16::<LittleEndian>(l));
+            try!(self.out.write_i16::<LittleEndian>(r));
+        }
+        self.frames += samples.len() as u32;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished { return Ok(()); }
+        self.finished = true;
+        try!(self.out.seek(SeekFrom::Start(0)));
+        try!(self.write_header());
+        try!(self.out.seek(SeekFrom::End(0)));
+        self.out.flush()
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,99 @@
+// License below.
+//! Implements the Direct Sound channels A and B.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+/// Capacity of a Direct Sound FIFO in bytes.
+pub const FIFO_LEN: usize = 32;
+
+/// A FIFO holding this many bytes or less requests a refill.
+pub const FIFO_REFILL_THRESHOLD: usize = 16;
+
+
+/// A channel playing signed 8-bit PCM samples from a FIFO.
+///
+/// Each time the selected timer overflows, the channel
+/// plays the next sample. Samples are pushed by the CPU
+/// or by DMA 1 or 2 in special timing mode.
+pub struct DirectSoundChannel {
+    fifo: [i8; FIFO_LEN],
+    read: usize,
+    len: usize,
+    sample: i8,
+}
+
+impl DirectSoundChannel {
+    /// Creates a new silent channel with an empty FIFO.
+    pub fn new() -> DirectSoundChannel {
+        DirectSoundChannel {
+            fifo: [0; FIFO_LEN],
+            read: 0,
+            len: 0,
+            sample: 0,
+        }
+    }
+
+    /// Empties the FIFO.
+    ///
+    /// The currently playing sample is kept.
+    pub fn reset_fifo(&mut self) {
+        self.read = 0;
+        self.len = 0;
+    }
+
+    /// Get the number of bytes in the FIFO.
+    pub fn len(&self) -> usize { self.len }
+
+    /// Checks whether the FIFO is empty.
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Checks whether the FIFO requests a refill.
+    pub fn needs_refill(&self) -> bool { self.len <= FIFO_REFILL_THRESHOLD }
+
+    /// Pushes a sample into the FIFO.
+    ///
+    /// If the FIFO is full, the oldest sample is overwritten.
+    pub fn push(&mut self, b: u8) {
+        let write = (self.read + self.len) % FIFO_LEN;
+        self.fifo[write] = b as i8;
+        if self.len < FIFO_LEN { self.len += 1; }
+        else { self.read = (self.read + 1) % FIFO_LEN; }
+    }
+
+    /// Plays the next sample, if any, when the selected timer overflows.
+    pub fn play_next(&mut self) {
+        if self.len == 0 { return; }
+        self.sample = self.fifo[self.read];
+        self.read = (self.read + 1) % FIFO_LEN;
+        self.len -= 1;
+    }
+
+    /// Get the currently playing sample.
+    pub fn output(&self) -> i8 { self.sample }
+}
+
+impl Default for DirectSoundChannel {
+    fn default() -> DirectSoundChannel { DirectSoundChannel::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
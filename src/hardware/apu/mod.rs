@@ -0,0 +1,407 @@
+// License below.
+//! Implements the GBA's audio processing unit.
+//!
+//! The APU consists of the four legacy GameBoy sound channels,
+//! also called PSG channels, and two Direct Sound channels.
+//! All channels are mixed into a single stereo output, which
+//! is biased, clipped and quantised by `SOUNDBIAS`.
+//!
+//! The APU runs on the scheduler's timeline. The frame
+//! sequencer clocks length counters, envelopes and the sweep
+//! unit at 512Hz, and output samples are mixed at the rate
+//! selected by `SOUNDBIAS`, i.e. at 32768Hz by default.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::ioregs::*;
+use super::memory::{Rom8, Rom16, Ram8};
+
+pub use self::units::*;
+pub use self::square::*;
+pub use self::wave::*;
+pub use self::noise::*;
+pub use self::directsound::*;
+
+pub mod units;
+pub mod square;
+pub mod wave;
+pub mod noise;
+pub mod directsound;
+
+#[cfg(test)]
+mod test;
+
+
+/// Number of CPU cycles between two frame sequencer steps, i.e. 512Hz.
+pub const FRAME_SEQUENCER_PERIOD: u64 = 32768;
+
+/// Offset of the first sound register handled by the APU.
+pub const SOUND_REGISTERS_FIRST: u32 = REG_SOUND1CNT_L;
+
+/// Offset of the last sound register handled by the APU.
+pub const SOUND_REGISTERS_LAST: u32 = REG_FIFO_B + 3;
+
+const CNT_H_A_FULL_VOLUME: u16 = 1 << 2;
+const CNT_H_B_FULL_VOLUME: u16 = 1 << 3;
+const CNT_H_A_RIGHT: u16 = 1 << 8;
+const CNT_H_A_LEFT: u16 = 1 << 9;
+const CNT_H_A_TIMER1: u16 = 1 << 10;
+const CNT_H_A_RESET: u16 = 1 << 11;
+const CNT_H_B_RIGHT: u16 = 1 << 12;
+const CNT_H_B_LEFT: u16 = 1 << 13;
+const CNT_H_B_TIMER1: u16 = 1 << 14;
+const CNT_H_B_RESET: u16 = 1 << 15;
+
+/// Bits of the sound registers `0x060..0x08C` that can be read back.
+const READ_MASKS: [u8; 0x2C] = [
+    0x7F, 0x00, 0xC0, 0xFF, 0x00, 0x40, 0x00, 0x00, // SOUND1CNT_L/H/X
+    0xC0, 0xFF, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, // SOUND2CNT_L/H
+    0xE0, 0x00, 0x00, 0xE0, 0x00, 0x40, 0x00, 0x00, // SOUND3CNT_L/H/X
+    0x00, 0xFF, 0x00, 0x00, 0xFF, 0x40, 0x00, 0x00, // SOUND4CNT_L/H
+    0x77, 0xFF, 0x0F, 0x77, 0x80, 0x00, 0x00, 0x00, // SOUNDCNT_L/H/X
+    0xFE, 0xC3, 0x00, 0x00,                         // SOUNDBIAS
+];
+
+
+/// A single stereo output sample `(left, right)`.
+pub type StereoSample = (i16, i16);
+
+
+/// A run of output samples sharing the same sample rate.
+///
+/// The sample rate changes whenever a game selects a
+/// different amplitude resolution in `SOUNDBIAS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioChunk {
+    /// The samples' rate in Hz.
+    pub rate: u32,
+    /// The samples in chronological order.
+    pub samples: Vec<StereoSample>,
+}
+
+
+/// Emulates the GBA's sound hardware.
+pub struct Apu {
+    square1: SquareChannel,
+    square2: SquareChannel,
+    wave: WaveChannel,
+    noise: NoiseChannel,
+    fifo_a: DirectSoundChannel,
+    fifo_b: DirectSoundChannel,
+    master_enable: bool,
+    frame_step: u8,
+    last_sync: u64,
+    chunks: Vec<AudioChunk>,
+}
+
+impl Apu {
+    /// Creates a new silent APU.
+    pub fn new() -> Apu {
+        Apu {
+            square1: SquareChannel::new(true),
+            square2: SquareChannel::new(false),
+            wave: WaveChannel::new(),
+            noise: NoiseChannel::new(),
+            fifo_a: DirectSoundChannel::new(),
+            fifo_b: DirectSoundChannel::new(),
+            master_enable: false,
+            frame_step: 0,
+            last_sync: 0,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Resets all channels and drops all pending samples.
+    ///
+    /// # Params
+    /// - `now`: The current point in time on the scheduler's timeline.
+    pub fn reset(&mut self, now: u64) {
+        self.reset_psg();
+        self.fifo_a = DirectSoundChannel::new();
+        self.fifo_b = DirectSoundChannel::new();
+        self.master_enable = false;
+        self.frame_step = 0;
+        self.last_sync = now;
+        self.chunks.clear();
+    }
+
+    /// Checks whether a sound register offset is handled by the APU.
+    pub fn is_sound_register(offs: u32) -> bool {
+        (SOUND_REGISTERS_FIRST <= offs) & (offs <= SOUND_REGISTERS_LAST)
+    }
+
+    /// Get the number of CPU cycles between two output samples.
+    pub fn sample_period(regs: &IoRegisters) -> u64 {
+        512 >> Apu::resolution(regs)
+    }
+
+    /// Get the current output sample rate in Hz.
+    pub fn sample_rate(regs: &IoRegisters) -> u32 {
+        32768 << Apu::resolution(regs)
+    }
+
+    /// Get the channel enable flags as reported by bits 0-3 of `SOUNDCNT_X`.
+    pub fn status(&self) -> u8 {
+        (self.square1.is_active() as u8)
+        | ((self.square2.is_active() as u8) << 1)
+        | ((self.wave.is_active() as u8) << 2)
+        | ((self.noise.is_active() as u8) << 3)
+    }
+
+    /// Get the Direct Sound channel A.
+    pub fn fifo_a(&self) -> &DirectSoundChannel { &self.fifo_a }
+
+    /// Get the Direct Sound channel B.
+    pub fn fifo_b(&self) -> &DirectSoundChannel { &self.fifo_b }
+
+    /// Removes and returns all samples mixed so far.
+    pub fn take_audio(&mut self) -> Vec<AudioChunk> {
+        ::std::mem::replace(&mut self.chunks, Vec::new())
+    }
+
+    /// Get all samples mixed so far.
+    pub fn audio(&self) -> &[AudioChunk] { &self.chunks[..] }
+
+    /// Reads a byte from a sound register.
+    ///
+    /// Write-only bits and registers read as zero.
+    pub fn read_register(&self, offs: u32, regs: &IoRegisters) -> u8 {
+        match offs {
+            REG_SOUNDCNT_X => (regs.read_byte(offs) & 0x80) | self.status(),
+            _ if offs >= REG_FIFO_A => 0,
+            _ if offs >= REG_WAVE_RAM => self.wave.read_ram((offs - REG_WAVE_RAM) as usize),
+            _ if offs >= SOUND_REGISTERS_FIRST + (READ_MASKS.len() as u32) => 0,
+            _ => regs.read_byte(offs) & READ_MASKS[(offs - SOUND_REGISTERS_FIRST) as usize],
+        }
+    }
+
+    /// Writes a byte to a sound register and applies its effects.
+    ///
+    /// While the master enable bit of `SOUNDCNT_X` is cleared,
+    /// all PSG registers are read-only.
+    ///
+    /// # Params
+    /// - `offs`: The register's offset in IO memory.
+    /// - `b`: The byte to write.
+    /// - `regs`: The IO registers holding the sound registers' values.
+    /// - `now`: The current point in time on the scheduler's timeline.
+    pub fn write_register(&mut self, offs: u32, b: u8, regs: &mut IoRegisters, now: u64) {
+        // Let the channels catch up before changing their settings.
+        self.sync(now);
+
+        if offs >= REG_FIFO_B { self.fifo_b.push(b); return; }
+        if offs >= REG_FIFO_A { self.fifo_a.push(b); return; }
+        if offs >= REG_WAVE_RAM {
+            self.wave.write_ram((offs - REG_WAVE_RAM) as usize, b);
+            return;
+        }
+        if !self.master_enable & (offs < REG_SOUNDCNT_H) { return; }
+        regs.write_byte(offs, b);
+
+        match offs {
+            0x060 => self.square1.write_sweep(b),
+            0x062 => self.square1.write_length_duty(b),
+            0x063 => self.square1.write_envelope(b),
+            0x064 | 0x065 => self.square1.write_control(regs.read_halfword(REG_SOUND1CNT_X), offs == 0x065),
+            0x068 => self.square2.write_length_duty(b),
+            0x069 => self.square2.write_envelope(b),
+            0x06C | 0x06D => self.square2.write_control(regs.read_halfword(REG_SOUND2CNT_H), offs == 0x06D),
+            0x070 => self.wave.write_select(b),
+            0x072 => self.wave.write_length(b),
+            0x073 => self.wave.write_volume(b),
+            0x074 | 0x075 => self.wave.write_control(regs.read_halfword(REG_SOUND3CNT_X), offs == 0x075),
+            0x078 => self.noise.write_length(b),
+            0x079 => self.noise.write_envelope(b),
+            0x07C => self.noise.write_frequency(b),
+            0x07D => self.noise.write_control(b),
+            0x083 => {
+                // The FIFO reset bits are write-only.
+                let cnt_h = (b as u16) << 8;
+                if 0 != (cnt_h & CNT_H_A_RESET) { self.fifo_a.reset_fifo(); }
+                if 0 != (cnt_h & CNT_H_B_RESET) { self.fifo_b.reset_fifo(); }
+                regs.write_byte(offs, b & !(((CNT_H_A_RESET | CNT_H_B_RESET) >> 8) as u8));
+            },
+            REG_SOUNDCNT_X => {
+                let enable = 0 != (b & 0x80);
+                if self.master_enable & !enable {
+                    // Powering off clears all PSG registers.
+                    self.reset_psg();
+                    for i in SOUND_REGISTERS_FIRST..REG_SOUNDCNT_H { regs.write_byte(i, 0); }
+                }
+                if !self.master_enable & enable { self.frame_step = 0; }
+                self.master_enable = enable;
+            },
+            _ => {},
+        }
+    }
+
+    /// Plays the next Direct Sound samples when a timer overflows.
+    ///
+    /// # Params
+    /// - `timer`: The overflowing timer. Only timers 0 and 1 drive Direct Sound.
+    /// - `now`: The point in time the overflow was due at.
+    /// - `regs`: The IO registers holding the sound registers' values.
+    ///
+    /// # Returns
+    /// Whether FIFO A and FIFO B request a refill by DMA.
+    pub fn on_timer_overflow(&mut self, timer: usize, now: u64, regs: &IoRegisters) -> (bool, bool) {
+        self.sync(now);
+        if !self.master_enable | (timer > 1) { return (false, false); }
+        let cnt_h = regs.read_halfword(REG_SOUNDCNT_H);
+        let selects = |bit: u16| (0 != (cnt_h & bit)) == (timer == 1);
+
+        let mut refill = (false, false);
+        if selects(CNT_H_A_TIMER1) {
+            self.fifo_a.play_next();
+            refill.0 = self.fifo_a.needs_refill();
+        }
+        if selects(CNT_H_B_TIMER1) {
+            self.fifo_b.play_next();
+            refill.1 = self.fifo_b.needs_refill();
+        }
+        refill
+    }
+
+    /// Clocks the frame sequencer, which is due every `FRAME_SEQUENCER_PERIOD` cycles.
+    ///
+    /// # Params
+    /// - `now`: The point in time the step was due at.
+    pub fn clock_frame_sequencer(&mut self, now: u64) {
+        self.sync(now);
+        if !self.master_enable { return; }
+
+        let step = self.frame_step;
+        self.frame_step = (step + 1) % 8;
+        if 0 == (step & 1) {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if (step == 2) | (step == 6) { self.square1.clock_sweep(); }
+        if step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+    }
+
+    /// Mixes a new output sample, which is due every `sample_period` cycles.
+    ///
+    /// # Params
+    /// - `now`: The point in time the sample was due at.
+    /// - `regs`: The IO registers holding the sound registers' values.
+    pub fn mix_sample(&mut self, now: u64, regs: &IoRegisters) {
+        self.sync(now);
+        let (psg_left, psg_right) = self.mix_psg(regs);
+        let (ds_left, ds_right) = self.mix_direct_sound(regs);
+        let (left, right) = (psg_left + ds_left, psg_right + ds_right);
+        let sample = (Apu::quantise(left, regs), Apu::quantise(right, regs));
+        let rate = Apu::sample_rate(regs);
+        let same_rate = self.chunks.last().map_or(false, |c| c.rate == rate);
+        if !same_rate { self.chunks.push(AudioChunk { rate: rate, samples: Vec::with_capacity(4096) }); }
+        if let Some(c) = self.chunks.last_mut() { c.samples.push(sample); }
+    }
+
+    // Advances all channels up to the given point in time.
+    fn sync(&mut self, now: u64) {
+        let cycles = now.saturating_sub(self.last_sync) as u32;
+        self.last_sync = now;
+        if !self.master_enable | (cycles == 0) { return; }
+        self.square1.run(cycles);
+        self.square2.run(cycles);
+        self.wave.run(cycles);
+        self.noise.run(cycles);
+    }
+
+    fn reset_psg(&mut self) {
+        self.square1.reset();
+        self.square2.reset();
+        self.wave.reset();
+        self.noise.reset();
+    }
+
+    // Mixes the PSG channels into a signed `(left, right)` pair
+    // of 10-bit values, as selected by SOUNDCNT_L and SOUNDCNT_H.
+    fn mix_psg(&self, regs: &IoRegisters) -> (i32, i32) {
+        if !self.master_enable { return (0, 0); }
+        let cnt_l = regs.read_halfword(REG_SOUNDCNT_L);
+        let cnt_h = regs.read_halfword(REG_SOUNDCNT_H);
+
+        // Each active channel's DAC maps 0..16 to -15..+15.
+        let active = self.status();
+        let outputs = [self.square1.output(), self.square2.output(), self.wave.output(), self.noise.output()];
+        let (mut left, mut right) = (0_i32, 0_i32);
+        for (ch, &out) in outputs.iter().enumerate() {
+            if 0 == (active & (1 << ch)) { continue; }
+            let v = (out as i32) * 2 - 15;
+            if 0 != (cnt_l & (1 << (12 + ch))) { left  += v; }
+            if 0 != (cnt_l & (1 << ( 8 + ch))) { right += v; }
+        }
+
+        left  *= ((cnt_l >> 4) & 0b111) as i32 + 1;
+        right *= ( cnt_l       & 0b111) as i32 + 1;
+
+        // 25%, 50% or 100% PSG volume. 3 is prohibited.
+        let shift = match cnt_h & 0b11 { 0 => 2, 1 => 1, _ => 0 };
+        (left >> shift, right >> shift)
+    }
+
+    // Mixes the Direct Sound channels into a signed `(left, right)`
+    // pair of 10-bit values, as selected by SOUNDCNT_H.
+    fn mix_direct_sound(&self, regs: &IoRegisters) -> (i32, i32) {
+        if !self.master_enable { return (0, 0); }
+        let cnt_h = regs.read_halfword(REG_SOUNDCNT_H);
+
+        // 8-bit samples at 50% or 100% volume.
+        let a = (self.fifo_a.output() as i32) << if 0 != (cnt_h & CNT_H_A_FULL_VOLUME) { 2 } else { 1 };
+        let b = (self.fifo_b.output() as i32) << if 0 != (cnt_h & CNT_H_B_FULL_VOLUME) { 2 } else { 1 };
+
+        let (mut left, mut right) = (0, 0);
+        if 0 != (cnt_h & CNT_H_A_LEFT)  { left  += a; }
+        if 0 != (cnt_h & CNT_H_A_RIGHT) { right += a; }
+        if 0 != (cnt_h & CNT_H_B_LEFT)  { left  += b; }
+        if 0 != (cnt_h & CNT_H_B_RIGHT) { right += b; }
+        (left, right)
+    }
+
+    // Adds the bias, clips and quantises a mixed sample
+    // to the selected resolution, then removes the bias.
+    fn quantise(x: i32, regs: &IoRegisters) -> i16 {
+        let bias = (regs.read_halfword(REG_SOUNDBIAS) & 0x3FE) as i32;
+        let drop = (2 << Apu::resolution(regs)) - 1;
+        let v = ::std::cmp::max(0, ::std::cmp::min(0x3FF, bias + x)) & !drop;
+        ((v - 0x200) << 6) as i16
+    }
+
+    // The amplitude resolution, 0 for 9 bits up to 3 for 6 bits.
+    fn resolution(regs: &IoRegisters) -> u32 {
+        (regs.read_halfword(REG_SOUNDBIAS) >> 14) as u32
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Apu { Apu::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,132 @@
+// License below.
+//! Implements the noise sound channel 4.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::units::*;
+
+
+/// A channel playing pseudo random noise generated by a 15-bit or 7-bit LFSR.
+pub struct NoiseChannel {
+    active: bool,
+    timer: u32,
+    lfsr: u16,
+    narrow: bool,
+    divisor: u32,
+    shift: u32,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    /// Creates a new silent noise channel.
+    pub fn new() -> NoiseChannel {
+        NoiseChannel {
+            active: false,
+            timer: 0,
+            lfsr: 0x7FFF,
+            narrow: false,
+            divisor: 8,
+            shift: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    /// Resets the channel to its power-on state.
+    pub fn reset(&mut self) { *self = NoiseChannel::new(); }
+
+    /// Checks whether the channel is currently playing.
+    pub fn is_active(&self) -> bool { self.active }
+
+    /// Loads the length byte, bits 0-7 of `SOUND4CNT_L`.
+    pub fn write_length(&mut self, b: u8) { self.length.load((b & 0x3F) as u16); }
+
+    /// Loads the envelope byte, bits 8-15 of `SOUND4CNT_L`.
+    pub fn write_envelope(&mut self, b: u8) {
+        self.envelope.load(b);
+        if !self.envelope.dac_enabled() { self.active = false; }
+    }
+
+    /// Loads the frequency byte, bits 0-7 of `SOUND4CNT_H`.
+    ///
+    /// Bits 0-2 select the divisor, bit 3 the 7-bit LFSR,
+    /// and bits 4-7 the shift clock frequency.
+    pub fn write_frequency(&mut self, b: u8) {
+        let r = (b & 0b111) as u32;
+        self.divisor = if r == 0 { 8 } else { r * 16 };
+        self.narrow = 0 != (b & (1 << 3));
+        self.shift = (b >> 4) as u32;
+    }
+
+    /// Loads the control byte, bits 8-15 of `SOUND4CNT_H`.
+    pub fn write_control(&mut self, b: u8) {
+        self.length.set_enabled(0 != (b & (1 << 6)));
+        if 0 != (b & (1 << 7)) { self.trigger(); }
+    }
+
+    /// Restarts the channel with a fully set LFSR.
+    pub fn trigger(&mut self) {
+        self.active = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+    }
+
+    /// Clocks the length counter at 256Hz.
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() { self.active = false; }
+    }
+
+    /// Clocks the volume envelope at 64Hz.
+    pub fn clock_envelope(&mut self) { self.envelope.clock(); }
+
+    /// Advances the LFSR by a number of CPU cycles.
+    pub fn run(&mut self, mut cycles: u32) {
+        // Shift clocks 14 and 15 stop the LFSR.
+        if self.shift >= 14 { return; }
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+            if self.narrow { self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6); }
+        }
+        self.timer -= cycles;
+    }
+
+    /// Get the current digital output `0..16`.
+    pub fn output(&self) -> u8 {
+        if !self.active | (0 != (self.lfsr & 1)) { 0 } else { self.envelope.volume() }
+    }
+
+    // Number of CPU cycles per LFSR shift.
+    fn period(&self) -> u32 { (self.divisor << self.shift) * 4 }
+}
+
+impl Default for NoiseChannel {
+    fn default() -> NoiseChannel { NoiseChannel::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
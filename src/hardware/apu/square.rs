@@ -0,0 +1,156 @@
+// License below.
+//! Implements the square wave sound channels 1 and 2.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::units::*;
+
+/// Waveforms for the duty cycles 12.5%, 25%, 50% and 75%.
+const DUTY_WAVEFORMS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+
+/// A square wave channel with volume envelope and an optional frequency sweep.
+///
+/// Sound channel 1 has a sweep unit, sound channel 2 doesn't.
+pub struct SquareChannel {
+    active: bool,
+    frequency: u16,
+    timer: u32,
+    duty: usize,
+    duty_step: usize,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    /// Creates a new silent square wave channel.
+    ///
+    /// # Params
+    /// - `with_sweep`: `true` for sound channel 1.
+    pub fn new(with_sweep: bool) -> SquareChannel {
+        SquareChannel {
+            active: false,
+            frequency: 0,
+            timer: 0,
+            duty: 0,
+            duty_step: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: if with_sweep { Some(Sweep::new()) } else { None },
+        }
+    }
+
+    /// Resets the channel to its power-on state.
+    pub fn reset(&mut self) {
+        let with_sweep = self.sweep.is_some();
+        *self = SquareChannel::new(with_sweep);
+    }
+
+    /// Checks whether the channel is currently playing.
+    pub fn is_active(&self) -> bool { self.active }
+
+    /// Loads `SOUND1CNT_L`, if this channel has a sweep unit.
+    pub fn write_sweep(&mut self, b: u8) {
+        if let Some(ref mut s) = self.sweep { s.load(b); }
+    }
+
+    /// Loads the length and duty byte, bits 0-7 of `SOUNDxCNT_H`.
+    pub fn write_length_duty(&mut self, b: u8) {
+        self.length.load((b & 0x3F) as u16);
+        self.duty = (b >> 6) as usize;
+    }
+
+    /// Loads the envelope byte, bits 8-15 of `SOUNDxCNT_H`.
+    pub fn write_envelope(&mut self, b: u8) {
+        self.envelope.load(b);
+        if !self.envelope.dac_enabled() { self.active = false; }
+    }
+
+    /// Loads the frequency control register `SOUNDxCNT_X`.
+    ///
+    /// # Params
+    /// - `x`: The whole register's value.
+    /// - `high`: Whether the upper byte has been written,
+    ///   which may trigger the channel.
+    pub fn write_control(&mut self, x: u16, high: bool) {
+        self.frequency = x & 0x7FF;
+        if !high { return; }
+        self.length.set_enabled(0 != (x & (1 << 14)));
+        if 0 != (x & (1 << 15)) { self.trigger(); }
+    }
+
+    /// Restarts the channel.
+    pub fn trigger(&mut self) {
+        self.active = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        if let Some(ref mut s) = self.sweep {
+            if !s.trigger(self.frequency) { self.active = false; }
+        }
+    }
+
+    /// Clocks the length counter at 256Hz.
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() { self.active = false; }
+    }
+
+    /// Clocks the volume envelope at 64Hz.
+    pub fn clock_envelope(&mut self) { self.envelope.clock(); }
+
+    /// Clocks the frequency sweep at 128Hz.
+    pub fn clock_sweep(&mut self) {
+        let mut f = self.frequency;
+        if let Some(ref mut s) = self.sweep {
+            if !s.clock(&mut f) { self.active = false; }
+        }
+        self.frequency = f;
+    }
+
+    /// Advances the waveform by a number of CPU cycles.
+    pub fn run(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    /// Get the current digital output `0..16`.
+    pub fn output(&self) -> u8 {
+        if !self.active { return 0; }
+        DUTY_WAVEFORMS[self.duty][self.duty_step] * self.envelope.volume()
+    }
+
+    // Number of CPU cycles per waveform step.
+    fn period(&self) -> u32 { (2048 - self.frequency as u32) * 16 }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
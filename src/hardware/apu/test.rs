@@ -0,0 +1,166 @@
+// License below.
+#![allow(missing_docs)]
+
+use super::*;
+use super::super::ioregs::*;
+use super::super::memory::Rom16;
+
+// Register, value. Enables channel 2 at 50% duty, full volume, the
+// left speaker at maximum volume, and 100% PSG volume.
+const SQUARE2_LEFT: &'static [(u32, u16)] = &[
+    (REG_SOUNDCNT_X, 0x0080), (REG_SOUNDCNT_L, 0x2077), (REG_SOUNDCNT_H, 0x0002),
+    (REG_SOUNDBIAS, 0x0200), (REG_SOUND2CNT_L, 0xF080), (REG_SOUND2CNT_H, 0x8400),
+];
+
+fn write_registers(apu: &mut Apu, regs: &mut IoRegisters, writes: &[(u32, u16)]) {
+    for &(offs, x) in writes {
+        apu.write_register(offs, x as u8, regs, 0);
+        apu.write_register(offs + 1, (x >> 8) as u8, regs, 0);
+    }
+}
+
+#[test]
+fn length_counter_expires() {
+    let mut len = LengthCounter::new(64);
+    len.load(62);
+    assert!(len.clock());
+    len.set_enabled(true);
+    assert!(len.clock());
+    assert!(!len.clock());
+    assert!(len.clock());
+
+    len.trigger();
+    for _ in 0..63 { assert!(len.clock()); }
+    assert!(!len.clock());
+}
+
+#[test]
+fn envelope_fades_in_and_out() {
+    let mut env = Envelope::new();
+    env.load(0x00);
+    assert!(!env.dac_enabled());
+    env.load(0x32);
+    env.trigger();
+    let volumes: Vec<u8> = (0..8).map(|_| { env.clock(); env.volume() }).collect();
+    assert_eq!(volumes, vec![3, 2, 2, 1, 1, 0, 0, 0]);
+
+    env.load(0xE9);
+    env.trigger();
+    let volumes: Vec<u8> = (0..3).map(|_| { env.clock(); env.volume() }).collect();
+    assert_eq!(volumes, vec![15, 15, 15]);
+}
+
+#[test]
+fn sweep_changes_frequency_until_overflow() {
+    let mut sweep = Sweep::new();
+    let mut freq = 0x400;
+    sweep.load(0x19);
+    assert!(sweep.trigger(freq));
+    assert!(sweep.clock(&mut freq));
+    assert_eq!(freq, 0x200);
+    assert!(sweep.clock(&mut freq));
+    assert_eq!(freq, 0x100);
+
+    sweep.load(0x11);
+    assert!(sweep.trigger(0x500));
+    freq = 0x500;
+    assert!(!sweep.clock(&mut freq));
+    assert_eq!(freq, 0x780);
+    assert!(!sweep.trigger(0x700));
+}
+
+#[test]
+fn frame_sequencer_steps() {
+    let mut apu = Apu::new();
+    let mut regs = IoRegisters::new();
+    // Channel 1 sweeps from 0x400 into an overflow on its
+    // first sweep clock. Channel 2 plays for 8 length clocks.
+    write_registers(&mut apu, &mut regs, &[
+        (REG_SOUNDCNT_X, 0x0080), (REG_SOUND1CNT_L, 0x0011), (REG_SOUND1CNT_H, 0xF000),
+        (REG_SOUND1CNT_X, 0x8400), (REG_SOUND2CNT_L, 0xF038), (REG_SOUND2CNT_H, 0xC400),
+    ]);
+    assert_eq!(apu.status(), 0b11);
+
+    let status: Vec<u8> = (0..16).map(|_| { apu.clock_frame_sequencer(0); apu.status() }).collect();
+    assert_eq!(status, vec![
+        0b11, 0b11, 0b10, 0b10, 0b10, 0b10, 0b10, 0b10,
+        0b10, 0b10, 0b10, 0b10, 0b10, 0b10, 0b00, 0b00,
+    ]);
+}
+
+#[test]
+fn master_enable_guards_psg_registers() {
+    let mut apu = Apu::new();
+    let mut regs = IoRegisters::new();
+    write_registers(&mut apu, &mut regs, &[(REG_SOUND2CNT_L, 0xF080), (REG_SOUND2CNT_H, 0x8400)]);
+    assert_eq!((apu.status(), regs.read_halfword(REG_SOUND2CNT_L)), (0, 0));
+
+    write_registers(&mut apu, &mut regs, SQUARE2_LEFT);
+    assert_eq!(apu.status(), 0b10);
+    assert_eq!(apu.read_register(REG_SOUNDCNT_X, &regs), 0x82);
+
+    write_registers(&mut apu, &mut regs, &[(REG_SOUNDCNT_X, 0)]);
+    assert_eq!((apu.status(), regs.read_halfword(REG_SOUND2CNT_L)), (0, 0));
+}
+
+#[test]
+fn samples_are_mixed_biased_and_clipped() {
+    let mut apu = Apu::new();
+    let mut regs = IoRegisters::new();
+    write_registers(&mut apu, &mut regs, SQUARE2_LEFT);
+    apu.mix_sample(0, &regs);
+
+    // Direct Sound A at full volume on both speakers, driven by timer 0.
+    write_registers(&mut apu, &mut regs, &[(REG_SOUNDCNT_H, 0x0306)]);
+    for &b in &[0x40, 0x7F, 0x80, 0x00] { apu.write_register(REG_FIFO_A, b, &mut regs, 0); }
+    for _ in 0..3 {
+        apu.on_timer_overflow(0, 0, &regs);
+        apu.mix_sample(0, &regs);
+    }
+
+    // The new resolution starts a new chunk.
+    write_registers(&mut apu, &mut regs, &[(REG_SOUNDBIAS, 0xC200)]);
+    apu.mix_sample(0, &regs);
+
+    let chunks = apu.take_audio();
+    assert!(apu.audio().is_empty());
+    assert_eq!(chunks, vec![
+        AudioChunk { rate: 32768, samples: vec![(7680, 0), (24064, 16384), (32640, 32512), (-25088, -32768)] },
+        AudioChunk { rate: 262144, samples: vec![(-25600, -32768)] },
+    ]);
+}
+
+#[test]
+fn psg_waveforms_follow_the_timeline() {
+    let mut apu = Apu::new();
+    let mut regs = IoRegisters::new();
+    write_registers(&mut apu, &mut regs, SQUARE2_LEFT);
+
+    // Frequency 0x400 steps the waveform every 16384 cycles.
+    let period = Apu::sample_period(&regs);
+    let mut left = vec![];
+    for i in 0..8 {
+        apu.mix_sample(i * 16384 / period * period, &regs);
+        left.push(apu.audio().last().unwrap().samples.last().unwrap().0);
+    }
+    assert_eq!(left, vec![7680, -7680, -7680, -7680, -7680, 7680, 7680, 7680]);
+}
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
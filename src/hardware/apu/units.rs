@@ -0,0 +1,212 @@
+// License below.
+//! Implements the building blocks shared by the PSG sound channels.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+
+/// Disables a channel once a given number of frame sequencer steps passed.
+///
+/// The length counter is clocked at 256Hz.
+pub struct LengthCounter {
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    /// Creates a new length counter.
+    ///
+    /// # Params
+    /// - `max`: The maximum length, i.e. 64 for square
+    ///   and noise channels and 256 for the wave channel.
+    pub fn new(max: u16) -> LengthCounter {
+        LengthCounter { max: max, counter: 0, enabled: false }
+    }
+
+    /// Resets the counter to its power-on state.
+    pub fn reset(&mut self) {
+        self.counter = 0;
+        self.enabled = false;
+    }
+
+    /// Loads the length bits of a sound register.
+    ///
+    /// The channel plays for `max - len` steps.
+    pub fn load(&mut self, len: u16) { self.counter = self.max - (len % self.max); }
+
+    /// Enables or disables the counter, as selected by bit 14 of `SOUNDxCNT_X`.
+    pub fn set_enabled(&mut self, e: bool) { self.enabled = e; }
+
+    /// Restarts an expired counter when the channel is triggered.
+    pub fn trigger(&mut self) {
+        if self.counter == 0 { self.counter = self.max; }
+    }
+
+    /// Clocks the counter.
+    ///
+    /// # Returns
+    /// `false` if the channel should be disabled.
+    pub fn clock(&mut self) -> bool {
+        if self.enabled & (self.counter > 0) {
+            self.counter -= 1;
+            return self.counter != 0;
+        }
+        true
+    }
+}
+
+
+/// Fades a channel's volume in or out.
+///
+/// The envelope is clocked at 64Hz.
+pub struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    /// Creates a new silent envelope.
+    pub fn new() -> Envelope {
+        Envelope { initial: 0, increase: false, period: 0, volume: 0, timer: 0 }
+    }
+
+    /// Loads the envelope byte of a sound register.
+    ///
+    /// Bits 0-2 are the step time, bit 3 the direction,
+    /// and bits 4-7 the initial volume.
+    pub fn load(&mut self, b: u8) {
+        self.period = b & 0b111;
+        self.increase = 0 != (b & 0b1000);
+        self.initial = b >> 4;
+    }
+
+    /// Checks whether the channel's DAC is powered.
+    ///
+    /// The DAC is off if the envelope can only ever output silence.
+    pub fn dac_enabled(&self) -> bool { (self.initial != 0) | self.increase }
+
+    /// Get the current volume `0..16`.
+    pub fn volume(&self) -> u8 { self.volume }
+
+    /// Restarts the envelope when the channel is triggered.
+    pub fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = self.period;
+    }
+
+    /// Clocks the envelope.
+    pub fn clock(&mut self) {
+        if self.period == 0 { return; }
+        if self.timer > 0 { self.timer -= 1; }
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase & (self.volume < 15) { self.volume += 1; }
+            if !self.increase & (self.volume > 0) { self.volume -= 1; }
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope { Envelope::new() }
+}
+
+
+/// Periodically changes the frequency of sound channel 1.
+///
+/// The sweep unit is clocked at 128Hz.
+pub struct Sweep {
+    period: u8,
+    decrease: bool,
+    shift: u8,
+    timer: u8,
+    shadow: u16,
+    enabled: bool,
+}
+
+impl Sweep {
+    /// Creates a new disabled sweep unit.
+    pub fn new() -> Sweep {
+        Sweep { period: 0, decrease: false, shift: 0, timer: 0, shadow: 0, enabled: false }
+    }
+
+    /// Loads `SOUND1CNT_L`.
+    ///
+    /// Bits 0-2 are the shift, bit 3 the direction,
+    /// and bits 4-6 the sweep time.
+    pub fn load(&mut self, b: u8) {
+        self.shift = b & 0b111;
+        self.decrease = 0 != (b & 0b1000);
+        self.period = (b >> 4) & 0b111;
+    }
+
+    /// Restarts the sweep when the channel is triggered.
+    ///
+    /// # Returns
+    /// `false` if the channel should be disabled,
+    /// because the first sweep would overflow.
+    pub fn trigger(&mut self, freq: u16) -> bool {
+        self.shadow = freq;
+        self.timer = if self.period == 0 { 8 } else { self.period };
+        self.enabled = (self.period != 0) | (self.shift != 0);
+        (self.shift == 0) || (self.next_frequency() <= 2047)
+    }
+
+    /// Clocks the sweep unit.
+    ///
+    /// # Params
+    /// - `freq`: The channel's frequency, which might be updated.
+    ///
+    /// # Returns
+    /// `false` if the channel should be disabled,
+    /// because the frequency overflowed.
+    pub fn clock(&mut self, freq: &mut u16) -> bool {
+        if self.timer > 0 { self.timer -= 1; }
+        if self.timer != 0 { return true; }
+        self.timer = if self.period == 0 { 8 } else { self.period };
+        if !self.enabled | (self.period == 0) { return true; }
+
+        let f = self.next_frequency();
+        if f > 2047 { return false; }
+        if self.shift != 0 {
+            self.shadow = f;
+            *freq = f;
+            // The new frequency is checked again, but not applied.
+            return self.next_frequency() <= 2047;
+        }
+        true
+    }
+
+    fn next_frequency(&self) -> u16 {
+        let delta = self.shadow >> self.shift;
+        if self.decrease { self.shadow - delta } else { self.shadow + delta }
+    }
+}
+
+impl Default for Sweep {
+    fn default() -> Sweep { Sweep::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
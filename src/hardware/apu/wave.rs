@@ -0,0 +1,173 @@
+// License below.
+//! Implements the wave RAM sound channel 3.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::units::*;
+
+/// Number of bytes in a single wave RAM bank.
+pub const WAVE_BANK_LEN: usize = 16;
+
+/// Number of 4-bit samples in a single wave RAM bank.
+const WAVE_BANK_SAMPLES: usize = WAVE_BANK_LEN * 2;
+
+
+/// A channel playing 4-bit samples from wave RAM.
+///
+/// Wave RAM consists of two banks of 32 samples each. The
+/// channel either loops over a single bank, or over both
+/// banks as 64 samples. The CPU can only access the bank
+/// that is not selected for playback.
+pub struct WaveChannel {
+    dac: bool,
+    active: bool,
+    frequency: u16,
+    timer: u32,
+    banks: [[u8; WAVE_BANK_LEN]; 2],
+    two_banks: bool,
+    selected_bank: usize,
+    playing_bank: usize,
+    position: usize,
+    volume_shift: u8,
+    force_75: bool,
+    length: LengthCounter,
+}
+
+impl WaveChannel {
+    /// Creates a new silent wave channel with zero-filled wave RAM.
+    pub fn new() -> WaveChannel {
+        WaveChannel {
+            dac: false,
+            active: false,
+            frequency: 0,
+            timer: 0,
+            banks: [[0; WAVE_BANK_LEN]; 2],
+            two_banks: false,
+            selected_bank: 0,
+            playing_bank: 0,
+            position: 0,
+            volume_shift: 4,
+            force_75: false,
+            length: LengthCounter::new(256),
+        }
+    }
+
+    /// Resets the channel to its power-on state, leaving wave RAM untouched.
+    pub fn reset(&mut self) {
+        let banks = self.banks;
+        *self = WaveChannel::new();
+        self.banks = banks;
+    }
+
+    /// Checks whether the channel is currently playing.
+    pub fn is_active(&self) -> bool { self.active }
+
+    /// Loads `SOUND3CNT_L`.
+    ///
+    /// Bit 5 selects 64 sample playback, bit 6 the bank
+    /// to play, and bit 7 powers the DAC.
+    pub fn write_select(&mut self, b: u8) {
+        self.two_banks = 0 != (b & (1 << 5));
+        self.selected_bank = ((b >> 6) & 1) as usize;
+        self.dac = 0 != (b & (1 << 7));
+        if !self.dac { self.active = false; }
+    }
+
+    /// Loads the length byte, bits 0-7 of `SOUND3CNT_H`.
+    pub fn write_length(&mut self, b: u8) { self.length.load(b as u16); }
+
+    /// Loads the volume byte, bits 8-15 of `SOUND3CNT_H`.
+    pub fn write_volume(&mut self, b: u8) {
+        // 0%, 100%, 50% or 25%, expressed as right shifts.
+        self.volume_shift = [4, 0, 1, 2][((b >> 5) & 0b11) as usize];
+        self.force_75 = 0 != (b & (1 << 7));
+    }
+
+    /// Loads the frequency control register `SOUND3CNT_X`.
+    ///
+    /// # Params
+    /// - `x`: The whole register's value.
+    /// - `high`: Whether the upper byte has been written,
+    ///   which may trigger the channel.
+    pub fn write_control(&mut self, x: u16, high: bool) {
+        self.frequency = x & 0x7FF;
+        if !high { return; }
+        self.length.set_enabled(0 != (x & (1 << 14)));
+        if 0 != (x & (1 << 15)) { self.trigger(); }
+    }
+
+    /// Restarts the channel at the first sample of the selected bank.
+    pub fn trigger(&mut self) {
+        self.active = self.dac;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+        self.playing_bank = self.selected_bank;
+    }
+
+    /// Reads a byte of the wave RAM bank accessible by the CPU.
+    pub fn read_ram(&self, offs: usize) -> u8 {
+        self.banks[self.selected_bank ^ 1][offs % WAVE_BANK_LEN]
+    }
+
+    /// Writes a byte of the wave RAM bank accessible by the CPU.
+    pub fn write_ram(&mut self, offs: usize, b: u8) {
+        self.banks[self.selected_bank ^ 1][offs % WAVE_BANK_LEN] = b;
+    }
+
+    /// Clocks the length counter at 256Hz.
+    pub fn clock_length(&mut self) {
+        if !self.length.clock() { self.active = false; }
+    }
+
+    /// Advances the sample position by a number of CPU cycles.
+    pub fn run(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position += 1;
+            if self.position == WAVE_BANK_SAMPLES {
+                self.position = 0;
+                if self.two_banks { self.playing_bank ^= 1; }
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    /// Get the current digital output `0..16`.
+    pub fn output(&self) -> u8 {
+        if !self.active { return 0; }
+        let b = self.banks[self.playing_bank][self.position / 2];
+        let s = if 0 == (self.position & 1) { b >> 4 } else { b & 0x0F };
+        if self.force_75 { (s * 3) / 4 } else { s >> self.volume_shift }
+    }
+
+    // Number of CPU cycles per sample.
+    fn period(&self) -> u32 { (2048 - self.frequency as u32) * 8 }
+}
+
+impl Default for WaveChannel {
+    fn default() -> WaveChannel { WaveChannel::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
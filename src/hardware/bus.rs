@@ -5,32 +5,176 @@
 #![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
 #![warn(missing_docs)]
 
-use std::cell::RefCell;
-use std::rc::Rc;
-
 use super::memory::*;
 use super::gamepak::*;
 use super::ioregs::*;
+use super::scheduler::*;
+use super::apu::*;
+use super::timer::*;
+use super::dma::*;
 use super::error::*;
+use super::cpu::cpumemory::{CODE_PAGE_SHIFT, PowerDown};
+
+// Code pages of the on-board WRAM come first, then the on-chip WRAM's.
+const CODE_PAGES_ON_BOARD: usize = (WRAM_ON_BOARD_LEN >> CODE_PAGE_SHIFT) as usize;
+const CODE_PAGES: usize = CODE_PAGES_ON_BOARD + (WRAM_ON_CHIP_LEN >> CODE_PAGE_SHIFT) as usize;
 
 // TODO how to handle aborts?
 /// Implements the memory and bus system of the GBA.
 pub struct Bus {
-    bios_rom: Rc<RefCell<BiosRom>>,
+    bios_rom: BiosRom,
+    wram_board: OnBoardWram,
+    wram_chip: OnChipWram,
     ioregs: IoRegisters,
-    game_pak: Rc<RefCell<GamePak>>,
+    palette: PaletteRam,
+    vram: VideoRam,
+    oam: ObjAttributeMemory,
+    game_pak: GamePak,
+    scheduler: Scheduler,
+    apu: Apu,
+    timers: Timers,
+    dma: Dma,
+    watched_code: Vec<bool>,
+    code_writes: Vec<u32>,
+    io_written: bool,
+    power_down: Option<PowerDown>,
 }
 
 impl Bus {
     /// Creates a new memory and bus system object.
-    pub fn new(gpak: Rc<RefCell<GamePak>>, bios: Rc<RefCell<BiosRom>>) -> Bus {
-        Bus {
+    pub fn new(gpak: GamePak, bios: BiosRom) -> Bus {
+        let mut bus = Bus {
             bios_rom: bios,
+            wram_board: OnBoardWram::new(),
+            wram_chip: OnChipWram::new(),
             ioregs: IoRegisters::new(),
+            palette: PaletteRam::new(),
+            vram: VideoRam::new(),
+            oam: ObjAttributeMemory::new(),
             game_pak: gpak,
+            scheduler: Scheduler::new(),
+            apu: Apu::new(),
+            timers: Timers::new(),
+            dma: Dma::new(),
+            watched_code: vec![false; CODE_PAGES],
+            code_writes: Vec::new(),
+            io_written: false,
+            power_down: None,
+        };
+        bus.reset_timeline();
+        bus
+    }
+
+    /// Resets all timed hardware and restarts the timeline at cycle 0.
+    pub fn reset_timeline(&mut self) {
+        self.scheduler.reset();
+        self.apu.reset(0);
+        self.timers.reset();
+        self.scheduler.schedule(EventKind::ApuFrameSequencer, FRAME_SEQUENCER_PERIOD);
+        self.scheduler.schedule(EventKind::ApuSample, Apu::sample_period(&self.ioregs));
+    }
+
+    /// Advances the timeline and handles all events due until then.
+    ///
+    /// # Params
+    /// - `cycles`: Number of CPU cycles passed.
+    #[inline]
+    pub fn tick(&mut self, cycles: u64) {
+        self.scheduler.advance(cycles);
+        if self.scheduler.is_event_due() {
+            self.handle_due_events();
         }
     }
 
+    /// Handles all due events.
+    ///
+    /// Kept out of `tick`, which runs after every
+    /// CPU step, while events are rare.
+    fn handle_due_events(&mut self) {
+        while let Some((t, ev)) = self.scheduler.pop_due() {
+            match ev {
+                EventKind::ApuFrameSequencer => {
+                    self.apu.clock_frame_sequencer(t);
+                    self.scheduler.schedule_at(ev, t + FRAME_SEQUENCER_PERIOD);
+                },
+                EventKind::ApuSample => {
+                    self.apu.mix_sample(t, &self.ioregs);
+                    self.scheduler.schedule_at(ev, t + Apu::sample_period(&self.ioregs));
+                },
+                EventKind::TimerOverflow(n) => self.handle_timer_overflow(n, t),
+            }
+        }
+    }
+
+    /// Sets an interrupt's request flag in `IF`.
+    pub fn request_interrupt(&mut self, irq: Interrupt) {
+        let flags = self.ioregs.read_halfword(REG_IF);
+        self.ioregs.write_halfword(REG_IF, flags | irq.mask());
+    }
+
+    /// Get all interrupts that are both enabled and requested.
+    ///
+    /// This ignores `IME`, just like waking up from halt does.
+    pub fn pending_interrupts(&self) -> u16 {
+        self.ioregs.read_halfword(REG_IE) & self.ioregs.read_halfword(REG_IF)
+    }
+
+    /// Checks whether `IME` is set and any interrupt is pending.
+    pub fn is_irq_requested(&self) -> bool {
+        ((self.ioregs.read_halfword(REG_IME) & 1) != 0) && (self.pending_interrupts() != 0)
+    }
+
+    /// Takes the low power mode requested via `HALTCNT` since the last call.
+    pub fn take_power_down(&mut self) -> Option<PowerDown> { self.power_down.take() }
+
+    /// Checks whether any IO register was written to since the last call.
+    pub fn take_io_writes(&mut self) -> bool {
+        let written = self.io_written;
+        self.io_written = false;
+        written
+    }
+
+    /// Gets the number of cycles until the next timed event is due.
+    pub fn cycles_until_event(&self) -> Option<u64> {
+        self.scheduler.next_event_at().map(|t| t.saturating_sub(self.scheduler.now()))
+    }
+
+    /// Gets the BIOS ROM.
+    pub fn bios(&self) -> &BiosRom { &self.bios_rom }
+
+    /// Gets the BIOS ROM.
+    pub fn bios_mut(&mut self) -> &mut BiosRom { &mut self.bios_rom }
+
+    /// Gets the GamePak.
+    pub fn game_pak(&self) -> &GamePak { &self.game_pak }
+
+    /// Gets the GamePak.
+    pub fn game_pak_mut(&mut self) -> &mut GamePak { &mut self.game_pak }
+
+    /// Gets the scheduler keeping track of timed events.
+    pub fn scheduler(&self) -> &Scheduler { &self.scheduler }
+
+    /// Gets the timers.
+    pub fn timers(&self) -> &Timers { &self.timers }
+
+    /// Gets the audio processing unit.
+    pub fn apu(&self) -> &Apu { &self.apu }
+
+    /// Gets the audio processing unit.
+    pub fn apu_mut(&mut self) -> &mut Apu { &mut self.apu }
+
+    /// Gets the memory-mapped IO registers.
+    pub fn ioregs(&self) -> &IoRegisters { &self.ioregs }
+
+    /// Gets the palette RAM.
+    pub fn palette(&self) -> &PaletteRam { &self.palette }
+
+    /// Gets the VRAM.
+    pub fn vram(&self) -> &VideoRam { &self.vram }
+
+    /// Gets the object attribute memory.
+    pub fn oam(&self) -> &ObjAttributeMemory { &self.oam }
+
     /// Loads a word from the memory system.
     ///
     /// The given address will be rounded down to the next word-aligned
@@ -51,25 +195,26 @@ impl Bus {
     /// - `Err(InvalidPhysicalAddress)`: The given address is not part of the memory map.
     /// - `Err(InvalidMemoryBusWidth)`: The memory-mapped device cannot load words.
     pub fn load_word(&self, addr: u32) -> Result<i32, GbaError> {
-        match PhysicalAddress::from_u32(addr) {
-            PhysicalAddress::BiosROM(p)       => Ok(self.bios_rom.borrow().read_word(p) as i32),
-            PhysicalAddress::OnBoardWRAM(_)   => unimplemented!(),
-            PhysicalAddress::OnChipWRAM(_)    => unimplemented!(),
-            PhysicalAddress::RegistersIO(p)   => Ok(self.ioregs.read_word(p) as i32),
-            PhysicalAddress::PaletteRAM(_)    => unimplemented!(),
-            PhysicalAddress::VRAM(_)          => unimplemented!(),
-            PhysicalAddress::AttributesOBJ(_) => unimplemented!(),
-            PhysicalAddress::GamePak0ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak1ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak2ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePakSRAM(p)   => Err(GbaError::InvalidMemoryBusWidth(p, 32)),
-            PhysicalAddress::Invalid(p)       => Err(GbaError::InvalidPhysicalAddress(p)),
-        }
+        let word = match PhysicalAddress::from_u32(addr & !0b11) {
+            PhysicalAddress::BiosROM(p)       => self.bios_rom.read_word(p),
+            PhysicalAddress::OnBoardWRAM(p)   => self.wram_board.read_word(p),
+            PhysicalAddress::OnChipWRAM(p)    => self.wram_chip.read_word(p),
+            PhysicalAddress::RegistersIO(p)   => self.read_io_word(p),
+            PhysicalAddress::PaletteRAM(p)    => self.palette.read_word(p),
+            PhysicalAddress::VRAM(p)          => self.vram.read_word(p),
+            PhysicalAddress::AttributesOBJ(p) => self.oam.read_word(p),
+            PhysicalAddress::GamePak0ROM(p) |
+            PhysicalAddress::GamePak1ROM(p) |
+            PhysicalAddress::GamePak2ROM(p)   => self.game_pak.rom().read_word(p),
+            PhysicalAddress::GamePakSRAM(p)   => return Err(GbaError::InvalidMemoryBusWidth(p, 32)),
+            PhysicalAddress::Invalid(p)       => return Err(GbaError::InvalidPhysicalAddress(p)),
+        };
+        Ok(word.rotate_right(8 * (addr & 0b11)) as i32)
     }
 
     /// Stores a word in the memory system.
     ///
-    /// Any given address will be rounded down to the next
+    /// The given address will be rounded down to the next
     /// word-aligned address.
     ///
     /// ## Params
@@ -82,17 +227,17 @@ impl Bus {
     /// - `Err(InvalidMemoryBusWidth)`: The memory-mapped device cannot store words.
     /// - `Err(InvalidRomAccess)`: Tried storing data into a ROM.
     pub fn store_word(&mut self, addr: u32, data: i32) -> Result<(), GbaError> {
-        match PhysicalAddress::from_u32(addr) {
+        match PhysicalAddress::from_u32(addr & !0b11) {
             PhysicalAddress::BiosROM(p)       => Err(GbaError::InvalidRomAccess(p)),
-            PhysicalAddress::OnBoardWRAM(_)   => unimplemented!(),
-            PhysicalAddress::OnChipWRAM(_)    => unimplemented!(),
-            PhysicalAddress::RegistersIO(p)   => Ok(self.ioregs.write_word(p, data as u32)),
-            PhysicalAddress::PaletteRAM(_)    => unimplemented!(),
-            PhysicalAddress::VRAM(_)          => unimplemented!(),
-            PhysicalAddress::AttributesOBJ(_) => unimplemented!(),
-            PhysicalAddress::GamePak0ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak1ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak2ROM(_)   => unimplemented!(),
+            PhysicalAddress::OnBoardWRAM(p)   => { self.note_code_write_on_board(p); Ok(self.wram_board.write_word(p, data as u32)) },
+            PhysicalAddress::OnChipWRAM(p)    => { self.note_code_write_on_chip(p); Ok(self.wram_chip.write_word(p, data as u32)) },
+            PhysicalAddress::RegistersIO(p)   => Ok(self.write_io_word(p, data as u32)),
+            PhysicalAddress::PaletteRAM(p)    => Ok(self.palette.write_word(p, data as u32)),
+            PhysicalAddress::VRAM(p)          => Ok(self.vram.write_word(p, data as u32)),
+            PhysicalAddress::AttributesOBJ(p) => Ok(self.oam.write_word(p, data as u32)),
+            PhysicalAddress::GamePak0ROM(p) |
+            PhysicalAddress::GamePak1ROM(p) |
+            PhysicalAddress::GamePak2ROM(p)   => Err(GbaError::InvalidRomAccess(p)),
             PhysicalAddress::GamePakSRAM(p)   => Err(GbaError::InvalidMemoryBusWidth(p, 32)),
             PhysicalAddress::Invalid(p)       => Err(GbaError::InvalidPhysicalAddress(p)),
         }
@@ -109,23 +254,27 @@ impl Bus {
     /// - `Err(InvalidMemoryBusWidth)`: The memory-mapped device cannot load bytes.
     pub fn load_byte(&self, addr: u32) -> Result<i32, GbaError> {
         match PhysicalAddress::from_u32(addr) {
-            PhysicalAddress::BiosROM(p)       => Ok(self.bios_rom.borrow().read_byte(p) as u32 as i32),
-            PhysicalAddress::OnBoardWRAM(_)   => unimplemented!(),
-            PhysicalAddress::OnChipWRAM(_)    => unimplemented!(),
-            PhysicalAddress::RegistersIO(p)   => Ok(self.ioregs.read_byte(p) as u32 as i32),
-            PhysicalAddress::PaletteRAM(_)    => unimplemented!(),
-            PhysicalAddress::VRAM(_)          => unimplemented!(),
-            PhysicalAddress::AttributesOBJ(_) => unimplemented!(),
-            PhysicalAddress::GamePak0ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak1ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak2ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePakSRAM(p)   => Ok(self.game_pak.borrow().sram().read_byte(p) as u32 as i32),
+            PhysicalAddress::BiosROM(p)       => Ok(self.bios_rom.read_byte(p) as u32 as i32),
+            PhysicalAddress::OnBoardWRAM(p)   => Ok(self.wram_board.read_byte(p) as u32 as i32),
+            PhysicalAddress::OnChipWRAM(p)    => Ok(self.wram_chip.read_byte(p) as u32 as i32),
+            PhysicalAddress::RegistersIO(p)   => Ok(self.read_io_byte(p) as u32 as i32),
+            PhysicalAddress::PaletteRAM(p)    => Ok(self.palette.read_byte(p) as u32 as i32),
+            PhysicalAddress::VRAM(p)          => Ok(self.vram.read_byte(p) as u32 as i32),
+            PhysicalAddress::AttributesOBJ(p) => Ok(self.oam.read_byte(p) as u32 as i32),
+            PhysicalAddress::GamePak0ROM(p) |
+            PhysicalAddress::GamePak1ROM(p) |
+            PhysicalAddress::GamePak2ROM(p)   => Ok(self.game_pak.rom().read_byte(p) as u32 as i32),
+            PhysicalAddress::GamePakSRAM(p)   => Ok(self.game_pak.sram().read_byte(p) as u32 as i32),
             PhysicalAddress::Invalid(p)       => Err(GbaError::InvalidPhysicalAddress(p)),
         }
     }
 
     /// Stores a byte in the memory system.
     ///
+    /// Palette RAM and VRAM only have a 16-bit data bus, so
+    /// the byte will be written to both halves of the halfword.
+    /// Byte writes to OAM or to the OBJ area of VRAM are ignored.
+    ///
     /// ## Params
     /// - `addr`: The address to store the byte to.
     /// - `data`: The byte to store.
@@ -139,23 +288,24 @@ impl Bus {
         let byte = (data & 0xFF) as u8;
         match PhysicalAddress::from_u32(addr) {
             PhysicalAddress::BiosROM(p)       => Err(GbaError::InvalidRomAccess(p)),
-            PhysicalAddress::OnBoardWRAM(_)   => unimplemented!(),
-            PhysicalAddress::OnChipWRAM(_)    => unimplemented!(),
-            PhysicalAddress::RegistersIO(p)   => Ok(self.ioregs.write_byte(p, byte)),
-            PhysicalAddress::PaletteRAM(_)    => unimplemented!(),
-            PhysicalAddress::VRAM(_)          => unimplemented!(),
-            PhysicalAddress::AttributesOBJ(_) => unimplemented!(),
-            PhysicalAddress::GamePak0ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak1ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak2ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePakSRAM(p)   => Ok(self.game_pak.borrow_mut().sram_mut().write_byte(p, byte)),
+            PhysicalAddress::OnBoardWRAM(p)   => { self.note_code_write_on_board(p); Ok(self.wram_board.write_byte(p, byte)) },
+            PhysicalAddress::OnChipWRAM(p)    => { self.note_code_write_on_chip(p); Ok(self.wram_chip.write_byte(p, byte)) },
+            PhysicalAddress::RegistersIO(p)   => Ok(self.write_io_byte(p, byte)),
+            PhysicalAddress::PaletteRAM(p)    => Ok(self.palette.write_halfword(p, ((byte as u16) << 8) | (byte as u16))),
+            PhysicalAddress::VRAM(p)          => Ok(self.store_vram_byte(p, byte)),
+            PhysicalAddress::AttributesOBJ(_) => Ok(()), // OAM ignores byte writes.
+            PhysicalAddress::GamePak0ROM(p) |
+            PhysicalAddress::GamePak1ROM(p) |
+            PhysicalAddress::GamePak2ROM(p)   => Err(GbaError::InvalidRomAccess(p)),
+            PhysicalAddress::GamePakSRAM(p)   => Ok(self.game_pak.sram_mut().write_byte(p, byte)),
             PhysicalAddress::Invalid(p)       => Err(GbaError::InvalidPhysicalAddress(p)),
         }
     }
 
     /// Loads a halfword from the memory system.
     ///
-    /// The result of a missaligned load is undefined.
+    /// Just like `load_word`, a missaligned address will be rounded
+    /// down and the zero-extended halfword rotated right by 8 bits.
     ///
     /// ## Params
     /// - `addr`: The address to load the halfword from.
@@ -165,26 +315,27 @@ impl Bus {
     /// - `Err(InvalidPhysicalAddress)`: The given address is not part of the memory map.
     /// - `Err(InvalidMemoryBusWidth)`: The memory-mapped device cannot load halfwords.
     pub fn load_halfword(&self, addr: u32) -> Result<i32, GbaError> {
-        if 0 != (addr & 0b01) { warn!("Reading missaligned halfword address {:#010X}.", addr); }
-        match PhysicalAddress::from_u32(addr) {
-            PhysicalAddress::BiosROM(p)       => Ok(self.bios_rom.borrow().read_halfword(p) as u32 as i32),
-            PhysicalAddress::OnBoardWRAM(_)   => unimplemented!(),
-            PhysicalAddress::OnChipWRAM(_)    => unimplemented!(),
-            PhysicalAddress::RegistersIO(p)   => Ok(self.ioregs.read_halfword(p) as u32 as i32),
-            PhysicalAddress::PaletteRAM(_)    => unimplemented!(),
-            PhysicalAddress::VRAM(_)          => unimplemented!(),
-            PhysicalAddress::AttributesOBJ(_) => unimplemented!(),
-            PhysicalAddress::GamePak0ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak1ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak2ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePakSRAM(p)   => Err(GbaError::InvalidMemoryBusWidth(p, 16)),
-            PhysicalAddress::Invalid(p)       => Err(GbaError::InvalidPhysicalAddress(p)),
-        }
+        let halfword = match PhysicalAddress::from_u32(addr & !0b01) {
+            PhysicalAddress::BiosROM(p)       => self.bios_rom.read_halfword(p),
+            PhysicalAddress::OnBoardWRAM(p)   => self.wram_board.read_halfword(p),
+            PhysicalAddress::OnChipWRAM(p)    => self.wram_chip.read_halfword(p),
+            PhysicalAddress::RegistersIO(p)   => self.read_io_halfword(p),
+            PhysicalAddress::PaletteRAM(p)    => self.palette.read_halfword(p),
+            PhysicalAddress::VRAM(p)          => self.vram.read_halfword(p),
+            PhysicalAddress::AttributesOBJ(p) => self.oam.read_halfword(p),
+            PhysicalAddress::GamePak0ROM(p) |
+            PhysicalAddress::GamePak1ROM(p) |
+            PhysicalAddress::GamePak2ROM(p)   => self.game_pak.rom().read_halfword(p),
+            PhysicalAddress::GamePakSRAM(p)   => return Err(GbaError::InvalidMemoryBusWidth(p, 16)),
+            PhysicalAddress::Invalid(p)       => return Err(GbaError::InvalidPhysicalAddress(p)),
+        };
+        Ok((halfword as u32).rotate_right(8 * (addr & 0b01)) as i32)
     }
 
     /// Stores a halfword in the memory system.
     ///
-    /// The result of a missaligned store is undefined.
+    /// The given address will be rounded down to the next
+    /// halfword-aligned address.
     ///
     /// ## Params
     /// - `addr`: The address to store the halfword to.
@@ -196,23 +347,203 @@ impl Bus {
     /// - `Err(InvalidMemoryBusWidth)`: The memory-mapped device cannot store halfwords.
     /// - `Err(InvalidRomAccess)`: Tried storing data into a ROM.
     pub fn store_halfword(&mut self, addr: u32, data: i32) -> Result<(), GbaError> {
-        if 0 != (addr & 0b01) { warn!("Reading missaligned halfword address {:#010X}.", addr); }
         let halfword = (data & 0xFFFF) as u16;
-        match PhysicalAddress::from_u32(addr) {
+        match PhysicalAddress::from_u32(addr & !0b01) {
             PhysicalAddress::BiosROM(p)       => Err(GbaError::InvalidRomAccess(p)),
-            PhysicalAddress::OnBoardWRAM(_)   => unimplemented!(),
-            PhysicalAddress::OnChipWRAM(_)    => unimplemented!(),
-            PhysicalAddress::RegistersIO(p)   => Ok(self.ioregs.write_halfword(p, halfword)),
-            PhysicalAddress::PaletteRAM(_)    => unimplemented!(),
-            PhysicalAddress::VRAM(_)          => unimplemented!(),
-            PhysicalAddress::AttributesOBJ(_) => unimplemented!(),
-            PhysicalAddress::GamePak0ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak1ROM(_)   => unimplemented!(),
-            PhysicalAddress::GamePak2ROM(_)   => unimplemented!(),
+            PhysicalAddress::OnBoardWRAM(p)   => { self.note_code_write_on_board(p); Ok(self.wram_board.write_halfword(p, halfword)) },
+            PhysicalAddress::OnChipWRAM(p)    => { self.note_code_write_on_chip(p); Ok(self.wram_chip.write_halfword(p, halfword)) },
+            PhysicalAddress::RegistersIO(p)   => Ok(self.write_io_halfword(p, halfword)),
+            PhysicalAddress::PaletteRAM(p)    => Ok(self.palette.write_halfword(p, halfword)),
+            PhysicalAddress::VRAM(p)          => Ok(self.vram.write_halfword(p, halfword)),
+            PhysicalAddress::AttributesOBJ(p) => Ok(self.oam.write_halfword(p, halfword)),
+            PhysicalAddress::GamePak0ROM(p) |
+            PhysicalAddress::GamePak1ROM(p) |
+            PhysicalAddress::GamePak2ROM(p)   => Err(GbaError::InvalidRomAccess(p)),
             PhysicalAddress::GamePakSRAM(p)   => Err(GbaError::InvalidMemoryBusWidth(p, 16)),
             PhysicalAddress::Invalid(p)       => Err(GbaError::InvalidPhysicalAddress(p)),
         }
     }
+
+    /// Stores a byte in the memory system, including ROMs.
+    ///
+    /// This allows debuggers to patch code. Unlike a store
+    /// to WRAM, patching a ROM doesn't notify the CPU, which
+    /// may still have the old code cached.
+    ///
+    /// ## Params
+    /// - `addr`: The address to store the byte to.
+    /// - `byte`: The byte to store.
+    ///
+    /// ## Returns
+    /// - `Ok`: Storing succeeded.
+    /// - `Err`: See `store_byte`.
+    pub fn patch_byte(&mut self, addr: u32, byte: u8) -> Result<(), GbaError> {
+        match PhysicalAddress::from_u32(addr) {
+            PhysicalAddress::BiosROM(p) => { self.bios_rom.bytes_mut(p)[0] = byte; Ok(()) },
+            PhysicalAddress::GamePak0ROM(p) |
+            PhysicalAddress::GamePak1ROM(p) |
+            PhysicalAddress::GamePak2ROM(p) => { self.game_pak.rom_mut().bytes_mut(p)[0] = byte; Ok(()) },
+            _ => self.store_byte(addr, byte as i32),
+        }
+    }
+
+    fn handle_timer_overflow(&mut self, n: usize, at: u64) {
+        let overflowed = self.timers.overflow(n, at, &mut self.scheduler);
+        for i in (0..TIMERS).filter(|&i| 0 != (overflowed & (1 << i))) {
+            if self.timers.irq_enabled(i) { self.request_interrupt(Interrupt::timer(i)); }
+            let (refill_a, refill_b) = self.apu.on_timer_overflow(i, at, &self.ioregs);
+            if refill_a { self.request_fifo_dma(IO_REGISTERS_FIRST + REG_FIFO_A); }
+            if refill_b { self.request_fifo_dma(IO_REGISTERS_FIRST + REG_FIFO_B); }
+        }
+    }
+
+    /// Checks whether the CPU may cache decoded code at an address.
+    ///
+    /// This is true for the ROMs and for WRAM outside of mirrors,
+    /// as writes to WRAM code pages can be watched.
+    pub fn is_code_cacheable(&self, addr: u32) -> bool {
+        match PhysicalAddress::from_u32(addr) {
+            PhysicalAddress::BiosROM(_) | PhysicalAddress::OnBoardWRAM(_) |
+            PhysicalAddress::GamePak0ROM(_) | PhysicalAddress::GamePak1ROM(_) |
+            PhysicalAddress::GamePak2ROM(_) => true,
+            PhysicalAddress::OnChipWRAM(_) => addr <= WRAM_ON_CHIP_LAST,
+            _ => false,
+        }
+    }
+
+    /// Checks whether loads from an address return the same data
+    /// until the next timed event.
+    ///
+    /// Devices like the timers compute their registers when read,
+    /// so these may change at any time.
+    pub fn is_load_stable(&self, addr: u32) -> bool {
+        match PhysicalAddress::from_u32(addr) {
+            PhysicalAddress::RegistersIO(p) => {
+                let offs = p & !0b11;
+                !(Apu::is_sound_register(offs) || Timers::is_timer_register(offs) || Dma::is_dma_register(offs))
+            },
+            _ => true,
+        }
+    }
+
+    /// Starts watching for writes to the WRAM code page containing `addr`.
+    pub fn watch_code(&mut self, addr: u32) {
+        match PhysicalAddress::from_u32(addr) {
+            PhysicalAddress::OnBoardWRAM(p) => self.watched_code[(p >> CODE_PAGE_SHIFT) as usize] = true,
+            PhysicalAddress::OnChipWRAM(p)  => self.watched_code[CODE_PAGES_ON_BOARD + (p >> CODE_PAGE_SHIFT) as usize] = true,
+            _ => {},
+        }
+    }
+
+    /// Moves the addresses of all watched code pages written
+    /// to since the last call into `pages`.
+    pub fn take_code_writes(&mut self, pages: &mut Vec<u32>) {
+        pages.extend(self.code_writes.drain(..));
+    }
+
+    fn note_code_write_on_board(&mut self, offs: u32) {
+        let page = (offs >> CODE_PAGE_SHIFT) as usize;
+        if self.watched_code[page] {
+            self.watched_code[page] = false;
+            self.code_writes.push(WRAM_ON_BOARD_FIRST + ((page as u32) << CODE_PAGE_SHIFT));
+        }
+    }
+
+    fn note_code_write_on_chip(&mut self, offs: u32) {
+        let page = (offs >> CODE_PAGE_SHIFT) as usize;
+        if self.watched_code[CODE_PAGES_ON_BOARD + page] {
+            self.watched_code[CODE_PAGES_ON_BOARD + page] = false;
+            self.code_writes.push(WRAM_ON_CHIP_FIRST + ((page as u32) << CODE_PAGE_SHIFT));
+        }
+    }
+
+    // Starts the sound DMA channel refilling the FIFO at the given address.
+    fn request_fifo_dma(&mut self, fifo: u32) {
+        for n in 1..3 {
+            if Dma::is_special(n, &self.ioregs) & (self.dma.destination(n) == fifo) {
+                let t = self.dma.transfer(n, &self.ioregs);
+                self.run_dma(t);
+                return;
+            }
+        }
+    }
+
+    // Performs a DMA transfer. The CPU is not stalled meanwhile.
+    fn run_dma(&mut self, t: DmaTransfer) {
+        let unit = if t.words { 4 } else { 2 };
+        let (mut src, mut dst) = (t.source, t.destination);
+        for _ in 0..t.units {
+            let r = if t.words { self.load_word(src & !0b11).and_then(|x| self.store_word(dst & !0b11, x)) }
+                    else { self.load_halfword(src & !0b01).and_then(|x| self.store_halfword(dst & !0b01, x)) };
+            if let Err(e) = r { warn!("DMA {} aborted: {}", t.channel, e); break; }
+            src = src.wrapping_add(t.source_control.step(unit));
+            dst = dst.wrapping_add(t.destination_control.step(unit));
+        }
+        if self.dma.finish(&t, src, dst, &mut self.ioregs) { self.request_interrupt(Interrupt::dma(t.channel)); }
+    }
+
+    // Reads an IO register, letting devices provide their current state.
+    fn read_io_byte(&self, offs: u32) -> u8 {
+        if Apu::is_sound_register(offs) { self.apu.read_register(offs, &self.ioregs) }
+        else if Timers::is_timer_register(offs) { self.timers.read_register(offs, self.scheduler.now()) }
+        else if Dma::is_dma_register(offs) { Dma::read_register(offs, &self.ioregs) }
+        else { self.ioregs.read_byte(offs) }
+    }
+
+    fn read_io_halfword(&self, offs: u32) -> u16 {
+        let offs = offs & !0b01;
+        (self.read_io_byte(offs) as u16) | ((self.read_io_byte(offs + 1) as u16) << 8)
+    }
+
+    fn read_io_word(&self, offs: u32) -> u32 {
+        let offs = offs & !0b11;
+        (self.read_io_halfword(offs) as u32) | ((self.read_io_halfword(offs + 2) as u32) << 16)
+    }
+
+    // Writes an IO register, letting devices react to the new value.
+    fn write_io_byte(&mut self, offs: u32, b: u8) {
+        self.io_written = true;
+        if Apu::is_sound_register(offs) {
+            let now = self.scheduler.now();
+            self.apu.write_register(offs, b, &mut self.ioregs, now);
+        }
+        else if Timers::is_timer_register(offs) { self.timers.write_register(offs, b, &mut self.scheduler); }
+        else if Dma::is_dma_register(offs) {
+            if let Some(t) = self.dma.write_register(offs, b, &mut self.ioregs) { self.run_dma(t); }
+        }
+        else if offs == REG_HALTCNT {
+            self.power_down = Some(if (b & 0x80) == 0 { PowerDown::Halt } else { PowerDown::Stop });
+        }
+        else if (offs & !1) == REG_IF {
+            // Writing 1 acknowledges an interrupt.
+            let flags = self.ioregs.read_byte(offs);
+            self.ioregs.write_byte(offs, flags & !b);
+        }
+        else { self.ioregs.write_byte(offs, b); }
+    }
+
+    // Devices see the lower byte written first.
+    fn write_io_halfword(&mut self, offs: u32, data: u16) {
+        let offs = offs & !0b01;
+        self.write_io_byte(offs, data as u8);
+        self.write_io_byte(offs + 1, (data >> 8) as u8);
+    }
+
+    fn write_io_word(&mut self, offs: u32, data: u32) {
+        let offs = offs & !0b11;
+        self.write_io_halfword(offs, data as u16);
+        self.write_io_halfword(offs + 2, (data >> 16) as u16);
+    }
+
+    // Duplicates a byte into a whole halfword, unless
+    // it targets the OBJ tiles, depending on the BG mode.
+    fn store_vram_byte(&mut self, offs: u32, byte: u8) {
+        let bitmap_mode = (self.ioregs.read_halfword(REG_DISPCNT) & 0b111) >= 3;
+        let obj_first = if bitmap_mode { 0x14000 } else { 0x10000 };
+        if offs < obj_first {
+            self.vram.write_halfword(offs, ((byte as u16) << 8) | (byte as u16));
+        }
+    }
 }
 
 
@@ -0,0 +1,186 @@
+// License below.
+//! Implements a cache of decoded basic blocks.
+//!
+//! A basic block is a run of instructions that execute one
+//! after another, up to the first instruction that may change
+//! the control flow. Caching decoded blocks saves fetching and
+//! decoding the same instructions over and over again.
+//!
+//! Blocks are keyed by their first address and the CPU state.
+//! They never cross a code page, such that a write to a page
+//! only has to invalidate the blocks inside of that page.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use super::psr::State;
+use super::super::arminstruction::{ArmInstruction, ArmOpcode};
+use super::super::cpumemory::CODE_PAGE_SHIFT;
+
+/// Maximum number of instructions in a single basic block.
+pub const MAX_BLOCK_LEN: usize = 32;
+
+/// Gets the first address of the code page containing `addr`.
+pub fn code_page_of(addr: u32) -> u32 { addr & !((1 << CODE_PAGE_SHIFT) - 1) }
+
+/// Checks whether an instruction must be the last one of a block.
+///
+/// This is the case for all instructions that may write PC,
+/// change the CPU's mode or state, or cause an exception.
+pub fn ends_basic_block(inst: &ArmInstruction) -> bool {
+    const PC: usize = 15;
+    match inst.opcode() {
+        ArmOpcode::DataProcessing => inst.Rd() == PC,
+        ArmOpcode::LDR_STR | ArmOpcode::LDRH_STRH_Reg | ArmOpcode::LDRH_STRH_Imm => {
+            (inst.is_load() && inst.Rd() == PC) || inst.Rn() == PC
+        },
+        ArmOpcode::LDM_STM => {
+            // An empty list transfers PC.
+            let rmap = inst.register_map();
+            rmap == 0 || 0 != (rmap & 0x8000) || inst.Rn() == PC
+        },
+        ArmOpcode::MRS | ArmOpcode::SWP | ArmOpcode::MUL_MLA | ArmOpcode::MULL_MLAL => false,
+        _ => true,
+    }
+}
+
+/// A decoded run of ARM state instructions.
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    addr: u32,
+    state: State,
+    instructions: Vec<ArmInstruction>,
+}
+
+impl BasicBlock {
+    /// Creates an empty block starting at a given address.
+    pub fn new(addr: u32, state: State) -> BasicBlock {
+        BasicBlock { addr: addr, state: state, instructions: Vec::with_capacity(MAX_BLOCK_LEN) }
+    }
+
+    /// Gets the address of the block's first instruction.
+    pub fn address(&self) -> u32 { self.addr }
+
+    /// Gets the CPU state the block was decoded for.
+    pub fn state(&self) -> State { self.state }
+
+    /// Gets the address right after the block's last instruction.
+    pub fn end_address(&self) -> u32 { self.addr.wrapping_add(self.instructions.len() as u32 * 4) }
+
+    /// Gets the number of instructions in this block.
+    pub fn len(&self) -> usize { self.instructions.len() }
+
+    /// Checks whether this block has no instructions.
+    pub fn is_empty(&self) -> bool { self.instructions.is_empty() }
+
+    /// Checks whether this block can't take any more instructions.
+    ///
+    /// This is the case once it reaches the maximum length
+    /// or the end of its code page.
+    pub fn is_full(&self) -> bool {
+        (self.len() >= MAX_BLOCK_LEN) || (!self.is_empty() && code_page_of(self.end_address()) != code_page_of(self.addr))
+    }
+
+    /// Gets the instruction at a given index.
+    pub fn instruction(&self, index: usize) -> ArmInstruction { self.instructions[index] }
+
+    /// Appends an instruction to the block.
+    pub fn push(&mut self, inst: ArmInstruction) { self.instructions.push(inst); }
+}
+
+/// Stores basic blocks for quick lookup by address and state.
+#[derive(Debug, Default)]
+pub struct BlockCache {
+    blocks: Vec<Option<BasicBlock>>,
+    free: Vec<usize>,
+    lookup: HashMap<(u32, State), usize>,
+    pages: HashMap<u32, Vec<usize>>,
+}
+
+impl BlockCache {
+    /// Creates an empty block cache.
+    pub fn new() -> BlockCache { BlockCache::default() }
+
+    /// Removes all blocks.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.free.clear();
+        self.lookup.clear();
+        self.pages.clear();
+    }
+
+    /// Gets the number of cached blocks.
+    pub fn len(&self) -> usize { self.lookup.len() }
+
+    /// Checks whether no blocks are cached.
+    pub fn is_empty(&self) -> bool { self.lookup.is_empty() }
+
+    /// Finds the slot of the block starting at a given address.
+    pub fn find(&self, addr: u32, state: State) -> Option<usize> {
+        self.lookup.get(&(addr, state)).cloned()
+    }
+
+    /// Gets the block in a given slot, if it is still valid.
+    #[inline]
+    pub fn block(&self, slot: usize) -> Option<&BasicBlock> {
+        self.blocks.get(slot).and_then(|b| b.as_ref())
+    }
+
+    /// Adds a block, replacing any block with the same key.
+    ///
+    /// # Returns
+    /// The slot of the new block.
+    pub fn insert(&mut self, block: BasicBlock) -> usize {
+        let key = (block.address(), block.state());
+        if let Some(old) = self.lookup.remove(&key) { self.blocks[old] = None; self.free.push(old); }
+
+        let page = code_page_of(block.address());
+        let slot = match self.free.pop() {
+            Some(s) => { self.blocks[s] = Some(block); s },
+            None    => { self.blocks.push(Some(block)); self.blocks.len() - 1 },
+        };
+        self.lookup.insert(key, slot);
+        self.pages.entry(page).or_insert_with(Vec::new).push(slot);
+        slot
+    }
+
+    /// Removes all blocks inside a given code page.
+    ///
+    /// # Params
+    /// - `page`: The first address of the code page.
+    pub fn invalidate_page(&mut self, page: u32) {
+        let slots = match self.pages.remove(&page) { Some(s) => s, None => return };
+        for slot in slots {
+            // A slot may have been replaced and reused since.
+            let key = match self.blocks[slot] {
+                Some(ref b) if code_page_of(b.address()) == page => (b.address(), b.state()),
+                _ => continue,
+            };
+            self.lookup.remove(&key);
+            self.blocks[slot] = None;
+            self.free.push(slot);
+        }
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -6,6 +6,7 @@
 #![warn(missing_docs)]
 
 use super::Arm7Tdmi;
+use super::super::cpumemory::CpuMemory;
 use super::psr::{PSR, State, Mode};
 use super::super::arminstruction::ArmInstruction;
 use super::super::thumbinstruction::ThumbInstruction;
@@ -79,11 +80,11 @@ impl Arm7TdmiDiff {
     pub fn is_colourising(&self) -> bool { self.colour }
 
     /// Applys the new CPU state to this diff marking changes.
-    pub fn diff(&mut self, cpu: &Arm7Tdmi) {
+    pub fn diff<M: CpuMemory>(&mut self, cpu: &Arm7Tdmi<M>) {
         self.cpsr_old = self.cpsr_new;
         self.spsr_old = self.spsr_new;
         self.cpsr_new = cpu.cpsr;
-        self.spsr_new = cpu.spsr[cpu.cpsr.mode() as u8 as usize];
+        self.spsr_new = cpu.spsr[cpu.cpsr.mode().index()];
         self.gpr_new = 0;
         for i in 0_u32..16 {
             let j = i as usize;
@@ -108,7 +109,7 @@ impl Arm7TdmiDiff {
         print!("{}\n\t\tCPSR: ", blue.paint("# Arm7Tdmi\n\t- Register Set"));
         Arm7TdmiDiff::print_psr(self.cpsr_old, self.cpsr_new, self.colour);
         print!("\tSPSR: ");
-        if self.cpsr_new.mode() == Mode::User { print!("[---- -- ----- ---]"); }
+        if !self.cpsr_new.mode().has_spsr() { print!("[---- -- ----- ---]"); }
         else { Arm7TdmiDiff::print_psr(self.spsr_old, self.spsr_new, self.colour); }
         println!("");
 
@@ -7,13 +7,15 @@
 use super::*;
 use std::fmt;
 
-impl fmt::Display for Arm7Tdmi {
+impl<M: CpuMemory> fmt::Display for Arm7Tdmi<M> {
     /// Shows the current CPU state with all its registers and what not.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // Show CPSR and SPSR.
         try!(write!(f, "Arm7Tdmi\n\n- Register Set\n\tCPSR {}\tSPSR ", self.cpsr));
-        if self.mode == Mode::User { try!(write!(f, "[none]\n")); }
-        else { try!(write!(f, "{}\n", self.spsr[self.mode as u8 as usize])); }
+        match self.spsr(self.mode) {
+            Some(spsr) => try!(write!(f, "{}\n", spsr)),
+            None       => try!(write!(f, "[none]\n")),
+        }
 
         // Show all 16 GPRs in a nice table.
         for i in 0..16 {
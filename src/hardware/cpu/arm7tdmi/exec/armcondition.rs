@@ -43,6 +43,24 @@ impl ArmCondition {
         ArmCondition::CONDITION_NAMES[i]
     }
 
+    /// Parses the assembly name of an ARM condition.
+    ///
+    /// Besides the names `assembly_name` returns, this accepts
+    /// `al` as well as the aliases `cs` for `hs` and `cc` for `lo`.
+    pub fn from_assembly_name(name: &str) -> Option<ArmCondition> {
+        match name {
+            "eq" => Some(ArmCondition::EQ), "ne" => Some(ArmCondition::NE),
+            "hs" | "cs" => Some(ArmCondition::HS), "lo" | "cc" => Some(ArmCondition::LO),
+            "mi" => Some(ArmCondition::MI), "pl" => Some(ArmCondition::PL),
+            "vs" => Some(ArmCondition::VS), "vc" => Some(ArmCondition::VC),
+            "hi" => Some(ArmCondition::HI), "ls" => Some(ArmCondition::LS),
+            "ge" => Some(ArmCondition::GE), "lt" => Some(ArmCondition::LT),
+            "gt" => Some(ArmCondition::GT), "le" => Some(ArmCondition::LE),
+            "al" | "" => Some(ArmCondition::AL), "nv" => Some(ArmCondition::NV),
+            _ => None,
+        }
+    }
+
     /// Evaluates the condition field depending on the CPSR's state.
     pub fn check(self, cpsr: &PSR) -> Result<bool, GbaError> {
         match self {
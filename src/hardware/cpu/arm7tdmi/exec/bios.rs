@@ -0,0 +1,214 @@
+// License below.
+//! Implements calling high-level emulated BIOS functions.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::super::*;
+use super::super::super::super::ioregs::*;
+use super::super::super::super::memory::*;
+use super::super::super::super::hle::{self, BiosFunction};
+
+impl<M: CpuMemory> Arm7Tdmi<M> {
+    /// Executes a BIOS function without running any BIOS code.
+    ///
+    /// Registers, memory and the cycles spent are updated as if
+    /// the BIOS function was called and returned.
+    ///
+    /// # Returns
+    /// - `Ok(Some)` if the function has been emulated.
+    /// - `Ok(None)` if the function must be run in the BIOS ROM.
+    /// - `Err` if the function failed, e.g. due to invalid addresses.
+    pub fn execute_bios_function(&mut self, func: BiosFunction) -> Result<Option<CpuAction>, GbaError> {
+        trace!("Emulating BIOS function {}.", func);
+        let action = match func {
+            BiosFunction::SoftReset        => try!(self.bios_soft_reset()),
+            BiosFunction::RegisterRamReset => try!(self.bios_register_ram_reset()),
+            BiosFunction::Halt             => self.bios_halt(),
+            BiosFunction::IntrWait         => { let (d, m) = (self.gpr[0] != 0, self.gpr[1] as u16); try!(self.bios_intr_wait(d, m)) },
+            BiosFunction::VBlankIntrWait   => { self.gpr[0] = 1; self.gpr[1] = 1; try!(self.bios_intr_wait(true, Interrupt::VBlank.mask())) },
+            BiosFunction::Div              => { let (n, d) = (self.gpr[0], self.gpr[1]); self.bios_div(n, d, 0) },
+            BiosFunction::DivArm           => { let (d, n) = (self.gpr[0], self.gpr[1]); self.bios_div(n, d, 3) },
+            BiosFunction::Sqrt             => self.bios_sqrt(),
+            BiosFunction::ArcTan           => self.bios_arctan(),
+            BiosFunction::ArcTan2          => self.bios_arctan2(),
+            BiosFunction::CpuSet           => try!(self.bios_cpu_set(false)),
+            BiosFunction::CpuFastSet       => try!(self.bios_cpu_set(true)),
+            BiosFunction::BitUnPack | BiosFunction::LZ77UnCompWram | BiosFunction::LZ77UnCompVram |
+            BiosFunction::HuffUnComp | BiosFunction::RLUnCompWram | BiosFunction::RLUnCompVram |
+            BiosFunction::Diff8bitUnFilterWram | BiosFunction::Diff8bitUnFilterVram |
+            BiosFunction::Diff16bitUnFilter => try!(self.bios_uncomp(func)),
+            BiosFunction::GetBiosChecksum  => { self.gpr[0] = hle::BIOS_CHECKSUM as i32; self.delay_cycles += 0x4000 / 4 * 5; CpuAction::None },
+            BiosFunction::BgAffineSet      => try!(self.bios_bg_affine_set()),
+            BiosFunction::ObjAffineSet     => try!(self.bios_obj_affine_set()),
+            BiosFunction::MidiKey2Freq     => try!(self.bios_midi_key2freq()),
+            BiosFunction::SoundBias        => try!(self.bios_sound_bias()),
+            BiosFunction::SoundDriverInit | BiosFunction::SoundDriverMode | BiosFunction::SoundDriverMain |
+            BiosFunction::SoundDriverVSync | BiosFunction::SoundChannelClear |
+            BiosFunction::SoundWhatever0 | BiosFunction::SoundWhatever1 | BiosFunction::SoundWhatever2 |
+            BiosFunction::SoundWhatever3 | BiosFunction::SoundWhatever4 |
+            BiosFunction::SoundDriverVSyncOff | BiosFunction::SoundDriverVSyncOn |
+            BiosFunction::SoundGetJumpList => {
+                // The sound driver is not emulated. Skipping its calls
+                // leaves such games silent, but lets them keep running.
+                let bit = 1_u64 << (func as u8);
+                if 0 == (self.skipped_bios_functions & bit) {
+                    warn!("BIOS sound driver function {} skipped.", func);
+                    self.skipped_bios_functions |= bit;
+                }
+                CpuAction::None
+            },
+            _ => {
+                debug!("BIOS function {} is not emulated.", func);
+                return Ok(None);
+            },
+        };
+        Ok(Some(action))
+    }
+
+    fn bios_soft_reset(&mut self) -> Result<CpuAction, GbaError> {
+        let to_ram = 0 != try!(self.memory.load_byte(hle::BIOS_RESET_FLAG, MemoryAccess::NonSequential));
+        let bios_area = WRAM_ON_CHIP_LAST + 1 - 0x200;
+        for i in 0..(0x200 / 4) {
+            let access = if i == 0 { MemoryAccess::NonSequential } else { MemoryAccess::Sequential };
+            try!(self.memory.store_word(bios_area + i * 4, 0, access));
+        }
+        let entry = if to_ram { WRAM_ON_BOARD_FIRST } else { GAME_PAK_WS0_ROM_FIRST };
+
+        self.direct_boot(entry);
+        self.delay_cycles += 0x200 / 32 * 10;
+        Ok(CpuAction::FlushPipeline)
+    }
+
+    fn bios_register_ram_reset(&mut self) -> Result<CpuAction, GbaError> {
+        let flags = self.gpr[0] as u8;
+        let cycles = try!(hle::register_ram_reset(&mut self.memory, flags));
+        self.delay_cycles += cycles;
+        Ok(CpuAction::None)
+    }
+
+    fn bios_halt(&mut self) -> CpuAction {
+        self.halt = HaltState::Halted;
+        CpuAction::None
+    }
+
+    fn bios_intr_wait(&mut self, discard: bool, mask: u16) -> Result<CpuAction, GbaError> {
+        try!(self.memory.store_halfword(IO_REGISTERS_FIRST + REG_IME, 1, MemoryAccess::NonSequential));
+        let flags = try!(self.memory.load_halfword(hle::BIOS_IRQ_FLAGS, MemoryAccess::NonSequential)) as u16;
+        try!(self.memory.store_halfword(hle::BIOS_IRQ_FLAGS, (flags & !mask) as i32, MemoryAccess::NonSequential));
+
+        // Old flags only count if not discarded.
+        if discard || (flags & mask) == 0 { self.halt = HaltState::IntrWait(mask); }
+        Ok(CpuAction::None)
+    }
+
+    fn bios_div(&mut self, num: i32, den: i32, extra_cycles: u32) -> CpuAction {
+        let q = match hle::div(num, den) {
+            Some(q) => q,
+            None    => {
+                // The BIOS would hang for most numerators. Leave
+                // the same garbage behind as other emulators do.
+                warn!("BIOS division {} / 0.", num);
+                hle::Quotient { quotient: if num < 0 { -1 } else { 1 }, remainder: num, abs_quotient: 1, cycles: 0 }
+            },
+        };
+        self.gpr[0] = q.quotient;
+        self.gpr[1] = q.remainder;
+        self.gpr[3] = q.abs_quotient as i32;
+        self.delay_cycles += q.cycles + extra_cycles;
+        CpuAction::None
+    }
+
+    fn bios_sqrt(&mut self) -> CpuAction {
+        let (root, cycles) = hle::sqrt(self.gpr[0] as u32);
+        self.gpr[0] = root as i32;
+        self.delay_cycles += cycles;
+        CpuAction::None
+    }
+
+    fn bios_arctan(&mut self) -> CpuAction {
+        let a = hle::arctan(self.gpr[0]);
+        self.set_bios_angle(a);
+        CpuAction::None
+    }
+
+    fn bios_arctan2(&mut self) -> CpuAction {
+        let a = hle::arctan2(self.gpr[0], self.gpr[1]);
+        self.set_bios_angle(a);
+        CpuAction::None
+    }
+
+    fn set_bios_angle(&mut self, a: hle::Angle) {
+        self.gpr[0] = a.angle;
+        if let Some(r1) = a.r1 { self.gpr[1] = r1; }
+        self.gpr[3] = a.r3;
+        self.delay_cycles += a.cycles;
+    }
+
+    fn bios_uncomp(&mut self, func: BiosFunction) -> Result<CpuAction, GbaError> {
+        let (src, dst, info) = (self.gpr[0] as u32, self.gpr[1] as u32, self.gpr[2] as u32);
+        let cycles = try!(hle::run_uncomp(&mut self.memory, func, src, dst, info));
+        self.delay_cycles += cycles.unwrap_or(0);
+        Ok(CpuAction::None)
+    }
+
+    fn bios_bg_affine_set(&mut self) -> Result<CpuAction, GbaError> {
+        let (src, dst, count) = (self.gpr[0] as u32, self.gpr[1] as u32, self.gpr[2] as u32);
+        let cycles = try!(hle::bg_affine_set(&mut self.memory, src, dst, count));
+        self.delay_cycles += cycles;
+        Ok(CpuAction::None)
+    }
+
+    fn bios_obj_affine_set(&mut self) -> Result<CpuAction, GbaError> {
+        let (src, dst, count, stride) = (self.gpr[0] as u32, self.gpr[1] as u32, self.gpr[2] as u32, self.gpr[3] as u32);
+        let cycles = try!(hle::obj_affine_set(&mut self.memory, src, dst, count, stride));
+        self.delay_cycles += cycles;
+        Ok(CpuAction::None)
+    }
+
+    fn bios_midi_key2freq(&mut self) -> Result<CpuAction, GbaError> {
+        let (wave, key, fine) = (self.gpr[0] as u32, self.gpr[1] as u8, self.gpr[2] as u8);
+        self.gpr[0] = try!(hle::midi_key2freq(&mut self.memory, wave, key, fine)) as i32;
+        self.delay_cycles += 150;
+        Ok(CpuAction::None)
+    }
+
+    fn bios_sound_bias(&mut self) -> Result<CpuAction, GbaError> {
+        let (enable, delay) = (self.gpr[0] != 0, self.gpr[1] as u32);
+        let cycles = try!(hle::sound_bias(&mut self.memory, enable, delay));
+        self.delay_cycles += cycles;
+        Ok(CpuAction::None)
+    }
+
+    fn bios_cpu_set(&mut self, fast: bool) -> Result<CpuAction, GbaError> {
+        let (src, dst, ctrl) = (self.gpr[0] as u32, self.gpr[1] as u32, self.gpr[2] as u32);
+        let res = if fast { try!(hle::cpu_fast_set(&mut self.memory, src, dst, ctrl)) }
+                  else    { try!(hle::cpu_set(&mut self.memory, src, dst, ctrl)) };
+        self.gpr[0] = res.src as i32;
+        self.gpr[1] = res.dst as i32;
+        self.gpr[3] = res.last as i32;
+        self.delay_cycles += res.cycles;
+        Ok(CpuAction::None)
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -9,8 +9,9 @@ use std::u32;
 use super::super::*;
 use super::super::super::arminstruction::*;
 use super::super::super::super::error::*;
+use super::super::super::super::hle::BiosFunction;
 
-impl Arm7Tdmi {
+impl<M: CpuMemory> Arm7Tdmi<M> {
     /// Immediately executes a single ARM state instruction.
     pub fn execute_arm_state(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
         // TODO do research on when to flush the pipeline due to R15-writes
@@ -51,8 +52,10 @@ impl Arm7Tdmi {
     }
 
     fn execute_b_bl(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
-        if inst.is_branch_with_link() { self.gpr[14] = self.gpr[15].wrapping_sub(4); }
-        self.gpr[15] = self.gpr[15].wrapping_add(inst.branch_offset());
+        let pc = self.gpr[15];
+        if inst.is_branch_with_link() { self.gpr[14] = pc.wrapping_sub(4); }
+        else if self.detect_idle_loops { self.taken_branch = Some((pc.wrapping_sub(8) as u32, pc.wrapping_add(inst.branch_offset()) as u32)); }
+        self.gpr[15] = pc.wrapping_add(inst.branch_offset());
         Ok(CpuAction::FlushPipeline)
     }
 
@@ -61,7 +64,7 @@ impl Arm7Tdmi {
         let mut res = self.gpr[inst.Rs()].wrapping_mul(self.gpr[inst.Rm()]);
         if inst.is_accumulating() { res = res.wrapping_add(self.gpr[inst.Rd()]); }
         self.gpr[inst.Rn()] = res;
-        Ok(CpuAction::None)
+        Ok(if inst.Rn() == Arm7Tdmi::PC { CpuAction::FlushPipeline } else { CpuAction::None })
     }
 
     fn execute_mul_mla_s(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
@@ -71,7 +74,7 @@ impl Arm7Tdmi {
         self.cpsr.set_N(x < 0);
         self.cpsr.set_Z(x == 0);
         self.cpsr.set_C(false); // "some meaningless value"
-        Ok(CpuAction::None)
+        Ok(if inst.Rn() == Arm7Tdmi::PC { CpuAction::FlushPipeline } else { CpuAction::None })
     }
 
     fn execute_mull_mlal(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
@@ -83,8 +86,9 @@ impl Arm7Tdmi {
         if inst.is_accumulating() {
             res = res.wrapping_add(((self.gpr[inst.Rn()] as u64) << 32) | (self.gpr[inst.Rd()] as u64));
         }
-        self.gpr[inst.Rn()] = ((res >> 32) & (u32::MAX as u64)) as i32;
+        // The hardware writes RdHi last, so it wins if both are the same.
         self.gpr[inst.Rd()] = ((res      ) & (u32::MAX as u64)) as i32;
+        self.gpr[inst.Rn()] = ((res >> 32) & (u32::MAX as u64)) as i32;
 
         if inst.is_setting_flags() {
             self.cpsr.set_N((res & (1 << 63)) != 0);
@@ -93,7 +97,7 @@ impl Arm7Tdmi {
             self.cpsr.set_V(false); // "some meaningless value"
         }
 
-        Ok(CpuAction::None)
+        Ok(if (inst.Rn() == Arm7Tdmi::PC) || (inst.Rd() == Arm7Tdmi::PC) { CpuAction::FlushPipeline } else { CpuAction::None })
     }
 
     fn execute_data_processing(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
@@ -113,38 +117,37 @@ impl Arm7Tdmi {
 
         if inst.Rd() == Arm7Tdmi::PC { // FIXME really error or just ignore?
             if self.mode == Mode::User { error!("USR has no SPSR."); return Err(GbaError::PrivilegedUserCode); }
-            self.cpsr = self.spsr[self.mode as u8 as usize];
+            self.restore_cpsr();
         }
 
         Ok(if inst.Rd() == Arm7Tdmi::PC { CpuAction::FlushPipeline } else { CpuAction::None })
     }
 
     fn execute_mrs(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
+        // Modes without SPSR read CPSR instead.
         self.gpr[inst.Rd()] = if inst.is_accessing_spsr() {
-            if self.mode == Mode::User { error!("USR mode has no SPSR."); return Err(GbaError::PrivilegedUserCode); }
-            self.spsr[self.mode as u8 as usize].0 as i32
+            self.current_spsr().0 as i32
         } else {
             self.cpsr.0 as i32
         };
-        Ok(CpuAction::None)
+        Ok(if inst.Rd() == Arm7Tdmi::PC { CpuAction::FlushPipeline } else { CpuAction::None })
     }
 
     fn execute_msr_reg(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
         let rm = self.gpr[inst.Rm()] as u32;
-        if self.mode == Mode::User {
+        if inst.is_accessing_spsr() {
+            // Modes without SPSR ignore writes to it.
+            if self.mode.has_spsr() { self.spsr[self.mode.index()].override_non_reserved(rm); }
+        } else if self.mode == Mode::User {
             // User mode can only set the flag bits of CPSR.
-            if inst.is_accessing_spsr() { error!("USR mode has no SPSR."); return Err(GbaError::PrivilegedUserCode); }
             self.cpsr.override_flags(rm);
         } else {
-            if inst.is_accessing_spsr() { self.spsr[self.mode as u8 as usize].override_non_reserved(rm); }
-            else {
-                let s = self.cpsr.state();
-                self.cpsr.override_non_reserved(rm);
-                if self.cpsr.state() != s { warn!("MSR_Reg changed the T bit!"); }
-            }
+            let s = self.cpsr.state();
+            self.cpsr.override_non_reserved(rm);
+            if self.cpsr.state() != s { warn!("MSR_Reg changed the T bit!"); }
             // Mode might have changed.
-            let old_mode = self.cpsr.mode();
-            self.change_mode(old_mode);
+            let new_mode = self.cpsr.mode();
+            if new_mode != self.mode { self.change_mode(new_mode); }
         }
         Ok(CpuAction::None)
     }
@@ -152,8 +155,7 @@ impl Arm7Tdmi {
     fn execute_msr_flags(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
         let op = inst.calculate_shsr_field(&self.gpr[..]) as u32;
         if inst.is_accessing_spsr() {
-            if self.mode == Mode::User { error!("USR mode has no SPSR."); return Err(GbaError::PrivilegedUserCode); }
-            self.spsr[self.mode as u8 as usize].override_flags(op);
+            if self.mode.has_spsr() { self.spsr[self.mode.index()].override_flags(op); }
         } else {
             self.cpsr.override_flags(op);
         }
@@ -165,18 +167,23 @@ impl Arm7Tdmi {
         let mut base = self.gpr[inst.Rn()] as u32;
         let offs = inst.shifted_offset(&self.gpr[..], self.cpsr.C()) as u32;
         if inst.is_pre_indexed() { base = base.wrapping_add(offs); }
-
-        if inst.is_load() { // FIXME Rd_usr if post indexing and W-bit?
-            if inst.is_transfering_bytes() { self.gpr[inst.Rd()] = try!(self.bus.borrow().load_byte(base)); }
-            else                           { self.gpr[inst.Rd()] = try!(self.bus.borrow().load_word(base)); }
+        let writeback = Self::transfer_writeback(inst, base, offs);
+
+        // Post-indexing with W-bit set forces user mode access rights,
+        // which makes no difference without memory protection.
+        if inst.is_load() {
+            // Write back first, such that a loaded base wins.
+            if let Some(wb) = writeback { self.gpr[inst.Rn()] = wb as i32; }
+            if inst.is_transfering_bytes() { self.gpr[inst.Rd()] = try!(self.memory.load_byte(base, MemoryAccess::NonSequential)); }
+            else                           { self.gpr[inst.Rd()] = try!(self.load_rotated_word(base)); }
         } else {
-            if inst.is_transfering_bytes() { try!(self.bus.borrow_mut().store_byte(base, self.gpr[inst.Rd()])); }
-            else                           { try!(self.bus.borrow_mut().store_word(base, self.gpr[inst.Rd()])); }
+            let data = self.stored_register(inst.Rd());
+            if inst.is_transfering_bytes() { try!(self.memory.store_byte(base, data, MemoryAccess::NonSequential)); }
+            else                           { try!(self.memory.store_word(base & !0b11, data, MemoryAccess::NonSequential)); }
+            if let Some(wb) = writeback { self.gpr[inst.Rn()] = wb as i32; }
         }
 
-             if !inst.is_pre_indexed()       { self.gpr[inst.Rn()] = base.wrapping_add(offs) as i32; }
-        else if  inst.is_auto_incrementing() { self.gpr[inst.Rn()] = base as i32; }
-        Ok(CpuAction::None)
+        Ok(if Self::is_transfer_writing_pc(inst) { CpuAction::FlushPipeline } else { CpuAction::None })
     }
 
     fn execute_ldrh_strh(&mut self, inst: ArmInstruction, imm: bool) -> Result<CpuAction, GbaError> {
@@ -185,137 +192,153 @@ impl Arm7Tdmi {
                    else if inst.is_offset_added() { self.gpr[inst.Rm()] as u32 }
                    else { -self.gpr[inst.Rm()] as u32 };
         if inst.is_pre_indexed() { base = base.wrapping_add(offs); }
+        let writeback = Self::transfer_writeback(inst, base, offs);
+
+        if inst.is_load() {
+            if let Some(wb) = writeback { self.gpr[inst.Rn()] = wb as i32; }
+            match inst.ldrh_strh_op() {
+                ArmLdrhStrhOP::UH => { self.gpr[inst.Rd()] = try!(self.load_rotated_halfword(base)); },
+                ArmLdrhStrhOP::SB => { self.gpr[inst.Rd()] = try!(self.memory.load_byte(base, MemoryAccess::NonSequential)) as u8 as i8 as i32; },
+                // A misaligned LDRSH sign-extends the addressed byte instead.
+                ArmLdrhStrhOP::SH if 0 != (base & 0b01) => {
+                    self.gpr[inst.Rd()] = try!(self.memory.load_byte(base, MemoryAccess::NonSequential)) as u8 as i8 as i32;
+                },
+                ArmLdrhStrhOP::SH => { self.gpr[inst.Rd()] = try!(self.memory.load_halfword(base, MemoryAccess::NonSequential)) as u16 as i16 as i32; },
+                _ => panic!("LDRH instead of SWP!"),
+            }
+        } else {
+            let data = self.stored_register(inst.Rd());
+            match inst.ldrh_strh_op() {
+                ArmLdrhStrhOP::UH => { try!(self.memory.store_halfword(base & !0b01, data, MemoryAccess::NonSequential)); },
+                ArmLdrhStrhOP::SB => { warn!("Signed store."); try!(self.memory.store_byte(base, data, MemoryAccess::NonSequential)); },
+                ArmLdrhStrhOP::SH => { warn!("Signed store."); try!(self.memory.store_halfword(base & !0b01, data, MemoryAccess::NonSequential)); },
+                _ => panic!("STRH instead of SWP!"),
+            }
+            if let Some(wb) = writeback { self.gpr[inst.Rn()] = wb as i32; }
+        }
 
-        if inst.is_load() { match inst.ldrh_strh_op() {
-            ArmLdrhStrhOP::UH => { self.gpr[inst.Rd()] = try!(self.bus.borrow().load_halfword(base)); },
-            ArmLdrhStrhOP::SB => { self.gpr[inst.Rd()] = try!(self.bus.borrow().load_byte(base)) as u8 as i8 as i32; },
-            ArmLdrhStrhOP::SH => { self.gpr[inst.Rd()] = try!(self.bus.borrow().load_halfword(base)) as u16 as i16 as i32; },
-            _ => panic!("LDRH instead of SWP!"),
-        }}
-        else { match inst.ldrh_strh_op() {
-            ArmLdrhStrhOP::UH => { try!(self.bus.borrow_mut().store_halfword(base, self.gpr[inst.Rd()])); },
-            ArmLdrhStrhOP::SB => { warn!("Signed store."); try!(self.bus.borrow_mut().store_byte(base, self.gpr[inst.Rd()])); },
-            ArmLdrhStrhOP::SH => { warn!("Signed store."); try!(self.bus.borrow_mut().store_halfword(base, self.gpr[inst.Rd()])); },
-            _ => panic!("STRH instead of SWP!"),
-        }}
-
-             if !inst.is_pre_indexed()       { self.gpr[inst.Rn()] = base.wrapping_add(offs) as i32; }
-        else if  inst.is_auto_incrementing() { self.gpr[inst.Rn()] = base as i32; }
-        Ok(CpuAction::None)
+        Ok(if Self::is_transfer_writing_pc(inst) { CpuAction::FlushPipeline } else { CpuAction::None })
     }
 
     fn execute_ldm_stm(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
-        // TODO Handle store/load base as first or later register.
         let base  = self.gpr[inst.Rn()] as u32;
-        let rmap  = inst.register_map();
-        let bytes = 4 * rmap.count_ones();
+        let rn    = inst.Rn();
+        let load  = inst.is_load();
+        // An empty list transfers PC, but moves the base as if all 16 registers were transferred.
+        let rmap  = if inst.register_map() == 0 { 0x8000 } else { inst.register_map() };
+        let bytes = if inst.register_map() == 0 { 0x40 } else { 4 * rmap.count_ones() };
         let r15   = 0 != (rmap & 0x8000);
         let psr   = inst.is_enforcing_user_mode();
         let offs  = if inst.is_pre_indexed() == inst.is_offset_added() { (4_u32, 0) } else { (0_u32, 4) };
-        let mut addr = if inst.is_offset_added() { base } else { base.wrapping_sub(bytes) }; // Go back N regs if decr.
-
-        // Write back Rn now to avoid special cases with loading Rn.
-        if inst.is_auto_incrementing() {
-            self.gpr[inst.Rn()] = if inst.is_offset_added() { base.wrapping_add(bytes) as i32 } else { base.wrapping_sub(bytes) as i32 };
-        }
-
-        // Handle privileged transfers.
-        if psr & !(r15 & inst.is_load()) {
-            if self.mode == Mode::User { return Err(GbaError::PrivilegedUserCode); }
-            try!(self.execute_ldm_stm_user_bank(rmap, addr, offs, inst.is_load()));
-            if inst.is_auto_incrementing() { warn!("W-bit set for LDM/STM with PSR transfer/USR banks."); }
-        } else {
-            for i in 0_u32..16 { if 0 != (rmap & (1 << i)) {
-                addr = addr.wrapping_add(offs.0);
-                if inst.is_load() { self.gpr[i as usize] = try!(self.bus.borrow().load_word(addr)); }
-                else              { try!(self.bus.borrow_mut().store_word(addr, self.gpr[i as usize])); }
-                addr = addr.wrapping_add(offs.1);
-            }}
-        }
+        let mut addr = (if inst.is_offset_added() { base } else { base.wrapping_sub(bytes) }) & !0b11; // Go back N regs if decr.
+        let new_base = if inst.is_offset_added() { base.wrapping_add(bytes) } else { base.wrapping_sub(bytes) };
+        let writeback = inst.is_auto_incrementing();
 
-        // Handle mode change.
-        if r15 & psr & inst.is_load() {
-            if self.mode == Mode::User { warn!("USR mode has no SPSR."); return Err(GbaError::PrivilegedUserCode); }
-            let new_mode = self.spsr[self.mode as u8 as usize].mode();
-            self.change_mode(new_mode);
-        }
+        // Transfer the user bank's registers for STM and LDM without PC.
+        // With write-back, the hardware updates the current mode's base register.
+        let user_bank = psr & !(r15 & load);
 
-        Ok(CpuAction::None)
-    }
+        // Write back Rn now, such that a loaded base wins.
+        if writeback & load { self.gpr[rn] = new_base as i32; }
 
-    fn execute_ldm_stm_user_bank(&mut self, rmap: u16, mut addr: u32, offs: (u32, u32), load: bool) -> Result<CpuAction, GbaError> {
-        // R0...R7 aren't banked.
-        for i in 0_u32..8 { if 0 != (rmap & (1 << i)) {
+        // A stored base is only the original one if it's the first register stored.
+        let first = rmap.trailing_zeros() as usize;
+        let mut access = MemoryAccess::NonSequential;
+        for i in 0_usize..16 { if 0 != (rmap & (1 << i)) {
             addr = addr.wrapping_add(offs.0);
-            if load { self.gpr[i as usize] = try!(self.bus.borrow().load_word(addr)); }
-            else    { try!(self.bus.borrow_mut().store_word(addr, self.gpr[i as usize])); }
+            if load {
+                let x = try!(self.memory.load_word(addr, access));
+                if user_bank { self.set_banked_register(Mode::User, i, x); } else { self.gpr[i] = x; }
+            } else {
+                let x = if writeback && (i == rn) && (i != first) { new_base as i32 }
+                        else if i == Arm7Tdmi::PC { self.stored_register(i) }
+                        else if user_bank { self.banked_register(Mode::User, i) }
+                        else { self.gpr[i] };
+                try!(self.memory.store_word(addr, x, access));
+            }
             addr = addr.wrapping_add(offs.1);
+            access = MemoryAccess::Sequential;
         }}
+        if writeback & !load { self.gpr[rn] = new_base as i32; }
 
-        // R8...R12 is banked for FIQ mode.
-        if self.mode == Mode::FIQ {
-            for i in 8_u32..12 { if 0 != (rmap & (1 << i)) {
-                addr = addr.wrapping_add(offs.0);
-                if load { self.gpr_r8_r12_other[(i-8) as usize] = try!(self.bus.borrow().load_word(addr)); }
-                else    { try!(self.bus.borrow_mut().store_word(addr, self.gpr_r8_r12_other[(i-8) as usize])); }
-                addr = addr.wrapping_add(offs.1);
-            }}
-        } else {
-            for i in 8_u32..12 { if 0 != (rmap & (1 << i)) {
-                addr = addr.wrapping_add(offs.0);
-                if load { self.gpr[i as usize] = try!(self.bus.borrow().load_word(addr)); }
-                else    { try!(self.bus.borrow_mut().store_word(addr, self.gpr[i as usize])); }
-                addr = addr.wrapping_add(offs.1);
-            }}
-        }
-
-        // R13..R14 is banked for everyone.
-        if 0 != (rmap & 0x2000) {
-            addr = addr.wrapping_add(offs.0);
-            if load { self.gpr_r13_all[Mode::User as u8 as usize] = try!(self.bus.borrow().load_word(addr)); }
-            else    { try!(self.bus.borrow_mut().store_word(addr, self.gpr_r13_all[Mode::User as u8 as usize])); }
-            addr = addr.wrapping_add(offs.1);
-        }
-        if 0 != (rmap & 0x4000) {
-            addr = addr.wrapping_add(offs.0);
-            if load { self.gpr_r14_all[Mode::User as u8 as usize] = try!(self.bus.borrow().load_word(addr)); }
-            else    { try!(self.bus.borrow_mut().store_word(addr, self.gpr_r14_all[Mode::User as u8 as usize])); }
+        // Handle mode change.
+        if r15 & psr & load {
+            if self.mode == Mode::User { warn!("USR mode has no SPSR."); return Err(GbaError::PrivilegedUserCode); }
+            self.restore_cpsr();
         }
 
-        Ok(CpuAction::None)
+        let writes_pc = (r15 & load) || (writeback && (rn == Arm7Tdmi::PC));
+        Ok(if writes_pc { CpuAction::FlushPipeline } else { CpuAction::None })
     }
 
     fn execute_swp(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
         let base = self.gpr[inst.Rn()] as u32;
 
         if inst.is_transfering_bytes() {
-            let temp = try!(self.bus.borrow().load_byte(base));
-            try!(self.bus.borrow_mut().store_byte(base, self.gpr[inst.Rm()]));
+            let temp = try!(self.memory.load_byte(base, MemoryAccess::NonSequential));
+            try!(self.memory.store_byte(base, self.gpr[inst.Rm()], MemoryAccess::NonSequential));
             self.gpr[inst.Rd()] = temp;
         } else {
-            let temp = try!(self.bus.borrow().load_word(base));
-            try!(self.bus.borrow_mut().store_word(base, self.gpr[inst.Rm()]));
+            let temp = try!(self.load_rotated_word(base));
+            try!(self.memory.store_word(base & !0b11, self.gpr[inst.Rm()], MemoryAccess::NonSequential));
             self.gpr[inst.Rd()] = temp;
         }
 
-        Ok(CpuAction::None)
+        Ok(if inst.Rd() == Arm7Tdmi::PC { CpuAction::FlushPipeline } else { CpuAction::None })
+    }
+
+    // Loads the word containing `addr`, rotated such that the
+    // addressed byte ends up in the lowest byte.
+    fn load_rotated_word(&mut self, addr: u32) -> Result<i32, GbaError> {
+        let w = try!(self.memory.load_word(addr & !0b11, MemoryAccess::NonSequential)) as u32;
+        Ok(w.rotate_right(8 * (addr & 0b11)) as i32)
+    }
+
+    // Loads the halfword containing `addr`. A misaligned halfword
+    // gets rotated right by 8 bits within the whole register.
+    fn load_rotated_halfword(&mut self, addr: u32) -> Result<i32, GbaError> {
+        let h = try!(self.memory.load_halfword(addr & !0b01, MemoryAccess::NonSequential)) as u32 & 0xFFFF;
+        Ok(h.rotate_right(8 * (addr & 0b01)) as i32)
+    }
+
+    // Gets the value a store writes for a register.
+    //
+    // Stores see PC 12 bytes ahead of the instruction.
+    fn stored_register(&self, index: usize) -> i32 {
+        if index == Arm7Tdmi::PC { self.gpr[Arm7Tdmi::PC].wrapping_add(4) } else { self.gpr[index] }
+    }
+
+    // Gets the address a single data transfer writes back, if any.
+    fn transfer_writeback(inst: ArmInstruction, base: u32, offs: u32) -> Option<u32> {
+             if !inst.is_pre_indexed()       { Some(base.wrapping_add(offs)) }
+        else if  inst.is_auto_incrementing() { Some(base) }
+        else                                 { None }
+    }
+
+    // Checks whether a single data transfer loads into PC or writes back to it.
+    fn is_transfer_writing_pc(inst: ArmInstruction) -> bool {
+        let writeback = !inst.is_pre_indexed() || inst.is_auto_incrementing();
+        (inst.is_load() && (inst.Rd() == Arm7Tdmi::PC)) || (writeback && (inst.Rn() == Arm7Tdmi::PC))
     }
 
     fn execute_swi(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
         debug!("{}", inst);
         if self.optimise_swi {
-            unimplemented!()
-        } else {
-            self.exception(Exception::SoftwareInterrupt);
-            Ok(CpuAction::FlushPipeline)
+            if let Some(func) = BiosFunction::from_arm_comment(inst.comment()) {
+                if let Some(action) = try!(self.execute_bios_function(func)) { return Ok(action); }
+            }
         }
+        self.exception(Exception::SoftwareInterrupt);
+        Ok(CpuAction::FlushPipeline)
     }
 
     fn execute_unknown(&mut self, inst: ArmInstruction) -> Result<CpuAction, GbaError> {
-        error!("No offering to co-processors implemented yet."); // TODO
+        // TODO Offer the instruction to co-processors.
+        let addr = self.current_instruction_address();
+        error!("Undefined instruction {:#010X} at {:#010X}.", inst.raw(), addr);
         debug!("{}", inst);
         self.exception(Exception::UndefinedInstruction);
-        Ok(CpuAction::None)
+        Ok(CpuAction::FlushPipeline)
     }
 }
 
@@ -0,0 +1,204 @@
+// License below.
+//! Implements the cached interpreter executing whole basic blocks.
+//!
+//! Instead of fetching and decoding every instruction on each
+//! execution, straight-line runs of ARM instructions are decoded
+//! once into a `BlockCache`. Each block behaves exactly like the
+//! same number of pipeline steps, apart from the timing hardware
+//! only being ticked after the whole block.
+//!
+//! Only code the memory reports as cacheable is cached. Writes
+//! to watched code pages invalidate their blocks before the next
+//! instruction executes. However, stores into one of the two
+//! instructions following the store take effect right away,
+//! whereas the pipeline would still execute the old ones.
+//!
+//! Blocks also end after any store into IO registers, so that
+//! newly enabled or requested interrupts are taken before the
+//! next instruction, just like in the pipeline.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::*;
+
+impl<M: CpuMemory> Arm7Tdmi<M> {
+    /// Checks whether the cached interpreter is enabled.
+    pub fn is_block_cache_enabled(&self) -> bool { self.use_block_cache }
+
+    /// Configures whether `step` uses the cached interpreter.
+    ///
+    /// If `false`, `step` only executes single pipeline steps.
+    /// Disabling the cached interpreter also drops all cached blocks.
+    pub fn set_block_cache_enabled(&mut self, enable: bool) {
+        self.use_block_cache = enable;
+        if !enable { self.block_cache.clear(); }
+    }
+
+    /// Gets the cache of decoded basic blocks.
+    pub fn block_cache(&self) -> &BlockCache { &self.block_cache }
+
+    /// Drops all cached blocks and idle loop verdicts of the code
+    /// pages overlapping the given range.
+    ///
+    /// Writes to ROMs aren't watched, so code patching them
+    /// behind the bus's back must call this.
+    pub fn invalidate_code(&mut self, addr: u32, len: u32) {
+        if len == 0 { return; }
+        let last = code_page_of(addr.wrapping_add(len - 1));
+        let mut page = code_page_of(addr);
+        loop {
+            self.block_cache.invalidate_page(page);
+            self.idle_loops.invalidate_page(page);
+            if page == last { break; }
+            page = page.wrapping_add(1 << CODE_PAGE_SHIFT);
+        }
+    }
+
+    /// Executes a chunk of instructions with the configured interpreter.
+    ///
+    /// Without block cache, this is a single pipeline step.
+    /// Otherwise, this executes a whole basic block if possible.
+    ///
+    /// # Returns
+    /// - `Ok`: The number of pipeline steps the executed instructions took.
+    /// - `Err`: The error that stopped execution.
+    #[inline]
+    pub fn step(&mut self) -> Result<u32, GbaError> {
+        let steps = if self.use_block_cache { try!(self.run_cached_block()) } else {
+            if self.detect_idle_loops { self.sync_code_caches(); }
+            try!(self.pipeline_step());
+            1
+        };
+        if self.taken_branch.is_none() { return Ok(steps); }
+        Ok(steps + try!(self.skip_idle_loop(steps)))
+    }
+
+    fn run_cached_block(&mut self) -> Result<u32, GbaError> {
+        self.sync_code_caches();
+        self.enter_power_down();
+        self.memory.take_io_writes();
+        if self.delay_cycles > 0 || self.halt != HaltState::Running || self.state != State::ARM
+        || self.is_irq_pending() {
+            try!(self.pipeline_step());
+            return Ok(1);
+        }
+
+        // Right after a flush, the block starts at PC. Otherwise,
+        // the pipeline already holds the first two instructions.
+        let pc = self.gpr[Arm7Tdmi::PC] as u32;
+        let flushed = self.fetch_access == MemoryAccess::NonSequential;
+        let addr = if flushed { pc } else { pc.wrapping_sub(8) };
+        let slot = match try!(self.find_or_build_block(addr)) {
+            Some(s) if flushed || self.is_pipeline_at_block(s) => s,
+            _ => { try!(self.pipeline_step()); return Ok(1); },
+        };
+
+        // Filling a flushed pipeline takes two steps.
+        let mut steps = if flushed { 2 } else { 0 };
+        let mut index = 0;
+        loop {
+            let (inst, len) = {
+                let block = self.block_cache.block(slot).expect("Executing an invalidated block!");
+                (block.instruction(index), block.len())
+            };
+            let inst_addr = addr.wrapping_add(index as u32 * 4);
+            self.gpr[Arm7Tdmi::PC] = inst_addr.wrapping_add(8) as i32;
+            let action = try!(self.execute_arm_state(inst));
+            steps += 1;
+            index += 1;
+
+            if let CpuAction::FlushPipeline = action {
+                self.flush_pipeline();
+                return Ok(steps);
+            }
+            if self.sync_code_caches() || self.enter_power_down() || self.memory.take_io_writes() || index == len {
+                break;
+            }
+        }
+
+        try!(self.refill_pipeline(addr.wrapping_add(index as u32 * 4)));
+        Ok(steps)
+    }
+
+    fn find_or_build_block(&mut self, addr: u32) -> Result<Option<usize>, GbaError> {
+        if let Some(slot) = self.block_cache.find(addr, self.state) { return Ok(Some(slot)); }
+        if !self.memory.is_code_cacheable(addr) { return Ok(None); }
+
+        let mut block = BasicBlock::new(addr, self.state);
+        while !block.is_full() {
+            let raw = try!(self.memory.load_word(block.end_address(), MemoryAccess::Sequential)) as u32;
+            // Leave invalid instructions to the pipeline, which
+            // reports them at the right time.
+            let inst = match self.arm_decoder.decode(raw) { Ok(i) => i, Err(_) => break };
+            if inst.check_is_valid().is_err() { break; }
+            block.push(inst);
+            if ends_basic_block(&inst) { break; }
+        }
+        if block.is_empty() { return Ok(None); }
+
+        self.memory.watch_code(addr);
+        Ok(Some(self.block_cache.insert(block)))
+    }
+
+    // The pipeline's instructions were fetched before the block
+    // was decoded, so they may differ after self-modifying code.
+    fn is_pipeline_at_block(&self, slot: usize) -> bool {
+        match self.block_cache.block(slot) {
+            Some(b) if b.len() >= 2 => {
+                (b.instruction(0) == self.decoded_arm) && (b.instruction(1).raw() == self.fetched_arm)
+            },
+            _ => false,
+        }
+    }
+
+    // Puts the pipeline into the state it has after executing
+    // the instruction before `next`.
+    fn refill_pipeline(&mut self, next: u32) -> Result<(), GbaError> {
+        let raw = try!(self.memory.load_word(next, MemoryAccess::Sequential)) as u32;
+        let decoded = try!(self.arm_decoder.decode(raw));
+        self.decoded_arm_error = decoded.check_is_valid().err();
+        self.decoded_arm  = decoded;
+        self.fetched_arm  = try!(self.memory.load_word(next.wrapping_add(4), MemoryAccess::Sequential)) as u32;
+        self.filled_stages = 2;
+        self.fetch_access = MemoryAccess::Sequential;
+        self.gpr[Arm7Tdmi::PC] = next.wrapping_add(8) as i32;
+        Ok(())
+    }
+
+    // Drops all blocks and idle loop verdicts of code pages
+    // written to.
+    //
+    // Returns `true` if any blocks may have been dropped.
+    fn sync_code_caches(&mut self) -> bool {
+        self.memory.take_code_writes(&mut self.code_writes);
+        if self.code_writes.is_empty() { return false; }
+        for &page in &self.code_writes {
+            self.block_cache.invalidate_page(page);
+            self.idle_loops.invalidate_page(page);
+        }
+        self.code_writes.clear();
+        true
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
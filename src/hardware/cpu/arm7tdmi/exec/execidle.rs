@@ -0,0 +1,120 @@
+// License below.
+//! Implements skipping idle loops.
+//!
+//! While enabled, each taken backward branch without link is
+//! checked for closing an idle loop. Once it does, `step`
+//! reports all cycles until the memory's next timed event as
+//! passed, instead of running the loop again and again.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::cmp;
+use std::u32;
+use super::*;
+
+impl<M: CpuMemory> Arm7Tdmi<M> {
+    /// Checks whether idle loops are detected and skipped.
+    pub fn is_idle_loop_detection_enabled(&self) -> bool { self.detect_idle_loops }
+
+    /// Configures whether `step` detects and skips idle loops.
+    pub fn set_idle_loop_detection_enabled(&mut self, enable: bool) {
+        self.detect_idle_loops = enable;
+        self.taken_branch = None;
+    }
+
+    /// Gets all idle loops detected since the last reset.
+    pub fn idle_loops(&self) -> &[IdleLoop] { self.idle_loops.loops() }
+
+    /// Skips ahead if the last taken branch closed an idle loop.
+    ///
+    /// # Params
+    /// - `steps`: The number of pipeline steps done since the
+    ///   memory's timeline last advanced.
+    ///
+    /// # Returns
+    /// - `Ok`: The number of skipped pipeline steps.
+    /// - `Err`: Analysing the loop failed.
+    pub fn skip_idle_loop(&mut self, steps: u32) -> Result<u32, GbaError> {
+        let (branch, first) = match self.taken_branch.take() { Some(b) => b, None => return Ok(0) };
+
+        // Only skip while sitting at the loop's start.
+        if self.fetch_access != MemoryAccess::NonSequential || (self.gpr[Arm7Tdmi::PC] as u32) != first {
+            return Ok(0);
+        }
+        let index = match self.idle_loops.verdict(branch) {
+            Some(v) => v,
+            None => try!(self.analyse_idle_loop(first, branch)),
+        };
+        let index = match index { Some(i) => i, None => return Ok(0) };
+        if !try!(self.are_idle_loop_loads_stable(first, branch)) { return Ok(0); }
+
+        let cycles = match self.memory.cycles_until_event() { Some(c) => c, None => return Ok(0) };
+        let skip = cmp::min(cycles.saturating_sub(steps as u64), u32::MAX as u64) as u32;
+        if skip > 0 { self.idle_loops.record_skip(index, skip as u64); }
+        Ok(skip)
+    }
+
+    fn analyse_idle_loop(&mut self, first: u32, branch: u32) -> Result<Option<usize>, GbaError> {
+        let len = (branch.wrapping_sub(first) / 4) as usize + 1;
+        if (branch < first) || (len > MAX_IDLE_LOOP_LEN) || (code_page_of(first) != code_page_of(branch))
+        || !self.memory.is_code_cacheable(first) {
+            return Ok(self.idle_loops.decide(first, branch, false));
+        }
+
+        let body = match try!(self.decode_loop_body(first, branch)) {
+            Some(b) => b,
+            None => return Ok(self.idle_loops.decide(first, branch, false)),
+        };
+        self.memory.watch_code(first);
+
+        let index = self.idle_loops.decide(first, branch, is_idle_loop_body(&body[..]));
+        if index.is_some() { info!("Detected an idle loop at {:#010X}...{:#010X}.", first, branch); }
+        Ok(index)
+    }
+
+    // The addresses may depend on registers set before the loop,
+    // so this can't be part of the cached verdict.
+    fn are_idle_loop_loads_stable(&mut self, first: u32, branch: u32) -> Result<bool, GbaError> {
+        let body = match try!(self.decode_loop_body(first, branch)) { Some(b) => b, None => return Ok(false) };
+        Ok(match load_addresses(&body[..], first, &self.gpr[..]) {
+            Some(addrs) => addrs.iter().all(|&a| self.memory.is_load_stable(a)),
+            None => false,
+        })
+    }
+
+    // Decodes all instructions of a loop except the closing branch.
+    fn decode_loop_body(&mut self, first: u32, branch: u32) -> Result<Option<Vec<ArmInstruction>>, GbaError> {
+        let len = (branch.wrapping_sub(first) / 4) as usize;
+        let mut body = Vec::with_capacity(len);
+        for i in 0..(len as u32) {
+            let raw = try!(self.memory.load_word(first + i * 4, MemoryAccess::Sequential)) as u32;
+            match self.arm_decoder.decode(raw) {
+                Ok(inst) => body.push(inst),
+                Err(_) => return Ok(None),
+            }
+        }
+        Ok(Some(body))
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
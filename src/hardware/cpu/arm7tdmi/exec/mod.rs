@@ -11,13 +11,19 @@ pub use self::armdpop::*;
 pub use self::armbsop::*;
 pub use self::execarm::*;
 pub use self::armcondition::*;
+pub use self::bios::*;
+pub use self::execblock::*;
+pub use self::execidle::*;
 
 pub mod armdpop;
 pub mod armbsop;
 pub mod armcondition;
 pub mod execarm;
+pub mod bios;
+pub mod execblock;
+pub mod execidle;
 
-impl Arm7Tdmi {
+impl<M: CpuMemory> Arm7Tdmi<M> {
     fn alu_data_processing(&self, dpop: ArmDPOP, op1: i32, op2: i32) -> i32 {
         let c = self.cpsr.C() as i32;
         match dpop {
@@ -48,12 +54,12 @@ impl Arm7Tdmi {
         let res: i32 = match dpop {
             ArmDPOP::AND | ArmDPOP::TST => { op1 & op2 },
             ArmDPOP::EOR | ArmDPOP::TEQ => { op1 ^ op2 },
-            ArmDPOP::SUB | ArmDPOP::CMP => { Arm7Tdmi::alu_sub_carry_overflow(op1, op2, &mut cf, &mut vf) },
-            ArmDPOP::RSB                => { Arm7Tdmi::alu_sub_carry_overflow(op2, op1, &mut cf, &mut vf) },
-            ArmDPOP::ADD | ArmDPOP::CMN => { Arm7Tdmi::alu_add_carry_overflow(op1, op2, &mut cf, &mut vf) },
-            ArmDPOP::ADC                => { Arm7Tdmi::alu_add_carry_overflow(op1, op2.wrapping_add(c), &mut cf, &mut vf) },
-            ArmDPOP::SBC                => { Arm7Tdmi::alu_sub_carry_overflow(op1, op2.wrapping_sub(1-c), &mut cf, &mut vf) },
-            ArmDPOP::RSC                => { Arm7Tdmi::alu_sub_carry_overflow(op2, op1.wrapping_sub(1-c), &mut cf, &mut vf) },
+            ArmDPOP::SUB | ArmDPOP::CMP => { Self::alu_sub_carry_overflow(op1, op2, &mut cf, &mut vf) },
+            ArmDPOP::RSB                => { Self::alu_sub_carry_overflow(op2, op1, &mut cf, &mut vf) },
+            ArmDPOP::ADD | ArmDPOP::CMN => { Self::alu_add_carry_overflow(op1, op2, &mut cf, &mut vf) },
+            ArmDPOP::ADC                => { Self::alu_add_carry_overflow(op1, op2.wrapping_add(c), &mut cf, &mut vf) },
+            ArmDPOP::SBC                => { Self::alu_sub_carry_overflow(op1, op2.wrapping_sub(1-c), &mut cf, &mut vf) },
+            ArmDPOP::RSC                => { Self::alu_sub_carry_overflow(op2, op1.wrapping_sub(1-c), &mut cf, &mut vf) },
             ArmDPOP::ORR                => { op1 | op2 },
             ArmDPOP::MOV                => { op2 },
             ArmDPOP::BIC                => { op1 & !op2 },
@@ -78,8 +84,9 @@ impl Arm7Tdmi {
     }
 
     fn alu_sub_carry_overflow(a: i32, b: i32, c: &mut bool, v: &mut bool) -> i32 {
+        // ARM's carry flag is an inverted borrow for subtractions.
         let res64: u64 = (a as u32 as u64).wrapping_sub(b as u32 as u64);
-        *c = 0 != (res64 & (1 << 32));
+        *c = 0 == (res64 & (1 << 32));
         let x = a.overflowing_sub(b);
         *v = x.1;
         x.0
@@ -95,28 +102,28 @@ impl Arm7Tdmi {
             ArmBSOP::NOP        => op1,
             ArmBSOP::LSR_32     => 0,
             ArmBSOP::ASR_32     => op1 >> 31,
-            ArmBSOP::RRX        => Arm7Tdmi::alu_rrx(op1, self.cpsr.C()),
-            ArmBSOP::LSL_Reg(r) => Arm7Tdmi::alu_lsl_reg(op1, (self.gpr[r] as u32) & 0xFF),
-            ArmBSOP::LSR_Reg(r) => Arm7Tdmi::alu_lsr_reg(op1, (self.gpr[r] as u32) & 0xFF),
-            ArmBSOP::ASR_Reg(r) => Arm7Tdmi::alu_asr_reg(op1, (self.gpr[r] as u32) & 0xFF),
-            ArmBSOP::ROR_Reg(r) => Arm7Tdmi::alu_ror_reg(op1, (self.gpr[r] as u32) & 0xFF),
+            ArmBSOP::RRX        => Self::alu_rrx(op1, self.cpsr.C()),
+            ArmBSOP::LSL_Reg(r) => Self::alu_lsl_reg(op1, (self.gpr[r] as u32) & 0xFF),
+            ArmBSOP::LSR_Reg(r) => Self::alu_lsr_reg(op1, (self.gpr[r] as u32) & 0xFF),
+            ArmBSOP::ASR_Reg(r) => Self::alu_asr_reg(op1, (self.gpr[r] as u32) & 0xFF),
+            ArmBSOP::ROR_Reg(r) => Self::alu_ror_reg(op1, (self.gpr[r] as u32) & 0xFF),
         }
     }
 
     fn alu_barrel_shifter_carry(&mut self, bsop: ArmBSOP, op1: i32) -> (i32, bool) {
         match bsop {
-            ArmBSOP::LSL_Imm(x) => Arm7Tdmi::alu_lsl_imm_carry(op1, x),
-            ArmBSOP::LSR_Imm(x) => Arm7Tdmi::alu_lsr_imm_carry(op1, x),
-            ArmBSOP::ASR_Imm(x) => Arm7Tdmi::alu_asr_imm_carry(op1, x),
-            ArmBSOP::ROR_Imm(x) => Arm7Tdmi::alu_ror_imm_carry(op1, x),
+            ArmBSOP::LSL_Imm(x) => Self::alu_lsl_imm_carry(op1, x),
+            ArmBSOP::LSR_Imm(x) => Self::alu_lsr_imm_carry(op1, x),
+            ArmBSOP::ASR_Imm(x) => Self::alu_asr_imm_carry(op1, x),
+            ArmBSOP::ROR_Imm(x) => Self::alu_ror_imm_carry(op1, x),
             ArmBSOP::NOP        => (op1, self.cpsr.C()),
             ArmBSOP::LSR_32     => (((op1 as u32) >> 31) as i32, false),
             ArmBSOP::ASR_32     => (op1 >> 31, 0 != (op1 & (1 << 31))),
-            ArmBSOP::RRX        => (Arm7Tdmi::alu_rrx(op1, self.cpsr.C()), 0 != (op1 & 0b1)),
-            ArmBSOP::LSL_Reg(r) => Arm7Tdmi::alu_lsl_reg_carry(op1, (self.gpr[r] as u32) & 0xFF, self.cpsr.C()),
-            ArmBSOP::LSR_Reg(r) => Arm7Tdmi::alu_lsr_reg_carry(op1, (self.gpr[r] as u32) & 0xFF, self.cpsr.C()),
-            ArmBSOP::ASR_Reg(r) => Arm7Tdmi::alu_asr_reg_carry(op1, (self.gpr[r] as u32) & 0xFF, self.cpsr.C()),
-            ArmBSOP::ROR_Reg(r) => Arm7Tdmi::alu_ror_reg_carry(op1, (self.gpr[r] as u32) & 0xFF, self.cpsr.C()),
+            ArmBSOP::RRX        => (Self::alu_rrx(op1, self.cpsr.C()), 0 != (op1 & 0b1)),
+            ArmBSOP::LSL_Reg(r) => Self::alu_lsl_reg_carry(op1, (self.gpr[r] as u32) & 0xFF, self.cpsr.C()),
+            ArmBSOP::LSR_Reg(r) => Self::alu_lsr_reg_carry(op1, (self.gpr[r] as u32) & 0xFF, self.cpsr.C()),
+            ArmBSOP::ASR_Reg(r) => Self::alu_asr_reg_carry(op1, (self.gpr[r] as u32) & 0xFF, self.cpsr.C()),
+            ArmBSOP::ROR_Reg(r) => Self::alu_ror_reg_carry(op1, (self.gpr[r] as u32) & 0xFF, self.cpsr.C()),
         }
     }
 
@@ -134,25 +141,25 @@ impl Arm7Tdmi {
     }
     fn alu_lsl_reg_carry(op1: i32, op2: u32, c: bool) -> (i32, bool) { match op2 {
         0           => (op1, c),
-        x if x < 32 => Arm7Tdmi::alu_lsl_imm_carry(op1, op2),
+        x if x < 32 => Self::alu_lsl_imm_carry(op1, op2),
         32          => (0, 0 != (op1 & 0b1)),
         _           => (0, false),
     }}
     fn alu_lsr_reg_carry(op1: i32, op2: u32, c: bool) -> (i32, bool) { match op2 {
         0           => (op1, c),
-        x if x < 32 => Arm7Tdmi::alu_lsr_imm_carry(op1, op2),
+        x if x < 32 => Self::alu_lsr_imm_carry(op1, op2),
         32          => (0, 0 != (op1 & (1 << 31))),
         _           => (0, false),
     }}
     fn alu_asr_reg_carry(op1: i32, op2: u32, c: bool) -> (i32, bool) { match op2 {
         0           => (op1, c),
-        x if x < 32 => Arm7Tdmi::alu_asr_imm_carry(op1, op2),
+        x if x < 32 => Self::alu_asr_imm_carry(op1, op2),
         _           => (op1 >> 31, 0 != (op1 & (1 << 31))),
     }}
     fn alu_ror_reg_carry(op1: i32, op2: u32, c: bool) -> (i32, bool) { match op2 {
         0  => (op1, c),
         32 => (op1, 0 != (op1 & (1 << 31))),
-        x  => Arm7Tdmi::alu_ror_imm_carry(op1, op2 % 32),
+        x  => Self::alu_ror_imm_carry(op1, op2 % 32),
     }}
 }
 
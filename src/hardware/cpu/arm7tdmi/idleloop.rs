@@ -0,0 +1,240 @@
+// License below.
+//! Implements the detection of idle loops.
+//!
+//! Many games wait for e.g. VBlank by polling memory in a tight
+//! loop instead of halting the CPU. Such a loop does the very
+//! same on each iteration until the polled memory changes, which
+//! only happens on timed events. Recognising these loops allows
+//! skipping ahead to the next event right away.
+//!
+//! A loop counts as idle if it is a short backward branch whose
+//! body only loads data and processes registers, and whose body
+//! recomputes all registers and flags it reads. Additionally, it
+//! must only load from addresses the memory reports as stable,
+//! which is checked on every skip, as the registers the addresses
+//! are based on may differ each time the loop is entered.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use super::exec::{ArmCondition, ArmDPOP};
+use super::blockcache::code_page_of;
+use super::super::arminstruction::{ArmInstruction, ArmOpcode};
+
+/// Maximum number of instructions in an idle loop, including the branch.
+pub const MAX_IDLE_LOOP_LEN: usize = 8;
+
+// Pseudo register index standing for the CPSR flags.
+const FLAGS: u32 = 1 << 16;
+const PC: usize = 15;
+
+/// An idle loop found while running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleLoop {
+    /// Address of the loop's first instruction.
+    pub first: u32,
+
+    /// Address of the backward branch closing the loop.
+    pub branch: u32,
+
+    /// Number of times the loop has been skipped.
+    pub skips: u64,
+
+    /// Number of cycles skipped in total.
+    pub skipped_cycles: u64,
+}
+
+/// Checks whether a loop body does the same on each iteration.
+///
+/// # Params
+/// - `body`: All instructions of the loop except the closing branch.
+pub fn is_idle_loop_body(body: &[ArmInstruction]) -> bool {
+    let mut written = 0_u32;
+    let mut read_first = 0_u32;
+    for inst in body {
+        let (mut reads, writes) = match register_usage(inst) { Some(u) => u, None => return false };
+        if inst.condition() != ArmCondition::AL { reads |= FLAGS; }
+        read_first |= reads & !written;
+        written |= writes;
+    }
+    // Anything read before being written must stay the same.
+    (read_first & written) == 0
+}
+
+// Gets the registers read and written by an instruction without
+// side effects, as bit masks.
+fn register_usage(inst: &ArmInstruction) -> Option<(u32, u32)> {
+    let bit = |r: usize| 1_u32 << r;
+    match inst.opcode() {
+        ArmOpcode::DataProcessing => {
+            if inst.Rd() == PC { return None; }
+            let dpop = inst.dpop();
+            let mut reads = match dpop { ArmDPOP::MOV | ArmDPOP::MVN => 0, _ => bit(inst.Rn()) };
+            let mut writes = match dpop { ArmDPOP::TST | ArmDPOP::TEQ | ArmDPOP::CMP | ArmDPOP::CMN => 0, _ => bit(inst.Rd()) };
+            if inst.is_shift_field_register() {
+                reads |= bit(inst.Rm());
+                if !inst.is_register_shift_immediate() { reads |= bit(inst.Rs()); }
+                else if (inst.raw() & 0x0FE0) == 0x0060 { reads |= FLAGS; } // RRX
+            }
+            match dpop { ArmDPOP::ADC | ArmDPOP::SBC | ArmDPOP::RSC => reads |= FLAGS, _ => {} }
+            if inst.is_setting_flags() { writes |= FLAGS; }
+            Some((reads, writes))
+        },
+        ArmOpcode::LDR_STR | ArmOpcode::LDRH_STRH_Reg | ArmOpcode::LDRH_STRH_Imm => {
+            if !inst.is_load() || inst.Rd() == PC { return None; }
+            if inst.is_auto_incrementing() || !inst.is_pre_indexed() { return None; }
+            let has_rm = match inst.opcode() {
+                ArmOpcode::LDR_STR => !inst.is_offset_field_immediate(),
+                op => op == ArmOpcode::LDRH_STRH_Reg,
+            };
+            let reads = bit(inst.Rn()) | if has_rm { bit(inst.Rm()) } else { 0 };
+            Some((reads, bit(inst.Rd())))
+        },
+        ArmOpcode::MRS => Some((FLAGS, bit(inst.Rd()))),
+        _ => None,
+    }
+}
+
+/// Gets the addresses an idle loop body loads data from.
+///
+/// Registers the body doesn't write keep their values, and moves
+/// of immediates are followed.
+///
+/// # Params
+/// - `body`: All instructions of the loop except the closing branch.
+/// - `first`: Address of the loop's first instruction.
+/// - `regs`: The GPRs when entering the loop.
+///
+/// # Returns
+/// `None` if an address depends on anything else.
+pub fn load_addresses(body: &[ArmInstruction], first: u32, regs: &[i32]) -> Option<Vec<u32>> {
+    let bit = |r: usize| 1_u32 << r;
+    let mut values = [0_i32; 16];
+    for (v, &r) in values.iter_mut().zip(regs) { *v = r; }
+    let mut known = 0xFFFF_u32;
+    let mut addrs = Vec::new();
+
+    for (i, inst) in body.iter().enumerate() {
+        values[PC] = first.wrapping_add(i as u32 * 4 + 8) as i32;
+        match inst.opcode() {
+            ArmOpcode::DataProcessing => match inst.dpop() {
+                ArmDPOP::TST | ArmDPOP::TEQ | ArmDPOP::CMP | ArmDPOP::CMN => {},
+                dpop => {
+                    let imm = match dpop {
+                        ArmDPOP::MOV if !inst.is_shift_field_register() => Some(inst.rotated_immediate()),
+                        ArmDPOP::MVN if !inst.is_shift_field_register() => Some(!inst.rotated_immediate()),
+                        _ => None,
+                    };
+                    match imm {
+                        Some(x) if inst.condition() == ArmCondition::AL => { values[inst.Rd()] = x; known |= bit(inst.Rd()); },
+                        _ => known &= !bit(inst.Rd()),
+                    }
+                },
+            },
+            ArmOpcode::LDR_STR | ArmOpcode::LDRH_STRH_Reg | ArmOpcode::LDRH_STRH_Imm => {
+                let offs = match inst.opcode() {
+                    ArmOpcode::LDR_STR if inst.is_offset_field_immediate() => inst.offset12(),
+                    ArmOpcode::LDRH_STRH_Imm => inst.split_offset8(),
+                    _ if (known & bit(inst.Rm())) == 0 => return None,
+                    ArmOpcode::LDR_STR => {
+                        // RRX offsets depend on the carry flag.
+                        if (inst.raw() & 0x0FF0) == 0x0060 { return None; }
+                        inst.shifted_offset(&values[..], false)
+                    },
+                    _ => if inst.is_offset_added() { values[inst.Rm()] } else { values[inst.Rm()].wrapping_neg() },
+                };
+                if (known & bit(inst.Rn())) == 0 { return None; }
+                addrs.push((values[inst.Rn()] as u32).wrapping_add(offs as u32));
+                known &= !bit(inst.Rd());
+            },
+            _ => known &= !bit(inst.Rd()),
+        }
+    }
+    Some(addrs)
+}
+
+/// Remembers which backward branches close idle loops.
+#[derive(Debug, Default)]
+pub struct IdleLoopDetector {
+    verdicts: HashMap<u32, Option<usize>>,
+    loops: Vec<IdleLoop>,
+}
+
+impl IdleLoopDetector {
+    /// Creates a detector that doesn't know any loops yet.
+    pub fn new() -> IdleLoopDetector { IdleLoopDetector::default() }
+
+    /// Forgets all verdicts and detected loops.
+    pub fn clear(&mut self) {
+        self.verdicts.clear();
+        self.loops.clear();
+    }
+
+    /// Gets all idle loops detected so far.
+    pub fn loops(&self) -> &[IdleLoop] { &self.loops[..] }
+
+    /// Looks up the verdict for a given backward branch.
+    ///
+    /// # Returns
+    /// - `Some(Some(i))`: The branch closes the idle loop at index `i`.
+    /// - `Some(None)`: The branch does not close an idle loop.
+    /// - `None`: The branch has not been analysed yet.
+    pub fn verdict(&self, branch: u32) -> Option<Option<usize>> {
+        self.verdicts.get(&branch).cloned()
+    }
+
+    /// Stores the verdict for a given backward branch.
+    ///
+    /// # Returns
+    /// The index of the idle loop, if it is one.
+    pub fn decide(&mut self, first: u32, branch: u32, is_idle: bool) -> Option<usize> {
+        let index = if !is_idle { None } else {
+            match self.loops.iter().position(|l| l.first == first && l.branch == branch) {
+                Some(i) => Some(i),
+                None => {
+                    self.loops.push(IdleLoop { first: first, branch: branch, skips: 0, skipped_cycles: 0 });
+                    Some(self.loops.len() - 1)
+                },
+            }
+        };
+        self.verdicts.insert(branch, index);
+        index
+    }
+
+    /// Counts skipping an idle loop.
+    pub fn record_skip(&mut self, index: usize, cycles: u64) {
+        self.loops[index].skips += 1;
+        self.loops[index].skipped_cycles += cycles;
+    }
+
+    /// Forgets all verdicts for branches inside a given code page.
+    ///
+    /// # Params
+    /// - `page`: The first address of the code page.
+    pub fn invalidate_page(&mut self, page: u32) {
+        let stale: Vec<u32> = self.verdicts.keys().cloned().filter(|&b| code_page_of(b) == page).collect();
+        for b in stale { self.verdicts.remove(&b); }
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -5,34 +5,104 @@
 #![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
 #![warn(missing_docs)]
 
-use std::cell::RefCell;
-use std::rc::Rc;
-use super::arminstruction::ArmInstruction;
-use super::thumbinstruction::ThumbInstruction;
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+use super::cpumemory::*;
+use super::arminstruction::{ArmInstruction, ArmDecodeTable};
+use super::thumbinstruction::{ThumbInstruction, ThumbDecodeTable};
 use super::super::bus::*;
 use super::super::error::*;
+use super::super::ioregs::Interrupt;
+use super::super::hle::{BIOS_IRQ_FLAGS, BIOS_SP_SVC, BIOS_SP_IRQ, BIOS_SP_USR};
 
 pub use self::exception::*;
 pub use self::psr::*;
 pub use self::exec::*;
 pub use self::diff::*;
 pub use self::display::*;
+pub use self::blockcache::*;
+pub use self::idleloop::*;
 
 pub mod exception;
 pub mod psr;
 pub mod exec;
 pub mod diff;
+pub mod blockcache;
+pub mod idleloop;
 
 mod display;
 
+#[cfg(test)]
+mod test;
+
 /// Decides what the CPU should do after executing an instruction.
 pub enum CpuAction {
     #[doc = "Continue execution normally."]                         None,
     #[doc = "PC has changed, don't modify it, flush the pipeline."] FlushPipeline,
 }
 
+/// Decides whether the CPU executes instructions or waits for interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltState {
+    #[doc = "The CPU executes instructions."]
+    Running,
+    #[doc = "The CPU waits until any enabled interrupt is requested."]
+    Halted,
+    #[doc = "The CPU waits until a keypad, serial or GamePak interrupt is requested."]
+    Stopped,
+    #[doc = "The CPU waits until the BIOS interrupt flags contain any of the given interrupts."]
+    IntrWait(u16),
+    #[doc = "The CPU runs an IRQ handler during `IntrWait` and waits again once it returns to the given address."]
+    IntrWaitServing(u16, u32),
+}
+
+/// Decides what the CPU does when executing unpredictable instructions.
+///
+/// The ARM7TDMI manual calls some encodings unpredictable, e.g.
+/// writing back to PC or reusing registers in multiplications.
+/// The actual silicon still behaves deterministically for them,
+/// which is what the CPU emulates unless the policy is strict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnpredictablePolicy {
+    #[doc = "Stop executing with an error."]            Strict,
+    #[doc = "Log a warning and emulate the hardware."]  Warn,
+    #[doc = "Silently emulate the hardware."]           Silent,
+}
+
+impl FromStr for UnpredictablePolicy {
+    type Err = String;
+
+    /// Parses one of `strict`, `warn` or `silent`.
+    fn from_str(s: &str) -> Result<UnpredictablePolicy, String> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(UnpredictablePolicy::Strict),
+            "warn"   => Ok(UnpredictablePolicy::Warn),
+            "silent" => Ok(UnpredictablePolicy::Silent),
+            _        => Err(format!("Unknown unpredictable instruction policy `{}`.", s)),
+        }
+    }
+}
+
+impl fmt::Display for UnpredictablePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            UnpredictablePolicy::Strict => "strict",
+            UnpredictablePolicy::Warn   => "warn",
+            UnpredictablePolicy::Silent => "silent",
+        })
+    }
+}
+
+impl Default for UnpredictablePolicy {
+    fn default() -> UnpredictablePolicy { UnpredictablePolicy::Strict }
+}
+
 /// Implements the logic needed to emulate an ARM7TDMI CPU.
-pub struct Arm7Tdmi {
+///
+/// The CPU runs on any memory implementing `CpuMemory`,
+/// which is the GBA's bus system by default.
+pub struct Arm7Tdmi<M: CpuMemory = Bus> {
     // Main register set.
     gpr: [i32; 16],
     cpsr: PSR,
@@ -40,9 +110,18 @@ pub struct Arm7Tdmi {
 
     // Pipeline implementation.
     decoded_arm: ArmInstruction,
+    decoded_arm_error: Option<GbaError>,
     fetched_arm: u32,
     decoded_thumb: ThumbInstruction,
     fetched_thumb: u16,
+    filled_stages: u32,
+    fetch_access: MemoryAccess,
+    arm_decoder: ArmDecodeTable,
+    thumb_decoder: ThumbDecodeTable,
+    block_cache: BlockCache,
+    code_writes: Vec<u32>,
+    idle_loops: IdleLoopDetector,
+    taken_branch: Option<(u32, u32)>,
 
     // Register backups for mode changes.
     gpr_r8_r12_fiq: [i32; 5],
@@ -56,12 +135,20 @@ pub struct Arm7Tdmi {
     irq_disable: bool,
     fiq_disable: bool,
     optimise_swi: bool,
-    delay_cycles: u8,
+    unpredictable: UnpredictablePolicy,
+    use_block_cache: bool,
+    detect_idle_loops: bool,
+    delay_cycles: u32,
+    halt: HaltState,
+    // Bit mask of the skipped BIOS functions logged so far, by SWI number.
+    skipped_bios_functions: u64,
 
     // Connected devices.
-    bus: Rc<RefCell<Bus>>,
+    memory: M,
 }
 
+// Register indices are the same for any memory, so they live on
+// the default type to keep `Arm7Tdmi::PC` free of type parameters.
 impl Arm7Tdmi {
     /// Register index for the stack pointer.
     ///
@@ -81,18 +168,29 @@ impl Arm7Tdmi {
     /// address beyond the read instruction's address,
     /// due to pipelining and other things.
     pub const PC: usize = 15;
+}
 
+impl<M: CpuMemory> Arm7Tdmi<M> {
     /// Creates a new CPU where all registers are zeroed.
-    pub fn new(bus: Rc<RefCell<Bus>>) -> Arm7Tdmi {
+    pub fn new(memory: M) -> Arm7Tdmi<M> {
         Arm7Tdmi {
             gpr: [0; 16],
             cpsr: PSR::default(),
             spsr: [PSR::default(); 7],
 
             decoded_arm: ArmInstruction::nop(),
+            decoded_arm_error: None,
             fetched_arm: ArmInstruction::NOP_RAW,
             decoded_thumb: ThumbInstruction::nop(),
             fetched_thumb: ThumbInstruction::NOP_RAW,
+            filled_stages: 0,
+            fetch_access: MemoryAccess::NonSequential,
+            arm_decoder: ArmDecodeTable::new(),
+            thumb_decoder: ThumbDecodeTable::new(),
+            block_cache: BlockCache::new(),
+            code_writes: Vec::new(),
+            idle_loops: IdleLoopDetector::new(),
+            taken_branch: None,
 
             gpr_r8_r12_fiq: [0; 5],
             gpr_r8_r12_other: [0; 5],
@@ -104,12 +202,23 @@ impl Arm7Tdmi {
             irq_disable: false,
             fiq_disable: false,
             optimise_swi: false,
+            unpredictable: UnpredictablePolicy::default(),
+            use_block_cache: false,
+            detect_idle_loops: false,
             delay_cycles: 0,
+            halt: HaltState::Running,
+            skipped_bios_functions: 0,
 
-            bus: bus,
+            memory: memory,
         }
     }
 
+    /// Gets the memory the CPU runs on.
+    pub fn memory(&self) -> &M { &self.memory }
+
+    /// Gets the memory the CPU runs on.
+    pub fn memory_mut(&mut self) -> &mut M { &mut self.memory }
+
     /// Checks whether optimising BIOS functions is enabled.
     pub fn is_swi_optimised(&self) -> bool { self.optimise_swi }
 
@@ -120,13 +229,167 @@ impl Arm7Tdmi {
     /// the BIOS ROM area.
     pub fn set_swi_optimised(&mut self, optimise: bool) { self.optimise_swi = optimise; }
 
+    /// Gets how unpredictable instructions are handled.
+    pub fn unpredictable_policy(&self) -> UnpredictablePolicy { self.unpredictable }
+
+    /// Configures how unpredictable instructions are handled.
+    ///
+    /// Instructions failing `ArmInstruction::check_is_valid`
+    /// are checked against this policy right before they
+    /// execute, such that e.g. literal pools never count.
+    pub fn set_unpredictable_policy(&mut self, policy: UnpredictablePolicy) { self.unpredictable = policy; }
+
+    /// Checks whether the CPU is halted.
+    pub fn halt_state(&self) -> HaltState { self.halt }
+
+    /// Gets the current CPU mode.
+    pub fn mode(&self) -> Mode { self.mode }
+
+    /// Gets the current instruction set state.
+    pub fn state(&self) -> State { self.state }
+
+    /// Gets a register of the current mode.
+    ///
+    /// # Params
+    /// - `index`: The register index, `0..16`.
+    pub fn gpr(&self, index: usize) -> i32 { self.gpr[index] }
+
+    /// Sets a register of the current mode.
+    ///
+    /// Writing PC flushes the pipeline, such that execution
+    /// continues at the given address.
+    ///
+    /// # Params
+    /// - `index`: The register index, `0..16`.
+    /// - `value`: The new register value.
+    pub fn set_gpr(&mut self, index: usize, value: i32) {
+        self.gpr[index] = value;
+        if index == Arm7Tdmi::PC { self.flush_pipeline(); }
+    }
+
+    /// Gets the address of the instruction executed next.
+    ///
+    /// PC runs two instructions ahead of the executed one due
+    /// to pipelining. Right after a pipeline flush, the next
+    /// two steps only execute pseudo NOPs, so this is the
+    /// branch target until it reaches the execute stage.
+    pub fn current_instruction_address(&self) -> u32 {
+        let size = if self.state == State::ARM { 4 } else { 2 };
+        (self.gpr[Arm7Tdmi::PC] as u32).wrapping_sub(size * self.filled_stages)
+    }
+
+    /// Gets the current program status register.
+    pub fn cpsr(&self) -> PSR { self.cpsr }
+
+    /// Sets the current program status register.
+    ///
+    /// Changing the mode bits switches register banks, and
+    /// changing the state bit switches the instruction set.
+    pub fn set_cpsr(&mut self, psr: PSR) {
+        let new_mode = psr.mode();
+        if new_mode != self.mode { self.change_mode(new_mode); }
+        if psr.state() != self.state { self.flush_pipeline(); }
+        self.cpsr  = psr;
+        self.state = psr.state();
+        self.irq_disable = psr.irq_disabled();
+        self.fiq_disable = psr.fiq_disabled();
+    }
+
+    /// Gets the saved program status register of a given mode.
+    ///
+    /// # Returns
+    /// - `Some`: The SPSR.
+    /// - `None`: User, system, and invalid modes have no SPSR.
+    pub fn spsr(&self, mode: Mode) -> Option<PSR> {
+        if mode.has_spsr() { Some(self.spsr[mode.index()]) } else { None }
+    }
+
+    /// Sets the saved program status register of a given mode.
+    ///
+    /// User, system, and invalid modes have no SPSR, so
+    /// setting theirs does nothing.
+    pub fn set_spsr(&mut self, mode: Mode, psr: PSR) {
+        if mode.has_spsr() { self.spsr[mode.index()] = psr; }
+        else { warn!("{:?} mode has no SPSR.", mode); }
+    }
+
+    /// Gets a register as seen by a given CPU mode.
+    ///
+    /// # Params
+    /// - `mode`: The mode whose register bank to look at.
+    /// - `index`: The register index, `0..16`.
+    pub fn banked_register(&self, mode: Mode, index: usize) -> i32 {
+        match index {
+            8...12 if (mode == Mode::FIQ) != (self.mode == Mode::FIQ) => {
+                if mode == Mode::FIQ { self.gpr_r8_r12_fiq[index - 8] } else { self.gpr_r8_r12_other[index - 8] }
+            },
+            13 if Self::bank_index(mode) != Self::bank_index(self.mode) => self.gpr_r13_all[Self::bank_index(mode)],
+            14 if Self::bank_index(mode) != Self::bank_index(self.mode) => self.gpr_r14_all[Self::bank_index(mode)],
+            _ => self.gpr[index],
+        }
+    }
+
+    /// Sets a register as seen by a given CPU mode.
+    ///
+    /// Unlike writing to the current registers, this allows
+    /// setting up e.g. the stack pointers of all modes
+    /// without switching modes.
+    ///
+    /// # Params
+    /// - `mode`: The mode whose register bank to modify.
+    /// - `index`: The register index, `0..16`.
+    /// - `value`: The new register value.
+    pub fn set_banked_register(&mut self, mode: Mode, index: usize, value: i32) {
+        match index {
+            8...12 if (mode == Mode::FIQ) != (self.mode == Mode::FIQ) => {
+                if mode == Mode::FIQ { self.gpr_r8_r12_fiq[index - 8] = value; } else { self.gpr_r8_r12_other[index - 8] = value; }
+            },
+            13 if Self::bank_index(mode) != Self::bank_index(self.mode) => self.gpr_r13_all[Self::bank_index(mode)] = value,
+            14 if Self::bank_index(mode) != Self::bank_index(self.mode) => self.gpr_r14_all[Self::bank_index(mode)] = value,
+            _ => self.gpr[index] = value,
+        }
+    }
+
+    /// Resets the CPU into the state the BIOS leaves behind
+    /// after booting.
+    ///
+    /// All modes get the BIOS' stack pointers, and execution
+    /// continues at `entry` in ARM state and system mode.
+    ///
+    /// # Params
+    /// - `entry`: The address to jump to, usually `0x08000000`.
+    pub fn direct_boot(&mut self, entry: u32) {
+        self.reset();
+        for r in self.gpr.iter_mut() { *r = 0; }
+        for &m in &[Mode::Supervisor, Mode::IRQ] {
+            self.set_banked_register(m, Arm7Tdmi::LR, 0);
+            self.spsr[m.index()] = PSR::default();
+        }
+        self.set_banked_register(Mode::Supervisor, Arm7Tdmi::SP, BIOS_SP_SVC as i32);
+        self.set_banked_register(Mode::IRQ,        Arm7Tdmi::SP, BIOS_SP_IRQ as i32);
+        self.set_banked_register(Mode::User,       Arm7Tdmi::SP, BIOS_SP_USR as i32);
+        self.change_mode(Mode::System);
+        self.cpsr.enable_irq();
+        self.cpsr.enable_fiq();
+        self.irq_disable = false;
+        self.fiq_disable = false;
+        self.gpr[Arm7Tdmi::PC] = entry as i32;
+        self.flush_pipeline();
+    }
+
     /// Resets the CPU.
     ///
     /// The CPU starts up by setting few
     /// register states and entering a
-    /// reset exception.
+    /// reset exception. As the memory may
+    /// have been replaced, this also drops
+    /// all cached blocks and idle loops.
     pub fn reset(&mut self) {
+        self.block_cache.clear();
+        self.idle_loops.clear();
+        self.taken_branch = None;
         self.gpr[Arm7Tdmi::PC] = 0;
+        self.flush_pipeline();
 
         self.cpsr = PSR::default();
 
@@ -134,31 +397,61 @@ impl Arm7Tdmi {
         self.state = State::ARM;
         self.irq_disable = true;
         self.fiq_disable = true;
+        self.delay_cycles = 0;
+        self.halt = HaltState::Running;
     }
 
     /// Causes an exception, switching execution modes and states.
+    ///
+    /// Must be called while executing the instruction causing
+    /// the exception, such that LR receives the address of
+    /// the following instruction.
     pub fn exception(&mut self, ex: Exception) {
-        self.change_mode(ex.mode_on_entry()); // Also sets LR.
+        let old_cpsr = self.cpsr;
+        let ret_addr = self.gpr[Arm7Tdmi::PC].wrapping_sub(if self.state == State::ARM { 4 } else { 2 });
+        self.change_mode(ex.mode_on_entry());
+        self.gpr[Arm7Tdmi::LR] = ret_addr;
+        self.spsr[self.mode.index()] = old_cpsr;
         self.cpsr.set_state(State::ARM);
         self.state = State::ARM;
         self.cpsr.disable_irq();
         if ex.disable_fiq_on_entry() { self.cpsr.disable_fiq(); }
-        // TODO LR = PC + whatevs
         self.gpr[Arm7Tdmi::PC] = ex.vector_address() as i32;
     }
 
+    /// Returns from an exception by copying the current SPSR into CPSR.
+    ///
+    /// Modes without SPSR keep their CPSR.
+    fn restore_cpsr(&mut self) {
+        let spsr = self.current_spsr();
+        self.change_mode(spsr.mode());
+        self.cpsr  = spsr;
+        self.state = spsr.state();
+    }
+
+    /// Gets the current mode's SPSR, or CPSR if it has none.
+    ///
+    /// This is what the hardware reads for SPSR in modes without one.
+    fn current_spsr(&self) -> PSR {
+        if self.mode.has_spsr() { self.spsr[self.mode.index()] } else { self.cpsr }
+    }
+
+    /// User and system mode share their registers.
+    fn bank_index(mode: Mode) -> usize {
+        if mode == Mode::System { Mode::User.index() } else { mode.index() }
+    }
+
+    /// Switches the register banks and the CPSR mode bits.
     fn change_mode(&mut self, new_mode: Mode) {
-        let current_mi = self.mode as u8 as usize;
-        let next_mi    =  new_mode as u8 as usize;
+        if let Mode::Invalid(m) = new_mode { warn!("Entering invalid mode {:#07b}.", m); }
+        let current_mi = Self::bank_index(self.mode);
+        let next_mi    = Self::bank_index(new_mode);
 
-        // Save banked registers R13, R14, SPSR.
-        let ret_addr = self.gpr[Arm7Tdmi::PC]; // FIXME TODO add special offset by exception type
+        // Swap banked registers R13, R14.
         self.gpr_r14_all[current_mi] = self.gpr[14];
-        self.gpr_r14_all[next_mi]    = ret_addr;
-        self.gpr[14]                 = ret_addr;
+        self.gpr[14]                 = self.gpr_r14_all[next_mi];
         self.gpr_r13_all[current_mi] = self.gpr[13];
         self.gpr[13]                 = self.gpr_r13_all[next_mi];
-        self.spsr[next_mi]           = self.cpsr;
 
         // Now the banked registers R8..R12.
         if (new_mode == Mode::FIQ) ^ (self.mode == Mode::FIQ) {
@@ -177,11 +470,88 @@ impl Arm7Tdmi {
         self.mode = new_mode;
     }
 
+    fn wake_up(&mut self) -> Result<bool, GbaError> {
+        if let HaltState::IntrWait(mask) = self.halt {
+            // The game's IRQ handler acknowledges interrupts
+            // to the BIOS by setting their flags.
+            let flags = try!(self.memory.load_halfword(BIOS_IRQ_FLAGS, MemoryAccess::NonSequential)) as u16;
+            if (flags & mask) != 0 {
+                try!(self.memory.store_halfword(BIOS_IRQ_FLAGS, (flags & !mask) as i32, MemoryAccess::NonSequential));
+                self.halt = HaltState::Running;
+                return Ok(true);
+            }
+            // Serve the interrupt, then keep waiting.
+            if !self.is_irq_pending() { return Ok(false); }
+            self.halt = HaltState::IntrWaitServing(mask, self.current_instruction_address());
+            return Ok(true);
+        }
+        let mut wake_mask = self.memory.pending_interrupts();
+        if self.halt == HaltState::Stopped {
+            wake_mask &= Interrupt::Keypad.mask() | Interrupt::Serial.mask() | Interrupt::GamePak.mask();
+        }
+        if wake_mask == 0 { return Ok(false); }
+        self.halt = HaltState::Running;
+        Ok(true)
+    }
+
+    /// Applies any low power mode requested by the memory.
+    ///
+    /// Returns `true` if the CPU stopped running.
+    fn enter_power_down(&mut self) -> bool {
+        match self.memory.take_power_down() {
+            Some(PowerDown::Halt) => self.halt = HaltState::Halted,
+            Some(PowerDown::Stop) => self.halt = HaltState::Stopped,
+            None => return false,
+        }
+        true
+    }
+
+    // The IRQ handler returns by jumping right
+    // after the `IntrWait` call.
+    fn resume_intr_wait(&mut self) {
+        if let HaltState::IntrWaitServing(mask, resume) = self.halt {
+            if (self.filled_stages == 0) && ((self.gpr[Arm7Tdmi::PC] as u32) == resume) {
+                self.halt = HaltState::IntrWait(mask);
+            }
+        }
+    }
+
+    /// Checks whether the CPU takes an IRQ before the next instruction.
+    fn is_irq_pending(&self) -> bool {
+        !self.cpsr.irq_disabled() && self.memory.is_irq_requested()
+    }
+
+    /// Enters the IRQ handler between two instructions.
+    ///
+    /// LR receives the address of the next instruction plus
+    /// 4, such that handlers return with `subs pc, lr, #4`.
+    fn interrupt(&mut self) {
+        let next = self.current_instruction_address();
+        self.exception(Exception::NormalInterrupt);
+        self.gpr[Arm7Tdmi::LR] = next.wrapping_add(4) as i32;
+        self.flush_pipeline();
+    }
+
+    fn allow_unpredictable(&self, inst: ArmInstruction, err: GbaError) -> Result<(), GbaError> {
+        match self.unpredictable {
+            UnpredictablePolicy::Strict => Err(err),
+            UnpredictablePolicy::Warn   => {
+                let addr = (self.gpr[Arm7Tdmi::PC] as u32).wrapping_sub(8);
+                warn!("Emulating unpredictable `{}` at {:#010X}: {}", inst, addr, err);
+                Ok(())
+            },
+            UnpredictablePolicy::Silent => Ok(()),
+        }
+    }
+
     fn flush_pipeline(&mut self) {
         self.decoded_arm   =   ArmInstruction::nop();
+        self.decoded_arm_error = None;
         self.fetched_arm   =   ArmInstruction::NOP_RAW;
         self.decoded_thumb = ThumbInstruction::nop();
         self.fetched_thumb = ThumbInstruction::NOP_RAW;
+        self.filled_stages = 0;
+        self.fetch_access  = MemoryAccess::NonSequential;
     }
 
     #[inline]
@@ -202,32 +572,52 @@ impl Arm7Tdmi {
     /// fills all pipeline stages with pseudo NOP
     /// instructions, i.e. instructions without any (side)
     /// effects.
+    ///
+    /// While halted, a pipeline step only checks whether
+    /// the CPU should wake up. If `IME` and CPSR allow
+    /// a requested interrupt, the step enters the IRQ
+    /// handler instead of executing an instruction.
     pub fn pipeline_step(&mut self) -> Result<(), GbaError> {
         if self.delay_cycles > 0 {
             self.delay_cycles -= 1;
             return Ok(());
         }
+        self.enter_power_down();
+        self.resume_intr_wait();
+        let halted = match self.halt {
+            HaltState::Halted | HaltState::Stopped | HaltState::IntrWait(_) => true,
+            _ => false,
+        };
+        if halted && !try!(self.wake_up()) {
+            return Ok(());
+        }
+        if self.is_irq_pending() {
+            self.interrupt();
+            return Ok(());
+        }
 
         let action: CpuAction = if self.state == State::ARM {
             // Fetch.
-            let new_fetched_arm = try!(self.bus.borrow().load_word(self.gpr[Arm7Tdmi::PC] as u32)) as u32;
+            let new_fetched_arm = try!(self.memory.load_word(self.gpr[Arm7Tdmi::PC] as u32, self.fetch_access)) as u32;
             // Decode.
-            let new_decoded_arm = try!(ArmInstruction::decode(self.fetched_arm));
-            try!(new_decoded_arm.check_is_valid());
+            let new_decoded_arm = try!(self.arm_decoder.decode(self.fetched_arm));
+            let new_decoded_arm_error = new_decoded_arm.check_is_valid().err();
             // Execute.
             let old_decoded_arm = self.decoded_arm;
+            if let Some(e) = self.decoded_arm_error.take() { try!(self.allow_unpredictable(old_decoded_arm, e)); }
             let action = try!(self.execute_arm_state(old_decoded_arm));
 
             // Apply new state.
             self.fetched_arm = new_fetched_arm;
             self.decoded_arm = new_decoded_arm;
+            self.decoded_arm_error = new_decoded_arm_error;
 
             action
         } else {
             // Fetch.
-            let new_fetched_thumb = try!(self.bus.borrow().load_halfword(self.gpr[Arm7Tdmi::PC] as u32)) as u16;
+            let new_fetched_thumb = try!(self.memory.load_halfword(self.gpr[Arm7Tdmi::PC] as u32, self.fetch_access)) as u16;
             // Decode.
-            let new_decoded_thumb = try!(ThumbInstruction::decode(self.fetched_thumb));
+            let new_decoded_thumb = try!(self.thumb_decoder.decode(self.fetched_thumb));
 
             // Apply new state.
             self.fetched_thumb = new_fetched_thumb;
@@ -236,8 +626,12 @@ impl Arm7Tdmi {
             unimplemented!();
         };
 
+        self.fetch_access = MemoryAccess::Sequential;
         match action {
-            CpuAction::None          => self.increment_pc(),
+            CpuAction::None          => {
+                self.filled_stages = cmp::min(self.filled_stages + 1, 2);
+                self.increment_pc();
+            },
             CpuAction::FlushPipeline => self.flush_pipeline(),
         }
 
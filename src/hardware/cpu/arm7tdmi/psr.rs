@@ -9,7 +9,7 @@ use std::fmt;
 use std::mem;
 
 /// The CPU's instruction decoding states.
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 #[repr(u8)]
 pub enum State {
     /// Currently executing 32-bit ARM instructions.
@@ -21,16 +21,16 @@ pub enum State {
 
 
 /// The CPU's different execution modes.
-#[derive(Debug, PartialEq, Clone, Copy)]
-#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Mode {
-    #[doc = "CPU mode for running normal user code."]                  User = 0,
+    #[doc = "CPU mode for running normal user code."]                  User,
     #[doc = "CPU mode for handling fast interrupts."]                  FIQ,
     #[doc = "CPU mode for handling normal interrupts."]                IRQ,
     #[doc = "CPU mode for executing supervisor code."]                 Supervisor,
     #[doc = "CPU mode entered if memory lookups are aborted."]         Abort,
     #[doc = "CPU mode entered if executing an undefined instruction."] Undefined,
     #[doc = "CPU mode for executing system code."]                     System,
+    #[doc = "Any other mode bit pattern, as written by a broken MSR."] Invalid(u8),
 }
 
 impl Mode {
@@ -43,7 +43,34 @@ impl Mode {
             Mode::Supervisor => PSR::MODE_SUPERVISOR,
             Mode::Abort      => PSR::MODE_ABORT,
             Mode::Undefined  => PSR::MODE_UNDEFINED,
-            Mode::System     => PSR::MODE_SYSTEM
+            Mode::System     => PSR::MODE_SYSTEM,
+            Mode::Invalid(m) => (m as u32) & PSR::MODE_MASK,
+        }
+    }
+
+    /// Gets the index of this mode's register bank and SPSR.
+    ///
+    /// Just like on hardware, invalid modes use the
+    /// registers of user mode.
+    pub fn index(self) -> usize {
+        match self {
+            Mode::User | Mode::Invalid(_) => 0,
+            Mode::FIQ        => 1,
+            Mode::IRQ        => 2,
+            Mode::Supervisor => 3,
+            Mode::Abort      => 4,
+            Mode::Undefined  => 5,
+            Mode::System     => 6,
+        }
+    }
+
+    /// Checks whether this mode has its own SPSR.
+    ///
+    /// User mode, system mode, and invalid modes have none.
+    pub fn has_spsr(self) -> bool {
+        match self {
+            Mode::User | Mode::System | Mode::Invalid(_) => false,
+            _ => true,
         }
     }
 }
@@ -102,10 +129,7 @@ impl PSR {
             PSR::MODE_ABORT      => Mode::Abort,
             PSR::MODE_UNDEFINED  => Mode::Undefined,
             PSR::MODE_SYSTEM     => Mode::System,
-            _ => {
-                error!("PSR: Unrecognised mode bit pattern {:#010b}.", self.0 & PSR::MODE_MASK);
-                panic!("Aborting due to illegal mode bits.");
-            },
+            m                    => Mode::Invalid(m as u8),
         }
     }
 
@@ -231,6 +255,7 @@ impl fmt::Display for Mode {
                 Mode::Abort      => "ABT",
                 Mode::Undefined  => "UND",
                 Mode::System     => "SYS",
+                Mode::Invalid(_) => "INV",
             }
         )
     }
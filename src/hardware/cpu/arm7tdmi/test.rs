@@ -0,0 +1,820 @@
+// License below.
+#![allow(missing_docs)]
+
+use std::env;
+use std::path::PathBuf;
+use byteorder::{ByteOrder, LittleEndian};
+use super::*;
+use super::super::super::gamepak::GamePak;
+use super::super::super::memory::*;
+use super::super::super::ioregs::Interrupt;
+use super::super::super::error::GbaError;
+
+// mvn r0, #99; mov r1, #7; swi 0x06; mov r4, r0; mov r5, r1;
+// mov r6, r3; mov r0, #0x10000; swi 0x08; mov r7, r0; b .
+const DIV_SQRT: &'static [u32] = &[
+    0xE3E00063, 0xE3A01007, 0xEF060000, 0xE1A04000, 0xE1A05001,
+    0xE1A06003, 0xE3A00801, 0xEF080000, 0xE1A07000, 0xEAFFFFFE,
+];
+
+// mvn r0, #4; mov r1, #0; swi 0x06; b .
+const DIV_BY_ZERO: &'static [u32] = &[0xE3E00004, 0xE3A01000, 0xEF060000, 0xEAFFFFFE];
+
+// swi 0x09; b .; swi 0x0A; b .
+const ARCTANS: &'static [u32] = &[0xEF090000, 0xEAFFFFFE, 0xEF0A0000, 0xEAFFFFFE];
+
+// mov r4, #0x04000000; add r1, r4, #0x200; mov r0, #0x1000; add r0, r0, #1;
+// strh r0, [r1]; swi 0x02; mov r5, #1; mov r2, #0x80; swi 0x27;
+// mov r5, #2; b .
+const HALT_AND_STOP: &'static [u32] = &[
+    0xE3A04301, 0xE2841C02, 0xE3A00A01, 0xE2800001, 0xE1C100B0,
+    0xEF020000, 0xE3A05001, 0xE3A02080, 0xEF270000, 0xE3A05002,
+    0xEAFFFFFE,
+];
+
+// mov r0, #5; mov r1, #0x100; str r0, [r1, #8]; ldr r2, [r1, #8];
+// add r3, r2, r2; stmia r1, {r2, r3}; mov r0, #100; mov r1, #7;
+// swi 0x06; b .
+const LOAD_STORE_DIV: &'static [u32] = &[
+    0xE3A00005, 0xE3A01C01, 0xE5810008, 0xE5912008, 0xE0823002,
+    0xE881000C, 0xE3A00064, 0xE3A01007, 0xEF060000, 0xEAFFFFFE,
+];
+
+// mov r0, #0x18; mov r1, #0x40; mov r2, #1; mov r3, #2; swi 0x0F; b .
+// .hword 0x100, 0x100, 0
+const OBJ_AFFINE_SET: &'static [u32] = &[
+    0xE3A00018, 0xE3A01040, 0xE3A02001, 0xE3A03002, 0xEF0F0000, 0xEAFFFFFE,
+    0x01000100, 0x00000000,
+];
+
+// b start; b undef; ...; start: mov r0, #0xDF; msr cpsr_fc, r0;
+// mov lr, #0x55; .word 0xE7F000F0; add r5, r5, #1; mov r4, lr; b .;
+// undef: mrs r2, spsr; mov r3, lr; add r6, r6, #1; movs pc, lr
+const UNDEFINED_INSTRUCTION: &'static [u32] = &[
+    0xEA000006, 0xEA00000D, 0, 0, 0, 0, 0, 0,
+    0xE3A000DF, 0xE129F000, 0xE3A0E055, 0xE7F000F0, 0xE2855001,
+    0xE1A0400E, 0xEAFFFFFE, 0, 0xE14F2000, 0xE1A0300E, 0xE2866001,
+    0xE1B0F00E,
+];
+
+// mov r0, #0xD2; mov r1, #0xD3; mov lr, #1; msr cpsr_fc, r0; mov lr, #2;
+// msr spsr_fc, r1; msr cpsr_fc, r1; mov r2, lr; msr cpsr_fc, r0;
+// mov r3, lr; b .
+const MSR_MODE_SWITCHES: &'static [u32] = &[
+    0xE3A000D2, 0xE3A010D3, 0xE3A0E001, 0xE129F000, 0xE3A0E002,
+    0xE169F001, 0xE129F001, 0xE1A0200E, 0xE129F000, 0xE1A0300E,
+    0xEAFFFFFE,
+];
+
+// ldmfd sp!, {pc}^; b .
+const RETURN_TO_THUMB: &'static [u32] = &[0xE8FD8000, 0xEAFFFFFE];
+
+// mov r4, #0x04000000; add r1, r4, #0x200; mov r0, #1; strh r0, [r1, #8];
+// strh r0, [r1]; add r5, r5, #1; add r5, r5, #1; add r5, r5, #1; b .
+const ENABLE_IRQ: &'static [u32] = &[
+    0xE3A04301, 0xE2841C02, 0xE3A00001, 0xE1C100B8, 0xE1C100B0,
+    0xE2855001, 0xE2855001, 0xE2855001, 0xEAFFFFFE,
+];
+
+// R0, R1, carry flag after `subs r2, r0, r1`.
+const SUB_CARRIES: &'static [(u32, u32, bool)] = &[
+    (5, 3, true), (3, 5, false), (5, 5, true), (0, 1, false),
+    (0xFFFFFFFF, 1, true), (0x80000000, 1, true), (1, 0x80000000, false),
+];
+
+// mov r4, #0x100; ldr r3, =0xE3A0002A; mov r2, #0; loop: bl 0x100;
+// add r2, r2, #1; cmp r2, #2; beq done; str r3, [r4]; b loop; done: b .
+// The function at 0x100 is `mov r0, #7; mov pc, lr` until the
+// store above replaces its first instruction by `mov r0, #42`.
+const SELF_MODIFYING: &'static [u32] = &[
+    0xE3A04C01, 0xE59F3034, 0xE3A02000, 0xEB00003B, 0xE2822001,
+    0xE3520002, 0x0A000001, 0xE5843000, 0xEAFFFFF9, 0xEAFFFFFE,
+];
+const SELF_MODIFYING_DATA: &'static [(usize, u32)] = &[
+    (0x040, 0xE3A0002A), (0x100, 0xE3A00007), (0x104, 0xE1A0F00E),
+];
+
+// mov r1, #0x100; mov r0, #0; count: add r0, r0, #1; cmp r0, #100;
+// bne count; poll: ldr r2, [r1]; cmp r2, #0; beq poll; b .
+const COUNT_AND_POLL: &'static [u32] = &[
+    0xE3A01C01, 0xE3A00000, 0xE2800001, 0xE3500064, 0x1AFFFFFC,
+    0xE5912000, 0xE3520000, 0x0AFFFFFC, 0xEAFFFFFE,
+];
+
+// mov r1, #0x04000000; add r2, r1, #0x100; mov r0, #0x80;
+// strh r0, [r2, #2]; poll: ldrh r3, [r2]; cmp r3, #0x100;
+// bcc poll; mov r5, #1; b .
+const TIMER_POLL: &'static [u32] = &[
+    0xE3A01301, 0xE2812C01, 0xE3A00080, 0xE1C200B2, 0xE1D230B0,
+    0xE3530C01, 0x3AFFFFFC, 0xE3A05001, 0xEAFFFFFE,
+];
+
+// mov r1, #3; mov r0, #5; mul r0, r0, r1; mov r2, #4; mov r3, #8;
+// mul pc, r2, r3; mov r5, #1; mov r5, #2; mov r6, #7; b .
+const UNPREDICTABLE_MUL: &'static [u32] = &[
+    0xE3A01003, 0xE3A00005, 0xE0000190, 0xE3A02004, 0xE3A03008,
+    0xE00F0392, 0xE3A05001, 0xE3A05002, 0xE3A06007, 0xEAFFFFFE,
+];
+
+// msr cpsr_fc, r0; mrs r1, spsr; mov r3, sp; msr cpsr_fc, r2;
+// mov r4, sp; b .
+const INVALID_MODE: &'static [u32] = &[
+    0xE129F000, 0xE14F1000, 0xE1A0300D, 0xE129F002, 0xE1A0400D, 0xEAFFFFFE,
+];
+
+// msr spsr_fc, r0; msr cpsr_fc, r0; mrs r1, spsr; b .
+const USER_MODE_MSR: &'static [u32] = &[
+    0xE169F000, 0xE129F000, 0xE14F1000, 0xEAFFFFFE,
+];
+
+// mov r4, #0x04000000; ldr r0, =handler; str r0, [r4, #-4];
+// add r1, r4, #0x200; mov r0, #1; strh r0, [r1]; mov r1, #1;
+// swi 0x04; mov r6, #42; b .; handler: add r3, r4, #0x200;
+// mov r0, #1; strh r0, [r3, #2]; ldrh r1, [r4, #-8]; orr r1, r1, #1;
+// strh r1, [r4, #-8]; add r7, r7, #1; bx lr
+const INTR_WAIT: &'static [u32] = &[
+    0xE3A04301, 0xE59F003C, 0xE5040004, 0xE2841C02, 0xE3A00001,
+    0xE1C100B0, 0xE3A01001, 0xEF040000, 0xE3A0602A, 0xEAFFFFFE,
+    0xE2843C02, 0xE3A00001, 0xE1C300B2, 0xE15410B8, 0xE3811001,
+    0xE14410B8, 0xE2877001, 0xE12FFF1E, 0x08000028,
+];
+
+// SWI number, R0, R1, R2. Copies read from eight words at 0x02000000.
+const BIOS_CALLS: &'static [(u32, i32, i32, i32)] = &[
+    (0x06, -100, 7, 0), (0x06, 1000000, -3, 0), (0x06, i32::MIN, -1, 0), (0x07, 7, -100, 0),
+    (0x08, 0, 0, 0), (0x08, 2, 0, 0), (0x08, 1000, 0, 0), (0x08, -1, 0, 0),
+    (0x09, 0x2000, 0, 0), (0x09, -0x4000, 0, 0),
+    (0x0A, 1, 1, 0), (0x0A, -0x4000, 0x2000, 0), (0x0A, 0, -5, 0),
+    (0x0B, 0x02000000, 0x02001000, 0x00000010), (0x0B, 0x02000000, 0x02001000, 0x04000008),
+    (0x0B, 0x02000000, 0x02001000, 0x05000008),
+    (0x0C, 0x02000000, 0x02001000, 0x00000008), (0x0C, 0x02000000, 0x02001000, 0x01000008),
+];
+
+// SWI, R0, source data at 0x02000000, R1, R2, R3.
+const MEMORY_BIOS_CALLS: &'static [(u32, i32, &'static [u8], i32, i32, i32)] = &[
+    // BgAffineSet with two entries.
+    (0x0E, 0x02000000, &[
+        0x00, 0x34, 0x12, 0x00, 0x80, 0x67, 0x05, 0x00, 0x78, 0x00, 0xB0, 0xFF,
+        0x80, 0x01, 0x00, 0xFF, 0x45, 0x23, 0x00, 0x00,
+        0x00, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x04, 0x00, 0xFD, 0xFF, 0x07, 0x00,
+        0xFF, 0x7F, 0x00, 0x80, 0xFF, 0xC0, 0x00, 0x00,
+    ], 0x02001000, 2, 0),
+    // ObjAffineSet with two entries, packed and OAM-like.
+    (0x0F, 0x02000000, &[
+        0x00, 0x01, 0x00, 0x02, 0x00, 0x40, 0x00, 0x00,
+        0x00, 0x80, 0xFF, 0x7F, 0xFF, 0x80, 0x00, 0x00,
+    ], 0x02001000, 2, 2),
+    (0x0F, 0x02000000, &[
+        0x00, 0x01, 0x00, 0x02, 0x00, 0x40, 0x00, 0x00,
+        0x00, 0x80, 0xFF, 0x7F, 0xFF, 0x80, 0x00, 0x00,
+    ], 0x02001006, 2, 8),
+    // BitUnPack with the info at 0x02000008.
+    (0x10, 0x02000000, &[0x1B, 0x81, 0, 0, 0, 0, 0, 0, 2, 0, 2, 8, 1, 0, 0, 0], 0x02001000, 0x02000008, 0),
+    (0x10, 0x02000000, &[0x1B, 0x81, 0, 0, 0, 0, 0, 0, 2, 0, 2, 8, 1, 0, 0, 0x80], 0x02001000, 0x02000008, 0),
+    (0x10, 0x02000000, &[0x1B, 0x81, 0, 0, 0, 0, 0, 0, 1, 0, 1, 4, 0, 0, 0, 0], 0x02001000, 0x02000008, 0),
+    (0x10, 0x02000000, &[0x1B, 0x81, 0, 0, 0, 0, 0, 0, 2, 0, 4, 32, 7, 0, 0, 0], 0x02001000, 0x02000008, 0),
+    (0x10, 0x02000000, &[0x1B, 0x81, 0, 0, 0, 0, 0, 0, 1, 0, 4, 2, 0, 0, 0, 0], 0x02001000, 0x02000008, 0),
+    // LZ77, with references before the start and to a pending byte.
+    (0x11, 0x02000000, &[0x10, 0x0D, 0x00, 0x00, 0x10, b'A', b'B', b'C', 0x60, 0x02, b'D'], 0x02001001, 0, 0),
+    (0x12, 0x02000000, &[0x10, 0x0D, 0x00, 0x00, 0x10, b'A', b'B', b'C', 0x60, 0x02, b'D'], 0x06000000, 0, 0),
+    (0x12, 0x02000000, &[0x10, 0x04, 0x00, 0x00, 0x80, 0x00, 0x05, b'Q'], 0x06000000, 0, 0),
+    (0x12, 0x02000000, &[0x10, 0x06, 0x00, 0x00, 0x10, b'A', b'B', b'C', 0x00, 0x00], 0x06000000, 0, 0),
+    (0x11, 0x00000000, &[], 0x02001000, 0, 0),
+    // Huffman with 8 and 4 bit units.
+    (0x13, 0x02000000, &[
+        0x28, 0x05, 0x00, 0x00, 0x03, 0x80, b'a', 0xC0, b'b', b'c', 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x5A,
+    ], 0x02001000, 0, 0),
+    (0x13, 0x02000000, &[
+        0x24, 0x02, 0x00, 0x00, 0x01, 0xC0, 0x01, 0x02, 0x00, 0x00, 0x00, 0x60,
+    ], 0x02001000, 0, 0),
+    // Run-length encoding.
+    (0x14, 0x02000000, &[0x30, 0x0A, 0x00, 0x00, 0x83, b'A', 0x03, b'B', b'x', b'y', b'z'], 0x02001000, 0, 0),
+    (0x15, 0x02000000, &[0x30, 0x09, 0x00, 0x00, 0x83, b'A', 0x03, b'B', b'x', b'y', b'z'], 0x06000000, 0, 0),
+    // Delta filters.
+    (0x16, 0x02000000, &[0x81, 0x04, 0x00, 0x00, 10, 2, 0xFF, 9], 0x02001000, 0, 0),
+    (0x17, 0x02000000, &[0x81, 0x03, 0x00, 0x00, 10, 2, 0xFF, 9], 0x06000000, 0, 0),
+    (0x18, 0x02000000, &[0x82, 0x06, 0x00, 0x00, 0x00, 0x10, 0x01, 0x00, 0xFE, 0xFF], 0x06000000, 0, 0),
+];
+
+type Registers = &'static [(usize, i32)];
+type Words = &'static [(usize, u32)];
+
+// Instruction, initial registers, stored words, final registers.
+const BLOCK_TRANSFERS: &'static [(u32, Registers, Words, Registers)] = &[
+    // stmia r0!, {r0, r1}: The first register stores the original base.
+    (0xE8A00003, &[(0, 0x100), (1, 1)], &[(0x100, 0x100), (0x104, 1)], &[(0, 0x108)]),
+    // stmia r1!, {r0, r1}: Later registers store the written back base.
+    (0xE8A10003, &[(0, 7), (1, 0x100)], &[(0x100, 7), (0x104, 0x108)], &[(1, 0x108)]),
+    // stmdb r6!, {r5, r6}
+    (0xE9260060, &[(5, 5), (6, 0x110)], &[(0x108, 5), (0x10C, 0x108)], &[(6, 0x108)]),
+    // ldmia r2!, {r1, r2}: The loaded base wins over the written back one.
+    (0xE8B20006, &[(2, 0)], &[], &[(1, 0xE8B20006_u32 as i32), (2, 0xEAFFFFFE_u32 as i32)]),
+    // stmia r3!, {}: An empty list stores PC and moves the base by 0x40.
+    (0xE8A30000, &[(3, 0x100)], &[(0x100, 12)], &[(3, 0x140)]),
+    // stmdb r3!, {}
+    (0xE9230000, &[(3, 0x140)], &[(0x100, 12)], &[(3, 0x100)]),
+    // ldmia r5, {}: An empty list loads PC.
+    (0xE8950000, &[(5, 0x100)], &[], &[(15, 0)]),
+    // str pc, [r4]: Stores see PC 12 bytes ahead.
+    (0xE584F000, &[(4, 0x100)], &[(0x100, 12)], &[]),
+];
+
+// Instruction, R1, R0 before, R0 after, word at 0x08 after.
+// R2 always holds 0x12345678.
+const MISALIGNED_TRANSFERS: &'static [(u32, u32, u32, u32, u32)] = &[
+    // ldr r0, [r1]: Rotates the addressed byte into the lowest byte.
+    (0xE5910000, 0x08, 0, 0x44338211, 0x44338211),
+    (0xE5910000, 0x09, 0, 0x11443382, 0x44338211),
+    (0xE5910000, 0x0A, 0, 0x82114433, 0x44338211),
+    (0xE5910000, 0x0B, 0, 0x33821144, 0x44338211),
+    // ldrh r0, [r1]: Rotates the halfword within the whole register.
+    (0xE1D100B0, 0x08, 0, 0x00008211, 0x44338211),
+    (0xE1D100B0, 0x09, 0, 0x11000082, 0x44338211),
+    (0xE1D100B0, 0x0A, 0, 0x00004433, 0x44338211),
+    (0xE1D100B0, 0x0B, 0, 0x33000044, 0x44338211),
+    // ldrsh r0, [r1]: Sign-extends the addressed byte if misaligned.
+    (0xE1D100F0, 0x08, 0, 0xFFFF8211, 0x44338211),
+    (0xE1D100F0, 0x09, 0, 0xFFFFFF82, 0x44338211),
+    (0xE1D100F0, 0x0A, 0, 0x00004433, 0x44338211),
+    (0xE1D100F0, 0x0B, 0, 0x00000044, 0x44338211),
+    // str r0, [r1] and strh r0, [r1]: Stores are force-aligned.
+    (0xE5810000, 0x0B, 0xAABBCCDD, 0xAABBCCDD, 0xAABBCCDD),
+    (0xE1C100B0, 0x0B, 0xAABBCCDD, 0xAABBCCDD, 0xCCDD8211),
+    // swp r0, r2, [r1]
+    (0xE1010092, 0x09, 0, 0x11443382, 0x12345678),
+    // ldmia r1, {r0}
+    (0xE8910001, 0x0A, 0, 0x44338211, 0x44338211),
+];
+
+/// A flat memory starting at address zero that records all stores.
+struct FlatMemory {
+    bytes: Vec<u8>,
+    stores: Vec<(u32, MemoryAccess)>,
+    watched: Vec<bool>,
+    code_writes: Vec<u32>,
+    next_event: Option<u64>,
+}
+
+impl FlatMemory {
+    fn new(prog: &[u32]) -> FlatMemory {
+        let mut bytes = vec![0; 0x200];
+        for (i, &w) in prog.iter().enumerate() { LittleEndian::write_u32(&mut bytes[i * 4..], w); }
+        FlatMemory { bytes: bytes, stores: vec![], watched: vec![false; 2], code_writes: vec![], next_event: None }
+    }
+
+    fn record_store(&mut self, addr: u32, access: MemoryAccess) {
+        self.stores.push((addr, access));
+        let page = (addr >> CODE_PAGE_SHIFT) as usize;
+        if self.watched.get(page) == Some(&true) {
+            self.watched[page] = false;
+            self.code_writes.push((page as u32) << CODE_PAGE_SHIFT);
+        }
+    }
+
+    fn slice(&mut self, addr: u32, len: usize) -> Result<&mut [u8], GbaError> {
+        let addr = addr as usize & !(len - 1);
+        if addr + len > self.bytes.len() { return Err(GbaError::InvalidPhysicalAddress(addr as u32)); }
+        Ok(&mut self.bytes[addr..(addr + len)])
+    }
+}
+
+impl CpuMemory for FlatMemory {
+    fn load_byte(&mut self, addr: u32, _: MemoryAccess) -> Result<i32, GbaError> { Ok(try!(self.slice(addr, 1))[0] as i32) }
+    fn load_halfword(&mut self, addr: u32, _: MemoryAccess) -> Result<i32, GbaError> { Ok(LittleEndian::read_u16(try!(self.slice(addr, 2))) as i32) }
+    fn load_word(&mut self, addr: u32, _: MemoryAccess) -> Result<i32, GbaError> { Ok(LittleEndian::read_u32(try!(self.slice(addr, 4))) as i32) }
+    fn store_byte(&mut self, addr: u32, data: i32, access: MemoryAccess) -> Result<(), GbaError> {
+        self.record_store(addr, access);
+        try!(self.slice(addr, 1))[0] = data as u8;
+        Ok(())
+    }
+    fn store_halfword(&mut self, addr: u32, data: i32, access: MemoryAccess) -> Result<(), GbaError> {
+        self.record_store(addr, access);
+        LittleEndian::write_u16(try!(self.slice(addr, 2)), data as u16);
+        Ok(())
+    }
+    fn store_word(&mut self, addr: u32, data: i32, access: MemoryAccess) -> Result<(), GbaError> {
+        self.record_store(addr, access);
+        LittleEndian::write_u32(try!(self.slice(addr, 4)), data as u32);
+        Ok(())
+    }
+    fn is_code_cacheable(&self, addr: u32) -> bool { (addr as usize) < self.bytes.len() }
+    fn watch_code(&mut self, addr: u32) { self.watched[(addr >> CODE_PAGE_SHIFT) as usize] = true; }
+    fn take_code_writes(&mut self, pages: &mut Vec<u32>) { pages.extend(self.code_writes.drain(..)); }
+    fn cycles_until_event(&self) -> Option<u64> { self.next_event }
+}
+
+fn cpu_with_replacement_bios(prog: &[u32]) -> Arm7Tdmi {
+    let mut gpak = GamePak::new();
+    let mut bios = BiosRom::new();
+    bios.load_replacement();
+    for (i, &w) in prog.iter().enumerate() {
+        LittleEndian::write_u32(gpak.rom_mut().bytes_mut(i as u32 * 4), w);
+    }
+    let mut cpu = Arm7Tdmi::new(Bus::new(gpak, bios));
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn replacement_bios_boots_and_runs_swis() {
+    let mut cpu = cpu_with_replacement_bios(DIV_SQRT);
+    for _ in 0..2000 { cpu.pipeline_step().unwrap(); }
+
+    assert_eq!(cpu.mode, Mode::System);
+    assert_eq!(cpu.gpr[Arm7Tdmi::SP] as u32, 0x03007F00);
+    assert_eq!((cpu.gpr[4], cpu.gpr[5], cpu.gpr[6]), (-14, -2, 14));
+    assert_eq!(cpu.gpr[7], 0x100);
+    assert_eq!(cpu.gpr_r13_all[Mode::Supervisor.index()] as u32, 0x03007FE0);
+    assert_eq!(cpu.gpr_r13_all[Mode::IRQ.index()] as u32, 0x03007FA0);
+}
+
+#[test]
+fn replacement_bios_arctan_matches_hle() {
+    let inputs = &[(0x2000, 0), (-0x4000, 0), (0x4000, 0), (0, -5), (-1, 0), (1, 1),
+                   (-0x4000, 0x2000), (0x1000, -0x3000), (-0x3000, -0x1000), (0x1234, 0x4321)];
+    for &entry in &[0x08000000, 0x08000008] {
+        for &(r0, r1) in inputs {
+            let run = |optimise: bool| {
+                let mut cpu = cpu_with_replacement_bios(ARCTANS);
+                cpu.direct_boot(entry);
+                cpu.set_swi_optimised(optimise);
+                cpu.set_gpr(0, r0);
+                cpu.set_gpr(1, r1);
+                for _ in 0..1000 { cpu.pipeline_step().unwrap(); }
+                (cpu.gpr(0), cpu.gpr(1), cpu.gpr(3))
+            };
+            assert_eq!(run(false), run(true));
+        }
+    }
+}
+
+#[test]
+fn replacement_bios_memory_functions_match_hle() {
+    for &(swi, r0, data, r1, r2, r3) in MEMORY_BIOS_CALLS {
+        let run = |optimise: bool| {
+            // swi <swi>; b .
+            let mut cpu = cpu_with_replacement_bios(&[0xEF000000 | swi << 16, 0xEAFFFFFE]);
+            for (i, &b) in data.iter().enumerate() { cpu.memory_mut().store_byte(0x02000000 + i as u32, b as i32).unwrap(); }
+            for i in 0..16 {
+                cpu.memory_mut().store_word(0x02001000 + i * 4, 0xAAAAAAAA_u32 as i32).unwrap();
+                cpu.memory_mut().store_word(0x06000000 + i * 4, 0xAAAAAAAA_u32 as i32).unwrap();
+            }
+            cpu.direct_boot(0x08000000);
+            cpu.set_swi_optimised(optimise);
+            for (i, &r) in [r0, r1, r2, r3].iter().enumerate() { cpu.set_gpr(i, r); }
+
+            for _ in 0..10000 {
+                if cpu.current_instruction_address() == 0x08000004 { break; }
+                cpu.pipeline_step().unwrap();
+            }
+            let stored = (0..16).map(|i| (cpu.memory().load_word(0x02001000 + i * 4).unwrap(),
+                                          cpu.memory().load_word(0x06000000 + i * 4).unwrap()))
+                                .collect::<Vec<_>>();
+            let preserved = (4..11).map(|i| cpu.gpr(i)).collect::<Vec<_>>();
+            (cpu.current_instruction_address(), preserved, stored)
+        };
+        assert_eq!(run(false), run(true), "SWI {:#04X}, R1 = {:#X}", swi, r1);
+    }
+}
+
+#[test]
+#[ignore]
+fn bios_image_matches_hle() {
+    // Needs a BIOS image, thus ignored by default. Prints all
+    // mismatches, so that cycle counts can be fixed in one go.
+    let path = match env::var_os("GBARS_BIOS") {
+        Some(p) => PathBuf::from(p),
+        None => { println!("Skipped, GBARS_BIOS doesn't name a BIOS image."); return; }
+    };
+    let mut mismatches = vec![];
+    for &(swi, r0, r1, r2) in BIOS_CALLS {
+        let run = |optimise: bool| {
+            // swi <swi>; b .
+            let mut gpak = GamePak::new();
+            for (i, &w) in [0xEF000000 | swi << 16, 0xEAFFFFFE].iter().enumerate() {
+                LittleEndian::write_u32(gpak.rom_mut().bytes_mut(i as u32 * 4), w);
+            }
+            let mut bios = BiosRom::new();
+            bios.load_from_file(&path).unwrap();
+            let mut cpu = Arm7Tdmi::new(Bus::new(gpak, bios));
+            for i in 0..8 { cpu.memory_mut().store_word(0x02000000 + i * 4, 0x11111111_u32.wrapping_mul(i + 1) as i32).unwrap(); }
+            cpu.direct_boot(0x08000000);
+            cpu.set_swi_optimised(optimise);
+            for (i, &r) in [r0, r1, r2].iter().enumerate() { cpu.set_gpr(i, r); }
+
+            let mut steps = 0;
+            while cpu.current_instruction_address() != 0x08000004 && steps < 100000 { cpu.pipeline_step().unwrap(); steps += 1; }
+            let copied = (0..8).map(|i| cpu.memory().load_word(0x02001000 + i * 4).unwrap()).collect::<Vec<_>>();
+            (steps, cpu.gpr(0), cpu.gpr(1), cpu.gpr(3), copied)
+        };
+        let (bios, hle) = (run(false), run(true));
+        if bios != hle {
+            mismatches.push(format!("SWI {:#04X}, R0..R2 = {:#X}, {:#X}, {:#X}:\n  BIOS: {:?}\n  HLE:  {:?}",
+                                    swi, r0, r1, r2, bios, hle));
+        }
+    }
+    assert!(mismatches.is_empty(), "\n{}", mismatches.join("\n"));
+}
+
+#[test]
+fn haltcnt_halts_and_stops() {
+    let mut cpu = cpu_with_replacement_bios(HALT_AND_STOP);
+    cpu.direct_boot(0x08000000);
+    for _ in 0..100 { cpu.pipeline_step().unwrap(); }
+    assert_eq!((cpu.halt_state(), cpu.gpr(5)), (HaltState::Halted, 0));
+
+    cpu.memory_mut().request_interrupt(Interrupt::VBlank);
+    for _ in 0..100 { cpu.pipeline_step().unwrap(); }
+    assert_eq!((cpu.halt_state(), cpu.gpr(5)), (HaltState::Stopped, 1));
+
+    cpu.memory_mut().request_interrupt(Interrupt::Keypad);
+    for _ in 0..100 { cpu.pipeline_step().unwrap(); }
+    assert_eq!((cpu.halt_state(), cpu.gpr(5)), (HaltState::Running, 2));
+}
+
+#[test]
+fn hle_div_by_zero_returns_garbage() {
+    let mut cpu = cpu_with_replacement_bios(DIV_BY_ZERO);
+    cpu.direct_boot(0x08000000);
+    cpu.set_swi_optimised(true);
+    for _ in 0..10 { cpu.pipeline_step().unwrap(); }
+    assert_eq!((cpu.gpr(0), cpu.gpr(1), cpu.gpr(3)), (-1, -5, 1));
+}
+
+#[test]
+fn skipped_sound_driver_functions_are_logged_once() {
+    // swi 0x1A; swi 0x1A; swi 0x1C; b .
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(&[0xEF1A0000, 0xEF1A0000, 0xEF1C0000, 0xEAFFFFFE]));
+    cpu.reset();
+    cpu.set_swi_optimised(true);
+    for _ in 0..6 { cpu.pipeline_step().unwrap(); }
+    assert_eq!(cpu.skipped_bios_functions, (1 << 0x1A) | (1 << 0x1C));
+    assert_eq!(cpu.current_instruction_address(), 0x0C);
+}
+
+#[test]
+fn memory_bios_functions_run_on_any_memory() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(OBJ_AFFINE_SET));
+    cpu.reset();
+    cpu.set_swi_optimised(true);
+    for _ in 0..8 { cpu.pipeline_step().unwrap(); }
+    assert_eq!(cpu.current_instruction_address(), 0x14);
+    assert_eq!(&cpu.memory_mut().bytes[0x40..0x48], &[0x00, 0x01, 0, 0, 0, 0, 0x00, 0x01]);
+}
+
+#[test]
+fn banked_registers_and_direct_boot() {
+    let mut cpu = cpu_with_replacement_bios(&[]);
+    cpu.direct_boot(0x08000000);
+    assert_eq!(cpu.mode, Mode::System);
+    assert_eq!(cpu.gpr[Arm7Tdmi::PC], 0x08000000);
+    assert_eq!(cpu.banked_register(Mode::User, Arm7Tdmi::SP) as u32, 0x03007F00);
+    assert_eq!(cpu.banked_register(Mode::Supervisor, Arm7Tdmi::SP) as u32, 0x03007FE0);
+    assert_eq!(cpu.banked_register(Mode::IRQ, Arm7Tdmi::SP) as u32, 0x03007FA0);
+
+    cpu.set_banked_register(Mode::FIQ, 8, 42);
+    cpu.set_banked_register(Mode::IRQ, Arm7Tdmi::LR, 7);
+    assert_eq!(cpu.gpr[8], 0);
+    cpu.change_mode(Mode::FIQ);
+    assert_eq!(cpu.gpr[8], 42);
+    cpu.change_mode(Mode::IRQ);
+    assert_eq!((cpu.gpr[8], cpu.gpr[Arm7Tdmi::LR]), (0, 7));
+}
+
+#[test]
+fn public_register_api() {
+    let mut cpu = cpu_with_replacement_bios(&[]);
+    cpu.direct_boot(0x08000000);
+    assert_eq!(cpu.current_instruction_address(), 0x08000000);
+    cpu.set_gpr(13, 0x1234);
+
+    let mut psr = cpu.cpsr();
+    psr.set_mode(Mode::IRQ);
+    cpu.set_cpsr(psr);
+    assert_eq!(cpu.mode(), Mode::IRQ);
+    assert_eq!(cpu.gpr(Arm7Tdmi::SP) as u32, 0x03007FA0);
+    assert_eq!(cpu.banked_register(Mode::User, Arm7Tdmi::SP), 0x1234);
+
+    assert!(cpu.spsr(Mode::System).is_none());
+    cpu.set_spsr(Mode::Abort, PSR(0x1F));
+    assert_eq!(cpu.spsr(Mode::Abort).map(|p| p.0), Some(0x1F));
+}
+
+#[test]
+fn irqs_end_intr_wait() {
+    for &optimise in &[false, true] {
+        let mut cpu = cpu_with_replacement_bios(INTR_WAIT);
+        cpu.direct_boot(0x08000000);
+        cpu.set_swi_optimised(optimise);
+        for _ in 0..200 { cpu.pipeline_step().unwrap(); }
+        assert_eq!((cpu.gpr(6), cpu.gpr(7)), (0, 0));
+
+        cpu.memory_mut().request_interrupt(Interrupt::VBlank);
+        for _ in 0..200 { cpu.pipeline_step().unwrap(); }
+        assert_eq!((cpu.gpr(6), cpu.gpr(7)), (42, 1));
+        assert_eq!(cpu.mode(), Mode::System);
+        assert_eq!(cpu.halt_state(), HaltState::Running);
+        assert_eq!(Bus::load_halfword(cpu.memory_mut(), 0x03007FF8).unwrap(), 0);
+    }
+}
+
+#[test]
+fn instruction_address_follows_flushes() {
+    // b 0x10
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(&[0xEA000002]));
+    cpu.reset();
+    let mut addrs = vec![cpu.current_instruction_address()];
+    for _ in 0..6 {
+        cpu.pipeline_step().unwrap();
+        addrs.push(cpu.current_instruction_address());
+    }
+    assert_eq!(addrs, vec![0x00, 0x00, 0x00, 0x10, 0x10, 0x10, 0x14]);
+}
+
+#[test]
+fn snippet_on_flat_memory() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(LOAD_STORE_DIV));
+    cpu.reset();
+    cpu.set_swi_optimised(true);
+    for _ in 0..20 { cpu.pipeline_step().unwrap(); }
+
+    assert_eq!((cpu.gpr(0), cpu.gpr(1), cpu.gpr(2)), (14, 2, 5));
+    let mem = cpu.memory_mut();
+    assert_eq!(LittleEndian::read_u32(&mem.bytes[0x100..]), 5);
+    assert_eq!(LittleEndian::read_u32(&mem.bytes[0x104..]), 10);
+    assert_eq!(LittleEndian::read_u32(&mem.bytes[0x108..]), 5);
+    assert_eq!(mem.stores, vec![
+        (0x108, MemoryAccess::NonSequential),
+        (0x100, MemoryAccess::NonSequential),
+        (0x104, MemoryAccess::Sequential),
+    ]);
+}
+
+#[test]
+fn sub_carry_is_inverted_borrow() {
+    for &(a, b, c) in SUB_CARRIES {
+        // subs r2, r0, r1; b .
+        let mut cpu = Arm7Tdmi::new(FlatMemory::new(&[0xE0502001, 0xEAFFFFFE]));
+        cpu.reset();
+        cpu.set_gpr(0, a as i32);
+        cpu.set_gpr(1, b as i32);
+        for _ in 0..3 { cpu.pipeline_step().unwrap(); }
+        assert_eq!(cpu.gpr(2) as u32, a.wrapping_sub(b));
+        assert_eq!(cpu.cpsr().C(), c, "{:#X} - {:#X}", a, b);
+    }
+}
+
+#[test]
+fn exceptions_save_return_address_and_cpsr() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(UNDEFINED_INSTRUCTION));
+    cpu.reset();
+    for _ in 0..10 { cpu.pipeline_step().unwrap(); }
+    assert_eq!(cpu.mode(), Mode::Undefined);
+    assert_eq!(cpu.spsr(Mode::Undefined).map(|p| p.0), Some(0xDF));
+    assert_eq!(cpu.gpr(Arm7Tdmi::LR), 0x30);
+    assert_eq!(cpu.banked_register(Mode::System, Arm7Tdmi::LR), 0x55);
+}
+
+#[test]
+fn undefined_instructions_flush_the_pipeline() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(UNDEFINED_INSTRUCTION));
+    cpu.reset();
+    for _ in 0..30 { cpu.pipeline_step().unwrap(); }
+    assert_eq!((cpu.gpr(2), cpu.gpr(3)), (0xDF, 0x30));
+    assert_eq!((cpu.gpr(5), cpu.gpr(6)), (1, 1));
+}
+
+#[test]
+fn returns_restore_the_cpsr() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(UNDEFINED_INSTRUCTION));
+    cpu.reset();
+    for _ in 0..30 { cpu.pipeline_step().unwrap(); }
+    assert_eq!(cpu.mode(), Mode::System);
+    assert_eq!(cpu.cpsr().0, 0xDF);
+    assert_eq!(cpu.gpr(4), 0x55);
+
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(RETURN_TO_THUMB));
+    cpu.reset();
+    LittleEndian::write_u32(&mut cpu.memory_mut().bytes[0x100..], 0x08);
+    cpu.set_gpr(Arm7Tdmi::SP, 0x100);
+    cpu.set_spsr(Mode::Supervisor, PSR(0x3F));
+    for _ in 0..3 { cpu.pipeline_step().unwrap(); }
+    assert_eq!((cpu.mode(), cpu.state), (Mode::System, State::THUMB));
+    assert_eq!(cpu.cpsr().0, 0x3F);
+    assert_eq!(cpu.gpr(Arm7Tdmi::PC), 0x08);
+    assert_eq!(cpu.banked_register(Mode::Supervisor, Arm7Tdmi::SP), 0x104);
+}
+
+#[test]
+fn msr_switches_register_banks() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(MSR_MODE_SWITCHES));
+    cpu.reset();
+    for _ in 0..15 { cpu.pipeline_step().unwrap(); }
+    assert_eq!(cpu.mode(), Mode::IRQ);
+    assert_eq!((cpu.gpr(2), cpu.gpr(3)), (1, 2));
+    assert_eq!(cpu.spsr(Mode::IRQ).map(|p| p.0), Some(0xD3));
+}
+
+
+#[test]
+fn block_cache_matches_pipeline_on_self_modifying_code() {
+    let self_modifying_memory = || {
+        let mut mem = FlatMemory::new(SELF_MODIFYING);
+        for &(addr, w) in SELF_MODIFYING_DATA { LittleEndian::write_u32(&mut mem.bytes[addr..], w); }
+        mem
+    };
+    let mut cached = Arm7Tdmi::new(self_modifying_memory());
+    let mut interpreted = Arm7Tdmi::new(self_modifying_memory());
+    cached.reset();
+    interpreted.reset();
+    cached.set_block_cache_enabled(true);
+
+    let mut steps = 0;
+    while steps < 100 { steps += cached.step().unwrap(); }
+    for _ in 0..steps { interpreted.pipeline_step().unwrap(); }
+
+    assert!(!cached.block_cache().is_empty());
+    assert_eq!((cached.gpr(0), cached.gpr(2)), (42, 2));
+    assert_eq!(cached.gpr, interpreted.gpr);
+    assert_eq!(cached.cpsr().0, interpreted.cpsr().0);
+    assert_eq!(cached.memory().bytes, interpreted.memory().bytes);
+}
+
+
+#[test]
+fn block_cache_takes_irqs_after_io_stores() {
+    // Runs until the IRQ handler is entered.
+    let run = |use_blocks: bool| {
+        let mut cpu = cpu_with_replacement_bios(ENABLE_IRQ);
+        cpu.direct_boot(0x08000000);
+        cpu.set_block_cache_enabled(use_blocks);
+        cpu.memory_mut().request_interrupt(Interrupt::VBlank);
+        let mut steps = 0;
+        while cpu.mode() != Mode::IRQ && steps < 100 { steps += cpu.step().unwrap(); }
+        (steps, cpu.gpr(5), cpu.banked_register(Mode::IRQ, Arm7Tdmi::LR))
+    };
+    assert_eq!(run(true), run(false));
+    assert_eq!(run(true), (8, 0, 0x08000018));
+}
+
+
+#[test]
+fn idle_loops_are_detected_and_skipped() {
+    let mut mem = FlatMemory::new(COUNT_AND_POLL);
+    mem.next_event = Some(4096);
+    let mut cpu = Arm7Tdmi::new(mem);
+    cpu.reset();
+    cpu.set_idle_loop_detection_enabled(true);
+
+    let mut calls = 0;
+    let mut steps = 0;
+    while steps < 100000 { steps += cpu.step().unwrap(); calls += 1; }
+
+    assert_eq!(cpu.gpr(0), 100);
+    assert!(calls < 1000);
+    let loops = cpu.idle_loops();
+    assert_eq!(loops.len(), 1);
+    assert_eq!((loops[0].first, loops[0].branch), (0x14, 0x1C));
+    assert!(loops[0].skips > 0);
+}
+
+
+#[test]
+fn timer_polls_are_not_skipped() {
+    let mut cpu = cpu_with_replacement_bios(TIMER_POLL);
+    cpu.direct_boot(0x08000000);
+    cpu.set_idle_loop_detection_enabled(true);
+
+    let mut steps = 0;
+    while steps < 1000 {
+        let s = cpu.step().unwrap();
+        cpu.memory_mut().tick(s as u64);
+        steps += s;
+    }
+
+    assert_eq!(cpu.gpr(5), 1);
+    assert!(cpu.gpr(3) < 0x110);
+    let poll = cpu.idle_loops().iter().find(|l| l.first == 0x08000010).expect("Poll loop not detected!");
+    assert_eq!(poll.skips, 0);
+}
+
+#[test]
+fn unpredictable_instructions_follow_policy() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(UNPREDICTABLE_MUL));
+    cpu.reset();
+    assert_eq!(cpu.unpredictable_policy(), UnpredictablePolicy::Strict);
+    let err = (0..10).map(|_| cpu.pipeline_step()).find(|r| r.is_err());
+    assert_eq!(err, Some(Err(GbaError::InvalidRegisterReuse(0, 0, 1, 0))));
+    assert_eq!(cpu.current_instruction_address(), 0x08);
+
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(UNPREDICTABLE_MUL));
+    cpu.reset();
+    cpu.set_unpredictable_policy("silent".parse().unwrap());
+    for _ in 0..20 { cpu.pipeline_step().unwrap(); }
+    assert_eq!((cpu.gpr(0), cpu.gpr(5), cpu.gpr(6)), (15, 0, 7));
+}
+
+
+#[test]
+fn block_transfer_edge_cases() {
+    for &(inst, before, stored, after) in BLOCK_TRANSFERS {
+        let mut cpu = Arm7Tdmi::new(FlatMemory::new(&[inst, 0xEAFFFFFE]));
+        cpu.reset();
+        for &(r, x) in before { cpu.set_gpr(r, x); }
+        for _ in 0..3 { cpu.pipeline_step().unwrap(); }
+
+        for &(addr, w) in stored {
+            assert_eq!(LittleEndian::read_u32(&cpu.memory().bytes[addr..]), w, "{:#010X} at {:#X}", inst, addr);
+        }
+        for &(r, x) in after { assert_eq!(cpu.gpr(r), x, "{:#010X} R{}", inst, r); }
+    }
+}
+
+#[test]
+fn block_transfer_of_user_bank() {
+    // stmia r0, {r8-r14}^
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(&[0xE8C07F00, 0xEAFFFFFE]));
+    cpu.reset();
+    let mut psr = cpu.cpsr();
+    psr.set_mode(Mode::FIQ);
+    cpu.set_cpsr(psr);
+    for r in 8..15 { cpu.set_gpr(r, r as i32); cpu.set_banked_register(Mode::User, r, 100 + r as i32); }
+    cpu.set_gpr(0, 0x100);
+    for _ in 0..3 { cpu.pipeline_step().unwrap(); }
+
+    for r in 8..15 {
+        assert_eq!(LittleEndian::read_u32(&cpu.memory().bytes[0x100 + (r - 8) * 4..]), 100 + r as u32);
+        assert_eq!(cpu.gpr(r), r as i32);
+    }
+}
+
+
+#[test]
+fn misaligned_transfers() {
+    for &(inst, addr, before, after, word) in MISALIGNED_TRANSFERS {
+        let mut cpu = Arm7Tdmi::new(FlatMemory::new(&[inst, 0xEAFFFFFE, 0x44338211]));
+        cpu.reset();
+        cpu.set_gpr(0, before as i32);
+        cpu.set_gpr(1, addr as i32);
+        cpu.set_gpr(2, 0x12345678);
+        for _ in 0..3 { cpu.pipeline_step().unwrap(); }
+
+        assert_eq!(cpu.gpr(0) as u32, after, "{:#010X} at {:#X}", inst, addr);
+        assert_eq!(cpu.gpr(1) as u32, addr, "{:#010X} at {:#X}", inst, addr);
+        assert_eq!(LittleEndian::read_u32(&cpu.memory().bytes[8..]), word, "{:#010X} at {:#X}", inst, addr);
+    }
+}
+
+
+#[test]
+fn invalid_modes_use_user_bank() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(INVALID_MODE));
+    cpu.reset();
+    cpu.set_banked_register(Mode::User, Arm7Tdmi::SP, 0x100);
+    cpu.set_gpr(Arm7Tdmi::SP, 0x200);
+    cpu.set_gpr(0, 0xD4);
+    cpu.set_gpr(2, 0xD3);
+    for _ in 0..3 { cpu.pipeline_step().unwrap(); }
+    assert_eq!(cpu.mode(), Mode::Invalid(0b1_0100));
+    assert!(cpu.spsr(cpu.mode()).is_none());
+
+    for _ in 0..4 { cpu.pipeline_step().unwrap(); }
+    assert_eq!(cpu.mode(), Mode::Supervisor);
+    assert_eq!((cpu.gpr(1), cpu.gpr(3), cpu.gpr(4)), (0xD4, 0x100, 0x200));
+}
+
+#[test]
+fn user_mode_msr_only_sets_flags() {
+    let mut cpu = Arm7Tdmi::new(FlatMemory::new(USER_MODE_MSR));
+    cpu.reset();
+    let mut psr = cpu.cpsr();
+    psr.set_mode(Mode::User);
+    cpu.set_cpsr(psr);
+    cpu.set_gpr(0, 0xF00000D3_u32 as i32);
+    for _ in 0..5 { cpu.pipeline_step().unwrap(); }
+
+    assert_eq!(cpu.mode(), Mode::User);
+    assert_eq!(cpu.cpsr().0, 0xF00000D0);
+    assert_eq!(cpu.gpr(1) as u32, 0xF00000D0);
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
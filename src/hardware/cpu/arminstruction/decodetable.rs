@@ -0,0 +1,140 @@
+// License below.
+//! Implements table based decoding of ARM state instructions.
+//!
+//! Almost all ARM instructions can be told apart by looking
+//! at bits 27-20 and 7-4 alone. These 12 bits index a table of
+//! decoding handlers, such that decoding an instruction only
+//! takes a single indirect call instead of walking the whole
+//! mask chain of `ArmInstruction::decode`.
+//!
+//! The few table entries whose instructions also depend on
+//! other bits, e.g. `BX` or `MRS`, fall back to the chain.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::{ArmInstruction, ArmOpcode};
+use super::super::super::error::GbaError;
+
+/// Decodes a raw ARM instruction of a known table entry.
+pub type ArmDecodeFn = fn(u32) -> Result<ArmInstruction, GbaError>;
+
+/// Bits of an ARM instruction used as table index.
+const KEY_MASK: u32 = 0x0FF000F0;
+
+/// The opcode masks of `ArmInstruction::decode`, in the same order.
+const ARM_OPCODE_MASKS: &'static [(u32, u32, ArmDecodeFn)] = &[
+    (0x0FFFFFF0, 0x012FFF10, decode_bx),
+    (0x0E000000, 0x0A000000, decode_b_bl),
+    (0x0E000010, 0x06000010, decode_unknown),
+    (0x0FB00FF0, 0x01000090, decode_swp),
+    (0x0FC000F0, 0x00000090, decode_mul_mla),
+    (0x0F8000F0, 0x00800090, decode_mull_mlal),
+    (0x0E0000F0, 0x00000090, decode_unknown),
+    (0x0FBF0FFF, 0x010F0000, decode_mrs),
+    (0x0FBFFFF0, 0x0129F000, decode_msr_reg),
+    (0x0DBFF000, 0x0128F000, decode_msr_flags),
+    (0x0C000000, 0x04000000, decode_ldr_str),
+    (0x0E400F90, 0x00000090, decode_ldrh_strh_reg),
+    (0x0E400090, 0x00400090, decode_ldrh_strh_imm),
+    (0x0E000000, 0x08000000, decode_ldm_stm),
+    (0x0F000000, 0x0F000000, decode_swi),
+    (0x0F000010, 0x0E000000, decode_cdp),
+    (0x0F000010, 0x0E000010, decode_mrc_mcr),
+    (0x0E000000, 0x0C000000, decode_ldc_stc),
+    (0x0C000000, 0x00000000, decode_data_processing),
+];
+
+macro_rules! arm_decode_fns {
+    ($($name:ident => $op:ident),*) => {$(
+        fn $name(raw: u32) -> Result<ArmInstruction, GbaError> {
+            Ok(ArmInstruction { raw: raw, op: ArmOpcode::$op })
+        }
+    )*}
+}
+
+arm_decode_fns! {
+    decode_bx => BX, decode_b_bl => B_BL, decode_unknown => Unknown,
+    decode_swp => SWP, decode_mul_mla => MUL_MLA, decode_mull_mlal => MULL_MLAL,
+    decode_mrs => MRS, decode_msr_reg => MSR_Reg, decode_msr_flags => MSR_Flags,
+    decode_ldr_str => LDR_STR, decode_ldrh_strh_reg => LDRH_STRH_Reg,
+    decode_ldrh_strh_imm => LDRH_STRH_Imm, decode_ldm_stm => LDM_STM, decode_swi => SWI,
+    decode_cdp => CDP, decode_mrc_mcr => MRC_MCR, decode_ldc_stc => LDC_STC,
+    decode_data_processing => DataProcessing
+}
+
+fn decode_invalid(raw: u32) -> Result<ArmInstruction, GbaError> {
+    Err(GbaError::InvalidArmInstruction(raw))
+}
+
+/// A table mapping ARM instructions to their decoding handlers.
+pub struct ArmDecodeTable {
+    handlers: Vec<ArmDecodeFn>,
+}
+
+impl ArmDecodeTable {
+    /// Number of table entries.
+    pub const LEN: usize = 4096;
+
+    /// Builds the decoding table.
+    pub fn new() -> ArmDecodeTable {
+        ArmDecodeTable {
+            handlers: (0..ArmDecodeTable::LEN).map(ArmDecodeTable::handler_for_key).collect(),
+        }
+    }
+
+    /// Extracts bits 27-20 and 7-4 of an instruction as table index.
+    #[inline]
+    pub fn key(raw: u32) -> usize {
+        (((raw >> 16) & 0xFF0) | ((raw >> 4) & 0x00F)) as usize
+    }
+
+    /// Gets the decoding handler of a table entry.
+    pub fn handler(&self, key: usize) -> ArmDecodeFn { self.handlers[key] }
+
+    /// Decodes a raw 32-bit integer as an ARM instruction.
+    ///
+    /// The result is always the same as the one of
+    /// `ArmInstruction::decode`.
+    #[inline]
+    pub fn decode(&self, raw: u32) -> Result<ArmInstruction, GbaError> {
+        (self.handlers[ArmDecodeTable::key(raw)])(raw)
+    }
+
+    fn handler_for_key(key: usize) -> ArmDecodeFn {
+        let raw = (((key as u32) & 0xFF0) << 16) | (((key as u32) & 0x00F) << 4);
+        for &(mask, value, handler) in ARM_OPCODE_MASKS {
+            if (raw & mask & KEY_MASK) != (value & KEY_MASK) { continue; }
+            // Whether the instruction matches depends on bits
+            // outside the table index, so walk the chain.
+            if 0 != (mask & !KEY_MASK & 0x0FFFFFFF) { return ArmInstruction::decode; }
+            return handler;
+        }
+        decode_invalid
+    }
+}
+
+impl Default for ArmDecodeTable {
+    fn default() -> ArmDecodeTable { ArmDecodeTable::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
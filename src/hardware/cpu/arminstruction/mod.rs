@@ -25,6 +25,7 @@
 //!     COND 1110  yyyL CprN  RegD CPID  xxx1 CprM | MRC/MCR with CoCPU Op3 yyy and CP Info xxx
 //!     COND 110+  -NWL RegN  CprD CPID  imm_ imm_ | LDC/STC with unsigned Immediate
 //!     COND 011?  ???? ????  ???? ????  ???1 ???? | Unknown Instruction
+//!     COND 000?  ???? ????  ???? ????  1001 ???? | Unknown Instruction, unless MUL/MULL/SWP
 //!
 //! Bit Flags:
 //!     I: 1=shftIsRegister,  0=shftIsImmediate
@@ -83,8 +84,10 @@ use super::arm7tdmi::{Arm7Tdmi, ArmCondition};
 use super::arm7tdmi::exec::armdpop::ArmDPOP;
 
 pub use self::display::*;
+pub use self::decodetable::*;
 
 mod display;
+pub mod decodetable;
 
 #[cfg(test)]
 mod test;
@@ -168,6 +171,7 @@ impl ArmInstruction {
         else if (raw & 0x0FB00FF0) == 0x01000090 { ArmOpcode::SWP }
         else if (raw & 0x0FC000F0) == 0x00000090 { ArmOpcode::MUL_MLA }
         else if (raw & 0x0F8000F0) == 0x00800090 { ArmOpcode::MULL_MLAL }
+        else if (raw & 0x0E0000F0) == 0x00000090 { ArmOpcode::Unknown } // Rest of the multiply space.
         else if (raw & 0x0FBF0FFF) == 0x010F0000 { ArmOpcode::MRS } // Order matters here, as...
         else if (raw & 0x0FBFFFF0) == 0x0129F000 { ArmOpcode::MSR_Reg } // ... these here are subsets...
         else if (raw & 0x0DBFF000) == 0x0128F000 { ArmOpcode::MSR_Flags } // ... of DataProcessing.
@@ -260,6 +264,11 @@ impl ArmInstruction {
         self.op
     }
 
+    /// Get the raw 32-bit integer this instruction was decoded from.
+    pub fn raw(&self) -> u32 {
+        self.raw
+    }
+
     /// Get the data processing opcode field of the ARM instruction.
     pub fn dpop(&self) -> ArmDPOP {
         let o = ((self.raw >> 21) & 0b1111) as u8;
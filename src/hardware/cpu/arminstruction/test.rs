@@ -1,7 +1,9 @@
 // License below.
 #![allow(missing_docs)]
 
-use super::ArmInstruction;
+use super::{ArmInstruction, ArmDecodeTable, ArmOpcode};
+use super::super::assembler::Assembler;
+use super::super::disassembler::normalized_assembly;
 use super::super::super::error::GbaError;
 use test::*;
 
@@ -189,6 +191,74 @@ pub fn instruction_decoding_and_validity_check_speed(b: &mut Bencher) {
     });
 }
 
+#[test]
+fn decode_table_agrees_with_mask_chain() {
+    // Neither decoder looks at the condition, and the mask chain
+    // only compares whole nibbles outside the table index against
+    // 0x0, 0x8, 0x9, or 0xF. Trying these and any other value
+    // for each such nibble covers every possible instruction.
+    const NIBBLES: &'static [u32] = &[0x0, 0x5, 0x8, 0x9, 0xF];
+    let table = ArmDecodeTable::new();
+    for key in 0..(ArmDecodeTable::LEN as u32) {
+        let base = 0xE0000000 | ((key & 0xFF0) << 16) | ((key & 0x00F) << 4);
+        for &a in NIBBLES { for &b in NIBBLES { for &c in NIBBLES { for &d in NIBBLES {
+            let raw = base | (a << 16) | (b << 12) | (c << 8) | d;
+            assert_eq!(table.decode(raw), ArmInstruction::decode(raw), "{:#010X}", raw);
+        }}}}
+    }
+}
+
+// Instructions whose disassembly keeps every bit of them.
+const EXACT_OPCODES: &'static [ArmOpcode] = &[
+    ArmOpcode::BX, ArmOpcode::B_BL, ArmOpcode::MULL_MLAL, ArmOpcode::MRS,
+    ArmOpcode::MSR_Reg, ArmOpcode::MSR_Flags, ArmOpcode::LDM_STM, ArmOpcode::SWP,
+    ArmOpcode::SWI, ArmOpcode::CDP, ArmOpcode::MRC_MCR,
+];
+
+#[test]
+fn disassembly_reassembles() {
+    // Far too many instructions to try them all, so fill each
+    // table index with pseudo-random bits a couple of times.
+    // Disassembly drops some bits, e.g. `Rn` of `mov`, so
+    // unless it keeps them all make sure both encodings
+    // disassemble the same way.
+    const ADDR: u32 = 0x0800_1000;
+    const SAMPLES: u32 = 16;
+    let table = ArmDecodeTable::new();
+    let mut seed = 0x2545_F491_u32;
+    for key in 0..(ArmDecodeTable::LEN as u32) {
+        for _ in 0..SAMPLES {
+            // Xorshift, see Marsaglia's "Xorshift RNGs".
+            seed ^= seed << 13; seed ^= seed >> 17; seed ^= seed <<  5;
+            let raw = (seed & 0xF00F_FF0F) | ((key & 0xFF0) << 16) | ((key & 0x00F) << 4);
+            let inst = table.decode(raw);
+            assert_eq!(inst, ArmInstruction::decode(raw), "{:#010X}", raw);
+
+            let inst = match inst { Ok(i) => i, Err(_) => continue };
+            if inst.opcode() == ArmOpcode::Unknown { continue; }
+            let text = normalized_assembly(&inst);
+            let asm = match Assembler::new(ADDR).assemble(&text) {
+                Ok(a) => a,
+                Err(e) => panic!("{:#010X} `{}`: {}", raw, text, e),
+            };
+            if EXACT_OPCODES.contains(&inst.opcode()) {
+                assert_eq!(asm.words()[0], raw, "`{}`", text);
+                continue;
+            }
+            let again = ArmInstruction::decode(asm.words()[0]).unwrap();
+            assert_eq!(normalized_assembly(&again), text, "{:#010X}", raw);
+        }
+    }
+}
+
+#[bench]
+pub fn table_decoding_speed(b: &mut Bencher) {
+    let table = ArmDecodeTable::new();
+    b.iter(|| for inst in self::INSTRUCTIONS {
+        table.decode(inst.0).unwrap_or(ArmInstruction::nop());
+    });
+}
+
 
 /*
 Licensed to the Apache Software Foundation (ASF) under one
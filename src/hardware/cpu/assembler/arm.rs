@@ -0,0 +1,415 @@
+// License below.
+//! Implements encoding ARM state instructions.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::operands::*;
+use super::super::arm7tdmi::ArmCondition;
+
+const PC: usize = 15;
+
+const DPOP_NAMES: &'static [&'static str] = &[
+    "and", "eor", "sub", "rsb", "add", "adc", "sbc", "rsc",
+    "tst", "teq", "cmp", "cmn", "orr", "mov", "bic", "mvn",
+];
+
+// An ARM instruction mnemonic without condition.
+#[derive(Debug, Clone, Copy)]
+enum Mnemonic {
+    DataProcessing(u32, bool),
+    Multiply(bool, bool),
+    MultiplyLong(bool, bool, bool),
+    Branch(bool),
+    BranchExchange,
+    SoftwareInterrupt,
+    Mrs,
+    Msr,
+    Swap(bool),
+    CoDataProcessing,
+    CoRegisterTransfer(bool),
+    CoDataTransfer(bool, bool),
+    BlockTransfer(bool, bool, bool),
+    Transfer(bool, bool, bool),
+    HalfwordTransfer(bool, u32),
+}
+
+/// Encodes an ARM state instruction.
+///
+/// # Params
+/// - `mnemonic`: The mnemonic including condition, e.g. `addeq`.
+/// - `ops`: The instruction's operands.
+/// - `ctx`: The instruction's address and all known symbols.
+pub fn assemble(mnemonic: &str, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    // Mnemonics like `teq` or `smlals` would be misread with
+    // the last two letters as condition, so try without first.
+    let m = mnemonic.to_lowercase();
+    let split = m.len().saturating_sub(2);
+    let (mn, cond) = match parse_mnemonic(&m) {
+        Some(mn) => (mn, ArmCondition::AL),
+        None if m.is_char_boundary(split) => {
+            match (parse_mnemonic(&m[..split]), ArmCondition::from_assembly_name(&m[split..])) {
+                (Some(mn), Some(cond)) => (mn, cond),
+                _ => return Err(format!("Unknown ARM instruction `{}`.", mnemonic)),
+            }
+        },
+        None => return Err(format!("Unknown ARM instruction `{}`.", mnemonic)),
+    };
+
+    let raw = match mn {
+        Mnemonic::DataProcessing(op, s)       => try!(data_processing(op, s, ops, ctx)),
+        Mnemonic::Multiply(acc, s)            => try!(multiply(acc, s, ops)),
+        Mnemonic::MultiplyLong(sign, acc, s)  => try!(multiply_long(sign, acc, s, ops)),
+        Mnemonic::Branch(link)                => try!(branch(link, ops, ctx)),
+        Mnemonic::BranchExchange              => 0x012FFF10 | try!(ops.register()) as u32,
+        Mnemonic::SoftwareInterrupt           => { ops.eat('#'); try!(ctx.field(ops.expression(), 0xFFFFFF, 1)) | 0x0F000000 },
+        Mnemonic::Mrs                         => try!(mrs(ops)),
+        Mnemonic::Msr                         => try!(msr(ops, ctx)),
+        Mnemonic::Swap(byte)                  => try!(swap(byte, ops)),
+        Mnemonic::CoDataProcessing            => try!(co_data_processing(ops, ctx)),
+        Mnemonic::CoRegisterTransfer(load)    => try!(co_register_transfer(load, ops, ctx)),
+        Mnemonic::CoDataTransfer(load, long)  => try!(co_data_transfer(load, long, ops, ctx)),
+        Mnemonic::BlockTransfer(load, pre, up) => try!(block_transfer(load, pre, up, ops)),
+        Mnemonic::Transfer(load, byte, t)     => try!(transfer(load, byte, t, ops, ctx)),
+        Mnemonic::HalfwordTransfer(load, sh)  => try!(halfword_transfer(load, sh, ops, ctx)),
+    };
+    Ok(raw | ((cond as u32) << 28))
+}
+
+fn parse_mnemonic(m: &str) -> Option<Mnemonic> {
+    let mn = match m {
+        "b"     => Mnemonic::Branch(false),
+        "bl"    => Mnemonic::Branch(true),
+        "bx"    => Mnemonic::BranchExchange,
+        "swi"   => Mnemonic::SoftwareInterrupt,
+        "mrs"   => Mnemonic::Mrs,
+        "msr"   => Mnemonic::Msr,
+        "swp"   => Mnemonic::Swap(false),
+        "swpb"  => Mnemonic::Swap(true),
+        "cdp"   => Mnemonic::CoDataProcessing,
+        "mrc"   => Mnemonic::CoRegisterTransfer(true),
+        "mcr"   => Mnemonic::CoRegisterTransfer(false),
+        "ldc"   => Mnemonic::CoDataTransfer(true,  false),
+        "ldcl"  => Mnemonic::CoDataTransfer(true,  true),
+        "stc"   => Mnemonic::CoDataTransfer(false, false),
+        "stcl"  => Mnemonic::CoDataTransfer(false, true),
+        "ldr"   => Mnemonic::Transfer(true,  false, false),
+        "ldrb"  => Mnemonic::Transfer(true,  true,  false),
+        "ldrt"  => Mnemonic::Transfer(true,  false, true),
+        "ldrbt" => Mnemonic::Transfer(true,  true,  true),
+        "str"   => Mnemonic::Transfer(false, false, false),
+        "strb"  => Mnemonic::Transfer(false, true,  false),
+        "strt"  => Mnemonic::Transfer(false, false, true),
+        "strbt" => Mnemonic::Transfer(false, true,  true),
+        "ldrh"  => Mnemonic::HalfwordTransfer(true,  0b01),
+        "ldrsb" => Mnemonic::HalfwordTransfer(true,  0b10),
+        "ldrsh" => Mnemonic::HalfwordTransfer(true,  0b11),
+        "strh"  => Mnemonic::HalfwordTransfer(false, 0b01),
+        "strsb" => Mnemonic::HalfwordTransfer(false, 0b10),
+        "strsh" => Mnemonic::HalfwordTransfer(false, 0b11),
+        _ if m.starts_with("ldm") || m.starts_with("stm") => {
+            let load = m.starts_with("ldm");
+            // Stack modes depend on the direction of the transfer.
+            let (pre, up) = match (&m[3..], load) {
+                ("", _) | ("ia", _) | ("fd", true)  | ("ea", false) => (false, true),
+                ("ib", _)           | ("ed", true)  | ("fa", false) => (true,  true),
+                ("da", _)           | ("fa", true)  | ("ed", false) => (false, false),
+                ("db", _)           | ("ea", true)  | ("fd", false) => (true,  false),
+                _ => return None,
+            };
+            Mnemonic::BlockTransfer(load, pre, up)
+        },
+        _ => {
+            let (base, s) = if (m.len() > 3) && m.ends_with('s') { (&m[..(m.len() - 1)], true) } else { (m, false) };
+            match base {
+                "mul"   => Mnemonic::Multiply(false, s),
+                "mla"   => Mnemonic::Multiply(true,  s),
+                "umull" => Mnemonic::MultiplyLong(false, false, s),
+                "umlal" => Mnemonic::MultiplyLong(false, true,  s),
+                "smull" => Mnemonic::MultiplyLong(true,  false, s),
+                "smlal" => Mnemonic::MultiplyLong(true,  true,  s),
+                _ => match DPOP_NAMES.iter().position(|&n| n == base) {
+                    Some(op) => Mnemonic::DataProcessing(op as u32, s),
+                    None => return None,
+                },
+            }
+        },
+    };
+    Some(mn)
+}
+
+/// Encodes a rotated 8-bit immediate with the smallest rotation.
+pub fn rotated_immediate(value: u32) -> Option<u32> {
+    (0..16).map(|r| (r, value.rotate_left(2 * r))).find(|&(_, x)| x <= 0xFF).map(|(r, x)| (r << 8) | x)
+}
+
+fn immediate_operand(ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let value = try!(ctx.eval(try!(ops.immediate())));
+    if (value < -0x8000_0000) || (value > 0xFFFF_FFFF) { return Err(format!("Immediate {} is out of range.", value)); }
+    rotated_immediate(value as u32).ok_or(format!("Immediate {:#010X} can't be encoded as rotated byte.", value as u32))
+}
+
+// Parses `Rm` with an optional shift like `, lsl #7`
+// or `, asr R4`, returning the lower 12 encoding bits.
+fn shifted_register(ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let rm = try!(ops.register()) as u32;
+    if !ops.eat(',') { return Ok(rm); }
+    let shift = try!(ops.word()).to_lowercase();
+    let (op, max) = match shift.as_str() {
+        "rrx" => return Ok(0x60 | rm),
+        "lsl" | "asl" => (0, 31),
+        "lsr" => (1, 32),
+        "asr" => (2, 32),
+        "ror" => (3, 31),
+        _ => return Err(format!("Unknown shift `{}`.", shift)),
+    };
+    if let Some(rs) = ops.try_register() { return Ok(((rs as u32) << 8) | (op << 5) | 0x10 | rm); }
+    // Shifting right by 32 is encoded as shifting by 0.
+    let x = try!(ctx.field(try!(ops.immediate()), max, 1));
+    Ok(((x & 0x1F) << 7) | (op << 5) | rm)
+}
+
+fn data_processing(op: u32, s: bool, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    // Test instructions always set flags and have no
+    // destination, whereas moves have no first operand.
+    let is_test = (op & 0b1100) == 0b1000;
+    let is_move = (op & 0b1101) == 0b1101;
+    let mut raw = (op << 21) | if s || is_test { 1 << 20 } else { 0 };
+    if !is_test { raw |= (try!(ops.register()) as u32) << 12; try!(ops.expect(',')); }
+    if !is_move { raw |= (try!(ops.register()) as u32) << 16; try!(ops.expect(',')); }
+    if ops.peek() == Some('#') { Ok(raw | (1 << 25) | try!(immediate_operand(ops, ctx))) }
+    else { Ok(raw | try!(shifted_register(ops, ctx))) }
+}
+
+fn multiply(acc: bool, s: bool, ops: &mut Operands) -> Result<u32, String> {
+    let rd = try!(ops.register()) as u32; try!(ops.expect(','));
+    let rm = try!(ops.register()) as u32; try!(ops.expect(','));
+    let rs = try!(ops.register()) as u32;
+    let rn = if acc { try!(ops.expect(',')); try!(ops.register()) as u32 } else { 0 };
+    Ok(0x00000090 | ((acc as u32) << 21) | ((s as u32) << 20) | (rd << 16) | (rn << 12) | (rs << 8) | rm)
+}
+
+fn multiply_long(signed: bool, acc: bool, s: bool, ops: &mut Operands) -> Result<u32, String> {
+    let lo = try!(ops.register()) as u32; try!(ops.expect(','));
+    let hi = try!(ops.register()) as u32; try!(ops.expect(','));
+    let rm = try!(ops.register()) as u32; try!(ops.expect(','));
+    let rs = try!(ops.register()) as u32;
+    Ok(0x00800090 | ((signed as u32) << 22) | ((acc as u32) << 21) | ((s as u32) << 20)
+        | (hi << 16) | (lo << 12) | (rs << 8) | rm)
+}
+
+fn branch(link: bool, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let offs = try!(ctx.branch_target(ops)) - (ctx.addr as i64 + 8);
+    if (offs % 4 != 0) || (offs < -0x0200_0000) || (offs >= 0x0200_0000) {
+        return Err(format!("Branch offset {} is out of range.", offs));
+    }
+    Ok(0x0A000000 | ((link as u32) << 24) | (((offs >> 2) as u32) & 0x00FFFFFF))
+}
+
+fn psr(ops: &mut Operands) -> Result<(bool, bool), String> {
+    let name = try!(ops.word()).to_lowercase();
+    let (base, field) = if name.is_char_boundary(4) { name.split_at(4) } else { (&name[..], "") };
+    let spsr = match base {
+        "cpsr" => false,
+        "spsr" => true,
+        _ => return Err(format!("Expected CPSR or SPSR, found `{}`.", name)),
+    };
+    let flags = match field {
+        "" | "_all" | "_fc" | "_cf" => false,
+        "_flg" | "_f" => true,
+        field => return Err(format!("Unknown PSR field `{}`.", field)),
+    };
+    Ok((spsr, flags))
+}
+
+fn mrs(ops: &mut Operands) -> Result<u32, String> {
+    let rd = try!(ops.register()) as u32;
+    try!(ops.expect(','));
+    let (spsr, _) = try!(psr(ops));
+    Ok(0x010F0000 | ((spsr as u32) << 22) | (rd << 12))
+}
+
+fn msr(ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let (spsr, flags) = try!(psr(ops));
+    try!(ops.expect(','));
+    let raw = ((spsr as u32) << 22) | if flags { 0x0128F000 } else { 0x0129F000 };
+    if ops.peek() != Some('#') { return Ok(raw | try!(ops.register()) as u32); }
+    if !flags { return Err("Only the flags can be set to an immediate.".to_string()); }
+    Ok(raw | (1 << 25) | try!(immediate_operand(ops, ctx)))
+}
+
+fn swap(byte: bool, ops: &mut Operands) -> Result<u32, String> {
+    let rd = try!(ops.register()) as u32; try!(ops.expect(','));
+    let rm = try!(ops.register()) as u32; try!(ops.expect(','));
+    try!(ops.expect('['));
+    let rn = try!(ops.register()) as u32;
+    try!(ops.expect(']'));
+    Ok(0x01000090 | ((byte as u32) << 22) | (rn << 16) | (rd << 12) | rm)
+}
+
+// Parses a numbered name like `P7` or `CR3`.
+fn numbered(ops: &mut Operands, prefixes: &[&str]) -> Result<u32, String> {
+    let word = try!(ops.word()).to_lowercase();
+    for p in prefixes {
+        if !word.starts_with(p) { continue; }
+        if let Ok(x) = u32::from_str_radix(&word[p.len()..], 10) { if x < 16 { return Ok(x); } }
+    }
+    Err(format!("Expected {}0 to {}15, found `{}`.", prefixes[0].to_uppercase(), prefixes[0].to_uppercase(), word))
+}
+
+fn coprocessor(ops: &mut Operands) -> Result<u32, String> { numbered(ops, &["p"]) }
+
+fn coprocessor_register(ops: &mut Operands) -> Result<u32, String> { numbered(ops, &["cr", "c"]) }
+
+fn co_data_processing(ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let cp  = try!(coprocessor(ops)); try!(ops.expect(','));
+    let op  = try!(ctx.field(ops.expression(), 15, 1)); try!(ops.expect(','));
+    let crd = try!(coprocessor_register(ops)); try!(ops.expect(','));
+    let crn = try!(coprocessor_register(ops)); try!(ops.expect(','));
+    let crm = try!(coprocessor_register(ops));
+    let info = if ops.eat(',') { try!(ctx.field(ops.expression(), 7, 1)) } else { 0 };
+    Ok(0x0E000000 | (op << 20) | (crn << 16) | (crd << 12) | (cp << 8) | (info << 5) | crm)
+}
+
+fn co_register_transfer(load: bool, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let cp  = try!(coprocessor(ops)); try!(ops.expect(','));
+    let op  = try!(ctx.field(ops.expression(), 7, 1)); try!(ops.expect(','));
+    let rd  = try!(ops.register()) as u32; try!(ops.expect(','));
+    let crn = try!(coprocessor_register(ops)); try!(ops.expect(','));
+    let crm = try!(coprocessor_register(ops));
+    let info = if ops.eat(',') { try!(ctx.field(ops.expression(), 7, 1)) } else { 0 };
+    Ok(0x0E000010 | (op << 21) | ((load as u32) << 20) | (crn << 16) | (rd << 12) | (cp << 8) | (info << 5) | crm)
+}
+
+fn co_data_transfer(load: bool, long: bool, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let cp  = try!(coprocessor(ops)); try!(ops.expect(','));
+    let crd = try!(coprocessor_register(ops)); try!(ops.expect(','));
+    let addr = try!(address(ops, ctx));
+    let offs = match addr.offset {
+        Offset::Immediate(x) if x <= 0xFF => x,
+        Offset::Immediate(x) => return Err(format!("Offset {} is out of range.", x)),
+        Offset::Register(_) => return Err("Coprocessor transfers only take immediate offsets.".to_string()),
+    };
+    Ok(0x0C000000 | addr.bits() | ((long as u32) << 22) | ((load as u32) << 20) | (crd << 12) | (cp << 8) | offs)
+}
+
+fn block_transfer(load: bool, pre: bool, up: bool, ops: &mut Operands) -> Result<u32, String> {
+    let rn = try!(ops.register()) as u32;
+    let writeback = ops.eat('!');
+    try!(ops.expect(','));
+    let list = try!(ops.register_list()) as u32;
+    let user = ops.eat('^');
+    Ok(0x08000000 | ((pre as u32) << 24) | ((up as u32) << 23) | ((user as u32) << 22)
+        | ((writeback as u32) << 21) | ((load as u32) << 20) | (rn << 16) | list)
+}
+
+fn transfer(load: bool, byte: bool, t: bool, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let rd = try!(ops.register()) as u32;
+    try!(ops.expect(','));
+    let mut addr = try!(address(ops, ctx));
+    if t {
+        if addr.pre { return Err("Forcing user mode requires post-indexing.".to_string()); }
+        addr.writeback = true;
+    }
+    let offs = match addr.offset {
+        Offset::Immediate(x) if x <= 0xFFF => x,
+        Offset::Immediate(x) => return Err(format!("Offset {} is out of range.", x)),
+        Offset::Register(x) if (x & 0x10) == 0 => (1 << 25) | x,
+        Offset::Register(_) => return Err("Offsets can't be shifted by a register.".to_string()),
+    };
+    Ok(0x04000000 | addr.bits() | ((byte as u32) << 22) | ((load as u32) << 20) | (rd << 12) | offs)
+}
+
+fn halfword_transfer(load: bool, sh: u32, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let rd = try!(ops.register()) as u32;
+    try!(ops.expect(','));
+    let addr = try!(address(ops, ctx));
+    let offs = match addr.offset {
+        Offset::Immediate(x) if x <= 0xFF => (1 << 22) | ((x & 0xF0) << 4) | (x & 0x0F),
+        Offset::Immediate(x) => return Err(format!("Offset {} is out of range.", x)),
+        Offset::Register(x) if x <= 0xF => x,
+        Offset::Register(_) => return Err("Halfword offsets can't be shifted.".to_string()),
+    };
+    Ok(0x00000090 | addr.bits() | ((load as u32) << 20) | (rd << 12) | (sh << 5) | offs)
+}
+
+// The offset of a load/store address.
+enum Offset {
+    Immediate(u32),
+    Register(u32),
+}
+
+// A load/store address like `[Rn, #+4]!` or `[Rn], -Rm`.
+struct Address {
+    rn: u32,
+    pre: bool,
+    up: bool,
+    writeback: bool,
+    offset: Offset,
+}
+
+impl Address {
+    // Encodes the P, U, W, and Rn fields.
+    fn bits(&self) -> u32 {
+        ((self.pre as u32) << 24) | ((self.up as u32) << 23) | ((self.writeback as u32) << 21) | (self.rn << 16)
+    }
+}
+
+// Parses an address, a label, or `=value` for loading a literal.
+// The latter two are turned into PC-relative addresses.
+fn address(ops: &mut Operands, ctx: &Context) -> Result<Address, String> {
+    if ops.eat('=') || (ops.peek() != Some('[')) {
+        let expr = ops.expression();
+        let target = match ctx.literal { Some(x) => x as i64, None => try!(ctx.eval(expr)) };
+        let offs = target - (ctx.addr as i64 + 8);
+        return Ok(Address { rn: PC as u32, pre: true, up: offs >= 0, writeback: false, offset: Offset::Immediate(offs.abs() as u32) });
+    }
+
+    try!(ops.expect('['));
+    let rn = try!(ops.register()) as u32;
+    if ops.eat(']') {
+        if !ops.eat(',') {
+            return Ok(Address { rn: rn, pre: true, up: true, writeback: false, offset: Offset::Immediate(0) });
+        }
+        let (up, offset) = try!(offset(ops, ctx));
+        return Ok(Address { rn: rn, pre: false, up: up, writeback: false, offset: offset });
+    }
+    try!(ops.expect(','));
+    let (up, offset) = try!(offset(ops, ctx));
+    try!(ops.expect(']'));
+    let writeback = ops.eat('!');
+    Ok(Address { rn: rn, pre: true, up: up, writeback: writeback, offset: offset })
+}
+
+fn offset(ops: &mut Operands, ctx: &Context) -> Result<(bool, Offset), String> {
+    if ops.eat('#') {
+        // Keep the sign of `#-0`.
+        let expr = ops.expression();
+        let value = try!(ctx.eval(expr));
+        return Ok(((value >= 0) && !expr.starts_with('-'), Offset::Immediate(value.abs() as u32)));
+    }
+    let up = !ops.eat('-');
+    if up { ops.eat('+'); }
+    Ok((up, Offset::Register(try!(shifted_register(ops, ctx)))))
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,348 @@
+// License below.
+//! Implements a two-pass assembler for ARM and THUMB state code.
+//!
+//! The assembler understands the syntax the disassemblers of
+//! `ArmInstruction` and `ThumbInstruction` produce, such as
+//! `addeq\tR2, R1, R3, lsl R4`, in any case. On top of that,
+//! it supports:
+//!
+//! - Labels like `loop:`, which branches and loads may refer to.
+//! - `.arm` and `.thumb` to switch the instruction set, where
+//!   `.arm` also aligns to a word boundary.
+//! - `.word`, `.hword`, and `.byte` with comma-separated values.
+//! - `.align N` to pad to a multiple of `2^N` bytes, where `N`
+//!   defaults to 2.
+//! - `.fill N, SIZE, VALUE` to repeat a value of 1, 2, or 4
+//!   bytes `N` times, where `SIZE` defaults to 1 and `VALUE`
+//!   to 0. All of these must be known in advance.
+//! - `ldr Rd, =value` to load a constant from a literal pool,
+//!   which is placed at the next `.pool` or at the end.
+//!
+//! `.syntax unified` is accepted for compatibility with GNU
+//! `as`, but does not change anything.
+//!
+//! Comments start with `@` or `//`, and `;` separates multiple
+//! statements on a single line. Immediate branch targets like
+//! `#-4` are relative to the branch, just like in disassembly,
+//! whereas plain numbers and labels are absolute addresses.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use super::arm7tdmi::State;
+use self::operands::*;
+
+mod operands;
+mod arm;
+mod thumb;
+
+#[cfg(test)]
+mod test;
+
+/// The output of an assembler run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    origin: u32,
+    bytes: Vec<u8>,
+    symbols: HashMap<String, u32>,
+}
+
+impl Assembly {
+    /// Gets the address of the first assembled byte.
+    pub fn origin(&self) -> u32 { self.origin }
+
+    /// Gets all assembled bytes.
+    pub fn bytes(&self) -> &[u8] { &self.bytes[..] }
+
+    /// Gets the number of assembled bytes.
+    pub fn len(&self) -> usize { self.bytes.len() }
+
+    /// Checks whether nothing has been assembled.
+    pub fn is_empty(&self) -> bool { self.bytes.is_empty() }
+
+    /// Gets the address of a symbol, such as a label.
+    pub fn symbol(&self, name: &str) -> Option<u32> { self.symbols.get(name).cloned() }
+
+    /// Gets the assembled bytes as little endian words.
+    ///
+    /// A trailing incomplete word is padded with zeros.
+    pub fn words(&self) -> Vec<u32> {
+        self.bytes.chunks(4).map(|c| c.iter().rev().fold(0, |w, &b| (w << 8) | (b as u32))).collect()
+    }
+}
+
+/// An error caused by invalid assembly source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblyError {
+    line: usize,
+    message: String,
+}
+
+impl AssemblyError {
+    /// Gets the number of the invalid line, starting at 1.
+    pub fn line(&self) -> usize { self.line }
+
+    /// Gets a description of what is wrong with the line.
+    pub fn message(&self) -> &str { &self.message[..] }
+}
+
+impl error::Error for AssemblyError {
+    fn description(&self) -> &str { "Invalid assembly source code." }
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid assembly in line {}: {}", self.line, self.message)
+    }
+}
+
+/// A two-pass assembler for ARM and THUMB state code.
+///
+/// The first pass assigns addresses to all labels, the second
+/// one encodes everything.
+#[derive(Debug, Clone)]
+pub struct Assembler {
+    origin: u32,
+    state: State,
+    symbols: HashMap<String, u32>,
+}
+
+impl Assembler {
+    /// Creates an assembler for ARM state code at the given address.
+    pub fn new(origin: u32) -> Assembler {
+        Assembler { origin: origin, state: State::ARM, symbols: HashMap::new() }
+    }
+
+    /// Configure the instruction set to start with.
+    pub fn with_state(&mut self, state: State) -> &mut Assembler { self.state = state; self }
+
+    /// Define a symbol the source code may refer to.
+    pub fn with_symbol(&mut self, name: &str, addr: u32) -> &mut Assembler {
+        self.symbols.insert(name.to_string(), addr);
+        self
+    }
+
+    /// Assembles the given source code.
+    ///
+    /// # Returns
+    /// - `Ok`: The assembled code.
+    /// - `Err`: The first invalid line and what is wrong with it.
+    pub fn assemble(&self, source: &str) -> Result<Assembly, AssemblyError> {
+        let mut layout = Layout {
+            addr: self.origin,
+            state: self.state,
+            items: Vec::new(),
+            symbols: self.symbols.clone(),
+            literals: Vec::new(),
+            pending: Vec::new(),
+        };
+
+        // First pass.
+        let mut line = 0;
+        for (i, text) in source.lines().enumerate() {
+            line = i + 1;
+            let end = text.find('@').unwrap_or(text.len());
+            let end = text[..end].find("//").unwrap_or(end);
+            for statement in text[..end].split(';') {
+                try!(layout.add(line, statement).map_err(|e| AssemblyError { line: line, message: e }));
+            }
+        }
+        try!(layout.flush_pool(line).map_err(|e| AssemblyError { line: line, message: e }));
+
+        // Second pass.
+        let mut bytes = Vec::with_capacity(layout.addr.wrapping_sub(self.origin) as usize);
+        for item in &layout.items {
+            try!(layout.encode(item, &mut bytes).map_err(|e| AssemblyError { line: item.line, message: e }));
+        }
+        Ok(Assembly { origin: self.origin, bytes: bytes, symbols: layout.symbols })
+    }
+}
+
+// Anything taking up space in the output.
+enum ItemKind<'a> {
+    // Mnemonic, operands, and the index of a literal.
+    Instruction(State, &'a str, &'a str, Option<usize>),
+    // Width in bytes and comma-separated values.
+    Data(usize, &'a str),
+    // Count, width in bytes, and value.
+    Fill(u32, usize, u32),
+    Padding(u32),
+}
+
+struct Item<'a> {
+    line: usize,
+    addr: u32,
+    kind: ItemKind<'a>,
+}
+
+// The state of the first pass.
+struct Layout<'a> {
+    addr: u32,
+    state: State,
+    items: Vec<Item<'a>>,
+    symbols: HashMap<String, u32>,
+    // Expressions and addresses of all literals.
+    literals: Vec<(&'a str, u32)>,
+    // Literals not yet placed in a pool.
+    pending: Vec<usize>,
+}
+
+fn is_symbol_name(name: &str) -> bool {
+    (name != ".") && name.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '.')
+    && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+}
+
+impl<'a> Layout<'a> {
+    fn push(&mut self, line: usize, size: u32, kind: ItemKind<'a>) {
+        self.items.push(Item { line: line, addr: self.addr, kind: kind });
+        self.addr = self.addr.wrapping_add(size);
+    }
+
+    fn align(&mut self, line: usize, alignment: u32) {
+        let padding = self.addr.wrapping_neg() & (alignment - 1);
+        if padding > 0 { self.push(line, padding, ItemKind::Padding(padding)); }
+    }
+
+    fn add(&mut self, line: usize, statement: &'a str) -> Result<(), String> {
+        let mut statement = statement.trim();
+        while let Some(i) = statement.find(':') {
+            let name = &statement[..i];
+            if !is_symbol_name(name) { break; }
+            if self.symbols.contains_key(name) { return Err(format!("Symbol `{}` is already defined.", name)); }
+            self.symbols.insert(name.to_string(), self.addr);
+            statement = statement[(i + 1)..].trim_left();
+        }
+        if statement.is_empty() { return Ok(()); }
+
+        let end = statement.find(char::is_whitespace).unwrap_or(statement.len());
+        let (name, operands) = (&statement[..end], statement[end..].trim());
+        if name.starts_with('.') { return self.add_directive(line, name, operands); }
+
+        let (size, alignment) = match self.state {
+            State::ARM => (4, 4),
+            State::THUMB => (if thumb::is_long(name) { 4 } else { 2 }, 2),
+        };
+        if (self.addr % alignment) != 0 {
+            return Err(format!("Misaligned instruction at {:#010X}.", self.addr));
+        }
+        let literal = match operands.find('=') {
+            Some(i) => Some(self.add_literal(operands[(i + 1)..].trim())),
+            None => None,
+        };
+        let state = self.state;
+        self.push(line, size, ItemKind::Instruction(state, name, operands, literal));
+        Ok(())
+    }
+
+    fn add_literal(&mut self, expr: &'a str) -> usize {
+        for &i in &self.pending { if self.literals[i].0 == expr { return i; } }
+        self.literals.push((expr, 0));
+        self.pending.push(self.literals.len() - 1);
+        self.literals.len() - 1
+    }
+
+    fn flush_pool(&mut self, line: usize) -> Result<(), String> {
+        if self.pending.is_empty() { return Ok(()); }
+        self.align(line, 4);
+        let pending: Vec<usize> = self.pending.drain(..).collect();
+        for i in pending {
+            self.literals[i].1 = self.addr;
+            let expr = self.literals[i].0;
+            self.push(line, 4, ItemKind::Data(4, expr));
+        }
+        Ok(())
+    }
+
+    fn add_directive(&mut self, line: usize, name: &'a str, operands: &'a str) -> Result<(), String> {
+        match (name.to_lowercase().as_str(), operands) {
+            (".arm", "") | (".code", "32") => { self.align(line, 4); self.state = State::ARM; },
+            (".thumb", "") | (".code", "16") => { self.align(line, 2); self.state = State::THUMB; },
+            (".pool", "") | (".ltorg", "") => { try!(self.flush_pool(line)); },
+            (".syntax", "unified") => {},
+            (".fill", _) => {
+                let args: Vec<&str> = operands.split(',').collect();
+                if args.len() > 3 { return Err("`.fill` takes up to three values.".to_string()); }
+                let ctx = Context { addr: self.addr, literal: None, symbols: &self.symbols };
+                let count = try!(ctx.field(args[0], 0x0200_0000, 1));
+                let width = if args.len() > 1 { try!(ctx.field(args[1], 4, 1)) } else { 1 };
+                if (width != 1) && (width != 2) && (width != 4) {
+                    return Err(format!("Invalid `.fill` size {}.", width));
+                }
+                let value = if args.len() > 2 { try!(ctx.eval(args[2])) as u32 } else { 0 };
+                self.push(line, count * width, ItemKind::Fill(count, width as usize, value));
+            },
+            (".align", _) => {
+                let n = if operands.is_empty() { 2 } else {
+                    let ctx = Context { addr: self.addr, literal: None, symbols: &self.symbols };
+                    try!(ctx.field(operands, 16, 1))
+                };
+                self.align(line, 1 << n);
+            },
+            (".word", _) | (".hword", _) | (".byte", _) => {
+                let name = name.to_lowercase();
+                let width = match name.as_str() { ".word" => 4, ".hword" => 2, _ => 1 };
+                if operands.is_empty() { return Err(format!("`{}` needs values.", name)); }
+                let count = operands.split(',').count() as u32;
+                self.push(line, count * width as u32, ItemKind::Data(width, operands));
+            },
+            _ => return Err(format!("Unknown directive `{}`.", name)),
+        }
+        Ok(())
+    }
+
+    fn encode(&self, item: &Item, bytes: &mut Vec<u8>) -> Result<(), String> {
+        let mut ctx = Context { addr: item.addr, literal: None, symbols: &self.symbols };
+        match item.kind {
+            ItemKind::Instruction(state, mnemonic, operands, literal) => {
+                ctx.literal = literal.map(|i| self.literals[i].1);
+                let mut ops = Operands::new(operands);
+                let (raw, size) = match state {
+                    State::ARM => (try!(arm::assemble(mnemonic, &mut ops, &ctx)), 4),
+                    State::THUMB => (try!(thumb::assemble(mnemonic, &mut ops, &ctx)), if thumb::is_long(mnemonic) { 4 } else { 2 }),
+                };
+                try!(ops.finish());
+                push_le(bytes, raw, size);
+            },
+            ItemKind::Data(width, values) => for expr in values.split(',') {
+                let value = try!(ctx.eval(expr));
+                let bits = width as u32 * 8;
+                if (value < -(1 << (bits - 1))) || (value >= (1 << bits)) {
+                    return Err(format!("Value {} doesn't fit into {} bits.", value, bits));
+                }
+                push_le(bytes, value as u32, width);
+                ctx.addr = ctx.addr.wrapping_add(width as u32);
+            },
+            ItemKind::Fill(n, width, value) => for _ in 0..n { push_le(bytes, value, width); },
+            ItemKind::Padding(n) => for _ in 0..n { bytes.push(0); },
+        }
+        Ok(())
+    }
+}
+
+fn push_le(bytes: &mut Vec<u8>, value: u32, size: usize) {
+    for i in 0..size { bytes.push((value >> (8 * i)) as u8); }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,238 @@
+// License below.
+//! Implements parsing instruction operands and expressions.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+
+/// Everything an instruction's operands may refer to.
+pub struct Context<'a> {
+    /// The address of the instruction being assembled.
+    pub addr: u32,
+
+    /// The address of the literal an `ldr Rd, =value` loads.
+    pub literal: Option<u32>,
+
+    /// All known symbols.
+    pub symbols: &'a HashMap<String, u32>,
+}
+
+impl<'a> Context<'a> {
+    /// Evaluates an expression.
+    ///
+    /// Expressions are sums and differences of numbers, symbols,
+    /// and `.` for the current address. Numbers are decimal,
+    /// or hexadecimal and binary with a `0x` or `0b` prefix.
+    pub fn eval(&self, expr: &str) -> Result<i64, String> {
+        let mut rest = expr.trim();
+        let mut value = 0_i64;
+        let mut sign = 1_i64;
+        loop {
+            // Unary signs, as in `#-4` or `#+12`.
+            loop {
+                if rest.starts_with('-') { sign = -sign; }
+                else if !rest.starts_with('+') { break; }
+                rest = rest[1..].trim_left();
+            }
+            let end = rest.find(|c: char| c == '+' || c == '-' || c.is_whitespace()).unwrap_or(rest.len());
+            value = value.wrapping_add(sign.wrapping_mul(try!(self.term(&rest[..end]))));
+            rest = rest[end..].trim_left();
+
+            sign = if rest.starts_with('+') { 1 } else if rest.starts_with('-') { -1 } else {
+                return if rest.is_empty() { Ok(value) } else { Err(format!("Unexpected `{}` in expression.", rest)) };
+            };
+            rest = rest[1..].trim_left();
+        }
+    }
+
+    fn term(&self, term: &str) -> Result<i64, String> {
+        if term.is_empty() { return Err("Missing expression.".to_string()); }
+        if term == "." { return Ok(self.addr as i64); }
+
+        let lower = term.to_lowercase();
+        let number = if lower.starts_with("0x") { Some(u32::from_str_radix(&lower[2..], 16)) }
+            else if lower.starts_with("0b") { Some(u32::from_str_radix(&lower[2..], 2)) }
+            else if term.starts_with(|c: char| c.is_digit(10)) { Some(u32::from_str_radix(term, 10)) }
+            else { None };
+        match number {
+            Some(Ok(x)) => Ok(x as i64),
+            Some(Err(_)) => Err(format!("Invalid number `{}`.", term)),
+            None => match self.symbols.get(term) {
+                Some(&x) => Ok(x as i64),
+                None => Err(format!("Unknown symbol `{}`.", term)),
+            },
+        }
+    }
+
+    /// Evaluates an expression into an unsigned immediate field.
+    ///
+    /// # Params
+    /// - `expr`: The expression.
+    /// - `max`: The greatest possible value.
+    /// - `scale`: The value must be a multiple of this and is
+    ///   divided by it, such that it fits the field.
+    pub fn field(&self, expr: &str, max: u32, scale: u32) -> Result<u32, String> {
+        let value = try!(self.eval(expr));
+        check_field(value, max, scale)
+    }
+
+    /// Parses a branch target.
+    ///
+    /// Immediates such as `#-4` are relative to the branch
+    /// itself, just like in disassembly. Anything else is an
+    /// absolute address, e.g. a label.
+    pub fn branch_target(&self, ops: &mut Operands) -> Result<i64, String> {
+        if ops.eat('#') { Ok(self.addr as i64 + try!(self.eval(ops.expression()))) }
+        else { self.eval(ops.expression()) }
+    }
+}
+
+/// Checks whether a value fits an unsigned immediate field.
+///
+/// See `Context::field` for details.
+pub fn check_field(value: i64, max: u32, scale: u32) -> Result<u32, String> {
+    if (value < 0) || (value > max as i64) || (value % scale as i64 != 0) {
+        return Err(format!("Immediate {} is out of range.", value));
+    }
+    Ok(value as u32 / scale)
+}
+
+/// Parses a register name.
+///
+/// Accepts `R0` to `R15` as well as the aliases `SL`, `FP`,
+/// `IP`, `SP`, `LR`, and `PC`, in any case.
+pub fn register_index(name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    match name.as_str() {
+        "sl" => Some(10),
+        "fp" => Some(11),
+        "ip" => Some(12),
+        "sp" => Some(13),
+        "lr" => Some(14),
+        "pc" => Some(15),
+        _ if name.starts_with('r') => match usize::from_str_radix(&name[1..], 10) {
+            Ok(i) if i < 16 => Some(i),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A parser for the operands of a single instruction.
+pub struct Operands<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Operands<'a> {
+    /// Creates a parser for the given operands.
+    pub fn new(text: &'a str) -> Operands<'a> { Operands { rest: text.trim() } }
+
+    /// Checks whether all operands have been parsed.
+    pub fn is_empty(&self) -> bool { self.rest.is_empty() }
+
+    /// Makes sure all operands have been parsed.
+    pub fn finish(&self) -> Result<(), String> {
+        if self.is_empty() { Ok(()) } else { Err(format!("Unexpected `{}`.", self.rest)) }
+    }
+
+    /// Gets the next character without parsing it.
+    pub fn peek(&self) -> Option<char> { self.rest.chars().next() }
+
+    /// Parses the given character, if it comes next.
+    pub fn eat(&mut self, c: char) -> bool {
+        if !self.rest.starts_with(c) { return false; }
+        self.rest = self.rest[c.len_utf8()..].trim_left();
+        true
+    }
+
+    /// Parses the given character, which must come next.
+    pub fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) { return Ok(()); }
+        if self.is_empty() { Err(format!("Expected `{}`.", c)) }
+        else { Err(format!("Expected `{}`, found `{}`.", c, self.rest)) }
+    }
+
+    fn peek_word(&self) -> &'a str {
+        let end = self.rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(self.rest.len());
+        &self.rest[..end]
+    }
+
+    /// Parses a name such as `lsl` or `CPSR_flg`.
+    pub fn word(&mut self) -> Result<&'a str, String> {
+        let word = self.peek_word();
+        if word.is_empty() { return Err(format!("Expected a name, found `{}`.", self.rest)); }
+        self.rest = self.rest[word.len()..].trim_left();
+        Ok(word)
+    }
+
+    /// Parses a register, if one comes next.
+    pub fn try_register(&mut self) -> Option<usize> {
+        let word = self.peek_word();
+        let reg = register_index(word);
+        if reg.is_some() { self.rest = self.rest[word.len()..].trim_left(); }
+        reg
+    }
+
+    /// Parses a register, which must come next.
+    pub fn register(&mut self) -> Result<usize, String> {
+        match self.try_register() {
+            Some(r) => Ok(r),
+            None if self.is_empty() => Err("Expected a register.".to_string()),
+            None => Err(format!("Expected a register, found `{}`.", self.rest)),
+        }
+    }
+
+    /// Parses an expression up to the next `,`, `]`, `}`, `!`, or `^`.
+    pub fn expression(&mut self) -> &'a str {
+        let end = self.rest.find(|c: char| ",]}!^".contains(c)).unwrap_or(self.rest.len());
+        let expr = self.rest[..end].trim();
+        self.rest = self.rest[end..].trim_left();
+        expr
+    }
+
+    /// Parses an immediate like `#42`, returning its expression.
+    pub fn immediate(&mut self) -> Result<&'a str, String> {
+        try!(self.expect('#'));
+        Ok(self.expression())
+    }
+
+    /// Parses a register list like `{R0, R2-R4, LR}`.
+    ///
+    /// # Returns
+    /// A bitmap, where bit N is set if register N is listed.
+    pub fn register_list(&mut self) -> Result<u16, String> {
+        try!(self.expect('{'));
+        let mut list = 0_u16;
+        if self.eat('}') { return Ok(list); }
+        loop {
+            let first = try!(self.register());
+            let last = if self.eat('-') { try!(self.register()) } else { first };
+            if last < first { return Err("Register ranges must be ascending.".to_string()); }
+            for r in first..(last + 1) { list |= 1 << r; }
+            if self.eat('}') { return Ok(list); }
+            try!(self.expect(','));
+        }
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,148 @@
+// License below.
+#![allow(missing_docs)]
+
+use super::Assembler;
+use super::super::arm7tdmi::State;
+
+const ARM_INSTRUCTIONS: &'static [(&'static str, u32)] = &[
+    ("swieq\t#0x777777",                0x0F777777),
+    ("bxeq\tR7",                        0x012FFF17),
+    ("beq\t#-4",                        0x0AFFFFFD),
+    ("bleq\t#12",                       0x0B000001),
+    ("andeq\tR2, R1, #335544321",       0x02012345),
+    ("eorseq\tR2, R1, R3, lsl #7",      0x00312383),
+    ("adceq\tR2, R1, R3, rrx",          0x00A12063),
+    ("rsceq\tR2, R1, R3, lsl R4",       0x00E12413),
+    ("moveq\tR2, R3",                   0x01A02003),
+    ("teqeq\tR1, R3, asr R4",           0x01310453),
+    ("mov\tr0, #0xFF000000",            0xE3A004FF),
+    ("movs\tR0, R1, lsr #32",           0xE1B00021),
+    ("mrseq\tR1, CPSR",                 0x010F1000),
+    ("msr\tCPSR_flg, #0xF0000000",      0xE328F20F),
+    ("msr\tSPSR, R3",                   0xE169F003),
+    ("ldr\tR0, [R1, #+12]!",            0xE5B1000C),
+    ("ldrb\tR0, [R1], #-4",             0xE4510004),
+    ("ldrt\tR0, [R1], #+4",             0xE4B10004),
+    ("str\tR0, [R1, -R2, lsl #7]",      0xE7010382),
+    ("ldrh\tR0, [R1, #+20]",            0xE1D101B4),
+    ("ldrsb\tR0, [R1], -R2",            0xE01100D2),
+    ("ldrsh\tR3, [R4, #-0]",            0xE15430F0),
+    ("stmdb\tSP!, {R0-R3, LR}",         0xE92D400F),
+    ("ldmfd\tSP!, {R0, PC}^",           0xE8FD8001),
+    ("swpb\tR0, R1, [R2]",              0xE1420091),
+    ("mla\tR0, R1, R2, R3",             0xE0203291),
+    ("umulls\tR0, R1, R2, R3",          0xE0910392),
+    ("mrc\tP15, 0, R0, CR1, CR0, 0",    0xEE110F10),
+    ("stc\tP1, CR2, [R3, #-2]!",        0xED232102),
+];
+
+const THUMB_INSTRUCTIONS: &'static [(&'static str, u16)] = &[
+    ("lsls\tR0, R1, #2",        0x0088),
+    ("lsrs\tR0, R1, #32",       0x0808),
+    ("adds\tR0, R1, #3",        0x1CC8),
+    ("subs\tR0, R1, R2",        0x1A88),
+    ("movs\tR0, #255",          0x20FF),
+    ("cmp \tR0, #1",            0x2801),
+    ("ands\tR0, R1",            0x4008),
+    ("muls\tR0, R1",            0x4348),
+    ("rsbs\tR0, R1",            0x4248),
+    ("cmp \tR0, R1",            0x4288),
+    ("add\tR8, SP",             0x44E8),
+    ("mov\tR8, R0",             0x4680),
+    ("bx\tLR",                  0x4770),
+    ("ldr\tR0, [PC, #8]",       0x4802),
+    ("ldrh\tR0, [R1, R2]",      0x5A88),
+    ("ldsb\tR0, [R1, R2]",      0x5688),
+    ("ldr \tR0, [R1, #4]",      0x6848),
+    ("strb\tR0, [R1, #31]",     0x77C8),
+    ("ldrh\tR0, [R1, #62]",     0x8FC8),
+    ("str\tR0, [SP, #1020]",    0x90FF),
+    ("add\tR0, SP, #8",         0xA802),
+    ("add\tSP, #-8",            0xB082),
+    ("push\t{R0-R7, LR}",       0xB5FF),
+    ("pop\t{R0, PC}",           0xBD01),
+    ("ldmia\tR0!, {R1, R2}",    0xC806),
+    ("swi\t6",                  0xDF06),
+    ("beq\t#0",                 0xD0FE),
+    ("b\t#4",                   0xE000),
+];
+
+#[test]
+fn arm_instructions() {
+    for &(src, raw) in ARM_INSTRUCTIONS {
+        let asm = Assembler::new(0).assemble(src).unwrap();
+        assert_eq!(asm.words(), vec![raw], "{}", src);
+    }
+}
+
+#[test]
+fn thumb_instructions() {
+    for &(src, raw) in THUMB_INSTRUCTIONS {
+        let asm = Assembler::new(0).with_state(State::THUMB).assemble(src).unwrap();
+        assert_eq!(asm.bytes(), &[raw as u8, (raw >> 8) as u8], "{}", src);
+    }
+}
+
+#[test]
+fn labels_and_literal_pools() {
+    let src = "
+        start:  ldr   r0, =0x04000000   @ Shared by both loads.
+                ldr   r1, =0x04000000
+                subs  r2, r2, #1 ; bne start
+                b     thumb
+        .pool
+        .thumb
+        thumb:  ldr   r3, =thumb + 1
+                bl    start
+        end:    .hword 0xBEEF
+                .word  . + 2, -1
+    ";
+    let asm = Assembler::new(0x0800_0000).assemble(src).unwrap();
+    assert_eq!(asm.symbol("start"), Some(0x0800_0000));
+    assert_eq!(asm.symbol("thumb"), Some(0x0800_0018));
+    assert_eq!(asm.symbol("end"),   Some(0x0800_001E));
+    assert_eq!(asm.words(), vec![
+        0xE59F000C, 0xE59F1008, 0xE2522001, 0x1AFFFFFB,
+        0xEA000000, 0x04000000, 0xF7FF4B03, 0xBEEFFFF1,
+        0x08000022, 0xFFFFFFFF, 0x08000019,
+    ]);
+}
+
+#[test]
+fn fill() {
+    let asm = Assembler::new(0).assemble(".syntax unified\n.fill 2, 2, 0xABCD\n.byte 1\n.fill 3").unwrap();
+    assert_eq!(asm.bytes(), &[0xCD, 0xAB, 0xCD, 0xAB, 0x01, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn invalid_assembly() {
+    let src = "nop\nnop\nmov r0, #0x101";
+    let e = Assembler::new(0).with_state(State::THUMB).assemble("lsls r0, r8, #1").unwrap_err();
+    assert_eq!((e.line(), e.message()), (1, "R8 is no low register."));
+    assert_eq!(e.to_string(), "Invalid assembly in line 1: R8 is no low register.");
+    assert_eq!(Assembler::new(0).with_state(State::THUMB).assemble(src).unwrap_err().line(), 3);
+    assert!(Assembler::new(0).assemble("b missing").is_err());
+    assert!(Assembler::new(0).assemble("x:\nx: b x").is_err());
+    assert!(Assembler::new(0).assemble("mrs r0, cpé").is_err());
+    assert!(Assembler::new(0).assemble("msr cpsé_f, r0").is_err());
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,264 @@
+// License below.
+//! Implements encoding THUMB state instructions.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::operands::*;
+use super::super::arm7tdmi::{Arm7Tdmi, ArmCondition};
+
+const SP: usize = 13;
+const PC: usize = 15;
+
+/// Checks whether a THUMB mnemonic assembles into two halfwords.
+pub fn is_long(mnemonic: &str) -> bool { mnemonic.to_lowercase() == "bl" }
+
+/// Encodes a THUMB state instruction.
+///
+/// # Params
+/// - `mnemonic`: The mnemonic, e.g. `adds`.
+/// - `ops`: The instruction's operands.
+/// - `ctx`: The instruction's address and all known symbols.
+///
+/// # Returns
+/// The encoded halfword, or both halfwords of a long branch
+/// with link with the first one in the lower half.
+pub fn assemble(mnemonic: &str, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let m = mnemonic.to_lowercase();
+    match m.as_str() {
+        "lsl" | "lsls" => shift(0, ops, ctx),
+        "lsr" | "lsrs" => shift(1, ops, ctx),
+        "asr" | "asrs" => shift(2, ops, ctx),
+        "and" | "ands" => alu(0,  ops),
+        "eor" | "eors" => alu(1,  ops),
+        "adc" | "adcs" => alu(5,  ops),
+        "sbc" | "sbcs" => alu(6,  ops),
+        "ror" | "rors" => alu(7,  ops),
+        "tst"          => alu(8,  ops),
+        "cmn"          => alu(11, ops),
+        "orr" | "orrs" => alu(12, ops),
+        "mul" | "muls" => alu(13, ops),
+        "bic" | "bics" => alu(14, ops),
+        "mvn" | "mvns" => alu(15, ops),
+        "neg" | "negs" | "rsb" | "rsbs" => {
+            let raw = try!(alu(9, ops));
+            // Also accept the explicit `rsbs Rd, Rs, #0`.
+            if ops.eat(',') { try!(ctx.field(try!(ops.immediate()), 0, 1)); }
+            Ok(raw)
+        },
+        "mov" | "movs" => mov(m == "movs", ops, ctx),
+        "cmp"          => cmp(ops, ctx),
+        "add" | "adds" => add_sub(false, m == "adds", ops, ctx),
+        "sub" | "subs" => add_sub(true,  m == "subs", ops, ctx),
+        "bx"           => Ok(0x4700 | ((try!(ops.register()) as u32) << 3)),
+        "nop"          => Ok(0x46C0),
+        "ldr" | "str" | "ldrb" | "strb" | "ldrh" | "strh"
+        | "ldsb" | "ldsh" | "ldrsb" | "ldrsh" => transfer(&m, ops, ctx),
+        "push" | "pop" => push_pop(m == "pop", ops),
+        "ldmia" | "stmia" | "ldm" | "stm" => {
+            let rb = try!(low(try!(ops.register())));
+            ops.eat('!');
+            try!(ops.expect(','));
+            let list = try!(ops.register_list()) as u32;
+            if list > 0xFF { return Err("Only low registers can be transferred.".to_string()); }
+            Ok(0xC000 | ((m.starts_with("ldm") as u32) << 11) | (rb << 8) | list)
+        },
+        "swi" => { ops.eat('#'); Ok(0xDF00 | try!(ctx.field(ops.expression(), 0xFF, 1))) },
+        "b" | "bal" => {
+            let offs = try!(branch_offset(ops, ctx, 0x800));
+            Ok(0xE000 | ((offs >> 1) & 0x7FF))
+        },
+        "bl" => {
+            let offs = try!(branch_offset(ops, ctx, 0x40_0000));
+            Ok((0xF000 | ((offs >> 12) & 0x7FF)) | ((0xF800 | ((offs >> 1) & 0x7FF)) << 16))
+        },
+        // The halves of a long branch with link, as disassembled.
+        "bl0" => {
+            let offs = try!(ctx.eval(try!(ops.immediate()))) as i32;
+            if ((offs & 0xFFF) != 0) || (((offs << 9) >> 9) != offs) {
+                return Err(format!("Offset {:#010X} is out of range.", offs));
+            }
+            Ok(0xF000 | (((offs as u32) >> 12) & 0x7FF))
+        },
+        "bl1" => Ok(0xF800 | try!(ctx.field(try!(ops.immediate()), 0xFFE, 2))),
+        _ => match ArmCondition::from_assembly_name(if m.starts_with('b') { &m[1..] } else { "-" }) {
+            Some(ArmCondition::AL) | Some(ArmCondition::NV) | None => {
+                Err(format!("Unknown THUMB instruction `{}`.", mnemonic))
+            },
+            Some(cond) => {
+                let offs = try!(branch_offset(ops, ctx, 0x100));
+                Ok(0xD000 | ((cond as u32) << 8) | ((offs >> 1) & 0xFF))
+            },
+        },
+    }
+}
+
+fn low(r: usize) -> Result<u32, String> {
+    if r < 8 { Ok(r as u32) } else { Err(format!("{} is no low register.", Arm7Tdmi::register_name(r))) }
+}
+
+fn low_register(ops: &mut Operands) -> Result<u32, String> { low(try!(ops.register())) }
+
+// Encodes ADD, CMP, MOV on any registers.
+fn hi_register_op(op: u32, hd: usize, hs: usize) -> u32 {
+    0x4400 | (op << 8) | (((hd as u32) & 0b1000) << 4) | ((hs as u32) << 3) | ((hd as u32) & 0b111)
+}
+
+fn branch_offset(ops: &mut Operands, ctx: &Context, range: i64) -> Result<u32, String> {
+    let offs = try!(ctx.branch_target(ops)) - (ctx.addr as i64 + 4);
+    if (offs % 2 != 0) || (offs < -range) || (offs >= range) {
+        return Err(format!("Branch offset {} is out of range.", offs));
+    }
+    Ok(offs as u32)
+}
+
+fn shift(op: u32, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let rd = try!(low_register(ops)); try!(ops.expect(','));
+    let rs = try!(low_register(ops));
+    if !ops.eat(',') { return Ok(0x4000 | ([2, 3, 4][op as usize] << 6) | (rs << 3) | rd); }
+    // Shifting right by 32 is encoded as shifting by 0.
+    let x = try!(ctx.field(try!(ops.immediate()), if op == 0 { 31 } else { 32 }, 1));
+    Ok((op << 11) | ((x & 0x1F) << 6) | (rs << 3) | rd)
+}
+
+fn alu(op: u32, ops: &mut Operands) -> Result<u32, String> {
+    let rd = try!(low_register(ops)); try!(ops.expect(','));
+    let rs = try!(low_register(ops));
+    Ok(0x4000 | (op << 6) | (rs << 3) | rd)
+}
+
+fn mov(flags: bool, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let rd = try!(ops.register()); try!(ops.expect(','));
+    if ops.peek() == Some('#') {
+        let imm = try!(ctx.field(try!(ops.immediate()), 0xFF, 1));
+        return Ok(0x2000 | (try!(low(rd)) << 8) | imm);
+    }
+    let rs = try!(ops.register());
+    // MOVS between registers is a shift by 0.
+    if flags { Ok((try!(low(rs)) << 3) | try!(low(rd))) } else { Ok(hi_register_op(2, rd, rs)) }
+}
+
+fn cmp(ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let rd = try!(ops.register()); try!(ops.expect(','));
+    if ops.peek() == Some('#') {
+        let imm = try!(ctx.field(try!(ops.immediate()), 0xFF, 1));
+        return Ok(0x2800 | (try!(low(rd)) << 8) | imm);
+    }
+    let rs = try!(ops.register());
+    if (rd < 8) && (rs < 8) { Ok(0x4280 | ((rs as u32) << 3) | rd as u32) } else { Ok(hi_register_op(1, rd, rs)) }
+}
+
+fn sp_offset(offs: i64) -> Result<u32, String> {
+    let imm = try!(check_field(offs.abs(), 508, 4));
+    Ok(0xB000 | (((offs < 0) as u32) << 7) | imm)
+}
+
+fn add_sub(sub: bool, flags: bool, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let op = sub as u32;
+    let rd = try!(ops.register()); try!(ops.expect(','));
+    if ops.peek() == Some('#') {
+        let value = try!(ctx.eval(try!(ops.immediate())));
+        if rd == SP { return sp_offset(if sub { -value } else { value }); }
+        let imm = try!(check_field(value, 0xFF, 1));
+        return Ok(0x3000 | (op << 11) | (try!(low(rd)) << 8) | imm);
+    }
+
+    let rs = try!(ops.register());
+    if !ops.eat(',') {
+        // Only ADD takes high registers, others use Rd twice.
+        if sub || flags { return Ok(0x1800 | (op << 9) | (try!(low(rs)) << 6) | (try!(low(rd)) << 3) | try!(low(rd))); }
+        return Ok(hi_register_op(0, rd, rs));
+    }
+    if ops.peek() == Some('#') {
+        let value = try!(ctx.eval(try!(ops.immediate())));
+        if (rd == SP) && (rs == SP) { return sp_offset(if sub { -value } else { value }); }
+        if (rs == SP) || (rs == PC) {
+            if sub { return Err("Can't subtract from SP or PC into another register.".to_string()); }
+            let imm = try!(check_field(value, 1020, 4));
+            return Ok(0xA000 | (((rs == SP) as u32) << 11) | (try!(low(rd)) << 8) | imm);
+        }
+        let imm = try!(check_field(value, 7, 1));
+        return Ok(0x1C00 | (op << 9) | (imm << 6) | (try!(low(rs)) << 3) | try!(low(rd)));
+    }
+    let rn = try!(low_register(ops));
+    Ok(0x1800 | (op << 9) | (rn << 6) | (try!(low(rs)) << 3) | try!(low(rd)))
+}
+
+fn transfer(m: &str, ops: &mut Operands, ctx: &Context) -> Result<u32, String> {
+    let rd = try!(low_register(ops));
+    try!(ops.expect(','));
+
+    // Load literals and labels relative to the word-aligned PC.
+    if ops.eat('=') || (ops.peek() != Some('[')) {
+        if m != "ldr" { return Err(format!("`{}` only takes an address in brackets.", m)); }
+        let expr = ops.expression();
+        let target = match ctx.literal { Some(x) => x as i64, None => try!(ctx.eval(expr)) };
+        let offs = target - ((ctx.addr as i64 + 4) & !0b11);
+        return Ok(0x4800 | (rd << 8) | try!(check_field(offs, 1020, 4)));
+    }
+
+    try!(ops.expect('['));
+    let rb = try!(ops.register());
+    let (imm, ro) = if !ops.eat(',') { (Some("0"), None) }
+        else if ops.peek() == Some('#') { (Some(try!(ops.immediate())), None) }
+        else { (None, Some(try!(low_register(ops)))) };
+    try!(ops.expect(']'));
+
+    let load = (m.starts_with("ld") as u32) << 11;
+    match (m, imm, ro) {
+        ("ldr", Some(imm), _) if rb == PC => Ok(0x4800 | (rd << 8) | try!(ctx.field(imm, 1020, 4))),
+        ("ldr", Some(imm), _) | ("str", Some(imm), _) if rb == SP => {
+            Ok(0x9000 | load | (rd << 8) | try!(ctx.field(imm, 1020, 4)))
+        },
+        (_, _, Some(ro)) => {
+            let rb = try!(low(rb));
+            // Signed and halfword transfers have their own opcodes.
+            let op = match m {
+                "str"  => 0x5000, "strb" => 0x5400, "ldr"  => 0x5800, "ldrb" => 0x5C00,
+                "strh" => 0x5200, "ldsb" | "ldrsb" => 0x5600, "ldrh" => 0x5A00, _ => 0x5E00,
+            };
+            Ok(op | (ro << 6) | (rb << 3) | rd)
+        },
+        (_, Some(imm), _) => {
+            let rb = try!(low(rb));
+            let (op, max, scale) = match m {
+                "ldr"  | "str"  => (0x6000, 124, 4),
+                "ldrb" | "strb" => (0x7000,  31, 1),
+                "ldrh" | "strh" => (0x8000,  62, 2),
+                _ => return Err(format!("`{}` only takes a register offset.", m)),
+            };
+            Ok(op | load | (try!(ctx.field(imm, max, scale)) << 6) | (rb << 3) | rd)
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn push_pop(pop: bool, ops: &mut Operands) -> Result<u32, String> {
+    let list = try!(ops.register_list()) as u32;
+    let extra = if pop { 1 << PC } else { 1 << 14 };
+    if (list & !(0xFF | extra)) != 0 {
+        return Err(format!("Only low registers and {} can be {}.", if pop { "PC" } else { "LR" }, if pop { "popped" } else { "pushed" }));
+    }
+    Ok(0xB400 | ((pop as u32) << 11) | (((list & extra != 0) as u32) << 8) | (list & 0xFF))
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,152 @@
+// License below.
+//! Implements the interface between a CPU and the memory it runs on.
+//!
+//! The CPU cores don't know anything about the GBA's memory map.
+//! Instead, they load and store data through the `CpuMemory`
+//! trait, which is implemented by the GBA's bus system, but may
+//! as well be implemented by a plain array for testing.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::super::bus::Bus;
+use super::super::error::GbaError;
+
+/// Size of the pages writes to cached code are tracked in, as power of two.
+pub const CODE_PAGE_SHIFT: u32 = 8;
+
+/// Describes how a memory access relates to the previous one.
+///
+/// The GBA's memory regions take longer for non-sequential
+/// accesses than for sequential ones, so implementors may
+/// use this hint for timing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccess {
+    #[doc = "The address is unrelated to the previous access."]
+    NonSequential,
+    #[doc = "The address directly follows the previous access."]
+    Sequential,
+}
+
+/// Low power modes a memory may put the CPU into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerDown {
+    #[doc = "Wait until any enabled interrupt is requested."]
+    Halt,
+    #[doc = "Wait until a keypad, serial or GamePak interrupt is requested."]
+    Stop,
+}
+
+/// A memory system a CPU can load data from and store data to.
+///
+/// Loads return their data zero-extended to 32 bits, and stores
+/// only use the lower bits of the given data. The CPU only passes
+/// aligned addresses for halfwords and words, and handles any
+/// misalignment itself.
+pub trait CpuMemory {
+    /// Loads a byte.
+    fn load_byte(&mut self, addr: u32, access: MemoryAccess) -> Result<i32, GbaError>;
+
+    /// Loads a halfword.
+    fn load_halfword(&mut self, addr: u32, access: MemoryAccess) -> Result<i32, GbaError>;
+
+    /// Loads a word.
+    fn load_word(&mut self, addr: u32, access: MemoryAccess) -> Result<i32, GbaError>;
+
+    /// Stores a byte.
+    fn store_byte(&mut self, addr: u32, data: i32, access: MemoryAccess) -> Result<(), GbaError>;
+
+    /// Stores a halfword.
+    fn store_halfword(&mut self, addr: u32, data: i32, access: MemoryAccess) -> Result<(), GbaError>;
+
+    /// Stores a word.
+    fn store_word(&mut self, addr: u32, data: i32, access: MemoryAccess) -> Result<(), GbaError>;
+
+    /// Gets all interrupts that are both enabled and requested.
+    ///
+    /// Memories without interrupt controller never wake up a
+    /// halted CPU.
+    fn pending_interrupts(&self) -> u16 { 0 }
+
+    /// Checks whether the interrupt controller signals an IRQ.
+    ///
+    /// Unlike `pending_interrupts`, this respects `IME`.
+    fn is_irq_requested(&self) -> bool { false }
+
+    /// Takes the low power mode requested since the last call, if any.
+    ///
+    /// On a GBA, writes to `HALTCNT` request these.
+    fn take_power_down(&mut self) -> Option<PowerDown> { None }
+
+    /// Checks whether any IO register was written to since the last call.
+    ///
+    /// Such writes may change which interrupts are pending, so the
+    /// cached interpreter ends its block after them.
+    fn take_io_writes(&mut self) -> bool { false }
+
+    /// Gets the number of cycles until the next timed event.
+    ///
+    /// Memories without timed events can't change on their
+    /// own, so idle loops are never skipped on them.
+    fn cycles_until_event(&self) -> Option<u64> { None }
+
+    /// Checks whether decoded code at the given address may be cached.
+    ///
+    /// This requires writes to the address to be reported
+    /// by `take_code_writes`, once watched.
+    fn is_code_cacheable(&self, _: u32) -> bool { false }
+
+    /// Starts watching for writes to the code page containing `addr`.
+    fn watch_code(&mut self, _: u32) {}
+
+    /// Moves the first addresses of all watched code pages written
+    /// to since the last call into `pages`.
+    ///
+    /// Written pages are no longer watched.
+    fn take_code_writes(&mut self, _: &mut Vec<u32>) {}
+
+    /// Checks whether loads from an address return the same
+    /// data until the next timed event.
+    ///
+    /// Idle loops polling other addresses are never skipped.
+    fn is_load_stable(&self, _: u32) -> bool { true }
+}
+
+impl CpuMemory for Bus {
+    fn load_byte(&mut self, addr: u32, _: MemoryAccess) -> Result<i32, GbaError> { Bus::load_byte(self, addr) }
+    fn load_halfword(&mut self, addr: u32, _: MemoryAccess) -> Result<i32, GbaError> { Bus::load_halfword(self, addr) }
+    fn load_word(&mut self, addr: u32, _: MemoryAccess) -> Result<i32, GbaError> { Bus::load_word(self, addr) }
+    fn store_byte(&mut self, addr: u32, data: i32, _: MemoryAccess) -> Result<(), GbaError> { Bus::store_byte(self, addr, data) }
+    fn store_halfword(&mut self, addr: u32, data: i32, _: MemoryAccess) -> Result<(), GbaError> { Bus::store_halfword(self, addr, data) }
+    fn store_word(&mut self, addr: u32, data: i32, _: MemoryAccess) -> Result<(), GbaError> { Bus::store_word(self, addr, data) }
+    fn pending_interrupts(&self) -> u16 { Bus::pending_interrupts(self) }
+    fn is_irq_requested(&self) -> bool { Bus::is_irq_requested(self) }
+    fn take_power_down(&mut self) -> Option<PowerDown> { Bus::take_power_down(self) }
+    fn take_io_writes(&mut self) -> bool { Bus::take_io_writes(self) }
+    fn cycles_until_event(&self) -> Option<u64> { Bus::cycles_until_event(self) }
+    fn is_code_cacheable(&self, addr: u32) -> bool { Bus::is_code_cacheable(self, addr) }
+    fn watch_code(&mut self, addr: u32) { Bus::watch_code(self, addr) }
+    fn take_code_writes(&mut self, pages: &mut Vec<u32>) { Bus::take_code_writes(self, pages) }
+    fn is_load_stable(&self, addr: u32) -> bool { Bus::is_load_stable(self, addr) }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,336 @@
+// License below.
+//! Implements an address-aware disassembler.
+//!
+//! The `Display` implementations of `ArmInstruction` and
+//! `ThumbInstruction` only know the instruction itself, so
+//! they print branch targets relative to the branch. The
+//! disassembler also knows where an instruction is located
+//! and what memory surrounds it. Hence it:
+//!
+//! - Prints branch targets as absolute addresses.
+//! - Merges both halves of a THUMB `bl` into one instruction.
+//! - Resolves PC-relative loads, adding the loaded literal
+//!   value as a comment.
+//! - Replaces addresses by labels of a `SymbolTable`.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::fmt;
+use std::ops::Range;
+use super::super::bus::Bus;
+use super::arm7tdmi::State;
+use super::arminstruction::{ArmInstruction, ArmOpcode, ArmLdrhStrhOP};
+use super::thumbinstruction::{ThumbInstruction, ThumbOpcode};
+
+pub use self::symbols::*;
+pub use self::rom::*;
+
+pub mod symbols;
+pub mod rom;
+
+#[cfg(test)]
+mod test;
+
+/// Memory the disassembler can read code and literals from.
+pub trait CodeMemory {
+    /// Reads a word from a word aligned address.
+    ///
+    /// Returns `None` if nothing is mapped at the address.
+    fn code_word(&self, addr: u32) -> Option<u32>;
+
+    /// Reads a halfword from a halfword aligned address.
+    ///
+    /// Returns `None` if nothing is mapped at the address.
+    fn code_halfword(&self, addr: u32) -> Option<u16>;
+}
+
+impl CodeMemory for Bus {
+    fn code_word(&self, addr: u32) -> Option<u32> { self.load_word(addr).ok().map(|w| w as u32) }
+    fn code_halfword(&self, addr: u32) -> Option<u16> { self.load_halfword(addr).ok().map(|h| h as u16) }
+}
+
+/// A memory image of raw bytes located at some address.
+#[derive(Debug, Clone, Copy)]
+pub struct CodeImage<'a> {
+    base: u32,
+    bytes: &'a [u8],
+}
+
+impl<'a> CodeImage<'a> {
+    /// Creates an image whose first byte is located at `base`.
+    pub fn new(base: u32, bytes: &'a [u8]) -> CodeImage<'a> { CodeImage { base: base, bytes: bytes } }
+
+    fn read(&self, addr: u32, n: usize) -> Option<u32> {
+        let offs = addr.wrapping_sub(self.base) as usize;
+        if (offs >= self.bytes.len()) || ((self.bytes.len() - offs) < n) { return None; }
+        Some(self.bytes[offs..(offs + n)].iter().rev().fold(0, |x, &b| (x << 8) | (b as u32)))
+    }
+}
+
+impl<'a> CodeMemory for CodeImage<'a> {
+    fn code_word(&self, addr: u32) -> Option<u32> { self.read(addr, 4) }
+    fn code_halfword(&self, addr: u32) -> Option<u16> { self.read(addr, 2).map(|h| h as u16) }
+}
+
+/// A single disassembled instruction.
+///
+/// Anything that does not decode to an instruction is
+/// disassembled as a `.word` or `.hword` directive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disassembly {
+    addr: u32,
+    state: State,
+    raw: u32,
+    size: u32,
+    text: String,
+    branch: Option<u32>,
+    literal: Option<(u32, u32)>,
+    comment: Option<String>,
+}
+
+impl Disassembly {
+    fn new(addr: u32, state: State, raw: u32, text: String) -> Disassembly {
+        let size = if state == State::ARM { 4 } else { 2 };
+        Disassembly {
+            addr: addr, state: state, raw: raw, size: size, text: text,
+            branch: None, literal: None, comment: None,
+        }
+    }
+
+    /// Gets the address of the instruction.
+    pub fn addr(&self) -> u32 { self.addr }
+
+    /// Gets the instruction set of the instruction.
+    pub fn state(&self) -> State { self.state }
+
+    /// Gets the raw instruction.
+    ///
+    /// For a THUMB `bl`, the first half is in the lower 16 bits
+    /// and the second half in the upper 16 bits.
+    pub fn raw(&self) -> u32 { self.raw }
+
+    /// Gets the size of the instruction in bytes.
+    pub fn size(&self) -> u32 { self.size }
+
+    /// Gets the disassembled instruction, without address,
+    /// raw instruction, or comment.
+    pub fn text(&self) -> &str { &self.text }
+
+    /// Gets the absolute target address of a branch.
+    pub fn branch_target(&self) -> Option<u32> { self.branch }
+
+    /// Gets the address and value of a PC-relative load's literal.
+    pub fn literal(&self) -> Option<(u32, u32)> { self.literal }
+
+    /// Gets a comment, e.g. the value of a loaded literal.
+    pub fn comment(&self) -> Option<&str> { self.comment.as_ref().map(|c| c.as_str()) }
+}
+
+impl fmt::Display for Disassembly {
+    /// Writes address, raw instruction, text, and comment.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(match (self.state, self.size) {
+            (State::ARM, _) => write!(f, "{:08X}  {:08X}\t{}", self.addr, self.raw, self.text),
+            (State::THUMB, 4) => {
+                write!(f, "{:08X}  {:04X} {:04X}\t{}", self.addr, self.raw & 0xFFFF, self.raw >> 16, self.text)
+            },
+            (State::THUMB, _) => write!(f, "{:08X}  {:04X}\t{}", self.addr, self.raw, self.text),
+        });
+        if let Some(ref c) = self.comment { write!(f, "\t@ {}", c) } else { Ok(()) }
+    }
+}
+
+// Strips the raw instruction a `Display` implementation prints first.
+fn without_raw<T: fmt::Display>(inst: &T) -> String {
+    let text = format!("{}", inst);
+    match text.find('\t') {
+        Some(i) => text[(i + 1)..].to_string(),
+        None => text,
+    }
+}
+
+/// Strips the raw instruction like the disassembler does, and
+/// ignores how operands are spaced. Lets tests compare text.
+#[cfg(test)]
+pub fn normalized_assembly<T: fmt::Display>(inst: &T) -> String {
+    without_raw(inst).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Gets the mnemonic of a disassembled instruction.
+fn mnemonic(text: &str) -> &str { text.split('\t').next().unwrap_or(text) }
+
+/// Disassembles code with knowledge of its location.
+pub struct Disassembler<'a, M: 'a + CodeMemory> {
+    memory: &'a M,
+    symbols: Option<&'a SymbolTable>,
+}
+
+impl<'a, M: 'a + CodeMemory> Disassembler<'a, M> {
+    /// Creates a disassembler reading code from the given memory.
+    pub fn new(memory: &'a M) -> Disassembler<'a, M> {
+        Disassembler { memory: memory, symbols: None }
+    }
+
+    /// Configure a symbol table to look up labels in.
+    pub fn with_symbols(&mut self, symbols: &'a SymbolTable) -> &mut Disassembler<'a, M> {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Gets the label of an address, if any.
+    ///
+    /// THUMB code addresses are also looked up with bit 0 set,
+    /// as linkers mark THUMB functions that way.
+    pub fn label(&self, addr: u32, state: State) -> Option<&'a str> {
+        let symbols = match self.symbols { Some(s) => s, None => return None };
+        symbols.name(addr).or_else(|| if state == State::THUMB { symbols.name(addr | 1) } else { None })
+    }
+
+    fn address_name(&self, addr: u32, state: State) -> String {
+        match self.label(addr, state) {
+            Some(name) => name.to_string(),
+            None => format!("{:#010X}", addr),
+        }
+    }
+
+    /// Disassembles a single instruction.
+    ///
+    /// Returns `None` if there is no code at the given address.
+    pub fn instruction(&self, addr: u32, state: State) -> Option<Disassembly> {
+        match state {
+            State::ARM => self.memory.code_word(addr & !3).map(|raw| self.arm(addr & !3, raw)),
+            State::THUMB => self.memory.code_halfword(addr & !1).map(|raw| self.thumb(addr & !1, raw)),
+        }
+    }
+
+    /// Disassembles all instructions starting within a range.
+    ///
+    /// Stops early where there is no code.
+    pub fn range(&self, range: Range<u32>, state: State) -> Vec<Disassembly> {
+        let mut lines = Vec::new();
+        let mut addr = range.start;
+        while addr < range.end {
+            let line = match self.instruction(addr, state) { Some(l) => l, None => break };
+            addr = line.addr.wrapping_add(line.size);
+            lines.push(line);
+            if addr == 0 { break; } // Wrapped around.
+        }
+        lines
+    }
+
+    /// Disassembles a range into a listing with labels.
+    pub fn listing(&self, range: Range<u32>, state: State) -> String {
+        let mut text = String::new();
+        for line in self.range(range, state) {
+            if let Some(name) = self.label(line.addr, state) { text.push_str(&format!("{}:\n", name)); }
+            text.push_str(&format!("{}\n", line));
+        }
+        text
+    }
+
+    fn with_branch(&self, mut line: Disassembly, target: u32) -> Disassembly {
+        line.text = format!("{}\t{}", mnemonic(&line.text), self.address_name(target, line.state));
+        line.branch = Some(target);
+        line
+    }
+
+    fn with_literal(&self, mut line: Disassembly, addr: u32, size: u32) -> Disassembly {
+        let value = match size {
+            4 if (addr & 3) == 0 => self.memory.code_word(addr),
+            2 if (addr & 1) == 0 => self.memory.code_halfword(addr).map(|h| h as u32),
+            1 => self.memory.code_halfword(addr & !1).map(|h| ((h >> (8 * (addr & 1))) & 0xFF) as u32),
+            _ => None,
+        };
+        if let Some(value) = value {
+            let mut comment = format!("{} = {:#X}", self.address_name(addr, State::ARM), value);
+            // Pointers to THUMB code have bit 0 set.
+            if let Some(name) = self.label(value, State::ARM).or_else(|| self.label(value & !1, State::THUMB)) {
+                comment.push_str(&format!(" <{}>", name));
+            }
+            line.literal = Some((addr, value));
+            line.comment = Some(comment);
+        }
+        line
+    }
+
+    fn arm(&self, addr: u32, raw: u32) -> Disassembly {
+        let inst = match ArmInstruction::decode(raw) {
+            Ok(i) if i.opcode() != ArmOpcode::Unknown => i,
+            _ => return Disassembly::new(addr, State::ARM, raw, format!(".word\t{:#010X}", raw)),
+        };
+        let line = Disassembly::new(addr, State::ARM, raw, without_raw(&inst));
+        let pc = addr.wrapping_add(8);
+        let is_literal = (inst.Rn() == 15) && inst.is_load() && inst.is_pre_indexed();
+        match inst.opcode() {
+            ArmOpcode::B_BL => {
+                self.with_branch(line, pc.wrapping_add(inst.branch_offset() as u32))
+            },
+            ArmOpcode::LDR_STR if is_literal && inst.is_offset_field_immediate() => {
+                let size = if inst.is_transfering_bytes() { 1 } else { 4 };
+                self.with_literal(line, pc.wrapping_add(inst.offset12() as u32), size)
+            },
+            ArmOpcode::LDRH_STRH_Imm if is_literal => {
+                let size = if inst.ldrh_strh_op() == ArmLdrhStrhOP::SB { 1 } else { 2 };
+                self.with_literal(line, pc.wrapping_add(inst.split_offset8() as u32), size)
+            },
+            _ => line,
+        }
+    }
+
+    fn thumb(&self, addr: u32, raw: u16) -> Disassembly {
+        let inst = match ThumbInstruction::decode(raw) {
+            Ok(i) => i,
+            Err(_) => return Disassembly::new(addr, State::THUMB, raw as u32, format!(".hword\t{:#06X}", raw)),
+        };
+        let mut line = Disassembly::new(addr, State::THUMB, raw as u32, without_raw(&inst));
+        let pc = addr.wrapping_add(4);
+        match inst.opcode() {
+            ThumbOpcode::BranchConditionOffs => {
+                self.with_branch(line, pc.wrapping_add(inst.offs9() as u32))
+            },
+            ThumbOpcode::BranchOffs => {
+                self.with_branch(line, pc.wrapping_add(inst.offs12() as u32))
+            },
+            ThumbOpcode::BranchLongOffs if !inst.is_low_offset_and_branch() => {
+                // Merge with the second half, if there is one.
+                let low = match self.memory.code_halfword(addr.wrapping_add(2)) {
+                    Some(low) if (low & 0xF800) == 0xF800 => low,
+                    _ => return line,
+                };
+                let high = ((inst.long_offs_part() << 21) >> 9) as u32;
+                let target = pc.wrapping_add(high).wrapping_add(((low & 0x7FF) as u32) << 1);
+                line.text = format!("bl\t{}", self.address_name(target, State::THUMB));
+                line.branch = Some(target);
+                line.raw |= (low as u32) << 16;
+                line.size = 4;
+                line
+            },
+            ThumbOpcode::LdrPcImm => {
+                self.with_literal(line, (pc & !3).wrapping_add(inst.imm10() as u32), 4)
+            },
+            _ => line,
+        }
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
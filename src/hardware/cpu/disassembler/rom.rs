@@ -0,0 +1,683 @@
+// License below.
+//! Implements a recursive-descent disassembler for GamePak ROMs.
+//!
+//! A ROM mixes ARM code, THUMB code, and data without telling
+//! which is which. Starting at the entry point in the ROM
+//! header, the disassembler therefore follows the control
+//! flow like the CPU would:
+//!
+//! - Branches add their targets, conditional ones and calls
+//!   also continue with the next instruction.
+//! - `bx`, `mov pc`, and `ldr pc` follow the target register
+//!   if its value is known, where `bx` also switches between
+//!   ARM and THUMB state.
+//! - Storing a pointer to `0x03007FFC` adds an IRQ handler.
+//!
+//! To know register values, it keeps track of constants
+//! loaded from literal pools or built by `mov` and `add`
+//! within a single run of instructions. This is enough for
+//! the usual `ldr r0, =func + 1; bx r0` and for the crt0 code
+//! installing the IRQ handler.
+//!
+//! Everything not reached this way is considered data. Our own
+//! `Assembler` turns the resulting listing back into the very
+//! same ROM. It is written for GNU `as` as well, which the
+//! ignored test `gnu_as_reassembles_roms` checks if installed.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use super::super::super::memory::GAME_PAK_WS0_ROM_FIRST;
+use super::super::arm7tdmi::{State, ArmCondition, ArmDPOP, ArmBSOP};
+use super::super::arminstruction::{ArmInstruction, ArmOpcode, ArmLdrhStrhOP};
+use super::super::thumbinstruction::{ThumbInstruction, ThumbOpcode, HiRegisterOp, LdrhStrhOp};
+use super::super::assembler::Assembler;
+use super::{CodeImage, CodeMemory, Disassembler, Disassembly, SymbolTable};
+
+/// The address the BIOS reads the IRQ handler from.
+pub const IRQ_HANDLER_POINTER: u32 = 0x03007FFC;
+
+// Label priorities, lower ones win.
+const LABEL_USER: u8 = 0;
+const LABEL_ENTRY: u8 = 1;
+const LABEL_CALL: u8 = 2;
+const LABEL_JUMP: u8 = 3;
+
+// Runs of equal data bytes at least this long become `.fill`.
+const MIN_FILL_LEN: usize = 32;
+
+/// Where a ROM contains code, as found by `RomDisassembler`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomTrace {
+    code: BTreeMap<u32, (State, u32)>,
+    literals: BTreeSet<u32>,
+    labels: SymbolTable,
+}
+
+impl RomTrace {
+    /// Gets the instruction set of the instruction starting at
+    /// the given address, or `None` if there is none.
+    pub fn state_at(&self, addr: u32) -> Option<State> { self.code.get(&addr).map(|&(s, _)| s) }
+
+    /// Checks whether the given address is part of any instruction.
+    pub fn is_code(&self, addr: u32) -> bool {
+        match self.code.range(..(addr.wrapping_add(1))).next_back() {
+            Some((&start, &(_, size))) => addr < start.wrapping_add(size),
+            None => false,
+        }
+    }
+
+    /// Gets the number of instructions found.
+    pub fn instruction_count(&self) -> usize { self.code.len() }
+
+    /// Gets the addresses of all literals loaded PC-relative.
+    pub fn literals(&self) -> &BTreeSet<u32> { &self.literals }
+
+    /// Gets the labels of all entry points and branch targets.
+    ///
+    /// THUMB code is labeled at even addresses.
+    pub fn labels(&self) -> &SymbolTable { &self.labels }
+}
+
+/// Disassembles a whole GamePak ROM.
+pub struct RomDisassembler<'a> {
+    image: CodeImage<'a>,
+    len: u32,
+    symbols: SymbolTable,
+    entries: Vec<(u32, State, String)>,
+}
+
+impl<'a> RomDisassembler<'a> {
+    /// Creates a disassembler for the given ROM content.
+    ///
+    /// The ROM header's entry point is an ARM entry point
+    /// labeled `start`.
+    pub fn new(rom: &'a [u8]) -> RomDisassembler<'a> {
+        RomDisassembler {
+            image: CodeImage::new(GAME_PAK_WS0_ROM_FIRST, rom),
+            len: rom.len() as u32,
+            symbols: SymbolTable::new(),
+            entries: vec![(GAME_PAK_WS0_ROM_FIRST, State::ARM, "start".to_string())],
+        }
+    }
+
+    /// Configures labels to use instead of generated ones.
+    ///
+    /// Bit 0 of THUMB code addresses may be set.
+    pub fn with_symbols(&mut self, symbols: &SymbolTable) -> &mut RomDisassembler<'a> {
+        self.symbols = symbols.clone();
+        self
+    }
+
+    /// Adds another entry point, e.g. of code only reached
+    /// by computed branches.
+    pub fn with_entry(&mut self, addr: u32, state: State, name: &str) -> &mut RomDisassembler<'a> {
+        self.entries.push((addr, state, name.to_string()));
+        self
+    }
+
+    /// Finds all code reachable from the entry points.
+    pub fn trace(&self) -> RomTrace {
+        let mut tracer = Tracer {
+            image: &self.image,
+            end: GAME_PAK_WS0_ROM_FIRST.wrapping_add(self.len),
+            code: BTreeMap::new(),
+            literals: BTreeSet::new(),
+            labels: BTreeMap::new(),
+            pending: Vec::new(),
+        };
+        for &(addr, state, ref name) in &self.entries {
+            tracer.add_entry(addr, state, name);
+        }
+        while let Some((addr, state)) = tracer.pending.pop() {
+            tracer.walk(addr, state);
+        }
+        tracer.finish(&self.symbols)
+    }
+
+    /// Traces the ROM and creates an assembly listing of it.
+    pub fn listing(&self) -> String {
+        let trace = self.trace();
+        let mut out = Listing {
+            trace: &trace,
+            dasm: Disassembler::new(&self.image),
+            plain: Disassembler::new(&self.image),
+            image: &self.image,
+            text: String::new(),
+            state: None,
+        };
+        out.dasm.with_symbols(&trace.labels);
+        out.write(GAME_PAK_WS0_ROM_FIRST, GAME_PAK_WS0_ROM_FIRST.wrapping_add(self.len));
+        out.text
+    }
+}
+
+// How to continue after an instruction.
+enum Flow {
+    Next(u32), // Continue after this many bytes.
+    Stop(u32), // This many bytes of code, but nothing follows.
+    Invalid,   // Not an instruction at all.
+}
+
+struct Tracer<'a, 'b: 'a> {
+    image: &'a CodeImage<'b>,
+    end: u32,
+    code: BTreeMap<u32, (State, u32)>,
+    literals: BTreeSet<u32>,
+    labels: BTreeMap<u32, (u8, String)>,
+    pending: Vec<(u32, State)>,
+}
+
+impl<'a, 'b: 'a> Tracer<'a, 'b> {
+    fn is_rom(&self, addr: u32) -> bool { (addr >= GAME_PAK_WS0_ROM_FIRST) && (addr < self.end) }
+
+    fn label(&mut self, addr: u32, prio: u8, name: String) {
+        let better = self.labels.get(&addr).map_or(true, |&(p, _)| prio < p);
+        if better { self.labels.insert(addr, (prio, name)); }
+    }
+
+    fn add_entry(&mut self, addr: u32, state: State, name: &str) {
+        let addr = if state == State::ARM { addr & !3 } else { addr & !1 };
+        if !self.is_rom(addr) { return; }
+        self.label(addr, LABEL_ENTRY, name.to_string());
+        self.pending.push((addr, state));
+    }
+
+    // Follows a branch, where bit 0 of the target selects
+    // the state for `bx`-like branches.
+    fn branch(&mut self, target: u32, state: State, call: bool) {
+        let target = if state == State::ARM { target & !3 } else { target & !1 };
+        if !self.is_rom(target) { return; }
+        let (prefix, prio) = if call { ("sub", LABEL_CALL) } else { ("loc", LABEL_JUMP) };
+        self.label(target, prio, format!("{}_{:08X}", prefix, target));
+        self.pending.push((target, state));
+    }
+
+    fn exchange(&mut self, target: u32, call: bool) {
+        let state = if (target & 1) == 0 { State::ARM } else { State::THUMB };
+        self.branch(target, state, call);
+    }
+
+    fn literal(&mut self, addr: u32) -> Option<u32> {
+        if (addr & 3) != 0 { return None; }
+        let value = self.image.code_word(addr);
+        if value.is_some() { self.literals.insert(addr); }
+        value
+    }
+
+    fn store(&mut self, addr: Option<u32>, value: Option<u32>) {
+        if let (Some(addr), Some(value)) = (addr, value) {
+            // IWRAM is mirrored every 32 KiB.
+            if (addr & 0xFF00_7FFF) == IRQ_HANDLER_POINTER {
+                self.add_entry(value, State::ARM, "irq_handler");
+            }
+        }
+    }
+
+    fn is_free(&self, addr: u32, size: u32) -> bool {
+        let overlapping = self.code.range(..(addr.wrapping_add(size))).next_back();
+        overlapping.map_or(true, |(&start, &(_, len))| start.wrapping_add(len) <= addr)
+    }
+
+    fn walk(&mut self, mut addr: u32, state: State) {
+        let mut regs: [Option<u32>; 16] = [None; 16];
+        while self.is_rom(addr) {
+            if let Some(&(s, _)) = self.code.get(&addr) {
+                if s != state { warn!("Code at {:#010X} is used as both ARM and THUMB.", addr); }
+                return;
+            }
+            let flow = match state {
+                State::ARM => self.arm(addr, &mut regs),
+                State::THUMB => self.thumb(addr, &mut regs),
+            };
+            let (size, next) = match flow {
+                Flow::Next(size) => (size, true),
+                Flow::Stop(size) => (size, false),
+                Flow::Invalid => return,
+            };
+            if !self.is_free(addr, size) {
+                warn!("Code at {:#010X} overlaps other instructions.", addr);
+                return;
+            }
+            self.code.insert(addr, (state, size));
+            if !next { return; }
+            addr = addr.wrapping_add(size);
+        }
+    }
+
+    fn arm(&mut self, addr: u32, regs: &mut [Option<u32>; 16]) -> Flow {
+        let inst = match self.image.code_word(addr).map(ArmInstruction::decode) {
+            Some(Ok(i)) => i,
+            _ => return Flow::Invalid,
+        };
+        let cond = inst.condition();
+        if (inst.opcode() == ArmOpcode::Unknown) || (cond == ArmCondition::NV) { return Flow::Invalid; }
+
+        let pc = addr.wrapping_add(8);
+        let read = |regs: &[Option<u32>; 16], r: usize| if r == 15 { Some(pc) } else { regs[r] };
+        let always = cond == ArmCondition::AL;
+        // A `mov lr, pc` makes the next instruction a call.
+        let call = regs[14] == Some(addr.wrapping_add(4));
+        let jump = if always && !call { Flow::Stop(4) } else { Flow::Next(4) };
+        let mut written: Option<(usize, Option<u32>)> = None;
+
+        match inst.opcode() {
+            ArmOpcode::B_BL => {
+                let link = inst.is_branch_with_link();
+                self.branch(pc.wrapping_add(inst.branch_offset() as u32), State::ARM, link);
+                if link { clobber_call(regs); return Flow::Next(4); }
+                return if always { Flow::Stop(4) } else { Flow::Next(4) };
+            },
+            ArmOpcode::BX => {
+                if let Some(target) = read(regs, inst.Rm()) { self.exchange(target, call); }
+                if call { clobber_call(regs); }
+                return jump;
+            },
+            ArmOpcode::DataProcessing => {
+                let op = inst.dpop();
+                if op.is_test() { return Flow::Next(4); }
+                let op2 = if !inst.is_shift_field_register() { Some(inst.rotated_immediate() as u32) }
+                    else if (inst.raw() & 0xFF0) == 0 { read(regs, inst.Rm()) }
+                    else { None };
+                let value = match (op, read(regs, inst.Rn()), op2) {
+                    (ArmDPOP::MOV, _, b) => b,
+                    (ArmDPOP::MVN, _, b) => b.map(|b| !b),
+                    (ArmDPOP::ADD, Some(a), Some(b)) => Some(a.wrapping_add(b)),
+                    (ArmDPOP::SUB, Some(a), Some(b)) => Some(a.wrapping_sub(b)),
+                    (ArmDPOP::RSB, Some(a), Some(b)) => Some(b.wrapping_sub(a)),
+                    (ArmDPOP::AND, Some(a), Some(b)) => Some(a & b),
+                    (ArmDPOP::EOR, Some(a), Some(b)) => Some(a ^ b),
+                    (ArmDPOP::ORR, Some(a), Some(b)) => Some(a | b),
+                    (ArmDPOP::BIC, Some(a), Some(b)) => Some(a & !b),
+                    _ => None,
+                };
+                if inst.Rd() == 15 {
+                    // `movs pc, lr` and friends return from exceptions.
+                    if let (Some(target), false) = (value, inst.is_setting_flags()) {
+                        self.branch(target, State::ARM, call);
+                    }
+                    if call { clobber_call(regs); }
+                    return jump;
+                }
+                written = Some((inst.Rd(), value));
+            },
+            ArmOpcode::LDR_STR => {
+                let base = read(regs, inst.Rn());
+                let offset = if inst.is_offset_field_immediate() { Some(inst.offset12() as u32) } else { None };
+                let target = if inst.is_pre_indexed() { add(base, offset) } else { base };
+                if inst.is_load() {
+                    let value = match target {
+                        Some(t) if (inst.Rn() == 15) && !inst.is_transfering_bytes() => self.literal(t),
+                        _ => None,
+                    };
+                    if inst.Rd() == 15 {
+                        if let Some(target) = value { self.branch(target, State::ARM, call); }
+                        if call { clobber_call(regs); }
+                        return jump;
+                    }
+                    written = Some((inst.Rd(), value));
+                } else if !inst.is_transfering_bytes() {
+                    self.store(target, read(regs, inst.Rd()));
+                }
+                if !inst.is_pre_indexed() || inst.is_auto_incrementing() { regs[inst.Rn()] = None; }
+            },
+            ArmOpcode::LDRH_STRH_Reg | ArmOpcode::LDRH_STRH_Imm => {
+                if inst.is_load() {
+                    if inst.Rd() == 15 { return jump; }
+                    regs[inst.Rd()] = None;
+                }
+                if !inst.is_pre_indexed() || inst.is_auto_incrementing() { regs[inst.Rn()] = None; }
+            },
+            ArmOpcode::LDM_STM => {
+                if inst.is_auto_incrementing() { regs[inst.Rn()] = None; }
+                if inst.is_load() {
+                    let list = inst.register_map();
+                    for (r, reg) in regs.iter_mut().enumerate() {
+                        if (list & (1 << r)) != 0 { *reg = None; }
+                    }
+                    if (list & 0x8000) != 0 { return jump; }
+                }
+            },
+            ArmOpcode::MUL_MLA => { written = Some((inst.Rn(), None)); },
+            ArmOpcode::MRS | ArmOpcode::SWP => { written = Some((inst.Rd(), None)); },
+            ArmOpcode::MULL_MLAL => { regs[inst.Rn()] = None; regs[inst.Rd()] = None; },
+            ArmOpcode::MRC_MCR if inst.is_load() => { written = Some((inst.Rd(), None)); },
+            ArmOpcode::SWI => { for reg in regs[0..4].iter_mut() { *reg = None; } },
+            _ => {},
+        }
+        if let Some((r, value)) = written {
+            if r != 15 { regs[r] = if always { value } else { None }; }
+        }
+        Flow::Next(4)
+    }
+
+    fn thumb(&mut self, addr: u32, regs: &mut [Option<u32>; 16]) -> Flow {
+        let inst = match self.image.code_halfword(addr).map(ThumbInstruction::decode) {
+            Some(Ok(i)) => i,
+            _ => return Flow::Invalid,
+        };
+        let pc = addr.wrapping_add(4);
+        let read = |regs: &[Option<u32>; 16], r: usize| if r == 15 { Some(pc) } else { regs[r] };
+        let call = regs[14].map(|lr| lr & !1) == Some(addr.wrapping_add(2));
+        let jump = if call { Flow::Next(2) } else { Flow::Stop(2) };
+
+        match inst.opcode() {
+            ThumbOpcode::BranchConditionOffs => {
+                self.branch(pc.wrapping_add(inst.offs9() as u32), State::THUMB, false);
+            },
+            ThumbOpcode::BranchOffs => {
+                self.branch(pc.wrapping_add(inst.offs12() as u32), State::THUMB, false);
+                return Flow::Stop(2);
+            },
+            ThumbOpcode::BranchLongOffs => {
+                // Only both halves together make a `bl`.
+                if inst.is_low_offset_and_branch() { return Flow::Invalid; }
+                let low = match self.image.code_halfword(addr.wrapping_add(2)) {
+                    Some(low) if (low & 0xF800) == 0xF800 => low,
+                    _ => return Flow::Invalid,
+                };
+                let high = ((inst.long_offs_part() << 21) >> 9) as u32;
+                let target = pc.wrapping_add(high).wrapping_add(((low & 0x7FF) as u32) << 1);
+                self.branch(target, State::THUMB, true);
+                // Calls via `bx` veneers like `_call_via_r3`.
+                let veneer = self.image.code_halfword(target).map(ThumbInstruction::decode);
+                if let Some(Ok(bx)) = veneer {
+                    let is_bx = (bx.opcode() == ThumbOpcode::HiRegOpBx) && (bx.op_HiRegOpBx() == HiRegisterOp::BxRsHs);
+                    if let (true, Some(target)) = (is_bx, regs[bx.Hs()]) { self.exchange(target, true); }
+                }
+                clobber_call(regs);
+                return Flow::Next(4);
+            },
+            ThumbOpcode::HiRegOpBx => {
+                let (hd, value) = (inst.Hd(), read(regs, inst.Hs()));
+                match inst.op_HiRegOpBx() {
+                    HiRegisterOp::BxRsHs => {
+                        if let Some(target) = value { self.exchange(target, call); }
+                        if call { clobber_call(regs); }
+                        return jump;
+                    },
+                    HiRegisterOp::CmpFlags => {},
+                    _ if hd == 15 => {
+                        if inst.op_HiRegOpBx() == HiRegisterOp::MovNoFlags {
+                            if let Some(target) = value { self.branch(target, State::THUMB, call); }
+                        }
+                        if call { clobber_call(regs); }
+                        return jump;
+                    },
+                    HiRegisterOp::MovNoFlags => { regs[hd] = value; },
+                    HiRegisterOp::AddNoFlags => { regs[hd] = add(regs[hd], value); },
+                }
+            },
+            ThumbOpcode::LdrPcImm => {
+                regs[inst.Rm()] = self.literal((pc & !3).wrapping_add(inst.imm10() as u32));
+            },
+            ThumbOpcode::CalcAddrImm => {
+                let base = if inst.is_base_SP() { None } else { Some(pc & !3) };
+                regs[inst.Rm()] = add(base, Some(inst.imm10() as u32));
+            },
+            ThumbOpcode::DataProcessingFlags => {
+                let (rd, imm) = (inst.Rm(), inst.imm8() as u32);
+                match inst.dpop_DataProcessingFlags() {
+                    ArmDPOP::MOV => { regs[rd] = Some(imm); },
+                    ArmDPOP::ADD => { regs[rd] = regs[rd].map(|x| x.wrapping_add(imm)); },
+                    ArmDPOP::SUB => { regs[rd] = regs[rd].map(|x| x.wrapping_sub(imm)); },
+                    _ => {},
+                }
+            },
+            ThumbOpcode::MoveShiftedReg => {
+                regs[inst.Rd()] = match inst.bsop_MoveShiftedReg() {
+                    ArmBSOP::NOP => regs[inst.Rs()],
+                    ArmBSOP::LSL_Imm(x) => regs[inst.Rs()].map(|v| v << x),
+                    ArmBSOP::LSR_Imm(x) => regs[inst.Rs()].map(|v| v >> x),
+                    _ => None,
+                };
+            },
+            ThumbOpcode::AddSub => {
+                let b = if inst.is_Rn_immediate() { Some(inst.Rn() as u32) } else { regs[inst.Rn()] };
+                regs[inst.Rd()] = match (inst.dpop_AddSub(), regs[inst.Rs()], b) {
+                    (ArmDPOP::ADD, Some(a), Some(b)) => Some(a.wrapping_add(b)),
+                    (ArmDPOP::SUB, Some(a), Some(b)) => Some(a.wrapping_sub(b)),
+                    _ => None,
+                };
+            },
+            ThumbOpcode::AluOperation => {
+                if !inst.dpop_bsop_AluOperation().0.is_test() { regs[inst.Rd()] = None; }
+            },
+            ThumbOpcode::AluMul => { regs[inst.Rd()] = None; },
+            ThumbOpcode::LdrStrImm => {
+                if inst.is_load() { regs[inst.Rd()] = None; }
+                else if !inst.is_transfering_bytes_LdrStrImm() {
+                    let target = add(regs[inst.Rs()], Some(inst.imm7() as u32));
+                    self.store(target, regs[inst.Rd()]);
+                }
+            },
+            ThumbOpcode::LdrStrReg | ThumbOpcode::LdrhStrhImm => {
+                if inst.is_load() { regs[inst.Rd()] = None; }
+            },
+            ThumbOpcode::LdrhStrhReg => {
+                if inst.op_LdrhStrhReg() != LdrhStrhOp::STRH { regs[inst.Rd()] = None; }
+            },
+            ThumbOpcode::LdrStrSpImm => {
+                if inst.is_load() { regs[inst.Rm()] = None; }
+            },
+            ThumbOpcode::LdmStmRegs => {
+                regs[inst.Rm()] = None;
+                if inst.is_load() { clobber_list(regs, inst.register_list()); }
+            },
+            ThumbOpcode::PushPopRegs => {
+                regs[13] = None;
+                if inst.is_load() {
+                    clobber_list(regs, inst.register_list());
+                    if inst.is_storing_LR_loading_PC() { return Flow::Stop(2); }
+                }
+            },
+            ThumbOpcode::AddSpOffs => { regs[13] = None; },
+            ThumbOpcode::SoftwareInterrupt => { for reg in regs[0..4].iter_mut() { *reg = None; } },
+        }
+        Flow::Next(2)
+    }
+
+    fn finish(self, symbols: &SymbolTable) -> RomTrace {
+        let mut labels = self.labels;
+        for (&addr, name) in symbols.iter() {
+            // Linkers mark THUMB functions by setting bit 0.
+            let thumb = ((addr & 1) != 0) && (self.code.get(&(addr & !1)).map(|&(s, _)| s) == Some(State::THUMB));
+            let addr = if thumb { addr & !1 } else { addr };
+            if (addr >= GAME_PAK_WS0_ROM_FIRST) && (addr < self.end) {
+                labels.insert(addr, (LABEL_USER, name.clone()));
+            }
+        }
+        let mut trace = RomTrace {
+            code: self.code,
+            literals: self.literals,
+            labels: SymbolTable::new(),
+        };
+        for (addr, (_, name)) in labels {
+            // Labels can only be defined between instructions.
+            if !trace.is_code(addr) || trace.code.contains_key(&addr) { trace.labels.with_symbol(addr, &name); }
+        }
+        trace
+    }
+}
+
+fn add(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) { (Some(a), Some(b)) => Some(a.wrapping_add(b)), _ => None }
+}
+
+fn clobber_list(regs: &mut [Option<u32>; 16], list: u8) {
+    for (r, reg) in regs[0..8].iter_mut().enumerate() {
+        if (list & (1 << r)) != 0 { *reg = None; }
+    }
+}
+
+// Forgets all registers a called function may change.
+fn clobber_call(regs: &mut [Option<u32>; 16]) {
+    for r in &[0, 1, 2, 3, 12, 14] { regs[*r] = None; }
+}
+
+struct Listing<'a, 'b: 'a> {
+    trace: &'a RomTrace,
+    dasm: Disassembler<'a, CodeImage<'b>>,
+    plain: Disassembler<'a, CodeImage<'b>>,
+    image: &'a CodeImage<'b>,
+    text: String,
+    state: Option<State>,
+}
+
+impl<'a, 'b: 'a> Listing<'a, 'b> {
+    fn write(&mut self, first: u32, end: u32) {
+        self.text.push_str("@ Disassembled GamePak ROM. To reassemble it, run e.g.\n");
+        self.text.push_str("@   arm-none-eabi-as -mcpu=arm7tdmi -o rom.o rom.s\n");
+        self.text.push_str("@   arm-none-eabi-objcopy -O binary rom.o rom.gba\n");
+        self.text.push_str("\t.syntax unified\n");
+
+        let mut addr = first;
+        while addr < end {
+            let code = self.trace.code.get(&addr).cloned();
+            // Switch first, so labels of THUMB code are THUMB labels.
+            if let Some((state, _)) = code { self.switch(state); }
+            if let Some(name) = self.trace.labels.name(addr) { self.text.push_str(&format!("{}:\n", name)); }
+            addr = match code {
+                Some((state, size)) => { self.code(addr, state); addr + size },
+                None => self.data(addr, end),
+            };
+        }
+    }
+
+    fn switch(&mut self, state: State) {
+        if self.state == Some(state) { return; }
+        self.text.push_str(if state == State::ARM { "\t.arm\n" } else { "\t.thumb\n" });
+        self.state = Some(state);
+    }
+
+    fn code(&mut self, addr: u32, state: State) {
+        let line = match self.dasm.instruction(addr, state) { Some(l) => l, None => return };
+        if self.is_reassemblable(&line) {
+            self.text.push_str(&format!("\t{}\t@ {:08X}", line.text(), addr));
+            if let Some(c) = line.comment() { self.text.push_str(&format!(": {}", c)); }
+        } else if state == State::ARM {
+            self.text.push_str(&format!("\t.word\t{:#010X}\t@ {:08X}: {}", line.raw(), addr, line.text()));
+        } else if line.size() == 4 {
+            self.text.push_str(&format!("\t.hword\t{:#06X}, {:#06X}\t@ {:08X}: {}",
+                line.raw() & 0xFFFF, line.raw() >> 16, addr, line.text()));
+        } else {
+            self.text.push_str(&format!("\t.hword\t{:#06X}\t@ {:08X}: {}", line.raw(), addr, line.text()));
+        }
+        self.text.push('\n');
+    }
+
+    // Checks whether assembling the disassembly results in the
+    // very same instruction, for both GNU `as` and ourselves.
+    fn is_reassemblable(&self, line: &Disassembly) -> bool {
+        if let Some(target) = line.branch_target() {
+            if self.trace.labels.name(target).is_none() { return false; }
+        }
+        if !is_gas_compatible(line) { return false; }
+        let plain = match self.plain.instruction(line.addr(), line.state()) { Some(l) => l, None => return false };
+        let asm = match Assembler::new(line.addr()).with_state(line.state()).assemble(plain.text()) {
+            Ok(a) => a,
+            Err(_) => return false,
+        };
+        let raw = asm.bytes().iter().rev().fold(0, |x, &b| (x << 8) | (b as u32));
+        (asm.len() == (line.size() as usize)) && (raw == line.raw())
+    }
+
+    fn byte(&self, addr: u32) -> u8 {
+        self.image.code_halfword(addr & !1).map_or(0, |h| (h >> (8 * (addr & 1))) as u8)
+    }
+
+    // Writes data up to the next label or code.
+    // Returns where the data ended.
+    fn data(&mut self, addr: u32, end: u32) -> u32 {
+        let mut stop = addr + 1;
+        while (stop < end) && self.trace.labels.name(stop).is_none() && !self.trace.code.contains_key(&stop) {
+            stop += 1;
+        }
+        let mut addr = addr;
+        while addr < stop {
+            addr = self.data_line(addr, stop);
+        }
+        addr
+    }
+
+    fn data_line(&mut self, addr: u32, stop: u32) -> u32 {
+        if self.trace.literals.contains(&addr) && ((stop - addr) >= 4) {
+            let value = self.image.code_word(addr).unwrap_or(0);
+            self.text.push_str(&format!("\t.word\t{:#010X}", value));
+            let name = self.dasm.label(value, State::ARM).or_else(|| self.dasm.label(value & !1, State::THUMB));
+            if let Some(name) = name { self.text.push_str(&format!("\t@ {}", name)); }
+            self.text.push('\n');
+            return addr + 4;
+        }
+        let first = self.byte(addr);
+        let run = (addr..stop).take_while(|&a| self.byte(a) == first).count();
+        if run >= MIN_FILL_LEN {
+            self.text.push_str(&format!("\t.fill\t{}, 1, {:#04X}\n", run, first));
+            return addr + (run as u32);
+        }
+        let mut bytes = Vec::new();
+        let mut next = addr;
+        while (next < stop) && (bytes.len() < 16) {
+            let run = (next..stop).take_while(|&a| self.byte(a) == self.byte(next)).count();
+            if ((next != addr) && self.trace.literals.contains(&next)) || (run >= MIN_FILL_LEN) { break; }
+            bytes.push(format!("{:#04X}", self.byte(next)));
+            next += 1;
+        }
+        self.text.push_str(&format!("\t.byte\t{}\n", bytes.join(", ")));
+        next
+    }
+}
+
+// Checks for encodings GNU `as` refuses to produce for ARMv4T.
+fn is_gas_compatible(line: &Disassembly) -> bool {
+    if line.state() == State::ARM {
+        let inst = match ArmInstruction::decode(line.raw()) { Ok(i) => i, Err(_) => return true };
+        let writeback = !inst.is_pre_indexed() || inst.is_auto_incrementing();
+        match inst.opcode() {
+            ArmOpcode::CDP | ArmOpcode::MRC_MCR | ArmOpcode::LDC_STC => false,
+            ArmOpcode::LDR_STR => !writeback || (inst.Rn() != inst.Rd()),
+            ArmOpcode::LDRH_STRH_Reg | ArmOpcode::LDRH_STRH_Imm => {
+                let signed_store = !inst.is_load() && (inst.ldrh_strh_op() != ArmLdrhStrhOP::UH);
+                !signed_store && (!writeback || (inst.Rn() != inst.Rd()))
+            },
+            ArmOpcode::LDM_STM => {
+                let user = inst.is_register_block_transfer() && ((inst.register_map() & 0x8000) == 0 || !inst.is_load());
+                !(user && inst.is_auto_incrementing())
+            },
+            _ => true,
+        }
+    } else {
+        let inst = match ThumbInstruction::decode(line.raw() as u16) { Ok(i) => i, Err(_) => return true };
+        match inst.opcode() {
+            ThumbOpcode::HiRegOpBx => (inst.op_HiRegOpBx() == HiRegisterOp::BxRsHs) || (inst.Hd() >= 8) || (inst.Hs() >= 8),
+            ThumbOpcode::PushPopRegs => (inst.register_list() != 0) || inst.is_storing_LR_loading_PC(),
+            ThumbOpcode::LdmStmRegs => {
+                let list = inst.register_list();
+                (list != 0) && !(inst.is_load() && ((list & (1 << inst.Rm())) != 0))
+            },
+            _ => true,
+        }
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,103 @@
+// License below.
+//! Implements symbol tables mapping addresses to labels.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::path::Path;
+
+/// Maps addresses to label names.
+///
+/// Symbol files contain one symbol per line, which is a
+/// hexadecimal address followed by a name, e.g.
+/// `08000000 main`. This is the format of no$gba `.sym`
+/// files. Anything between address and name, such as the
+/// symbol type `nm` prints, is ignored. Empty lines, lines
+/// starting with `;` or `#`, and names starting with `.`,
+/// which no$gba uses for directives like `.thumb`, are
+/// skipped.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SymbolTable {
+    names: BTreeMap<u32, String>,
+}
+
+impl SymbolTable {
+    /// Creates an empty symbol table.
+    pub fn new() -> SymbolTable { SymbolTable { names: BTreeMap::new() } }
+
+    /// Adds a symbol, replacing any other one at the same address.
+    pub fn with_symbol(&mut self, addr: u32, name: &str) -> &mut SymbolTable {
+        self.names.insert(addr, name.to_string());
+        self
+    }
+
+    /// Gets the name of the symbol at the given address.
+    pub fn name(&self, addr: u32) -> Option<&str> { self.names.get(&addr).map(|n| n.as_str()) }
+
+    /// Gets the number of symbols.
+    pub fn len(&self) -> usize { self.names.len() }
+
+    /// Checks whether there are no symbols at all.
+    pub fn is_empty(&self) -> bool { self.names.is_empty() }
+
+    /// Iterates over all symbols sorted by address.
+    pub fn iter<'a>(&'a self) -> btree_map::Iter<'a, u32, String> { self.names.iter() }
+
+    /// Adds all symbols of a symbol file's content.
+    ///
+    /// # Returns
+    /// - `Ok`: All symbols have been added.
+    /// - `Err`: A description of the first invalid line.
+    ///   Symbols of all lines before have been added.
+    pub fn parse(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') { continue; }
+            let mut words = line.split_whitespace();
+            let addr = words.next().unwrap_or("");
+            let name = words.last().unwrap_or("");
+            let hex = if addr.starts_with("0x") || addr.starts_with("0X") { &addr[2..] } else { addr };
+            let addr = match u32::from_str_radix(hex, 16) {
+                Ok(a) if !name.is_empty() => a,
+                _ => return Err(format!("Invalid symbol `{}` in line {}.", line, i + 1)),
+            };
+            if !name.starts_with('.') { self.with_symbol(addr, name); }
+        }
+        Ok(())
+    }
+
+    /// Adds all symbols of a symbol file.
+    ///
+    /// Invalid symbols result in an error of kind `InvalidData`.
+    pub fn load_from_file(&mut self, fp: &Path) -> io::Result<()> {
+        let mut text = String::new();
+        try!(try!(File::open(fp)).read_to_string(&mut text));
+        self.parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,156 @@
+// License below.
+#![allow(missing_docs)]
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::Command;
+use super::*;
+use super::super::arm7tdmi::State;
+use super::super::assembler::Assembler;
+
+const SOURCE: &'static str = "
+    start:  ldr  r0, =0x04000000    @ 0x08000000
+            ldr  r2, =func + 1
+            b    start
+    .pool                           @ 0x0800000C
+    .thumb
+    func:   ldr  r1, =0x04000000    @ 0x08000014
+            bl   func
+            beq  func
+            .hword 0xF000           @ Half a `bl`.
+";
+
+fn symbols() -> SymbolTable {
+    let mut symbols = SymbolTable::new();
+    symbols.parse("; Comment.\n08000000 main\n0x08000015 T func\n08000014 .thumb\n").unwrap();
+    symbols
+}
+
+#[test]
+fn symbol_files() {
+    let symbols = symbols();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols.name(0x0800_0000), Some("main"));
+    assert_eq!(symbols.name(0x0800_0015), Some("func"));
+    assert!(SymbolTable::new().parse("08000000 main\nmain").is_err());
+}
+
+#[test]
+fn resolves_addresses() {
+    let asm = Assembler::new(0x0800_0000).assemble(SOURCE).unwrap();
+    let image = CodeImage::new(asm.origin(), asm.bytes());
+    let symbols = symbols();
+    let mut dasm = Disassembler::new(&image);
+    dasm.with_symbols(&symbols);
+
+    let lines = dasm.range(0x0800_0000..0x0800_000C, State::ARM);
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0].literal(), Some((0x0800_000C, 0x0400_0000)));
+    assert_eq!(format!("{}", lines[0]), "08000000  E59F0004\tldr\tR0, [PC, #+4]\t@ 0x0800000C = 0x4000000");
+    assert_eq!(lines[1].comment(), Some("0x08000010 = 0x8000015 <func>"));
+    assert_eq!(lines[2].text(), "b\tmain");
+    assert_eq!(lines[2].branch_target(), Some(0x0800_0000));
+
+    let lines = dasm.range(0x0800_0014..0x0800_001E, State::THUMB);
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0].literal(), Some((0x0800_0020, 0x0400_0000)));
+    assert_eq!(format!("{}", lines[1]), "08000016  F7FF FFFD\tbl\tfunc");
+    assert_eq!(lines[1].branch_target(), Some(0x0800_0014));
+    assert_eq!(lines[2].text(), "beq\tfunc");
+    assert_eq!(lines[3].size(), 2);
+    assert_eq!(lines[3].branch_target(), None);
+
+    let listing = dasm.listing(0x0800_0014..0x0800_0016, State::THUMB);
+    assert_eq!(listing, "func:\n08000014  4902\tldr\tR1, [PC, #8]\t@ 0x08000020 = 0x4000000\n");
+}
+
+const ROM: &'static str = "
+            b    init                   @ ROM header entry point.
+            .word 0x12345678
+    init:   ldr  r0, =main + 1
+            bx   r0
+    .pool
+    .thumb
+    main:   ldr  r0, =irq
+            ldr  r1, =0x03007FFC
+            str  r0, [r1]
+            bl   func
+    loop:   b    loop
+    func:   movs r0, #1
+            bx   lr
+    .pool
+            .byte 1, 2, 3
+            .fill 40, 1, 0xFF
+    .arm
+    irq:    bx   lr
+";
+
+#[test]
+fn traces_roms() {
+    let asm = Assembler::new(0x0800_0000).assemble(ROM).unwrap();
+    let addr = |name| asm.symbol(name).unwrap();
+    let mut symbols = SymbolTable::new();
+    symbols.with_symbol(addr("func") | 1, "func");
+    let mut dasm = RomDisassembler::new(asm.bytes());
+    dasm.with_symbols(&symbols);
+
+    let trace = dasm.trace();
+    assert_eq!(trace.state_at(addr("init")), Some(State::ARM));
+    assert_eq!(trace.state_at(addr("main")), Some(State::THUMB));
+    assert_eq!(trace.state_at(addr("loop")), Some(State::THUMB));
+    assert_eq!(trace.state_at(addr("irq")), Some(State::ARM));
+    assert!(!trace.is_code(0x0800_0004));
+    assert!(!trace.is_code(addr("irq") - 4));
+    assert_eq!(trace.labels().name(addr("irq")), Some("irq_handler"));
+    assert_eq!(trace.labels().name(addr("func")), Some("func"));
+    assert_eq!(trace.labels().name(addr("loop")), Some(&format!("loc_{:08X}", addr("loop"))[..]));
+
+    let listing = dasm.listing();
+    assert!(listing.contains("\tbl\tfunc\t"), "{}", listing);
+    let again = Assembler::new(0x0800_0000).assemble(&listing).unwrap();
+    assert_eq!(again.bytes(), asm.bytes(), "{}", listing);
+}
+
+#[test]
+#[ignore]
+fn gnu_as_reassembles_roms() {
+    // Needs the GNU ARM toolchain, thus ignored by default.
+    let asm = Assembler::new(0x0800_0000).assemble(ROM).unwrap();
+    let listing = RomDisassembler::new(asm.bytes()).listing();
+    let dir = env::temp_dir();
+    let (src, obj, bin) = (dir.join("gbars-rom.s"), dir.join("gbars-rom.o"), dir.join("gbars-rom.gba"));
+    File::create(&src).and_then(|mut f| f.write_all(listing.as_bytes())).unwrap();
+
+    let status = match Command::new("arm-none-eabi-as").arg("-mcpu=arm7tdmi").arg("-o").arg(&obj).arg(&src).status() {
+        Ok(s) => s,
+        Err(e) => { println!("Skipped, can't run arm-none-eabi-as: {}", e); return; }
+    };
+    assert!(status.success(), "{}", listing);
+    let status = Command::new("arm-none-eabi-objcopy").arg("-O").arg("binary").arg(&obj).arg(&bin).status();
+    assert!(status.unwrap().success());
+
+    let mut bytes = Vec::new();
+    File::open(&bin).and_then(|mut f| f.read_to_end(&mut bytes)).unwrap();
+    assert_eq!(&bytes[..], asm.bytes(), "{}", listing);
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -15,13 +15,19 @@
 #![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
 #![warn(missing_docs)]
 
+pub use self::cpumemory::*;
 pub use self::arm7tdmi::*;
 pub use self::arminstruction::*;
 pub use self::thumbinstruction::*;
+pub use self::assembler::*;
+pub use self::disassembler::*;
 
+pub mod cpumemory;
 pub mod arm7tdmi;
 pub mod arminstruction;
 pub mod thumbinstruction;
+pub mod assembler;
+pub mod disassembler;
 
 
 /*
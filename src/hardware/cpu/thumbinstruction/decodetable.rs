@@ -0,0 +1,135 @@
+// License below.
+//! Implements table based decoding of THUMB state instructions.
+//!
+//! All THUMB instructions can be told apart by looking at
+//! bits 15-6 alone. These 10 bits index a table of decoding
+//! handlers, which replaces walking the mask chain of
+//! `ThumbInstruction::decode`.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::{ThumbInstruction, ThumbOpcode};
+use super::super::super::error::GbaError;
+
+/// Decodes a raw THUMB instruction of a known table entry.
+pub type ThumbDecodeFn = fn(u16) -> Result<ThumbInstruction, GbaError>;
+
+/// The opcode masks of `ThumbInstruction::decode`, in the same order.
+const THUMB_OPCODE_MASKS: &'static [(u16, u16, ThumbDecodeFn)] = &[
+    (0xF800, 0x1800, decode_add_sub),
+    (0xE000, 0x0000, decode_move_shifted_reg),
+    (0xE000, 0x2000, decode_data_processing_flags),
+    (0xFFC0, 0x4340, decode_alu_mul),
+    (0xFC00, 0x4000, decode_alu_operation),
+    (0xFC00, 0x4400, decode_hi_reg_op_bx),
+    (0xF800, 0x4800, decode_ldr_pc_imm),
+    (0xF200, 0x5000, decode_ldr_str_reg),
+    (0xF200, 0x5200, decode_ldrh_strh_reg),
+    (0xE000, 0x6000, decode_ldr_str_imm),
+    (0xF000, 0x8000, decode_ldrh_strh_imm),
+    (0xF000, 0x9000, decode_ldr_str_sp_imm),
+    (0xF000, 0xA000, decode_calc_addr_imm),
+    (0xFF00, 0xB000, decode_add_sp_offs),
+    (0xF600, 0xB400, decode_push_pop_regs),
+    (0xF000, 0xC000, decode_ldm_stm_regs),
+    (0xFF00, 0xDF00, decode_software_interrupt),
+    (0xFF00, 0xDE00, decode_invalid),
+    (0xF000, 0xD000, decode_branch_condition_offs),
+    (0xF800, 0xE000, decode_branch_offs),
+    (0xF000, 0xF000, decode_branch_long_offs),
+];
+
+macro_rules! thumb_decode_fns {
+    ($($name:ident => $op:ident),*) => {$(
+        fn $name(raw: u16) -> Result<ThumbInstruction, GbaError> {
+            Ok(ThumbInstruction { raw: raw, op: ThumbOpcode::$op })
+        }
+    )*}
+}
+
+thumb_decode_fns! {
+    decode_add_sub => AddSub, decode_move_shifted_reg => MoveShiftedReg,
+    decode_data_processing_flags => DataProcessingFlags, decode_alu_mul => AluMul,
+    decode_alu_operation => AluOperation, decode_hi_reg_op_bx => HiRegOpBx,
+    decode_ldr_pc_imm => LdrPcImm, decode_ldr_str_reg => LdrStrReg,
+    decode_ldrh_strh_reg => LdrhStrhReg, decode_ldr_str_imm => LdrStrImm,
+    decode_ldrh_strh_imm => LdrhStrhImm, decode_ldr_str_sp_imm => LdrStrSpImm,
+    decode_calc_addr_imm => CalcAddrImm, decode_add_sp_offs => AddSpOffs,
+    decode_push_pop_regs => PushPopRegs, decode_ldm_stm_regs => LdmStmRegs,
+    decode_software_interrupt => SoftwareInterrupt,
+    decode_branch_condition_offs => BranchConditionOffs,
+    decode_branch_offs => BranchOffs, decode_branch_long_offs => BranchLongOffs
+}
+
+fn decode_invalid(raw: u16) -> Result<ThumbInstruction, GbaError> {
+    Err(GbaError::InvalidThumbInstruction(raw))
+}
+
+/// A table mapping THUMB instructions to their decoding handlers.
+pub struct ThumbDecodeTable {
+    handlers: Vec<ThumbDecodeFn>,
+}
+
+impl ThumbDecodeTable {
+    /// Number of table entries.
+    pub const LEN: usize = 1024;
+
+    /// Builds the decoding table.
+    pub fn new() -> ThumbDecodeTable {
+        ThumbDecodeTable {
+            handlers: (0..ThumbDecodeTable::LEN).map(ThumbDecodeTable::handler_for_key).collect(),
+        }
+    }
+
+    /// Extracts bits 15-6 of an instruction as table index.
+    #[inline]
+    pub fn key(raw: u16) -> usize { (raw >> 6) as usize }
+
+    /// Gets the decoding handler of a table entry.
+    pub fn handler(&self, key: usize) -> ThumbDecodeFn { self.handlers[key] }
+
+    /// Decodes a raw 16-bit integer as a THUMB instruction.
+    ///
+    /// The result is always the same as the one of
+    /// `ThumbInstruction::decode`.
+    #[inline]
+    pub fn decode(&self, raw: u16) -> Result<ThumbInstruction, GbaError> {
+        (self.handlers[ThumbDecodeTable::key(raw)])(raw)
+    }
+
+    fn handler_for_key(key: usize) -> ThumbDecodeFn {
+        let raw = (key << 6) as u16;
+        for &(mask, value, handler) in THUMB_OPCODE_MASKS {
+            // No mask looks at bits 5-0, so the index alone
+            // decides which instruction this is.
+            if (raw & mask) == value { return handler; }
+        }
+        decode_invalid
+    }
+}
+
+impl Default for ThumbDecodeTable {
+    fn default() -> ThumbDecodeTable { ThumbDecodeTable::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
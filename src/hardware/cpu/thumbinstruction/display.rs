@@ -59,6 +59,8 @@ impl ThumbInstruction {
             ArmBSOP::LSL_Imm(x) => ("lsls", x),
             ArmBSOP::LSR_Imm(x) => ("lsrs", x),
             ArmBSOP::ASR_Imm(x) => ("asrs", x),
+            ArmBSOP::LSR_32     => ("lsrs", 32),
+            ArmBSOP::ASR_32     => ("asrs", 32),
             _ => unreachable!()
         };
         write!(f, "{}\t{}, {}, #{}", op, rd, rs, x)
@@ -66,7 +68,7 @@ impl ThumbInstruction {
 
     #[allow(non_snake_case)]
     fn fmt_DataProcessingFlags(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let rd  = Arm7Tdmi::register_name(self.Rd());
+        let rd  = Arm7Tdmi::register_name(self.Rm());
         let imm = self.imm8();
         let op  = self.dpop_DataProcessingFlags();
         let s   = if op == ArmDPOP::CMP { ' ' } else { 's' };
@@ -85,6 +87,7 @@ impl ThumbInstruction {
         let rs = Arm7Tdmi::register_name(self.Rs());
 
         if dpop == ArmDPOP::MOV { write!(f, "{}s\t{}, {}", bsop.name(), rd, rs) }
+        else if dpop == ArmDPOP::RSB { write!(f, "negs\t{}, {}", rd, rs) }
         else { write!(f, "{}{}\t{}, {}", dpop, if dpop.is_test() { ' ' } else { 's' }, rd, rs) }
     }
 
@@ -123,9 +126,9 @@ impl ThumbInstruction {
         let rn = Arm7Tdmi::register_name(self.Rn());
         let op = match self.op_LdrhStrhReg() {
             LdrhStrhOp::STRH => "strh",
+            LdrhStrhOp::LDSB => "ldrsb",
             LdrhStrhOp::LDRH => "ldrh",
-            LdrhStrhOp::LDSB => "ldsb",
-            LdrhStrhOp::LDSH => "ldsh",
+            LdrhStrhOp::LDSH => "ldrsh",
         };
         write!(f, "{}\t{}, [{}, {}]", op, rd, rs, rn)
     }
@@ -134,10 +137,10 @@ impl ThumbInstruction {
     fn fmt_LdrStrImm(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}{}\t{}, [{}, #{}]",
             if self.is_load() { "ldr" } else { "str" },
-            if self.is_transfering_bytes() { 'b' } else { ' ' },
+            if self.is_transfering_bytes_LdrStrImm() { 'b' } else { ' ' },
             Arm7Tdmi::register_name(self.Rd()),
             Arm7Tdmi::register_name(self.Rs()),
-            if self.is_transfering_bytes() { self.imm5() } else { self.imm7() },
+            if self.is_transfering_bytes_LdrStrImm() { self.imm5() } else { self.imm7() },
         )
     }
 
@@ -155,7 +158,7 @@ impl ThumbInstruction {
     fn fmt_LdrStrSpImm(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}\t{}, [SP, #{}]",
             if self.is_load() { "ldr" } else { "str" },
-            Arm7Tdmi::register_name(self.Rd()),
+            Arm7Tdmi::register_name(self.Rm()),
             self.imm10(),
         )
     }
@@ -163,14 +166,17 @@ impl ThumbInstruction {
     #[allow(non_snake_case)]
     fn fmt_CalcAddrImm(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "add\t{}, {}, #{}",
-            Arm7Tdmi::register_name(self.Rd()),
+            Arm7Tdmi::register_name(self.Rm()),
             if self.is_base_SP() { "SP" } else { "PC" },
             self.imm10(),
         )
     }
 
     #[allow(non_snake_case)]
-    fn fmt_AddSpOffs(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "add\tSP, #{}", self.offs9()) }
+    fn fmt_AddSpOffs(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let offs = ((self.raw & 0x7F) << 2) as i32;
+        write!(f, "add\tSP, #{}", if 0 != (self.raw & 0x80) { -offs } else { offs })
+    }
 
     #[allow(non_snake_case)]
     fn fmt_PushPopRegs(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -211,17 +217,17 @@ impl ThumbInstruction {
 
     #[allow(non_snake_case)]
     fn fmt_BranchConditionOffs(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "b{}\t{}", self.condition().assembly_name(), self.offs9())
+        write!(f, "b{}\t#{}", self.condition().assembly_name(), self.offs9() + 4) // Relative to B, not PC.
     }
 
     #[allow(non_snake_case)]
-    fn fmt_BranchOffs(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "b\t#{}", self.offs12()) }
+    fn fmt_BranchOffs(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "b\t#{}", self.offs12() + 4) }
 
     #[allow(non_snake_case)]
     fn fmt_BranchLongOffs(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let offs = self.long_offs_part();
-        if self.is_low_offset_and_branch() { write!(f, "bl1\t#{:010X}",  (offs <<  1) as u32) }
-        else                               { write!(f, "bl0\t#{:010X}", ((offs << 21) >> 10) as u32) }
+        if self.is_low_offset_and_branch() { write!(f, "bl1\t#{:#010X}",  (offs <<  1) as u32) }
+        else                               { write!(f, "bl0\t#{:#010X}", ((offs << 21) >> 9) as u32) }
     }
 }
 
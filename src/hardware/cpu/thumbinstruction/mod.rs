@@ -54,8 +54,13 @@ use super::arm7tdmi::exec::armdpop::*;
 use super::arm7tdmi::exec::armbsop::*;
 
 pub use self::display::*;
+pub use self::decodetable::*;
 
 pub mod display;
+pub mod decodetable;
+
+#[cfg(test)]
+mod test;
 
 
 /// A decoded THUMB high register operation.
@@ -73,8 +78,8 @@ pub enum HiRegisterOp {
 #[repr(u8)]
 pub enum LdrhStrhOp {
     #[doc = "Store halfword."]         STRH = 0,
-    #[doc = "Load unsigned halfword."] LDRH = 1,
-    #[doc = "Load signed byte."]       LDSB = 2,
+    #[doc = "Load signed byte."]       LDSB = 1,
+    #[doc = "Load unsigned halfword."] LDRH = 2,
     #[doc = "Load signed halfword."]   LDSH = 3,
 }
 
@@ -159,6 +164,16 @@ impl ThumbInstruction {
         Ok(ThumbInstruction { raw: raw, op: op })
     }
 
+    /// Get the decoded opcode of the THUMB instruction.
+    pub fn opcode(&self) -> ThumbOpcode {
+        self.op
+    }
+
+    /// Get the raw 16-bit integer this instruction was decoded from.
+    pub fn raw(&self) -> u16 {
+        self.raw
+    }
+
     /// Decodes the register operand index `Rd`.
     #[allow(non_snake_case)]
     pub fn Rd(&self) -> usize { ((self.raw     ) & 0b111) as usize }
@@ -291,6 +306,10 @@ impl ThumbInstruction {
     /// Checks whether the given load/store instruction transfers a single byte.
     pub fn is_transfering_bytes(&self) -> bool { 0 != (self.raw & (1 << 10)) }
 
+    /// Checks whether a `LdrStrImm` instruction transfers a single byte.
+    #[allow(non_snake_case)]
+    pub fn is_transfering_bytes_LdrStrImm(&self) -> bool { 0 != (self.raw & (1 << 12)) }
+
     /// Checks whether this load/store instruction transfers signed data.
     pub fn is_signed(&self) -> bool { self.is_transfering_bytes() }
 
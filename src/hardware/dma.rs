@@ -0,0 +1,270 @@
+// License below.
+//! Implements the GBA's four DMA channels.
+//!
+//! A DMA channel copies halfwords or words from one memory area
+//! to another without the CPU's help. Transfers start immediately,
+//! at VBlank, at HBlank, or at a channel specific special timing.
+//! DMA 1 and 2 use the special timing to refill the Direct Sound
+//! FIFOs with 4 words whenever they run low.
+//!
+//! The transfers themselves are performed by the bus. This module
+//! only keeps track of the channels' registers and internal state.
+//! VBlank and HBlank transfers are never started, as there is no
+//! LCD controller to signal them yet.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::ioregs::*;
+use super::memory::{Rom8, Rom16, Rom32, Ram8, Ram16};
+
+/// Number of DMA channels.
+pub const DMA_CHANNELS: usize = 4;
+
+/// Number of bytes of registers per DMA channel.
+const CHANNEL_REGISTERS_LEN: u32 = 12;
+
+const CNT_REPEAT: u16 = 1 << 9;
+const CNT_WORDS: u16 = 1 << 10;
+const CNT_IRQ: u16 = 1 << 14;
+const CNT_ENABLE: u16 = 1 << 15;
+
+
+/// When a DMA transfer starts, as selected by `DMAxCNT_H` bits 12-13.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaTiming {
+    #[doc = "Right after enabling the channel."] Immediate,
+    #[doc = "At the start of VBlank."]           VBlank,
+    #[doc = "At the start of each HBlank."]      HBlank,
+    #[doc = "Sound FIFO refills for DMA 1 and 2, video capture for DMA 3."] Special,
+}
+
+/// How an address changes after each transferred unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressControl {
+    #[doc = "Increment after each unit."]                 Increment,
+    #[doc = "Decrement after each unit."]                 Decrement,
+    #[doc = "Stay at the same address."]                  Fixed,
+    #[doc = "Increment, but reload on repeated transfers."] IncrementReload,
+}
+
+impl AddressControl {
+    fn from_bits(x: u16) -> AddressControl {
+        match x & 0b11 {
+            0 => AddressControl::Increment,
+            1 => AddressControl::Decrement,
+            2 => AddressControl::Fixed,
+            _ => AddressControl::IncrementReload,
+        }
+    }
+
+    /// Get the address delta for a given unit size.
+    pub fn step(self, unit: u32) -> u32 {
+        match self {
+            AddressControl::Increment | AddressControl::IncrementReload => unit,
+            AddressControl::Decrement => unit.wrapping_neg(),
+            AddressControl::Fixed     => 0,
+        }
+    }
+}
+
+
+/// Describes a single DMA transfer to be performed by the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaTransfer {
+    /// The channel performing the transfer.
+    pub channel: usize,
+    /// Address of the first unit to load.
+    pub source: u32,
+    /// Address of the first unit to store.
+    pub destination: u32,
+    /// Number of units to transfer.
+    pub units: u32,
+    /// Whether words or halfwords are transferred.
+    pub words: bool,
+    /// How the source address changes.
+    pub source_control: AddressControl,
+    /// How the destination address changes.
+    pub destination_control: AddressControl,
+}
+
+
+#[derive(Clone, Copy)]
+struct DmaChannel {
+    // Internal registers latched when enabling the channel.
+    source: u32,
+    destination: u32,
+    units: u32,
+}
+
+
+/// Emulates the registers and internal state of all DMA channels.
+pub struct Dma {
+    channels: [DmaChannel; DMA_CHANNELS],
+}
+
+impl Dma {
+    /// Creates new disabled DMA channels.
+    pub fn new() -> Dma {
+        Dma {
+            channels: [DmaChannel { source: 0, destination: 0, units: 0 }; DMA_CHANNELS],
+        }
+    }
+
+    /// Clears all internal state.
+    pub fn reset(&mut self) { *self = Dma::new(); }
+
+    /// Checks whether an IO register offset belongs to a DMA channel.
+    pub fn is_dma_register(offs: u32) -> bool {
+        (REG_DMA0SAD <= offs) & (offs < REG_DMA0SAD + (DMA_CHANNELS as u32) * CHANNEL_REGISTERS_LEN)
+    }
+
+    /// Reads a byte from a DMA register.
+    ///
+    /// Only `DMAxCNT_H` can be read back, all other
+    /// registers are write-only.
+    pub fn read_register(offs: u32, regs: &IoRegisters) -> u8 {
+        let rel = (offs - REG_DMA0SAD) % CHANNEL_REGISTERS_LEN;
+        match rel {
+            10 => regs.read_byte(offs) & 0xE0,
+            11 => regs.read_byte(offs) & if (offs - REG_DMA0SAD) / CHANNEL_REGISTERS_LEN == 3 { 0xFF } else { 0xF7 },
+            _  => 0,
+        }
+    }
+
+    /// Writes a byte to a DMA register and applies its effects.
+    ///
+    /// # Returns
+    /// `Some` transfer if the channel has been enabled with
+    /// immediate timing, and thus must start right now.
+    pub fn write_register(&mut self, offs: u32, b: u8, regs: &mut IoRegisters) -> Option<DmaTransfer> {
+        let n = ((offs - REG_DMA0SAD) / CHANNEL_REGISTERS_LEN) as usize;
+        let was_enabled = 0 != (Dma::control(n, regs) & CNT_ENABLE);
+        regs.write_byte(offs, b);
+        let cnt = Dma::control(n, regs);
+
+        // Enabling a channel latches its registers.
+        if !was_enabled & (0 != (cnt & CNT_ENABLE)) {
+            let base = Dma::base(n);
+            let src_mask = if n == 0 { 0x07FFFFFF } else { 0x0FFFFFFF };
+            let dst_mask = if n == 3 { 0x0FFFFFFF } else { 0x07FFFFFF };
+            let ch = &mut self.channels[n];
+            ch.source      = regs.read_word(base) & src_mask;
+            ch.destination = regs.read_word(base + 4) & dst_mask;
+            ch.units       = Dma::unit_count(n, regs);
+            if Dma::timing(n, regs) == DmaTiming::Immediate { return Some(self.transfer(n, regs)); }
+        }
+        None
+    }
+
+    /// Checks whether a channel is enabled for special timing.
+    pub fn is_special(n: usize, regs: &IoRegisters) -> bool {
+        let cnt = Dma::control(n, regs);
+        (0 != (cnt & CNT_ENABLE)) & (Dma::timing(n, regs) == DmaTiming::Special)
+    }
+
+    /// Get the latched destination address of a channel.
+    pub fn destination(&self, n: usize) -> u32 { self.channels[n].destination }
+
+    /// Describes the next transfer of an enabled channel.
+    ///
+    /// Sound FIFO transfers of DMA 1 and 2 always move
+    /// 4 words to a fixed destination.
+    pub fn transfer(&self, n: usize, regs: &IoRegisters) -> DmaTransfer {
+        let cnt = Dma::control(n, regs);
+        let ch = &self.channels[n];
+        let fifo = ((n == 1) | (n == 2)) & (Dma::timing(n, regs) == DmaTiming::Special);
+        DmaTransfer {
+            channel: n,
+            source: ch.source,
+            destination: ch.destination,
+            units: if fifo { 4 } else { ch.units },
+            words: fifo | (0 != (cnt & CNT_WORDS)),
+            source_control: AddressControl::from_bits(cnt >> 7),
+            destination_control: if fifo { AddressControl::Fixed } else { AddressControl::from_bits(cnt >> 5) },
+        }
+    }
+
+    /// Finishes a transfer performed by the bus.
+    ///
+    /// Repeating channels without immediate timing stay
+    /// enabled, all others are disabled.
+    ///
+    /// # Params
+    /// - `t`: The performed transfer.
+    /// - `source`: The source address after the transfer.
+    /// - `destination`: The destination address after the transfer.
+    /// - `regs`: The IO registers holding the DMA registers' values.
+    ///
+    /// # Returns
+    /// `true` if the channel requests an interrupt.
+    pub fn finish(&mut self, t: &DmaTransfer, source: u32, destination: u32, regs: &mut IoRegisters) -> bool {
+        let n = t.channel;
+        let cnt = Dma::control(n, regs);
+        let base = Dma::base(n);
+        let ch = &mut self.channels[n];
+        ch.source = source;
+        ch.destination = destination;
+
+        let repeat = (0 != (cnt & CNT_REPEAT)) & (Dma::timing(n, regs) != DmaTiming::Immediate);
+        if repeat {
+            ch.units = Dma::unit_count(n, regs);
+            if t.destination_control == AddressControl::IncrementReload {
+                ch.destination = regs.read_word(base + 4);
+            }
+        }
+        else {
+            regs.write_halfword(base + 10, cnt & !CNT_ENABLE);
+        }
+        0 != (cnt & CNT_IRQ)
+    }
+
+    fn base(n: usize) -> u32 { REG_DMA0SAD + (n as u32) * CHANNEL_REGISTERS_LEN }
+
+    fn control(n: usize, regs: &IoRegisters) -> u16 { regs.read_halfword(Dma::base(n) + 10) }
+
+    fn timing(n: usize, regs: &IoRegisters) -> DmaTiming {
+        match (Dma::control(n, regs) >> 12) & 0b11 {
+            0 => DmaTiming::Immediate,
+            1 => DmaTiming::VBlank,
+            2 => DmaTiming::HBlank,
+            _ => DmaTiming::Special,
+        }
+    }
+
+    // A unit count of zero means the maximum count.
+    fn unit_count(n: usize, regs: &IoRegisters) -> u32 {
+        let c = regs.read_halfword(Dma::base(n) + 8) as u32;
+        let c = if n == 3 { c } else { c & 0x3FFF };
+        match (c, n) {
+            (0, 3) => 0x10000,
+            (0, _) => 0x4000,
+            (c, _) => c,
+        }
+    }
+}
+
+impl Default for Dma {
+    fn default() -> Dma { Dma::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,99 @@
+// License below.
+//! Implements the framebuffer holding the picture shown on the GBA's LCD.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+
+/// Width of the GBA's LCD in pixels.
+pub const LCD_WIDTH: usize = 240;
+
+/// Height of the GBA's LCD in pixels.
+pub const LCD_HEIGHT: usize = 160;
+
+/// Number of pixels on the GBA's LCD.
+pub const LCD_PIXELS: usize = LCD_WIDTH * LCD_HEIGHT;
+
+
+/// Expands a 15-bit BGR555 colour to a 24-bit RGB colour.
+///
+/// The 5-bit channels are scaled to 8 bits by repeating their
+/// upper bits, such that `0x1F` maps to `0xFF` and `0x00` to `0x00`.
+///
+/// # Params
+/// - `c`: A colour in the GBA's `0bbbbbgggggrrrrr` format.
+///
+/// # Returns
+/// The channels `(r, g, b)` of the colour.
+pub fn bgr555_to_rgb888(c: u16) -> (u8, u8, u8) {
+    let r = ( c        & 0x1F) as u8;
+    let g = ((c >>  5) & 0x1F) as u8;
+    let b = ((c >> 10) & 0x1F) as u8;
+    ((r << 3) | (r >> 2), (g << 3) | (g >> 2), (b << 3) | (b >> 2))
+}
+
+
+/// A 240x160 pixels picture in BGR555 format.
+///
+/// Pixels are stored row by row, starting at the
+/// top left corner of the screen.
+pub struct FrameBuffer(Box<[u16; LCD_PIXELS]>);
+
+impl FrameBuffer {
+    /// Creates a new black framebuffer.
+    pub fn new() -> FrameBuffer { FrameBuffer(Box::new([0; LCD_PIXELS])) }
+
+    /// Fills the whole framebuffer with black pixels.
+    pub fn clear(&mut self) {
+        for p in self.0.iter_mut() { *p = 0; }
+    }
+
+    /// Gets the colour of a single pixel.
+    ///
+    /// # Panics
+    /// Panics if `x` or `y` are out of bounds.
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        debug_assert!((x < LCD_WIDTH) & (y < LCD_HEIGHT));
+        self.0[y * LCD_WIDTH + x]
+    }
+
+    /// Sets the colour of a single pixel.
+    ///
+    /// # Panics
+    /// Panics if `x` or `y` are out of bounds.
+    pub fn set_pixel(&mut self, x: usize, y: usize, c: u16) {
+        debug_assert!((x < LCD_WIDTH) & (y < LCD_HEIGHT));
+        self.0[y * LCD_WIDTH + x] = c;
+    }
+
+    /// Gets all pixels row by row.
+    pub fn pixels(&self) -> &[u16] { &*self.0 }
+
+    /// Gets all pixels row by row.
+    pub fn pixels_mut(&mut self) -> &mut [u16] { &mut *self.0 }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> FrameBuffer { FrameBuffer::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -157,7 +157,7 @@ impl<'a> fmt::Display for GamePakRomHeader<'a> {
 /// Implements a GamePak' ROM chip.
 pub struct GamePakRom {
     // Raw memory block. Nothing special here.
-    raw_bytes: Box<[u8; MAX_GBA_ROM_SIZE]>,
+    raw_bytes: Box<[u8]>,
 
     // Size of the currently loaded ROM.
     loaded_rom_len: usize,
@@ -173,7 +173,7 @@ impl GamePakRom {
     pub fn new() -> GamePakRom {
         GamePakRom {
             // Some ROMs use 0xFF as unused memory.
-            raw_bytes: box [0x00_u8; MAX_GBA_ROM_SIZE],
+            raw_bytes: vec![0x00_u8; MAX_GBA_ROM_SIZE].into_boxed_slice(),
 
             loaded_rom_len: 0,
 
@@ -246,7 +246,7 @@ pub struct GamePakSram(Box<[u8; GAME_PAK_SRAM_LEN as usize]>);
 impl GamePakSram {
     /// Creates a new zero-initialised SRAM.
     pub fn new() -> GamePakSram {
-        GamePakSram(box [0; GAME_PAK_SRAM_LEN as usize])
+        GamePakSram(Box::new([0; GAME_PAK_SRAM_LEN as usize]))
     }
 
     /// Clears the SRAM.
@@ -0,0 +1,204 @@
+// License below.
+//! Implements the BIOS functions calculating affine parameters.
+//!
+//! Angles are 16-bit fractions of a full turn, of which the
+//! BIOS only uses the upper 8 bits to look up its sine table.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::super::cpu::CpuMemory;
+use super::super::cpu::MemoryAccess::NonSequential;
+use super::super::error::*;
+
+/// The BIOS sine table of a full turn in 256 steps.
+///
+/// Each entry is `16384 * sin(2 * PI * i / 256)` rounded towards
+/// zero, just like in the BIOS ROM, i.e. a signed 1.14 fixed point
+/// number.
+pub const SINE_TABLE: [i16; 256] = [
+     0x0000,  0x0192,  0x0323,  0x04B5,  0x0645,  0x07D5,  0x0964,  0x0AF1,
+     0x0C7C,  0x0E05,  0x0F8C,  0x1111,  0x1294,  0x1413,  0x158F,  0x1708,
+     0x187D,  0x19EF,  0x1B5D,  0x1CC6,  0x1E2B,  0x1F8B,  0x20E7,  0x223D,
+     0x238E,  0x24DA,  0x261F,  0x275F,  0x2899,  0x29CD,  0x2AFA,  0x2C21,
+     0x2D41,  0x2E5A,  0x2F6B,  0x3076,  0x3179,  0x3274,  0x3367,  0x3453,
+     0x3536,  0x3612,  0x36E5,  0x37AF,  0x3871,  0x392A,  0x39DA,  0x3A82,
+     0x3B20,  0x3BB6,  0x3C42,  0x3CC5,  0x3D3E,  0x3DAE,  0x3E14,  0x3E71,
+     0x3EC5,  0x3F0E,  0x3F4E,  0x3F84,  0x3FB1,  0x3FD3,  0x3FEC,  0x3FFB,
+     0x4000,  0x3FFB,  0x3FEC,  0x3FD3,  0x3FB1,  0x3F84,  0x3F4E,  0x3F0E,
+     0x3EC5,  0x3E71,  0x3E14,  0x3DAE,  0x3D3E,  0x3CC5,  0x3C42,  0x3BB6,
+     0x3B20,  0x3A82,  0x39DA,  0x392A,  0x3871,  0x37AF,  0x36E5,  0x3612,
+     0x3536,  0x3453,  0x3367,  0x3274,  0x3179,  0x3076,  0x2F6B,  0x2E5A,
+     0x2D41,  0x2C21,  0x2AFA,  0x29CD,  0x2899,  0x275F,  0x261F,  0x24DA,
+     0x238E,  0x223D,  0x20E7,  0x1F8B,  0x1E2B,  0x1CC6,  0x1B5D,  0x19EF,
+     0x187D,  0x1708,  0x158F,  0x1413,  0x1294,  0x1111,  0x0F8C,  0x0E05,
+     0x0C7C,  0x0AF1,  0x0964,  0x07D5,  0x0645,  0x04B5,  0x0323,  0x0192,
+     0x0000, -0x0192, -0x0323, -0x04B5, -0x0645, -0x07D5, -0x0964, -0x0AF1,
+    -0x0C7C, -0x0E05, -0x0F8C, -0x1111, -0x1294, -0x1413, -0x158F, -0x1708,
+    -0x187D, -0x19EF, -0x1B5D, -0x1CC6, -0x1E2B, -0x1F8B, -0x20E7, -0x223D,
+    -0x238E, -0x24DA, -0x261F, -0x275F, -0x2899, -0x29CD, -0x2AFA, -0x2C21,
+    -0x2D41, -0x2E5A, -0x2F6B, -0x3076, -0x3179, -0x3274, -0x3367, -0x3453,
+    -0x3536, -0x3612, -0x36E5, -0x37AF, -0x3871, -0x392A, -0x39DA, -0x3A82,
+    -0x3B20, -0x3BB6, -0x3C42, -0x3CC5, -0x3D3E, -0x3DAE, -0x3E14, -0x3E71,
+    -0x3EC5, -0x3F0E, -0x3F4E, -0x3F84, -0x3FB1, -0x3FD3, -0x3FEC, -0x3FFB,
+    -0x4000, -0x3FFB, -0x3FEC, -0x3FD3, -0x3FB1, -0x3F84, -0x3F4E, -0x3F0E,
+    -0x3EC5, -0x3E71, -0x3E14, -0x3DAE, -0x3D3E, -0x3CC5, -0x3C42, -0x3BB6,
+    -0x3B20, -0x3A82, -0x39DA, -0x392A, -0x3871, -0x37AF, -0x36E5, -0x3612,
+    -0x3536, -0x3453, -0x3367, -0x3274, -0x3179, -0x3076, -0x2F6B, -0x2E5A,
+    -0x2D41, -0x2C21, -0x2AFA, -0x29CD, -0x2899, -0x275F, -0x261F, -0x24DA,
+    -0x238E, -0x223D, -0x20E7, -0x1F8B, -0x1E2B, -0x1CC6, -0x1B5D, -0x19EF,
+    -0x187D, -0x1708, -0x158F, -0x1413, -0x1294, -0x1111, -0x0F8C, -0x0E05,
+    -0x0C7C, -0x0AF1, -0x0964, -0x07D5, -0x0645, -0x04B5, -0x0323, -0x0192,
+];
+
+/// Size of a `BgAffineSet` source entry in bytes.
+pub const BG_AFFINE_SRC_LEN: u32 = 20;
+
+/// Size of a `BgAffineSet` destination entry in bytes.
+pub const BG_AFFINE_DST_LEN: u32 = 16;
+
+/// Size of an `ObjAffineSet` source entry in bytes.
+pub const OBJ_AFFINE_SRC_LEN: u32 = 8;
+
+
+/// Looks up the sine of an angle in the BIOS sine table.
+pub fn sin(angle: u16) -> i32 { SINE_TABLE[(angle >> 8) as usize] as i32 }
+
+/// Looks up the cosine of an angle in the BIOS sine table.
+pub fn cos(angle: u16) -> i32 { SINE_TABLE[((angle >> 8) as usize + 64) & 0xFF] as i32 }
+
+/// Calculates the rotation and scaling matrix `PA, PB, PC, PD`.
+///
+/// # Params
+/// - `sx`, `sy`: The 8.8 fixed point scaling factors.
+/// - `angle`: The counter-clockwise rotation.
+pub fn affine_matrix(sx: i16, sy: i16, angle: u16) -> [i16; 4] {
+    let (sx, sy) = (sx as i32, sy as i32);
+    let (s, c) = (sin(angle), cos(angle));
+    [((sx * c) >> 14) as i16, ((-sx * s) >> 14) as i16,
+     ((sy * s) >> 14) as i16, (( sy * c) >> 14) as i16]
+}
+
+/// A `BgAffineSet` source entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BgAffineSource {
+    /// The 19.8 fixed point texture coordinates of the rotation center.
+    pub tex_x: i32,
+    /// See `tex_x`.
+    pub tex_y: i32,
+    /// The screen coordinates of the rotation center.
+    pub scr_x: i16,
+    /// See `scr_x`.
+    pub scr_y: i16,
+    /// The 8.8 fixed point scaling factors.
+    pub sx: i16,
+    /// See `sx`.
+    pub sy: i16,
+    /// The counter-clockwise rotation.
+    pub angle: u16,
+}
+
+/// A `BgAffineSet` result, as written to `BG2PA` and following.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BgAffineParams {
+    /// The matrix `PA, PB, PC, PD`.
+    pub matrix: [i16; 4],
+    /// The 19.8 fixed point reference point `BGxX`.
+    pub x: i32,
+    /// The 19.8 fixed point reference point `BGxY`.
+    pub y: i32,
+}
+
+/// Calculates the parameters of an affine BG, as done by `BgAffineSet`.
+///
+/// The reference point is chosen such that the texture's rotation
+/// center is displayed at the given screen coordinates.
+pub fn bg_affine(src: &BgAffineSource) -> BgAffineParams {
+    let m = affine_matrix(src.sx, src.sy, src.angle);
+    let (pa, pb, pc, pd) = (m[0] as i32, m[1] as i32, m[2] as i32, m[3] as i32);
+    let (cx, cy) = (src.scr_x as i32, src.scr_y as i32);
+    BgAffineParams {
+        matrix: m,
+        x: src.tex_x.wrapping_sub(pa.wrapping_mul(cx).wrapping_add(pb.wrapping_mul(cy))),
+        y: src.tex_y.wrapping_sub(pc.wrapping_mul(cx).wrapping_add(pd.wrapping_mul(cy))),
+    }
+}
+
+
+/// Runs `BgAffineSet` on the bus.
+///
+/// # Params
+/// - `bus`: The memory to read from and write to.
+/// - `src`: Address of the source entries, passed in R0.
+/// - `dst`: Address of the destination entries, passed in R1.
+/// - `count`: Number of entries, passed in R2.
+///
+/// # Returns
+/// The number of cycles taken.
+pub fn bg_affine_set<M: CpuMemory>(bus: &mut M, src: u32, dst: u32, count: u32) -> Result<u32, GbaError> {
+    for i in 0..count {
+        let s = src.wrapping_add(i * BG_AFFINE_SRC_LEN);
+        let d = dst.wrapping_add(i * BG_AFFINE_DST_LEN);
+        let h = |bus: &mut M, offs: u32| bus.load_halfword(s + offs, NonSequential).map(|x| x as u16);
+        let entry = BgAffineSource {
+            tex_x: try!(bus.load_word(s, NonSequential)),
+            tex_y: try!(bus.load_word(s + 4, NonSequential)),
+            scr_x: try!(h(bus, 8)) as i16,
+            scr_y: try!(h(bus, 10)) as i16,
+            sx:    try!(h(bus, 12)) as i16,
+            sy:    try!(h(bus, 14)) as i16,
+            angle: try!(h(bus, 16)),
+        };
+        let p = bg_affine(&entry);
+        for (j, &x) in p.matrix.iter().enumerate() { try!(bus.store_halfword(d + j as u32 * 2, x as i32, NonSequential)); }
+        try!(bus.store_word(d +  8, p.x, NonSequential));
+        try!(bus.store_word(d + 12, p.y, NonSequential));
+    }
+    Ok(20 + count * 70)
+}
+
+/// Runs `ObjAffineSet` on the bus.
+///
+/// # Params
+/// - `bus`: The memory to read from and write to.
+/// - `src`: Address of the source entries, passed in R0.
+/// - `dst`: Address of the first `PA`, passed in R1.
+/// - `count`: Number of entries, passed in R2.
+/// - `stride`: Distance between `PA`, `PB`, etc. in bytes, passed
+///   in R3. Usually 2 for BG parameters and 8 for OAM.
+///
+/// # Returns
+/// The number of cycles taken.
+pub fn obj_affine_set<M: CpuMemory>(bus: &mut M, src: u32, dst: u32, count: u32, stride: u32) -> Result<u32, GbaError> {
+    for i in 0..count {
+        let s = src.wrapping_add(i * OBJ_AFFINE_SRC_LEN);
+        let d = dst.wrapping_add(i * stride * 4);
+        let sx    = try!(bus.load_halfword(s, NonSequential)) as i16;
+        let sy    = try!(bus.load_halfword(s + 2, NonSequential)) as i16;
+        let angle = try!(bus.load_halfword(s + 4, NonSequential)) as u16;
+        let m = affine_matrix(sx, sy, angle);
+        for (j, &x) in m.iter().enumerate() { try!(bus.store_halfword(d.wrapping_add(j as u32 * stride), x as i32, NonSequential)); }
+    }
+    Ok(20 + count * 45)
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,200 @@
+// License below.
+//! Implements the arithmetic BIOS functions.
+//!
+//! All functions return the same values as the BIOS, including
+//! the intermediate values the BIOS leaves in scratch registers.
+//! They also estimate how many cycles the BIOS code would have
+//! taken, following the loops of the BIOS routines. These counts
+//! have not been checked against a BIOS image yet. The CPU core's
+//! ignored `bios_image_matches_hle` test does that if the
+//! `GBARS_BIOS` environment variable names one.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::cmp;
+
+/// Approximate number of cycles the BIOS takes for `ArcTan`.
+pub const ARCTAN_CYCLES: u32 = 49;
+
+/// Approximate number of cycles `ArcTan2` takes if the vector lies on an axis.
+pub const ARCTAN2_AXIS_CYCLES: u32 = 11;
+
+
+/// The results of `Div` and `DivArm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quotient {
+    /// The quotient rounded towards zero, returned in R0.
+    pub quotient: i32,
+
+    /// The remainder with the sign of the numerator, returned in R1.
+    pub remainder: i32,
+
+    /// The absolute quotient, returned in R3.
+    pub abs_quotient: u32,
+
+    /// Approximate number of cycles the BIOS takes.
+    pub cycles: u32,
+}
+
+/// The results of `ArcTan` and `ArcTan2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Angle {
+    /// The angle returned in R0.
+    pub angle: i32,
+
+    /// The negated square of the tangent left in R1, if touched.
+    pub r1: Option<i32>,
+
+    /// The value left in R3.
+    pub r3: i32,
+
+    /// Approximate number of cycles the BIOS takes.
+    pub cycles: u32,
+}
+
+
+/// Signed division, as done by `Div`.
+///
+/// `i32::MIN / -1` wraps around just like on the real CPU.
+///
+/// # Returns
+/// - `Some` quotient if `den` is not zero.
+/// - `None` on division by zero, where the BIOS would hang.
+pub fn div(num: i32, den: i32) -> Option<Quotient> {
+    if den == 0 { return None; }
+    let q = num.wrapping_div(den);
+    Some(Quotient {
+        quotient: q,
+        remainder: num.wrapping_rem(den),
+        abs_quotient: abs(q),
+        cycles: div_cycles(num, den),
+    })
+}
+
+/// Approximate number of cycles the BIOS division loop takes.
+///
+/// The BIOS shifts the denominator up to the numerator's most
+/// significant bit, and then does one subtract-and-shift step
+/// per bit of the quotient.
+pub fn div_cycles(num: i32, den: i32) -> u32 {
+    let n = abs(num).leading_zeros() as i32;
+    let d = abs(den).leading_zeros() as i32;
+    let steps = cmp::max(1, d - n) as u32;
+    4 + 13 * steps + 7
+}
+
+/// Unsigned integer square root, as done by `Sqrt`.
+///
+/// The BIOS uses Newton's method starting at a power of two
+/// that is not less than the actual root.
+///
+/// # Returns
+/// The rounded down root and the approximate number of cycles taken.
+pub fn sqrt(x: u32) -> (u16, u32) {
+    if x == 0 { return (0, 53); }
+    let mut cycles = 15;
+
+    // Initial guess.
+    let mut upper = x;
+    let mut root = 1_u32;
+    while root < upper { upper >>= 1; root <<= 1; cycles += 6; }
+
+    // Refine the guess until it stops shrinking.
+    loop {
+        let q = x / root;
+        let bits = (32 - root.leading_zeros()) as u32;
+        let steps = (32 - x.leading_zeros()).saturating_sub(bits) + 1;
+        cycles += 6 + 5 * steps + 8 * steps;
+        let next = (root + q) >> 1;
+        if next >= root { break; }
+        root = next;
+    }
+    (root as u16, cycles)
+}
+
+/// Arc tangent, as done by `ArcTan`.
+///
+/// # Params
+/// - `tan`: A signed 1.14 fixed point tangent.
+///
+/// # Returns
+/// The angle in `-0x4000..0x4000`, i.e. `-PI/2..PI/2`.
+pub fn arctan(tan: i32) -> Angle {
+    let a = -(tan.wrapping_mul(tan) >> 14);
+    let mut b = ((0xA9 * a) >> 14) + 0x390;
+    b = (b.wrapping_mul(a) >> 14) + 0x091C;
+    b = (b.wrapping_mul(a) >> 14) + 0x0FB6;
+    b = (b.wrapping_mul(a) >> 14) + 0x16AA;
+    b = (b.wrapping_mul(a) >> 14) + 0x2081;
+    b = (b.wrapping_mul(a) >> 14) + 0x3651;
+    b = (b.wrapping_mul(a) >> 14) + 0xA2F9;
+    Angle {
+        angle: tan.wrapping_mul(b) >> 16,
+        r1: Some(a),
+        r3: b,
+        cycles: ARCTAN_CYCLES,
+    }
+}
+
+/// Arc tangent of a vector, as done by `ArcTan2`.
+///
+/// # Params
+/// - `x`, `y`: The vector's signed 1.14 fixed point coordinates.
+///
+/// # Returns
+/// The angle in `0..0x10000`, i.e. `0..2PI`.
+pub fn arctan2(x: i32, y: i32) -> Angle {
+    const R3: i32 = 0x170;
+    let axis = |a: i32| Angle { angle: a, r1: None, r3: R3, cycles: ARCTAN2_AXIS_CYCLES };
+    if y == 0 { return axis(if x >= 0 { 0x0000 } else { 0x8000 }); }
+    if x == 0 { return axis(if y >= 0 { 0x4000 } else { 0xC000 }); }
+
+    // Divide the smaller by the larger coordinate, such
+    // that the tangent is within -1..1. Negating wraps
+    // around just like the BIOS' RSB instructions.
+    let (nx, ny) = (x.wrapping_neg(), y.wrapping_neg());
+    let (num, den, offs, negate) = if y >= 0 {
+        if x >= 0 && x >= y       { (y, x, 0x00000, false) }
+        else if x < 0 && nx >= y  { (y, x, 0x08000, false) }
+        else                      { (x, y, 0x04000, true)  }
+    } else {
+        if x <= 0 && nx > ny      { (y, x, 0x08000, false) }
+        else if x > 0 && x >= ny  { (y, x, 0x10000, false) }
+        else                      { (x, y, 0x0C000, true)  }
+    };
+    let tan = (num << 14).wrapping_div(den);
+    let at = arctan(tan);
+    let angle = if negate { offs - at.angle } else { offs + at.angle };
+    Angle {
+        angle: angle & 0xFFFF,
+        r1: at.r1,
+        r3: R3,
+        cycles: div_cycles(num << 14, den) + at.cycles,
+    }
+}
+
+fn abs(x: i32) -> u32 {
+    if x < 0 { (x as u32).wrapping_neg() } else { x as u32 }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,425 @@
+// License below.
+//! Implements the BIOS decompression functions.
+//!
+//! The decoders work on plain byte slices, such that ROM tools
+//! can use them without emulating a GBA. All compressed formats
+//! start with the same 32-bit header:
+//!
+//! ```text
+//! Bit  0..3   Format specific parameter.
+//! Bit  4..7   Compression type, see `CompressionType`.
+//! Bit  8..31  Size of the decompressed data in bytes.
+//! ```
+//!
+//! The decoders ignore the header's compression type, just like
+//! the BIOS does. Use `decompress` to pick a decoder by type.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::error;
+use std::fmt;
+use super::BiosFunction;
+use super::super::cpu::CpuMemory;
+use super::super::cpu::MemoryAccess::NonSequential;
+use super::super::error::GbaError;
+
+#[cfg(test)]
+mod test;
+
+
+/// Compression types as encoded in bits 4..7 of a header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CompressionType {
+    #[doc = "LZ77 with back-references into the output."] LZ77 = 1,
+    #[doc = "Huffman coding with 1, 2, 4 or 8 bit units."] Huffman = 2,
+    #[doc = "Run-length encoding."]                        RunLength = 3,
+    #[doc = "Delta filtering with 8 or 16 bit units."]     Filter = 8,
+}
+
+impl CompressionType {
+    /// Decodes a compression type from a header's first byte.
+    pub fn from_header(h: u8) -> Option<CompressionType> {
+        match h >> 4 {
+            1 => Some(CompressionType::LZ77),
+            2 => Some(CompressionType::Huffman),
+            3 => Some(CompressionType::RunLength),
+            8 => Some(CompressionType::Filter),
+            _ => None,
+        }
+    }
+}
+
+
+/// An error caused by invalid compressed data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The compressed data ended before the given offset.
+    Truncated(usize),
+
+    /// The header's type is not a known compression type.
+    UnknownType(u8),
+
+    /// Units of `.0` bits cannot be unpacked into units of `.1` bits.
+    InvalidUnitWidth(u8, u8),
+}
+
+impl error::Error for DecompressError {
+    fn description(&self) -> &str {
+        match *self {
+            DecompressError::Truncated(_)          => "Compressed data is truncated.",
+            DecompressError::UnknownType(_)        => "Unknown compression type.",
+            DecompressError::InvalidUnitWidth(_,_) => "Invalid unit widths.",
+        }
+    }
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecompressError::Truncated(x)          => write!(f, "Compressed data ends before offset {:#X}", x),
+            DecompressError::UnknownType(x)        => write!(f, "Unknown compression type {:#X}", x),
+            DecompressError::InvalidUnitWidth(s,d) => write!(f, "Cannot unpack {}-bit units into {}-bit units", s, d),
+        }
+    }
+}
+
+
+/// Parameters of `BitUnPack`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitUnPackInfo {
+    /// Size of the packed data in bytes.
+    pub len: u16,
+
+    /// Width of packed units in bits, either 1, 2, 4 or 8.
+    pub src_width: u8,
+
+    /// Width of unpacked units in bits, either 1, 2, 4, 8, 16 or 32.
+    pub dst_width: u8,
+
+    /// Value added to each unit.
+    pub offset: u32,
+
+    /// If `true`, the offset is also added to zero units.
+    pub offset_zero: bool,
+}
+
+impl BitUnPackInfo {
+    /// Decodes the 8 byte parameter block passed to the BIOS.
+    pub fn from_bytes(b: &[u8; 8]) -> BitUnPackInfo {
+        let offs = (b[4] as u32) | ((b[5] as u32) << 8) | ((b[6] as u32) << 16) | ((b[7] as u32) << 24);
+        BitUnPackInfo {
+            len: (b[0] as u16) | ((b[1] as u16) << 8),
+            src_width: b[2],
+            dst_width: b[3],
+            offset: offs & 0x7FFFFFFF,
+            offset_zero: 0 != (offs & 0x80000000),
+        }
+    }
+}
+
+
+/// Decompresses data of any type, as given by its header.
+pub fn decompress(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    let h = try!(src.get(0).cloned().ok_or(DecompressError::Truncated(1)));
+    match CompressionType::from_header(h) {
+        Some(CompressionType::LZ77)      => lz77(src),
+        Some(CompressionType::Huffman)   => huffman(src),
+        Some(CompressionType::RunLength) => run_length(src),
+        Some(CompressionType::Filter)    => if (h & 0xF) == 2 { unfilter16(src) } else { unfilter8(src) },
+        None => Err(DecompressError::UnknownType(h >> 4)),
+    }
+}
+
+/// Decompresses LZ77 data, as done by `LZ77UnCompWram`.
+///
+/// Each flag byte is followed by 8 blocks, starting with its
+/// bit 7. A cleared bit stands for a raw byte, a set bit for a
+/// back-reference of two bytes:
+///
+/// ```text
+/// Bit 0..11   Distance to the copied bytes minus 1.
+/// Bit 12..15  Number of copied bytes minus 3.
+/// ```
+pub fn lz77(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    decode_lz77(&mut Reader::new(|i| src.get(i).cloned()))
+}
+
+/// Decompresses Huffman coded data, as done by `HuffUnComp`.
+///
+/// The header's parameter is the width of each unit in bits.
+/// It is followed by the tree and a bitstream of 32-bit words,
+/// which is read starting with bit 31.
+pub fn huffman(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    decode_huffman(&mut Reader::new(|i| src.get(i).cloned()))
+}
+
+/// Decompresses run-length encoded data, as done by `RLUnCompWram`.
+///
+/// Each flag byte either starts a run of `(flag & 0x7F) + 3`
+/// copies of the next byte if bit 7 is set, or is followed by
+/// `(flag & 0x7F) + 1` raw bytes.
+pub fn run_length(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    decode_run_length(&mut Reader::new(|i| src.get(i).cloned()))
+}
+
+/// Undoes 8-bit delta filtering, as done by `Diff8bitUnFilterWram`.
+pub fn unfilter8(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    decode_unfilter(&mut Reader::new(|i| src.get(i).cloned()), false)
+}
+
+/// Undoes 16-bit delta filtering, as done by `Diff16bitUnFilter`.
+pub fn unfilter16(src: &[u8]) -> Result<Vec<u8>, DecompressError> {
+    decode_unfilter(&mut Reader::new(|i| src.get(i).cloned()), true)
+}
+
+/// Expands each unit of packed data into a wider unit, as done by `BitUnPack`.
+///
+/// Units are packed starting with the least significant bits.
+/// The output is padded with zero bits to a multiple of 32 bits.
+pub fn bit_unpack(src: &[u8], info: &BitUnPackInfo) -> Result<Vec<u8>, DecompressError> {
+    decode_bit_unpack(&mut Reader::new(|i| src.get(i).cloned()), info)
+}
+
+
+/// Runs a BIOS decompression function on the bus.
+///
+/// Functions writing to WRAM store bytes, while functions
+/// writing to VRAM store halfwords, as VRAM ignores byte
+/// writes. `HuffUnComp` and `BitUnPack` store words.
+/// If the source lies within the BIOS ROM, nothing happens.
+///
+/// # Params
+/// - `bus`: The memory to decompress in.
+/// - `func`: The decompression function.
+/// - `src`: The source address, passed in R0.
+/// - `dst`: The destination address, passed in R1.
+/// - `info`: Address of the `BitUnPack` parameters, passed in R2.
+///
+/// # Returns
+/// - `Ok(Some)` number of cycles the BIOS takes.
+/// - `Ok(None)` if `func` is not a decompression function.
+/// - `Err` if the compressed data is invalid.
+pub fn run_uncomp<M: CpuMemory>(bus: &mut M, func: BiosFunction, src: u32, dst: u32, info: u32) -> Result<Option<u32>, GbaError> {
+    if (src & 0x0E000000) == 0 { return Ok(Some(UNCOMP_SETUP_CYCLES)); }
+    let mut b = [0_u8; 8];
+    if func == BiosFunction::BitUnPack {
+        for i in 0..8 { b[i] = try!(bus.load_byte(info + i as u32, NonSequential)) as u8; }
+    }
+    let (res, width, unit_cycles) = {
+        let mut rd = Reader::new(|i| bus.load_byte(src.wrapping_add(i as u32), NonSequential).ok().map(|b| b as u8));
+        match func {
+            BiosFunction::BitUnPack            => (decode_bit_unpack(&mut rd, &BitUnPackInfo::from_bytes(&b)), 4, 6),
+            BiosFunction::LZ77UnCompWram       => (decode_lz77(&mut rd), 1, 8),
+            BiosFunction::LZ77UnCompVram       => (decode_lz77(&mut rd), 2, 8),
+            BiosFunction::HuffUnComp           => (decode_huffman(&mut rd), 4, 14),
+            BiosFunction::RLUnCompWram         => (decode_run_length(&mut rd), 1, 6),
+            BiosFunction::RLUnCompVram         => (decode_run_length(&mut rd), 2, 6),
+            BiosFunction::Diff8bitUnFilterWram => (decode_unfilter(&mut rd, false), 1, 6),
+            BiosFunction::Diff8bitUnFilterVram => (decode_unfilter(&mut rd, false), 2, 6),
+            BiosFunction::Diff16bitUnFilter    => (decode_unfilter(&mut rd, true), 2, 6),
+            _ => return Ok(None),
+        }
+    };
+    let data = match res {
+        Ok(x) => x,
+        Err(DecompressError::Truncated(i)) => return Err(GbaError::InvalidPhysicalAddress(src.wrapping_add(i as u32))),
+        Err(e) => { warn!("{} in {}.", e, func); return Ok(Some(UNCOMP_SETUP_CYCLES)); },
+    };
+    try!(store_units(bus, dst, &data[..], width));
+    Ok(Some(UNCOMP_SETUP_CYCLES + unit_cycles * data.len() as u32))
+}
+
+const UNCOMP_SETUP_CYCLES: u32 = 30;
+
+// Stores units of 1, 2 or 4 bytes. Partial units
+// at the end are padded with zero bytes.
+fn store_units<M: CpuMemory>(bus: &mut M, dst: u32, data: &[u8], width: usize) -> Result<(), GbaError> {
+    let dst = dst & !(width as u32 - 1);
+    for (i, c) in data.chunks(width).enumerate() {
+        let mut x = 0_u32;
+        for (j, &b) in c.iter().enumerate() { x |= (b as u32) << (j * 8); }
+        let a = dst.wrapping_add((i * width) as u32);
+        match width {
+            1 => try!(bus.store_byte(a, x as i32, NonSequential)),
+            2 => try!(bus.store_halfword(a, x as i32, NonSequential)),
+            _ => try!(bus.store_word(a, x as i32, NonSequential)),
+        }
+    }
+    Ok(())
+}
+
+
+// Reads compressed data byte by byte.
+struct Reader<F: FnMut(usize) -> Option<u8>> {
+    read: F,
+    pos: usize,
+}
+
+impl<F: FnMut(usize) -> Option<u8>> Reader<F> {
+    fn new(read: F) -> Reader<F> { Reader { read: read, pos: 0 } }
+
+    fn byte_at(&mut self, i: usize) -> Result<u8, DecompressError> {
+        (self.read)(i).ok_or(DecompressError::Truncated(i + 1))
+    }
+
+    fn next(&mut self) -> Result<u8, DecompressError> {
+        let b = try!(self.byte_at(self.pos));
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn next_word(&mut self) -> Result<u32, DecompressError> {
+        let mut x = 0;
+        for i in 0..4 { x |= (try!(self.next()) as u32) << (i * 8); }
+        Ok(x)
+    }
+
+    // Reads the header, returning the parameter and size.
+    fn header(&mut self) -> Result<(u8, usize), DecompressError> {
+        let h = try!(self.next_word());
+        Ok(((h & 0xF) as u8, (h >> 8) as usize))
+    }
+}
+
+fn decode_lz77<F: FnMut(usize) -> Option<u8>>(rd: &mut Reader<F>) -> Result<Vec<u8>, DecompressError> {
+    let (_, size) = try!(rd.header());
+    let mut out = Vec::with_capacity(size);
+    while out.len() < size {
+        let flags = try!(rd.next());
+        for bit in (0..8).rev() {
+            if out.len() >= size { break; }
+            if 0 == (flags & (1 << bit)) { out.push(try!(rd.next())); continue; }
+            let (b0, b1) = (try!(rd.next()) as usize, try!(rd.next()) as usize);
+            let len  = (b0 >> 4) + 3;
+            let disp = (((b0 & 0xF) << 8) | b1) + 1;
+            for _ in 0..len {
+                if out.len() >= size { break; }
+                // References before the output's start read zeros.
+                let x = if disp <= out.len() { out[out.len() - disp] } else { 0 };
+                out.push(x);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn decode_huffman<F: FnMut(usize) -> Option<u8>>(rd: &mut Reader<F>) -> Result<Vec<u8>, DecompressError> {
+    let (bits, size) = try!(rd.header());
+    if bits == 0 || 32 % (bits as u32) != 0 { return Err(DecompressError::InvalidUnitWidth(bits, bits)); }
+    let tree_len = try!(rd.next()) as usize;
+    let root = rd.pos;
+    rd.pos = 4 + (tree_len + 1) * 2;
+
+    let mut out = Vec::with_capacity(size + 3);
+    let (mut word, mut shift) = (0_u32, 0_u32);
+    let (mut node_pos, mut node) = (root, try!(rd.byte_at(root)));
+    'stream: while out.len() < size {
+        let stream = try!(rd.next_word());
+        for bit in (0..32).rev() {
+            let right = 0 != (stream & (1 << bit));
+            let child_pos = (node_pos & !1) + ((node & 0x3F) as usize) * 2 + 2 + (right as usize);
+            let is_data = 0 != (node & (if right { 0x40 } else { 0x80 }));
+            let child = try!(rd.byte_at(child_pos));
+            if !is_data { node_pos = child_pos; node = child; continue; }
+
+            word |= ((child as u32) & ((1 << bits) - 1)) << shift;
+            shift += bits as u32;
+            if shift == 32 {
+                for i in 0..4 { out.push((word >> (i * 8)) as u8); }
+                word = 0;
+                shift = 0;
+                if out.len() >= size { break 'stream; }
+            }
+            node_pos = root;
+            node = try!(rd.byte_at(root));
+        }
+    }
+    out.truncate(size);
+    Ok(out)
+}
+
+fn decode_run_length<F: FnMut(usize) -> Option<u8>>(rd: &mut Reader<F>) -> Result<Vec<u8>, DecompressError> {
+    let (_, size) = try!(rd.header());
+    let mut out = Vec::with_capacity(size);
+    while out.len() < size {
+        let flag = try!(rd.next()) as usize;
+        if 0 != (flag & 0x80) {
+            let x = try!(rd.next());
+            for _ in 0..((flag & 0x7F) + 3) { out.push(x); }
+        } else {
+            for _ in 0..((flag & 0x7F) + 1) { out.push(try!(rd.next())); }
+        }
+    }
+    out.truncate(size);
+    Ok(out)
+}
+
+fn decode_unfilter<F: FnMut(usize) -> Option<u8>>(rd: &mut Reader<F>, wide: bool) -> Result<Vec<u8>, DecompressError> {
+    let (_, size) = try!(rd.header());
+    let mut out = Vec::with_capacity(size);
+    let mut x = 0_u16;
+    if wide {
+        while out.len() < size {
+            let d = (try!(rd.next()) as u16) | ((try!(rd.next()) as u16) << 8);
+            x = x.wrapping_add(d);
+            out.push(x as u8);
+            out.push((x >> 8) as u8);
+        }
+    } else {
+        while out.len() < size {
+            x = x.wrapping_add(try!(rd.next()) as u16);
+            out.push(x as u8);
+        }
+    }
+    out.truncate(size);
+    Ok(out)
+}
+
+fn decode_bit_unpack<F: FnMut(usize) -> Option<u8>>(rd: &mut Reader<F>, info: &BitUnPackInfo) -> Result<Vec<u8>, DecompressError> {
+    let (sw, dw) = (info.src_width as u32, info.dst_width as u32);
+    let valid = match (sw, dw) { (1, _) | (2, _) | (4, _) | (8, _) => sw <= dw, _ => false };
+    if !valid || dw == 0 || 32 % dw != 0 { return Err(DecompressError::InvalidUnitWidth(info.src_width, info.dst_width)); }
+
+    let mut out = Vec::new();
+    let (mut word, mut shift) = (0_u32, 0_u32);
+    for _ in 0..info.len {
+        let b = try!(rd.next()) as u32;
+        for i in 0..(8 / sw) {
+            let mut x = (b >> (i * sw)) & ((1 << sw) - 1);
+            if x != 0 || info.offset_zero { x = x.wrapping_add(info.offset); }
+            let mask = if dw == 32 { !0 } else { (1 << dw) - 1 };
+            word |= (x & mask) << shift;
+            shift += dw;
+            if shift == 32 {
+                for j in 0..4 { out.push((word >> (j * 8)) as u8); }
+                word = 0;
+                shift = 0;
+            }
+        }
+    }
+    if shift != 0 { for j in 0..4 { out.push((word >> (j * 8)) as u8); } }
+    Ok(out)
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
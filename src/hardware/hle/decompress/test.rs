@@ -0,0 +1,135 @@
+// License below.
+#![allow(missing_docs)]
+
+use super::*;
+use super::super::BiosFunction;
+use super::super::super::bus::Bus;
+use super::super::super::gamepak::GamePak;
+use super::super::super::memory::BiosRom;
+
+// "ABC", then 9 bytes copied from 3 bytes back, then "D".
+const LZ77_ABC: &'static [u8] = &[
+    0x10, 0x0D, 0x00, 0x00,
+    0x10, b'A', b'B', b'C', 0x60, 0x02, b'D',
+];
+
+// Six times "A" as a run, then "Bxyz" as raw bytes.
+const RL_AXYZ: &'static [u8] = &[
+    0x30, 0x0A, 0x00, 0x00,
+    0x83, b'A', 0x03, b'B', b'x', b'y', b'z',
+];
+
+// Codes: a = 0, b = 10, c = 11.
+const HUFF8_ABCAB: &'static [u8] = &[
+    0x28, 0x05, 0x00, 0x00,
+    0x03, 0x80, b'a', 0xC0, b'b', b'c', 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x5A,
+];
+
+// Codes: 1 = 0, 2 = 1. Data is 0x21, 0x12.
+const HUFF4_NIBBLES: &'static [u8] = &[
+    0x24, 0x02, 0x00, 0x00,
+    0x01, 0xC0, 0x01, 0x02,
+    0x00, 0x00, 0x00, 0x60,
+];
+
+const DIFF8: &'static [u8] = &[
+    0x81, 0x04, 0x00, 0x00,
+    10, 2, 0xFF, 9,
+];
+
+const DIFF16: &'static [u8] = &[
+    0x82, 0x06, 0x00, 0x00,
+    0x00, 0x10, 0x01, 0x00, 0xFE, 0xFF,
+];
+
+#[test]
+fn lz77_blobs() {
+    assert_eq!(lz77(LZ77_ABC).unwrap(), b"ABCABCABCABCD".to_vec());
+    assert_eq!(decompress(LZ77_ABC).unwrap(), b"ABCABCABCABCD".to_vec());
+    assert_eq!(lz77(&LZ77_ABC[..9]), Err(DecompressError::Truncated(10)));
+
+    // 256 raw bytes, then 18 bytes copied from 256 bytes back.
+    let mut blob = vec![0x10, 0x12, 0x01, 0x00];
+    for i in 0..32 {
+        blob.push(0x00);
+        for j in 0..8 { blob.push((i * 8 + j) as u8); }
+    }
+    blob.extend_from_slice(&[0x80, 0xF0, 0xFF]);
+    let out = lz77(&blob[..]).unwrap();
+    assert_eq!(out.len(), 0x112);
+    assert_eq!(&out[0x100..], &out[..0x12]);
+}
+
+#[test]
+fn run_length_blobs() {
+    assert_eq!(run_length(RL_AXYZ).unwrap(), b"AAAAAABxyz".to_vec());
+    assert_eq!(decompress(RL_AXYZ).unwrap(), b"AAAAAABxyz".to_vec());
+}
+
+#[test]
+fn huffman_blobs() {
+    assert_eq!(huffman(HUFF8_ABCAB).unwrap(), b"abcab".to_vec());
+    assert_eq!(huffman(HUFF4_NIBBLES).unwrap(), vec![0x21, 0x12]);
+    assert_eq!(decompress(HUFF8_ABCAB).unwrap(), b"abcab".to_vec());
+}
+
+#[test]
+fn unfilter_blobs() {
+    assert_eq!(unfilter8(DIFF8).unwrap(), vec![10, 12, 11, 20]);
+    assert_eq!(unfilter16(DIFF16).unwrap(), vec![0x00, 0x10, 0x01, 0x10, 0xFF, 0x0F]);
+    assert_eq!(decompress(DIFF16).unwrap(), vec![0x00, 0x10, 0x01, 0x10, 0xFF, 0x0F]);
+    assert_eq!(decompress(&[0x50, 0, 0, 0]), Err(DecompressError::UnknownType(5)));
+}
+
+#[test]
+fn bit_unpack_blobs() {
+    let info = BitUnPackInfo::from_bytes(&[1, 0, 2, 8, 1, 0, 0, 0]);
+    assert_eq!(bit_unpack(&[0x1B], &info).unwrap(), vec![4, 3, 2, 0]);
+
+    let info = BitUnPackInfo::from_bytes(&[1, 0, 2, 8, 1, 0, 0, 0x80]);
+    assert_eq!(bit_unpack(&[0x1B], &info).unwrap(), vec![4, 3, 2, 1]);
+
+    let info = BitUnPackInfo { len: 1, src_width: 1, dst_width: 4, offset: 0, offset_zero: false };
+    assert_eq!(bit_unpack(&[0x81], &info).unwrap(), vec![0x01, 0x00, 0x00, 0x10]);
+
+    let info = BitUnPackInfo { len: 1, src_width: 4, dst_width: 2, offset: 0, offset_zero: false };
+    assert_eq!(bit_unpack(&[0x81], &info), Err(DecompressError::InvalidUnitWidth(4, 2)));
+}
+
+#[test]
+fn vram_uncomp_writes_halfwords() {
+    let mut bus = Bus::new(GamePak::new(), BiosRom::new());
+    for (i, &b) in LZ77_ABC.iter().enumerate() { bus.store_byte(0x02000000 + i as u32, b as i32).unwrap(); }
+
+    run_uncomp(&mut bus, BiosFunction::LZ77UnCompVram, 0x02000000, 0x06000000, 0).unwrap().unwrap();
+    let out: Vec<u8> = (0..12).map(|i| bus.load_byte(0x06000000 + i).unwrap() as u8).collect();
+    assert_eq!(out, b"ABCABCABCABC".to_vec());
+
+    // The last odd byte is padded to a full halfword.
+    assert_eq!(bus.load_halfword(0x0600000C).unwrap(), b'D' as i32);
+
+    // Sources in the BIOS ROM are ignored.
+    run_uncomp(&mut bus, BiosFunction::RLUnCompWram, 0x00000000, 0x02001000, 0).unwrap().unwrap();
+    assert_eq!(bus.load_word(0x02001000).unwrap(), 0);
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
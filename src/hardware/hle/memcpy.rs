@@ -0,0 +1,217 @@
+// License below.
+//! Implements the BIOS functions copying and clearing memory.
+//!
+//! These functions access memory through the bus, just like the
+//! BIOS would, so copying into IO registers has the same side
+//! effects. Cycle counts are estimates that have not been checked
+//! against a BIOS image yet, see `arithmetic`.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::super::cpu::CpuMemory;
+use super::super::cpu::MemoryAccess::NonSequential;
+use super::super::ioregs::*;
+use super::super::memory::*;
+use super::super::error::*;
+
+/// Bit of the `CpuSet` and `CpuFastSet` control word selecting a fill.
+pub const CPU_SET_FILL: u32 = 1 << 24;
+
+/// Bit of the `CpuSet` control word selecting 32-bit units.
+pub const CPU_SET_32BIT: u32 = 1 << 26;
+
+/// Bits of the `CpuSet` and `CpuFastSet` control word holding the unit count.
+pub const CPU_SET_COUNT_MASK: u32 = 0x001FFFFF;
+
+/// Bits of `RegisterRamReset`'s parameter selecting what to reset.
+pub mod reset_flags {
+    /// Clear the 256kB on-board work RAM.
+    pub const WRAM_ON_BOARD: u8 = 1 << 0;
+    /// Clear the 32kB on-chip work RAM, except for the BIOS area.
+    pub const WRAM_ON_CHIP: u8 = 1 << 1;
+    /// Clear the palette RAM.
+    pub const PALETTE: u8 = 1 << 2;
+    /// Clear the VRAM.
+    pub const VRAM: u8 = 1 << 3;
+    /// Clear the OAM.
+    pub const OAM: u8 = 1 << 4;
+    /// Reset the serial IO registers.
+    pub const SIO: u8 = 1 << 5;
+    /// Reset the sound registers.
+    pub const SOUND: u8 = 1 << 6;
+    /// Reset all other IO registers.
+    pub const OTHER_IO: u8 = 1 << 7;
+}
+
+const SETUP_CYCLES: u32 = 20;
+
+// Size of the BIOS area at the end of on-chip WRAM.
+const BIOS_WRAM_LEN: u32 = 0x200;
+
+
+/// The state a memory copy leaves behind in registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyResult {
+    /// Source address after the copy, returned in R0.
+    pub src: u32,
+
+    /// Destination address after the copy, returned in R1.
+    pub dst: u32,
+
+    /// The last unit loaded, left in R3.
+    pub last: u32,
+
+    /// Approximate number of cycles the BIOS takes.
+    pub cycles: u32,
+}
+
+
+/// Copies or fills memory, as done by `CpuSet`.
+///
+/// Addresses are forcibly aligned to the unit size. If the
+/// source lies within the BIOS ROM, nothing is copied.
+///
+/// # Params
+/// - `bus`: The memory to copy in.
+/// - `src`: The source address, passed in R0.
+/// - `dst`: The destination address, passed in R1.
+/// - `ctrl`: Unit count and mode, passed in R2.
+pub fn cpu_set<M: CpuMemory>(bus: &mut M, src: u32, dst: u32, ctrl: u32) -> Result<CopyResult, GbaError> {
+    let fill  = 0 != (ctrl & CPU_SET_FILL);
+    let wide  = 0 != (ctrl & CPU_SET_32BIT);
+    let count = ctrl & CPU_SET_COUNT_MASK;
+    let unit  = if wide { 4 } else { 2 };
+    let mut src = src & !(unit - 1);
+    let mut dst = dst & !(unit - 1);
+    let mut res = CopyResult { src: src, dst: dst, last: 0, cycles: SETUP_CYCLES };
+    if is_bios_protected(src) { return Ok(res); }
+
+    let load = |bus: &mut M, a: u32| {
+        if wide { bus.load_word(a, NonSequential) } else { bus.load_halfword(a, NonSequential) }
+    };
+    let mut x = try!(load(bus, src));
+    for _ in 0..count {
+        if !fill { x = try!(load(bus, src)); src = src.wrapping_add(unit); }
+        if wide { try!(bus.store_word(dst, x, NonSequential)); }
+        else    { try!(bus.store_halfword(dst, x, NonSequential)); }
+        dst = dst.wrapping_add(unit);
+    }
+
+    res.src = src;
+    res.dst = dst;
+    res.last = x as u32;
+    res.cycles += count * (if fill { 4 } else { 6 });
+    Ok(res)
+}
+
+/// Copies or fills memory in blocks of 8 words, as done by `CpuFastSet`.
+///
+/// The word count is rounded up to a multiple of 8. Addresses
+/// are forcibly word-aligned. If the source lies within the
+/// BIOS ROM, nothing is copied.
+///
+/// # Params
+/// - `bus`: The memory to copy in.
+/// - `src`: The source address, passed in R0.
+/// - `dst`: The destination address, passed in R1.
+/// - `ctrl`: Word count and mode, passed in R2.
+pub fn cpu_fast_set<M: CpuMemory>(bus: &mut M, src: u32, dst: u32, ctrl: u32) -> Result<CopyResult, GbaError> {
+    let fill   = 0 != (ctrl & CPU_SET_FILL);
+    let blocks = ((ctrl & CPU_SET_COUNT_MASK) + 7) / 8;
+    let mut src = src & !0b11;
+    let mut dst = dst & !0b11;
+    let mut res = CopyResult { src: src, dst: dst, last: 0, cycles: SETUP_CYCLES };
+    if is_bios_protected(src) { return Ok(res); }
+
+    let mut x = try!(bus.load_word(src, NonSequential));
+    for _ in 0..(blocks * 8) {
+        if !fill { x = try!(bus.load_word(src, NonSequential)); src = src.wrapping_add(4); }
+        try!(bus.store_word(dst, x, NonSequential));
+        dst = dst.wrapping_add(4);
+    }
+
+    res.src = src;
+    res.dst = dst;
+    res.last = x as u32;
+    res.cycles += blocks * (if fill { 12 } else { 21 });
+    Ok(res)
+}
+
+/// Clears memory and resets IO registers, as done by `RegisterRamReset`.
+///
+/// Regardless of the flags, `DISPCNT` is set to `0x0080`, i.e.
+/// forced blank. The last 512 bytes of on-chip work RAM are never
+/// cleared, as the BIOS keeps its stacks there.
+///
+/// # Params
+/// - `bus`: The memory to reset.
+/// - `flags`: What to reset, see `reset_flags`.
+///
+/// # Returns
+/// The approximate number of cycles taken.
+pub fn register_ram_reset<M: CpuMemory>(bus: &mut M, flags: u8) -> Result<u32, GbaError> {
+    let mut cycles = SETUP_CYCLES;
+    let io = IO_REGISTERS_FIRST;
+
+    if 0 != (flags & reset_flags::WRAM_ON_BOARD) { cycles += try!(clear(bus, WRAM_ON_BOARD_FIRST, WRAM_ON_BOARD_LEN)); }
+    if 0 != (flags & reset_flags::WRAM_ON_CHIP)  { cycles += try!(clear(bus, WRAM_ON_CHIP_FIRST, WRAM_ON_CHIP_LEN - BIOS_WRAM_LEN)); }
+    if 0 != (flags & reset_flags::PALETTE)       { cycles += try!(clear(bus, PALETTE_RAM_FIRST, PALETTE_RAM_LEN)); }
+    if 0 != (flags & reset_flags::VRAM)          { cycles += try!(clear(bus, VRAM_FIRST, VRAM_LEN)); }
+    if 0 != (flags & reset_flags::OAM)           { cycles += try!(clear(bus, OBJ_ATTRIBUTES_FIRST, OBJ_ATTRIBUTES_LEN)); }
+    if 0 != (flags & reset_flags::SIO) {
+        cycles += try!(clear(bus, io + 0x120, 0x10));
+        cycles += try!(clear(bus, io + 0x140, 0x1C));
+        try!(bus.store_halfword(io + REG_RCNT, 0x8000, NonSequential));
+    }
+    if 0 != (flags & reset_flags::SOUND) {
+        // Turning the master enable off first resets all PSG channels.
+        try!(bus.store_halfword(io + REG_SOUNDCNT_X, 0, NonSequential));
+        cycles += try!(clear(bus, io + REG_SOUND1CNT_L, REG_SOUNDCNT_X - REG_SOUND1CNT_L));
+        cycles += try!(clear(bus, io + REG_WAVE_RAM, 0x10));
+        try!(bus.store_halfword(io + REG_SOUNDBIAS, 0x0200, NonSequential));
+    }
+    if 0 != (flags & reset_flags::OTHER_IO) {
+        cycles += try!(clear(bus, io + REG_DISPCNT, 0x60));
+        cycles += try!(clear(bus, io + REG_DMA0SAD, 0x30));
+        cycles += try!(clear(bus, io + REG_TM0CNT_L, 0x10));
+        try!(bus.store_halfword(io + REG_IE, 0, NonSequential));
+        try!(bus.store_halfword(io + REG_IF, 0xFFFF, NonSequential));
+        try!(bus.store_halfword(io + REG_WAITCNT, 0, NonSequential));
+        try!(bus.store_halfword(io + REG_IME, 0, NonSequential));
+    }
+
+    try!(bus.store_halfword(io + REG_DISPCNT, 0x0080, NonSequential));
+    Ok(cycles)
+}
+
+// Zero-fills memory using 8-word stores.
+fn clear<M: CpuMemory>(bus: &mut M, first: u32, len: u32) -> Result<u32, GbaError> {
+    for i in 0..(len / 4) { try!(bus.store_word(first + i * 4, 0, NonSequential)); }
+    Ok(((len + 31) / 32) * 10)
+}
+
+fn is_bios_protected(src: u32) -> bool {
+    (src & 0x0E000000) == 0
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,166 @@
+// License below.
+//! Implements high-level emulation of the GBA's BIOS functions.
+//!
+//! Games call BIOS functions using `SWI` instructions. Instead
+//! of running the BIOS code in the BIOS ROM area, the CPU can
+//! be configured to call the functions of this module, which
+//! have the same effects on memory and registers, and report
+//! roughly how many cycles the BIOS would have taken.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::fmt;
+
+pub use self::arithmetic::*;
+pub use self::memcpy::*;
+pub use self::decompress::*;
+pub use self::affine::*;
+pub use self::sound::*;
+
+pub mod arithmetic;
+pub mod memcpy;
+pub mod decompress;
+pub mod affine;
+pub mod sound;
+
+#[cfg(test)]
+mod test;
+
+
+/// Address of the interrupt flags checked by `IntrWait`.
+///
+/// A game's interrupt handler is expected to set the bits
+/// of all handled interrupts in this halfword.
+pub const BIOS_IRQ_FLAGS: u32 = 0x03007FF8;
+
+/// Address of the flag deciding where `SoftReset` jumps to.
+///
+/// If zero, execution restarts in the GamePak ROM,
+/// otherwise in the on-board work RAM.
+pub const BIOS_RESET_FLAG: u32 = 0x03007FFA;
+
+/// Initial stack pointer of supervisor mode.
+pub const BIOS_SP_SVC: u32 = 0x03007FE0;
+
+/// Initial stack pointer of IRQ mode.
+pub const BIOS_SP_IRQ: u32 = 0x03007FA0;
+
+/// Initial stack pointer of user and system mode.
+pub const BIOS_SP_USR: u32 = 0x03007F00;
+
+/// The value returned by `GetBiosChecksum` on a GBA.
+pub const BIOS_CHECKSUM: u32 = 0xBAAE187F;
+
+
+/// All functions of the GBA BIOS, named by their `SWI` number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BiosFunction {
+    #[doc = "Clears the BIOS area of work RAM and restarts the game."]   SoftReset = 0x00,
+    #[doc = "Clears selected memory areas and IO registers."]          RegisterRamReset,
+    #[doc = "Halts the CPU until an interrupt is requested."]          Halt,
+    #[doc = "Stops the CPU and most hardware until woken up."]         Stop,
+    #[doc = "Halts the CPU until selected interrupts were handled."]   IntrWait,
+    #[doc = "Halts the CPU until a VBlank interrupt was handled."]     VBlankIntrWait,
+    #[doc = "Signed division, returning quotient and remainder."]      Div,
+    #[doc = "Like `Div` with swapped parameters."]                     DivArm,
+    #[doc = "Unsigned square root."]                                   Sqrt,
+    #[doc = "Arc tangent of a fixed point tangent."]                   ArcTan,
+    #[doc = "Arc tangent of a fixed point vector."]                    ArcTan2,
+    #[doc = "Copies or fills memory in 16-bit or 32-bit units."]       CpuSet,
+    #[doc = "Copies or fills memory in blocks of 32 bytes."]           CpuFastSet,
+    #[doc = "Calculates the BIOS ROM's checksum."]                     GetBiosChecksum,
+    #[doc = "Calculates affine BG parameters."]                        BgAffineSet,
+    #[doc = "Calculates affine OBJ parameters."]                       ObjAffineSet,
+    #[doc = "Expands the bit depth of packed data."]                   BitUnPack,
+    #[doc = "LZ77 decompression writing bytes."]                       LZ77UnCompWram,
+    #[doc = "LZ77 decompression writing halfwords."]                   LZ77UnCompVram,
+    #[doc = "Huffman decompression."]                                  HuffUnComp,
+    #[doc = "Run-length decompression writing bytes."]                 RLUnCompWram,
+    #[doc = "Run-length decompression writing halfwords."]             RLUnCompVram,
+    #[doc = "Undoes 8-bit delta filtering writing bytes."]             Diff8bitUnFilterWram,
+    #[doc = "Undoes 8-bit delta filtering writing halfwords."]         Diff8bitUnFilterVram,
+    #[doc = "Undoes 16-bit delta filtering."]                          Diff16bitUnFilter,
+    #[doc = "Slowly ramps the sound bias up or down."]                 SoundBias,
+    #[doc = "Initialises the BIOS sound driver."]                      SoundDriverInit,
+    #[doc = "Configures the BIOS sound driver."]                       SoundDriverMode,
+    #[doc = "Mixes the next frame of the BIOS sound driver."]          SoundDriverMain,
+    #[doc = "Restarts sound DMA of the BIOS sound driver."]            SoundDriverVSync,
+    #[doc = "Stops all channels of the BIOS sound driver."]            SoundChannelClear,
+    #[doc = "Converts a MIDI key to a sample frequency."]              MidiKey2Freq,
+    #[doc = "Undocumented sound driver function."]                     SoundWhatever0,
+    #[doc = "Undocumented sound driver function."]                     SoundWhatever1,
+    #[doc = "Undocumented sound driver function."]                     SoundWhatever2,
+    #[doc = "Undocumented sound driver function."]                     SoundWhatever3,
+    #[doc = "Undocumented sound driver function."]                     SoundWhatever4,
+    #[doc = "Boots slave GBAs through the link port."]                 MultiBoot,
+    #[doc = "Restarts the GBA including the boot logo."]               HardReset,
+    #[doc = "Writes a value to `HALTCNT`."]                            CustomHalt,
+    #[doc = "Stops sound DMA of the BIOS sound driver."]               SoundDriverVSyncOff,
+    #[doc = "Restarts sound DMA of the BIOS sound driver."]            SoundDriverVSyncOn,
+    #[doc = "Gets the BIOS sound driver's function table."]            SoundGetJumpList,
+}
+
+impl BiosFunction {
+    const ALL: &'static [BiosFunction] = &[
+        BiosFunction::SoftReset,            BiosFunction::RegisterRamReset,     BiosFunction::Halt,
+        BiosFunction::Stop,                 BiosFunction::IntrWait,             BiosFunction::VBlankIntrWait,
+        BiosFunction::Div,                  BiosFunction::DivArm,               BiosFunction::Sqrt,
+        BiosFunction::ArcTan,               BiosFunction::ArcTan2,              BiosFunction::CpuSet,
+        BiosFunction::CpuFastSet,           BiosFunction::GetBiosChecksum,      BiosFunction::BgAffineSet,
+        BiosFunction::ObjAffineSet,         BiosFunction::BitUnPack,            BiosFunction::LZ77UnCompWram,
+        BiosFunction::LZ77UnCompVram,       BiosFunction::HuffUnComp,           BiosFunction::RLUnCompWram,
+        BiosFunction::RLUnCompVram,         BiosFunction::Diff8bitUnFilterWram, BiosFunction::Diff8bitUnFilterVram,
+        BiosFunction::Diff16bitUnFilter,    BiosFunction::SoundBias,            BiosFunction::SoundDriverInit,
+        BiosFunction::SoundDriverMode,      BiosFunction::SoundDriverMain,      BiosFunction::SoundDriverVSync,
+        BiosFunction::SoundChannelClear,    BiosFunction::MidiKey2Freq,         BiosFunction::SoundWhatever0,
+        BiosFunction::SoundWhatever1,       BiosFunction::SoundWhatever2,       BiosFunction::SoundWhatever3,
+        BiosFunction::SoundWhatever4,       BiosFunction::MultiBoot,            BiosFunction::HardReset,
+        BiosFunction::CustomHalt,           BiosFunction::SoundDriverVSyncOff,  BiosFunction::SoundDriverVSyncOn,
+        BiosFunction::SoundGetJumpList,
+    ];
+
+    /// Decodes a BIOS function number.
+    ///
+    /// # Returns
+    /// - `Some` function if the BIOS knows the number.
+    /// - `None` for any other number.
+    pub fn from_u8(x: u8) -> Option<BiosFunction> {
+        BiosFunction::ALL.get(x as usize).cloned()
+    }
+
+    /// Extracts the BIOS function number from an ARM state `SWI` comment.
+    ///
+    /// In ARM state, the BIOS only looks at bits 16..23.
+    pub fn from_arm_comment(comment: u32) -> Option<BiosFunction> {
+        BiosFunction::from_u8(((comment >> 16) & 0xFF) as u8)
+    }
+}
+
+impl fmt::Display for BiosFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,101 @@
+// License below.
+//! Implements the BIOS sound functions that work without the
+//! BIOS sound driver.
+//!
+//! The sound driver itself (`SoundDriverInit` and friends) is
+//! not emulated, games relying on it need a real BIOS.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::super::cpu::CpuMemory;
+use super::super::cpu::MemoryAccess::NonSequential;
+use super::super::ioregs::*;
+use super::super::memory::*;
+use super::super::error::*;
+
+/// Offset of the sample frequency within a sound driver `WaveData` header.
+pub const WAVE_DATA_FREQ_OFFSET: u32 = 4;
+
+/// The `SOUNDBIAS` level `SoundBias` approaches when enabling.
+pub const SOUND_BIAS_LEVEL: u16 = 0x0200;
+
+/// Bits of `SOUNDBIAS` holding the bias level.
+pub const SOUND_BIAS_LEVEL_MASK: u16 = 0x03FE;
+
+
+/// Calculates the frequency of a sample played as a given MIDI key.
+///
+/// # Params
+/// - `wave_freq`: The sample's frequency for MIDI key 180, as
+///   stored in its `WaveData` header.
+/// - `key`: The MIDI key to play.
+/// - `fine`: Fine adjustment in 1/256 keys.
+///
+/// # Returns
+/// `wave_freq / 2^((180 - key - fine/256) / 12)`, truncated.
+pub fn midi_key_to_freq(wave_freq: u32, key: u8, fine: u8) -> u32 {
+    let keys = 180.0 - (key as f64) - (fine as f64) / 256.0;
+    ((wave_freq as f64) / (keys / 12.0).exp2()) as u32
+}
+
+/// Runs `MidiKey2Freq` on the bus.
+///
+/// # Params
+/// - `bus`: The memory to read the `WaveData` header from.
+/// - `wave`: Address of the `WaveData` header, passed in R0.
+/// - `key`: The MIDI key, passed in R1.
+/// - `fine`: The fine adjustment, passed in R2.
+///
+/// # Returns
+/// The resulting frequency, returned in R0.
+pub fn midi_key2freq<M: CpuMemory>(bus: &mut M, wave: u32, key: u8, fine: u8) -> Result<u32, GbaError> {
+    let freq = try!(bus.load_word(wave.wrapping_add(WAVE_DATA_FREQ_OFFSET), NonSequential)) as u32;
+    Ok(midi_key_to_freq(freq, key, fine))
+}
+
+/// Runs `SoundBias` on the bus.
+///
+/// The BIOS moves the bias level towards its target in steps
+/// of 2, waiting `delay` loop iterations after each step. This
+/// only writes the final level, but reports the time the whole
+/// ramp would have taken.
+///
+/// # Params
+/// - `bus`: The memory holding `SOUNDBIAS`.
+/// - `enable`: Whether to ramp up to `SOUND_BIAS_LEVEL` (R0 not zero)
+///   or down to zero (R0 zero).
+/// - `delay`: The delay between steps, passed in R1.
+///
+/// # Returns
+/// The number of cycles taken.
+pub fn sound_bias<M: CpuMemory>(bus: &mut M, enable: bool, delay: u32) -> Result<u32, GbaError> {
+    let addr  = IO_REGISTERS_FIRST + REG_SOUNDBIAS;
+    let bias  = try!(bus.load_halfword(addr, NonSequential)) as u16;
+    let level = bias & SOUND_BIAS_LEVEL_MASK;
+    let target = if enable { SOUND_BIAS_LEVEL } else { 0 };
+    let steps = ((level as i32 - target as i32).abs() / 2) as u32;
+    try!(bus.store_halfword(addr, ((bias & !SOUND_BIAS_LEVEL_MASK) | target) as i32, NonSequential));
+    Ok(10 + steps.wrapping_mul(8 + delay.wrapping_mul(4)))
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,231 @@
+// License below.
+#![allow(missing_docs)]
+
+use std::i32;
+use super::*;
+use super::super::bus::Bus;
+use super::super::gamepak::GamePak;
+use super::super::ioregs::*;
+use super::super::memory::*;
+
+// Numerator, denominator, R0, R1, R3, cycles.
+const DIVISIONS: &'static [(i32, i32, i32, i32, u32, u32)] = &[
+    (-100, 7, -14, -2, 14, 63),
+    (100, -7, -14, 2, 14, 63),
+    (3, 100, 0, 3, 0, 24),
+    (i32::MIN, -1, i32::MIN, 0, 0x80000000, 414),
+];
+
+// Radicand, root, cycles.
+const ROOTS: &'static [(u32, u16, u32)] = &[
+    (0, 0, 53), (1, 1, 34), (2, 1, 72), (1000, 31, 200),
+    (0x10000, 256, 186), (0xFFFFFFFF, 0xFFFF, 552),
+];
+
+// Tangent, R0, R1, R3.
+const ARCTANS: &'static [(i32, i32, i32, i32)] = &[
+    (0x0000, 0x0000, 0x0000, 0xA2F9),
+    (0x2000, 0x12E4, -0x1000, 0x9720),
+    (0x4000, 0x2000, -0x4000, 0x8000),
+    (-0x4000, -0x2000, -0x4000, 0x8000),
+];
+
+// X, Y, R0, R1, cycles. R3 always is 0x170.
+const ARCTAN2S: &'static [(i32, i32, i32, Option<i32>, u32)] = &[
+    (0x4000, 0, 0x0000, None, ARCTAN2_AXIS_CYCLES),
+    (-1, 0, 0x8000, None, ARCTAN2_AXIS_CYCLES),
+    (0, -5, 0xC000, None, ARCTAN2_AXIS_CYCLES),
+    (1, 1, 0x2000, Some(-0x4000), 242),
+    (-0x4000, 0x2000, 0x6D1C, Some(-0x1000), 229),
+    (0x1000, -0x3000, 0xCD1C, Some(-0x071C), 229),
+    (i32::MIN, 1, 0x4000, Some(0), 73),
+    (1, i32::MIN, 0x0000, Some(0), 73),
+    (i32::MIN, i32::MIN, 0xC000, Some(0), 73),
+];
+
+// The first quarter of the sine table in the BIOS ROM, as halfwords.
+const BIOS_SINE_TABLE: &'static [u16] = &[
+    0x0000, 0x0192, 0x0323, 0x04B5, 0x0645, 0x07D5, 0x0964, 0x0AF1,
+    0x0C7C, 0x0E05, 0x0F8C, 0x1111, 0x1294, 0x1413, 0x158F, 0x1708,
+    0x187D, 0x19EF, 0x1B5D, 0x1CC6, 0x1E2B, 0x1F8B, 0x20E7, 0x223D,
+    0x238E, 0x24DA, 0x261F, 0x275F, 0x2899, 0x29CD, 0x2AFA, 0x2C21,
+    0x2D41, 0x2E5A, 0x2F6B, 0x3076, 0x3179, 0x3274, 0x3367, 0x3453,
+    0x3536, 0x3612, 0x36E5, 0x37AF, 0x3871, 0x392A, 0x39DA, 0x3A82,
+    0x3B20, 0x3BB6, 0x3C42, 0x3CC5, 0x3D3E, 0x3DAE, 0x3E14, 0x3E71,
+    0x3EC5, 0x3F0E, 0x3F4E, 0x3F84, 0x3FB1, 0x3FD3, 0x3FEC, 0x3FFB,
+    0x4000,
+];
+
+fn bus_with_words(addr: u32, count: u32) -> Bus {
+    let mut bus = Bus::new(GamePak::new(), BiosRom::new());
+    for i in 0..count { bus.store_word(addr + i * 4, 0x11111111_u32.wrapping_mul(i + 1) as i32).unwrap(); }
+    bus
+}
+
+#[test]
+fn div_returns_bios_registers() {
+    for &(n, d, r0, r1, r3, cycles) in DIVISIONS {
+        let q = div(n, d).unwrap();
+        assert_eq!((q.quotient, q.remainder, q.abs_quotient, q.cycles), (r0, r1, r3, cycles));
+    }
+    assert_eq!(div(5, 0), None);
+}
+
+#[test]
+fn sqrt_rounds_down() {
+    for &(x, root, cycles) in ROOTS {
+        assert_eq!(sqrt(x), (root, cycles));
+    }
+}
+
+#[test]
+fn arctan_returns_bios_registers() {
+    for &(tan, r0, r1, r3) in ARCTANS {
+        assert_eq!(arctan(tan), Angle { angle: r0, r1: Some(r1), r3: r3, cycles: ARCTAN_CYCLES });
+    }
+    for &(x, y, r0, r1, cycles) in ARCTAN2S {
+        assert_eq!(arctan2(x, y), Angle { angle: r0, r1: r1, r3: 0x170, cycles: cycles });
+    }
+}
+
+#[test]
+fn cpu_set_copies_and_fills() {
+    let mut bus = bus_with_words(0x02000000, 8);
+
+    // Five halfwords from a misaligned source.
+    let res = cpu_set(&mut bus, 0x02000001, 0x03000000, 5).unwrap();
+    assert_eq!(res, CopyResult { src: 0x0200000A, dst: 0x0300000A, last: 0x3333, cycles: 50 });
+    assert_eq!(bus.load_word(0x03000004).unwrap() as u32, 0x22222222);
+    assert_eq!(bus.load_word(0x03000008).unwrap() as u32, 0x00003333);
+
+    // Fills leave the source address alone.
+    let res = cpu_set(&mut bus, 0x02000000, 0x03000100, 3 | CPU_SET_32BIT | CPU_SET_FILL).unwrap();
+    assert_eq!(res, CopyResult { src: 0x02000000, dst: 0x0300010C, last: 0x11111111, cycles: 32 });
+    assert_eq!(bus.load_word(0x03000108).unwrap() as u32, 0x11111111);
+    assert_eq!(bus.load_word(0x0300010C).unwrap(), 0);
+
+    // Sources in the BIOS ROM are ignored.
+    let res = cpu_set(&mut bus, 0x00000000, 0x03000400, 4).unwrap();
+    assert_eq!(res, CopyResult { src: 0, dst: 0x03000400, last: 0, cycles: 20 });
+}
+
+#[test]
+fn cpu_fast_set_copies_whole_blocks() {
+    let mut bus = bus_with_words(0x02000000, 16);
+
+    // Three words round up to a block of eight.
+    let res = cpu_fast_set(&mut bus, 0x02000000, 0x03000200, 3).unwrap();
+    assert_eq!(res, CopyResult { src: 0x02000020, dst: 0x03000220, last: 0x88888888, cycles: 41 });
+    assert_eq!(bus.load_word(0x0300021C).unwrap() as u32, 0x88888888);
+    assert_eq!(bus.load_word(0x03000220).unwrap(), 0);
+
+    let res = cpu_fast_set(&mut bus, 0x02000004, 0x03000300, 9 | CPU_SET_FILL).unwrap();
+    assert_eq!(res, CopyResult { src: 0x02000004, dst: 0x03000340, last: 0x22222222, cycles: 44 });
+    assert_eq!(bus.load_word(0x0300033C).unwrap() as u32, 0x22222222);
+}
+
+#[test]
+fn register_ram_reset_spares_bios_area() {
+    let mut bus = bus_with_words(WRAM_ON_CHIP_FIRST, 0x2000);
+    bus.store_halfword(IO_REGISTERS_FIRST + REG_IE, 0x3FFF).unwrap();
+
+    assert_eq!(register_ram_reset(&mut bus, reset_flags::WRAM_ON_CHIP).unwrap(), 10100);
+    assert_eq!(bus.load_word(0x03007DFC).unwrap(), 0);
+    assert_eq!(bus.load_word(0x03007E00).unwrap() as u32, 0x11111111_u32.wrapping_mul(0x1F81));
+    assert_eq!(bus.load_halfword(IO_REGISTERS_FIRST + REG_DISPCNT).unwrap(), 0x0080);
+    assert_eq!(bus.load_halfword(IO_REGISTERS_FIRST + REG_IE).unwrap(), 0x3FFF);
+
+    assert_eq!(register_ram_reset(&mut bus, 0xFF).unwrap(), 123490);
+    assert_eq!(bus.load_halfword(IO_REGISTERS_FIRST + REG_IE).unwrap(), 0);
+    assert_eq!(bus.load_halfword(IO_REGISTERS_FIRST + REG_SOUNDBIAS).unwrap(), 0x0200);
+    assert_eq!(bus.load_halfword(IO_REGISTERS_FIRST + REG_RCNT).unwrap(), 0x8000);
+}
+
+
+#[test]
+fn sine_table_matches_bios() {
+    for (i, &h) in BIOS_SINE_TABLE.iter().enumerate() {
+        assert_eq!(SINE_TABLE[i] as u16, h);
+        assert_eq!(SINE_TABLE[128 - i] as u16, h);
+        assert_eq!(SINE_TABLE[(128 + i) % 256] as u16, h.wrapping_neg());
+    }
+    assert_eq!((sin(0x4000), cos(0x0000), sin(0x80FF), cos(0x8000)), (0x4000, 0x4000, 0, -0x4000));
+}
+
+#[test]
+fn bg_affine_set_centers_rotation() {
+    let mut bus = Bus::new(GamePak::new(), BiosRom::new());
+    for (i, &angle) in [0x0000, 0x4000].iter().enumerate() {
+        let s = 0x02000000 + i as u32 * BG_AFFINE_SRC_LEN;
+        bus.store_word(s, 0x1000).unwrap();
+        bus.store_word(s + 4, 0x2000).unwrap();
+        for (j, &h) in [120, 80, 0x100, 0x100, angle].iter().enumerate() { bus.store_halfword(s + 8 + j as u32 * 2, h).unwrap(); }
+    }
+
+    assert_eq!(bg_affine_set(&mut bus, 0x02000000, 0x03000000, 2).unwrap(), 160);
+    let h = |bus: &Bus, addr: u32| bus.load_halfword(addr).unwrap() as i16;
+    let w = |bus: &Bus, addr: u32| bus.load_word(addr).unwrap();
+    assert_eq!((0..4).map(|j| h(&bus, 0x03000000 + j * 2)).collect::<Vec<_>>(), vec![0x100, 0, 0, 0x100]);
+    assert_eq!((w(&bus, 0x03000008), w(&bus, 0x0300000C)), (-26624, -12288));
+    assert_eq!((0..4).map(|j| h(&bus, 0x03000010 + j * 2)).collect::<Vec<_>>(), vec![0, -0x100, 0x100, 0]);
+    assert_eq!((w(&bus, 0x03000018), w(&bus, 0x0300001C)), (24576, -22528));
+}
+
+#[test]
+fn obj_affine_set_writes_with_stride() {
+    let mut bus = Bus::new(GamePak::new(), BiosRom::new());
+    for (i, &h) in [0x100, 0x200, 0x4000, 0, 0x100, 0x100, 0x2000, 0].iter().enumerate() {
+        bus.store_halfword(0x02000000 + i as u32 * 2, h).unwrap();
+    }
+
+    // Into OAM, where the parameters are 8 bytes apart.
+    assert_eq!(obj_affine_set(&mut bus, 0x02000000, 0x07000006, 2, 8).unwrap(), 110);
+    let params = (0..8).map(|j| bus.load_halfword(0x07000006 + j * 8).unwrap() as i16).collect::<Vec<_>>();
+    assert_eq!(params, vec![0, -0x100, 0x200, 0, 181, -182, 181, 181]);
+    assert_eq!(bus.load_halfword(0x07000000).unwrap(), 0);
+}
+
+#[test]
+fn midi_keys_to_frequencies() {
+    assert_eq!(midi_key_to_freq(44100 << 10, 180, 0), 44100 << 10);
+    assert_eq!(midi_key_to_freq(44100 << 10, 168, 0), 22050 << 10);
+    assert_eq!(midi_key_to_freq(44100 << 10, 192, 0), 88200 << 10);
+    assert_eq!(midi_key_to_freq(1 << 20, 179, 0), 989723);
+    assert_eq!(midi_key_to_freq(1 << 20, 179, 128), 1018725);
+
+    let mut bus = Bus::new(GamePak::new(), BiosRom::new());
+    bus.store_word(0x02000000 + WAVE_DATA_FREQ_OFFSET, 1 << 20).unwrap();
+    assert_eq!(midi_key2freq(&mut bus, 0x02000000, 168, 0).unwrap(), 1 << 19);
+}
+
+#[test]
+fn sound_bias_ramps_level_only() {
+    let mut bus = Bus::new(GamePak::new(), BiosRom::new());
+    let addr = IO_REGISTERS_FIRST + REG_SOUNDBIAS;
+    bus.store_halfword(addr, 0xC000).unwrap();
+
+    assert_eq!(sound_bias(&mut bus, true, 1).unwrap(), 10 + 256 * 12);
+    assert_eq!(bus.load_halfword(addr).unwrap(), 0xC200);
+    assert_eq!(sound_bias(&mut bus, true, 1).unwrap(), 10);
+    assert_eq!(sound_bias(&mut bus, false, 0).unwrap(), 10 + 256 * 8);
+    assert_eq!(bus.load_halfword(addr).unwrap(), 0xC000);
+}
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -9,13 +9,179 @@ use super::memory::IO_REGISTERS_LEN;
 use super::memory::{RawBytes, Rom8, Rom16, Rom32, Ram8, Ram16, Ram32};
 
 
+/// Offset of the LCD control register.
+pub const REG_DISPCNT: u32 = 0x000;
+
+/// Offset of the general LCD status register.
+pub const REG_DISPSTAT: u32 = 0x004;
+
+/// Offset of the vertical counter register.
+pub const REG_VCOUNT: u32 = 0x006;
+
+/// Offset of the BG0 control register.
+pub const REG_BG0CNT: u32 = 0x008;
+
+/// Offset of the BG1 control register.
+pub const REG_BG1CNT: u32 = 0x00A;
+
+/// Offset of the BG2 control register.
+pub const REG_BG2CNT: u32 = 0x00C;
+
+/// Offset of the BG3 control register.
+pub const REG_BG3CNT: u32 = 0x00E;
+
+/// Offset of the BG2 affine parameter `PA`.
+pub const REG_BG2PA: u32 = 0x020;
+
+/// Offset of the BG2 affine parameter `PD`.
+pub const REG_BG2PD: u32 = 0x026;
+
+/// Offset of the BG3 affine parameter `PA`.
+pub const REG_BG3PA: u32 = 0x030;
+
+/// Offset of the BG3 affine parameter `PD`.
+pub const REG_BG3PD: u32 = 0x036;
+
+/// Offset of the sound channel 1 sweep register.
+pub const REG_SOUND1CNT_L: u32 = 0x060;
+
+/// Offset of the sound channel 1 duty, length and envelope register.
+pub const REG_SOUND1CNT_H: u32 = 0x062;
+
+/// Offset of the sound channel 1 frequency and control register.
+pub const REG_SOUND1CNT_X: u32 = 0x064;
+
+/// Offset of the sound channel 2 duty, length and envelope register.
+pub const REG_SOUND2CNT_L: u32 = 0x068;
+
+/// Offset of the sound channel 2 frequency and control register.
+pub const REG_SOUND2CNT_H: u32 = 0x06C;
+
+/// Offset of the sound channel 3 wave RAM select register.
+pub const REG_SOUND3CNT_L: u32 = 0x070;
+
+/// Offset of the sound channel 3 length and volume register.
+pub const REG_SOUND3CNT_H: u32 = 0x072;
+
+/// Offset of the sound channel 3 frequency and control register.
+pub const REG_SOUND3CNT_X: u32 = 0x074;
+
+/// Offset of the sound channel 4 length and envelope register.
+pub const REG_SOUND4CNT_L: u32 = 0x078;
+
+/// Offset of the sound channel 4 frequency and control register.
+pub const REG_SOUND4CNT_H: u32 = 0x07C;
+
+/// Offset of the PSG volume and panning register.
+pub const REG_SOUNDCNT_L: u32 = 0x080;
+
+/// Offset of the mixing and Direct Sound control register.
+pub const REG_SOUNDCNT_H: u32 = 0x082;
+
+/// Offset of the master sound enable and status register.
+pub const REG_SOUNDCNT_X: u32 = 0x084;
+
+/// Offset of the sound bias and resolution register.
+pub const REG_SOUNDBIAS: u32 = 0x088;
+
+/// Offset of the first of 16 bytes of wave RAM.
+pub const REG_WAVE_RAM: u32 = 0x090;
+
+/// Offset of the Direct Sound channel A FIFO.
+pub const REG_FIFO_A: u32 = 0x0A0;
+
+/// Offset of the Direct Sound channel B FIFO.
+pub const REG_FIFO_B: u32 = 0x0A4;
+
+/// Offset of the DMA 0 source address register.
+///
+/// Each DMA channel has 12 bytes of registers: source
+/// address, destination address, word count and control.
+pub const REG_DMA0SAD: u32 = 0x0B0;
+
+/// Offset of the timer 0 counter and reload register.
+///
+/// Each timer has 4 bytes of registers: counter and control.
+pub const REG_TM0CNT_L: u32 = 0x100;
+
+/// Offset of the SIO mode select and general purpose data register.
+pub const REG_RCNT: u32 = 0x134;
+
+/// Offset of the interrupt enable register.
+pub const REG_IE: u32 = 0x200;
+
+/// Offset of the interrupt request flags register.
+///
+/// Writing 1 to a bit acknowledges the interrupt.
+pub const REG_IF: u32 = 0x202;
+
+/// Offset of the GamePak waitstate control register.
+pub const REG_WAITCNT: u32 = 0x204;
+
+/// Offset of the interrupt master enable register.
+pub const REG_IME: u32 = 0x208;
+
+/// Offset of the post boot flag, set by the BIOS after booting.
+pub const REG_POSTFLG: u32 = 0x300;
+
+/// Offset of the power down control register.
+///
+/// Writing bit 7 cleared halts the CPU, set stops it.
+pub const REG_HALTCNT: u32 = 0x301;
+
+/// Gets the offset of the control register of a given BG.
+///
+/// # Params
+/// - `bg`: The background's index, `0..4`.
+pub fn reg_bgcnt(bg: usize) -> u32 {
+    debug_assert!(bg < 4);
+    REG_BG0CNT + (bg as u32 * 2)
+}
+
+
+/// All interrupt sources, named by their bit in `IE` and `IF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Interrupt {
+    #[doc = "The LCD entered the vertical blank period."]     VBlank = 0,
+    #[doc = "The LCD entered a horizontal blank period."]     HBlank,
+    #[doc = "The LCD reached the line selected in DISPSTAT."] VCount,
+    #[doc = "Timer 0 overflowed."]                            Timer0,
+    #[doc = "Timer 1 overflowed."]                            Timer1,
+    #[doc = "Timer 2 overflowed."]                            Timer2,
+    #[doc = "Timer 3 overflowed."]                            Timer3,
+    #[doc = "A serial transfer completed."]                   Serial,
+    #[doc = "DMA 0 finished its transfer."]                   Dma0,
+    #[doc = "DMA 1 finished its transfer."]                   Dma1,
+    #[doc = "DMA 2 finished its transfer."]                   Dma2,
+    #[doc = "DMA 3 finished its transfer."]                   Dma3,
+    #[doc = "A key combination selected in KEYCNT was hit."]  Keypad,
+    #[doc = "The GamePak was removed."]                       GamePak,
+}
+
+impl Interrupt {
+    /// Get the interrupt of a given timer `0..4`.
+    pub fn timer(n: usize) -> Interrupt {
+        [Interrupt::Timer0, Interrupt::Timer1, Interrupt::Timer2, Interrupt::Timer3][n]
+    }
+
+    /// Get the interrupt of a given DMA channel `0..4`.
+    pub fn dma(n: usize) -> Interrupt {
+        [Interrupt::Dma0, Interrupt::Dma1, Interrupt::Dma2, Interrupt::Dma3][n]
+    }
+
+    /// Get the interrupt's bit mask in `IE` and `IF`.
+    pub fn mask(self) -> u16 { 1 << (self as u8) }
+}
+
+
 /// All memory-mapped GBA IO registers.
 pub struct IoRegisters(Box<[u8; IO_REGISTERS_LEN as usize]>);
 
 impl IoRegisters {
     /// Creates new zero initialised IO registers.
     pub fn new() -> IoRegisters {
-        IoRegisters(box [0; IO_REGISTERS_LEN as usize])
+        IoRegisters(Box::new([0; IO_REGISTERS_LEN as usize]))
     }
 
     /// Zero-fills all IO registers.
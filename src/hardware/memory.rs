@@ -39,11 +39,17 @@ pub const WRAM_ON_CHIP_LAST: u32 = 0x03007FFF;
 /// Length of the on-chip WRAM area in bytes.
 pub const WRAM_ON_CHIP_LEN: u32 = (WRAM_ON_CHIP_LAST+1) - WRAM_ON_CHIP_FIRST;
 
+/// Address of the last byte mirroring on-chip WRAM.
+///
+/// The BIOS relies on this, e.g. reading the IRQ handler
+/// address at `0x03FFFFFC` instead of `0x03007FFC`.
+pub const WRAM_ON_CHIP_MIRROR_LAST: u32 = 0x03FFFFFF;
+
 /// Address of the first byte of IO registers.
 pub const IO_REGISTERS_FIRST: u32 = 0x04000000;
 
 /// Address of the last byte of IO registers.
-pub const IO_REGISTERS_LAST: u32 = 0x040003FE;
+pub const IO_REGISTERS_LAST: u32 = 0x040003FF;
 
 /// Length of the IO registers area in bytes.
 pub const IO_REGISTERS_LEN: u32 = (IO_REGISTERS_LAST+1) - IO_REGISTERS_FIRST;
@@ -165,7 +171,7 @@ impl PhysicalAddress {
         match p {
             BIOS_ROM_FIRST         ... BIOS_ROM_LAST         => PhysicalAddress::      BiosROM(p - BIOS_ROM_FIRST),
             WRAM_ON_BOARD_FIRST    ... WRAM_ON_BOARD_LAST    => PhysicalAddress::  OnBoardWRAM(p - WRAM_ON_BOARD_FIRST),
-            WRAM_ON_CHIP_FIRST     ... WRAM_ON_CHIP_LAST     => PhysicalAddress::   OnChipWRAM(p - WRAM_ON_CHIP_FIRST),
+            WRAM_ON_CHIP_FIRST     ... WRAM_ON_CHIP_MIRROR_LAST => PhysicalAddress::OnChipWRAM((p - WRAM_ON_CHIP_FIRST) % WRAM_ON_CHIP_LEN),
             IO_REGISTERS_FIRST     ... IO_REGISTERS_LAST     => PhysicalAddress::  RegistersIO(p - IO_REGISTERS_FIRST),
             PALETTE_RAM_FIRST      ... PALETTE_RAM_LAST      => PhysicalAddress::   PaletteRAM(p - PALETTE_RAM_FIRST),
             VRAM_FIRST             ... VRAM_LAST             => PhysicalAddress::         VRAM(p - VRAM_FIRST),
@@ -328,9 +334,7 @@ pub trait Rom32 : RawBytes {
     /// as bounds checking should be done while converting
     /// global to local addresses.
     fn read_word(&self, offs: u32) -> u32 {
-        let w = LittleEndian::read_u32( self.bytes(offs & !0b11) );
-        if (offs & 0b11) != 0 { w.rotate_right(8 * (offs & 0b11)); }
-        w
+        LittleEndian::read_u32( self.bytes(offs & !0b11) )
     }
 }
 
@@ -355,9 +359,15 @@ pub trait Ram32 : Rom32 {
 /// Implements the BIOS ROM area.
 pub struct BiosRom(Box<[u8; BIOS_ROM_LEN]>);
 
+/// The bundled replacement BIOS.
+///
+/// Assembled from `bios/replacement.s`. It boots straight into
+/// the GamePak and implements the most common BIOS functions.
+pub const REPLACEMENT_BIOS: &'static [u8] = include_bytes!("../../bios/replacement.bin");
+
 impl BiosRom {
     /// Creates a new zero-filled BIOS ROM.
-    pub fn new() -> BiosRom { BiosRom(box [0_u8; BIOS_ROM_LEN]) }
+    pub fn new() -> BiosRom { BiosRom(Box::new([0_u8; BIOS_ROM_LEN])) }
 
     /// Loads a ROM from a file.
     ///
@@ -381,6 +391,14 @@ impl BiosRom {
         for i in rbytes..BIOS_ROM_LEN { self.0[i] = 0 };
         Ok(())
     }
+
+    /// Loads the bundled replacement BIOS.
+    ///
+    /// Unused memory is zero-filled.
+    pub fn load_replacement(&mut self) {
+        trace!("Loading the replacement BIOS ROM.");
+        for i in 0..BIOS_ROM_LEN { self.0[i] = REPLACEMENT_BIOS.get(i).cloned().unwrap_or(0); }
+    }
 }
 
 impl RawBytes for BiosRom {
@@ -392,9 +410,205 @@ impl Rom16 for BiosRom {}
 impl Rom32 for BiosRom {}
 
 impl Default for BiosRom {
-    fn default() -> BiosRom { BiosRom(box [0_u8; BIOS_ROM_LEN]) }
+    fn default() -> BiosRom { BiosRom(Box::new([0_u8; BIOS_ROM_LEN])) }
+}
+
+
+
+/// Implements the 256kB on-board work RAM area.
+pub struct OnBoardWram(Box<[u8; WRAM_ON_BOARD_LEN as usize]>);
+
+impl OnBoardWram {
+    /// Creates a new zero-filled work RAM.
+    pub fn new() -> OnBoardWram { OnBoardWram(Box::new([0_u8; WRAM_ON_BOARD_LEN as usize])) }
+
+    /// Zero-fills the whole work RAM.
+    pub fn clear(&mut self) {
+        for b in self.0.iter_mut() { *b = 0; }
+    }
+}
+
+impl RawBytes for OnBoardWram {
+    fn bytes(&self, offs: u32) -> &[u8] { &self.0[(offs as usize)..] }
+    fn bytes_mut(&mut self, offs: u32) -> &mut [u8] { &mut self.0[(offs as usize)..] }
+}
+impl Rom8  for OnBoardWram {}
+impl Rom16 for OnBoardWram {}
+impl Rom32 for OnBoardWram {}
+impl Ram8  for OnBoardWram {}
+impl Ram16 for OnBoardWram {}
+impl Ram32 for OnBoardWram {}
+
+impl Default for OnBoardWram {
+    fn default() -> OnBoardWram { OnBoardWram::new() }
+}
+
+
+/// Implements the 32kB on-chip work RAM area.
+///
+/// The BIOS keeps its stacks and some variables
+/// in the last 256 bytes of this RAM.
+pub struct OnChipWram(Box<[u8; WRAM_ON_CHIP_LEN as usize]>);
+
+impl OnChipWram {
+    /// Creates a new zero-filled work RAM.
+    pub fn new() -> OnChipWram { OnChipWram(Box::new([0_u8; WRAM_ON_CHIP_LEN as usize])) }
+
+    /// Zero-fills the whole work RAM.
+    pub fn clear(&mut self) {
+        for b in self.0.iter_mut() { *b = 0; }
+    }
+}
+
+impl RawBytes for OnChipWram {
+    fn bytes(&self, offs: u32) -> &[u8] { &self.0[(offs as usize)..] }
+    fn bytes_mut(&mut self, offs: u32) -> &mut [u8] { &mut self.0[(offs as usize)..] }
+}
+impl Rom8  for OnChipWram {}
+impl Rom16 for OnChipWram {}
+impl Rom32 for OnChipWram {}
+impl Ram8  for OnChipWram {}
+impl Ram16 for OnChipWram {}
+impl Ram32 for OnChipWram {}
+
+impl Default for OnChipWram {
+    fn default() -> OnChipWram { OnChipWram::new() }
+}
+
+
+/// Implements the palette RAM area.
+///
+/// The first 256 colours are used for backgrounds,
+/// the last 256 colours are used for sprites.
+pub struct PaletteRam(Box<[u8; PALETTE_RAM_LEN as usize]>);
+
+impl PaletteRam {
+    /// Number of BGR555 colours stored in palette RAM.
+    pub const COLOURS: usize = (PALETTE_RAM_LEN / 2) as usize;
+
+    /// Creates a new zero-filled palette RAM.
+    pub fn new() -> PaletteRam { PaletteRam(Box::new([0_u8; PALETTE_RAM_LEN as usize])) }
+
+    /// Zero-fills the palette RAM.
+    pub fn clear(&mut self) {
+        for b in self.0.iter_mut() { *b = 0; }
+    }
+
+    /// Gets a single BGR555 colour.
+    ///
+    /// # Params
+    /// - `i`: Index of the colour, where `0..256` are
+    ///   background colours and `256..512` are sprite colours.
+    pub fn colour(&self, i: usize) -> u16 {
+        debug_assert!(i < PaletteRam::COLOURS);
+        self.read_halfword((i * 2) as u32)
+    }
+}
+
+impl RawBytes for PaletteRam {
+    fn bytes(&self, offs: u32) -> &[u8] { &self.0[(offs as usize)..] }
+    fn bytes_mut(&mut self, offs: u32) -> &mut [u8] { &mut self.0[(offs as usize)..] }
+}
+impl Rom8  for PaletteRam {}
+impl Rom16 for PaletteRam {}
+impl Rom32 for PaletteRam {}
+impl Ram8  for PaletteRam {}
+impl Ram16 for PaletteRam {}
+impl Ram32 for PaletteRam {}
+
+impl Default for PaletteRam {
+    fn default() -> PaletteRam { PaletteRam::new() }
+}
+
+
+/// Implements the VRAM area.
+///
+/// VRAM holds tile graphics, tile maps and bitmaps for
+/// backgrounds, as well as tile graphics for sprites.
+pub struct VideoRam(Box<[u8; VRAM_LEN as usize]>);
+
+impl VideoRam {
+    /// Size of a single character block in bytes.
+    pub const CHAR_BLOCK_LEN: usize = 0x4000;
+
+    /// Size of a single screen block in bytes.
+    pub const SCREEN_BLOCK_LEN: usize = 0x800;
+
+    /// Creates a new zero-filled VRAM.
+    pub fn new() -> VideoRam { VideoRam(Box::new([0_u8; VRAM_LEN as usize])) }
+
+    /// Zero-fills the VRAM.
+    pub fn clear(&mut self) {
+        for b in self.0.iter_mut() { *b = 0; }
+    }
+}
+
+impl RawBytes for VideoRam {
+    fn bytes(&self, offs: u32) -> &[u8] { &self.0[(offs as usize)..] }
+    fn bytes_mut(&mut self, offs: u32) -> &mut [u8] { &mut self.0[(offs as usize)..] }
+}
+impl Rom8  for VideoRam {}
+impl Rom16 for VideoRam {}
+impl Rom32 for VideoRam {}
+impl Ram8  for VideoRam {}
+impl Ram16 for VideoRam {}
+impl Ram32 for VideoRam {}
+
+impl Default for VideoRam {
+    fn default() -> VideoRam { VideoRam::new() }
+}
+
+
+/// Implements the object attribute memory area.
+///
+/// OAM holds the attributes of all 128 sprites, interleaved
+/// with the parameters of 32 affine transformation groups.
+pub struct ObjAttributeMemory(Box<[u8; OBJ_ATTRIBUTES_LEN as usize]>);
+
+impl ObjAttributeMemory {
+    /// Number of sprites described in OAM.
+    pub const OBJECTS: usize = 128;
+
+    /// Number of affine transformation groups stored in OAM.
+    pub const AFFINE_GROUPS: usize = 32;
+
+    /// Creates a new zero-filled OAM.
+    pub fn new() -> ObjAttributeMemory { ObjAttributeMemory(Box::new([0_u8; OBJ_ATTRIBUTES_LEN as usize])) }
+
+    /// Zero-fills the OAM.
+    pub fn clear(&mut self) {
+        for b in self.0.iter_mut() { *b = 0; }
+    }
+
+    /// Gets the three raw attribute halfwords of a sprite.
+    pub fn attributes(&self, i: usize) -> [u16; 3] {
+        debug_assert!(i < ObjAttributeMemory::OBJECTS);
+        let offs = (i * 8) as u32;
+        [self.read_halfword(offs), self.read_halfword(offs + 2), self.read_halfword(offs + 4)]
+    }
+
+    /// Gets the four parameters `PA`, `PB`, `PC`, `PD` of an affine group.
+    pub fn affine_parameters(&self, i: usize) -> [i16; 4] {
+        debug_assert!(i < ObjAttributeMemory::AFFINE_GROUPS);
+        let offs = (i * 32 + 6) as u32;
+        [self.read_halfword(offs) as i16,      self.read_halfword(offs +  8) as i16,
+         self.read_halfword(offs + 16) as i16, self.read_halfword(offs + 24) as i16]
+    }
 }
 
+impl RawBytes for ObjAttributeMemory {
+    fn bytes(&self, offs: u32) -> &[u8] { &self.0[(offs as usize)..] }
+    fn bytes_mut(&mut self, offs: u32) -> &mut [u8] { &mut self.0[(offs as usize)..] }
+}
+impl Rom8  for ObjAttributeMemory {}
+impl Rom16 for ObjAttributeMemory {}
+impl Rom32 for ObjAttributeMemory {}
+impl Ram16 for ObjAttributeMemory {}
+impl Ram32 for ObjAttributeMemory {}
+
+impl Default for ObjAttributeMemory {
+    fn default() -> ObjAttributeMemory { ObjAttributeMemory::new() }
+}
 
 /*
 Licensed to the Apache Software Foundation (ASF) under one
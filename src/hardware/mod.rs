@@ -5,13 +5,11 @@
 #![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
 #![warn(missing_docs)]
 
-use std::cell::{RefCell, Ref, RefMut};
-use std::rc::Rc;
-
 use self::cpu::Arm7Tdmi;
 use self::bus::*;
 pub use self::error::*;
 pub use self::gamepak::*;
+pub use self::framebuffer::*;
 
 
 pub mod cpu;
@@ -20,43 +18,107 @@ pub mod gamepak;
 pub mod error;
 pub mod ioregs;
 pub mod bus;
+pub mod framebuffer;
+pub mod scheduler;
+pub mod apu;
+pub mod timer;
+pub mod dma;
+pub mod hle;
+
+#[cfg(test)]
+mod test;
+
+
+/// Number of CPU cycles it takes the LCD to draw a single frame.
+///
+/// That's 228 scanlines, each taking 1232 cycles, including
+/// the horizontal and vertical blanking periods.
+pub const CYCLES_PER_FRAME: u32 = 280896;
 
 
 /// This is the actual GBA emulator. It handles all the virtual hardware,
 /// loads and saves ROMs and SRAMs, executes the CPU instructions, and
 /// what not.
+///
+/// A `Gba` owns all of its hardware, so it can be moved to a worker
+/// thread.
 pub struct Gba {
     cpu: Arm7Tdmi,
-    bus: Rc<RefCell<Bus>>,
-    bios: Rc<RefCell<memory::BiosRom>>,
-    game_pak: Rc<RefCell<GamePak>>,
+    frame_buffer: FrameBuffer,
+    skip_bios: bool,
+    frame_cycles: u32,
 }
 
 impl Gba {
     /// Creates a new GBA emulator instance.
     pub fn new() -> Gba {
-        let bios = Rc::new(RefCell::new(memory::BiosRom::new()));
-        let gpak = Rc::new(RefCell::new(GamePak::new()));
-        let bus = Rc::new(RefCell::new(Bus::new(gpak.clone(), bios.clone())));
         Gba {
-            cpu: Arm7Tdmi::new(bus.clone()),
-            bus: bus,
-            bios: bios,
-            game_pak: gpak,
+            cpu: Arm7Tdmi::new(Bus::new(GamePak::new(), memory::BiosRom::new())),
+            frame_buffer: FrameBuffer::new(),
+            skip_bios: false,
+            frame_cycles: 0,
+        }
+    }
+
+    /// Checks whether resetting skips the BIOS boot sequence.
+    pub fn is_bios_skipped(&self) -> bool { self.skip_bios }
+
+    /// Configures whether resetting skips the BIOS boot sequence.
+    ///
+    /// If `true`, a reset sets up the CPU and IO registers just
+    /// like the BIOS would, and starts the game right away.
+    pub fn set_bios_skipped(&mut self, skip: bool) { self.skip_bios = skip; }
+
+    /// Resets the CPU.
+    ///
+    /// Unless the BIOS is skipped, execution starts at the
+    /// BIOS' reset vector.
+    pub fn reset(&mut self) -> Result<(), GbaError> {
+        self.frame_cycles = 0;
+        if !self.skip_bios { self.cpu.reset(); return Ok(()); }
+
+        {
+            let bus = self.cpu.memory_mut();
+            let io = memory::IO_REGISTERS_FIRST;
+            let bios_area = memory::WRAM_ON_CHIP_LAST + 1 - 0x200;
+            for i in 0..(0x200 / 4) { try!(bus.store_word(bios_area + i * 4, 0)); }
+            try!(bus.store_byte(io + ioregs::REG_POSTFLG, 1));
+            try!(bus.store_halfword(io + ioregs::REG_SOUNDBIAS, 0x0200));
+            try!(bus.store_halfword(io + ioregs::REG_RCNT, 0x8000));
+            for &r in &[ioregs::REG_BG2PA, ioregs::REG_BG2PD, ioregs::REG_BG3PA, ioregs::REG_BG3PD] {
+                try!(bus.store_halfword(io + r, 0x0100));
+            }
         }
+        self.cpu.direct_boot(memory::GAME_PAK_WS0_ROM_FIRST);
+        Ok(())
+    }
+
+    /// Emulates the GBA until the LCD finished drawing a frame.
+    ///
+    /// A single pipeline step is treated as a single cycle.
+    /// Cycles the CPU ran past the end of a frame count
+    /// towards the next frame.
+    pub fn run_frame(&mut self) -> Result<(), GbaError> {
+        while self.frame_cycles < CYCLES_PER_FRAME {
+            let steps = try!(self.cpu.step());
+            self.cpu.memory_mut().tick(steps as u64);
+            self.frame_cycles += steps;
+        }
+        self.frame_cycles -= CYCLES_PER_FRAME;
+        Ok(())
     }
 
     /// Get an immutable reference to the GamePak.
-    pub fn game_pak(&self) -> Ref<GamePak> { self.game_pak.borrow() }
+    pub fn game_pak(&self) -> &GamePak { self.cpu.memory().game_pak() }
 
     /// Get a mutable reference to the GamePak.
-    pub fn game_pak_mut(&mut self) -> RefMut<GamePak> { self.game_pak.borrow_mut() }
+    pub fn game_pak_mut(&mut self) -> &mut GamePak { self.cpu.memory_mut().game_pak_mut() }
 
     /// Get an immutable reference to the BIOS ROM.
-    pub fn bios(&self) -> Ref<memory::BiosRom> { self.bios.borrow() }
+    pub fn bios(&self) -> &memory::BiosRom { self.cpu.memory().bios() }
 
     /// Get a mutable reference to the BIOS ROM.
-    pub fn bios_mut(&mut self) -> RefMut<memory::BiosRom> { self.bios.borrow_mut() }
+    pub fn bios_mut(&mut self) -> &mut memory::BiosRom { self.cpu.memory_mut().bios_mut() }
 
     /// Get an immmutable reference to the ARM7TDMI CPU emulator.
     pub fn cpu_arm7tdmi(&self) -> &Arm7Tdmi { &self.cpu }
@@ -65,10 +127,19 @@ impl Gba {
     pub fn cpu_arm7tdmi_mut(&mut self) -> &mut Arm7Tdmi { &mut self.cpu }
 
     /// Get an immutable reference to the bus system.
-    pub fn bus(&self) -> Ref<Bus> { self.bus.borrow() }
+    pub fn bus(&self) -> &Bus { self.cpu.memory() }
 
     /// Get a mutable reference to the bus system.
-    pub fn bus_mut(&mut self) -> RefMut<Bus> { self.bus.borrow_mut() }
+    pub fn bus_mut(&mut self) -> &mut Bus { self.cpu.memory_mut() }
+
+    /// Get an immutable reference to the most recently drawn frame.
+    pub fn frame_buffer(&self) -> &FrameBuffer { &self.frame_buffer }
+
+    /// Get a mutable reference to the most recently drawn frame.
+    pub fn frame_buffer_mut(&mut self) -> &mut FrameBuffer { &mut self.frame_buffer }
+
+    /// Takes all audio mixed by the APU since the last call.
+    pub fn take_audio(&mut self) -> Vec<apu::AudioChunk> { self.cpu.memory_mut().apu_mut().take_audio() }
 }
 
 impl Default for Gba {
@@ -0,0 +1,144 @@
+// License below.
+//! Implements a scheduler keeping track of timed hardware events.
+//!
+//! All hardware besides the CPU runs on a shared timeline
+//! measured in CPU cycles. Instead of ticking every device
+//! on every cycle, devices schedule the next point in time
+//! at which they have to do something, e.g. generate an
+//! audio sample, and the scheduler tells when it's due.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+
+/// Number of CPU cycles per second.
+pub const CPU_CLOCK_HZ: u32 = 1 << 24;
+
+
+/// All kinds of events that can be scheduled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    #[doc = "Clocks the APU's 512Hz frame sequencer."]
+    ApuFrameSequencer,
+    #[doc = "The APU should mix a new output sample."]
+    ApuSample,
+    #[doc = "The given timer `0..4` overflows."]
+    TimerOverflow(usize),
+}
+
+
+/// Keeps timed events sorted by the cycle they are due at.
+pub struct Scheduler {
+    now: u64,
+    // Sorted by time descending, such that the next event is the last one.
+    events: Vec<(u64, EventKind)>,
+}
+
+impl Scheduler {
+    /// Creates a new scheduler at cycle 0 without any events.
+    pub fn new() -> Scheduler {
+        Scheduler {
+            now: 0,
+            events: Vec::with_capacity(16),
+        }
+    }
+
+    /// Removes all events and rewinds the timeline to cycle 0.
+    pub fn reset(&mut self) {
+        self.now = 0;
+        self.events.clear();
+    }
+
+    /// Get the number of cycles passed since the last reset.
+    pub fn now(&self) -> u64 { self.now }
+
+    /// Get the cycle at which the next event is due, if any.
+    pub fn next_event_at(&self) -> Option<u64> {
+        self.events.last().map(|&(t, _)| t)
+    }
+
+    /// Schedules an event some cycles from now.
+    ///
+    /// # Params
+    /// - `kind`: The event to schedule.
+    /// - `delay`: Number of cycles from now the event is due.
+    pub fn schedule(&mut self, kind: EventKind, delay: u64) {
+        let t = self.now + delay;
+        self.schedule_at(kind, t);
+    }
+
+    /// Schedules an event at an absolute point in time.
+    ///
+    /// Events due at the same cycle are handed out in
+    /// the order they have been scheduled in.
+    pub fn schedule_at(&mut self, kind: EventKind, at: u64) {
+        let i = self.events.iter().position(|&(t, _)| t <= at).unwrap_or(self.events.len());
+        self.events.insert(i, (at, kind));
+    }
+
+    /// Removes all scheduled events of a given kind.
+    pub fn cancel(&mut self, kind: EventKind) {
+        self.events.retain(|&(_, k)| k != kind);
+    }
+
+    /// Checks whether an event of a given kind is scheduled.
+    pub fn is_scheduled(&self, kind: EventKind) -> bool {
+        self.events.iter().any(|&(_, k)| k == kind)
+    }
+
+    /// Moves the timeline forward.
+    ///
+    /// Due events are not handled here. Use `pop_due`
+    /// to fetch them afterwards.
+    #[inline]
+    pub fn advance(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+
+    /// Checks whether any event is due.
+    #[inline]
+    pub fn is_event_due(&self) -> bool {
+        match self.events.last() {
+            Some(&(t, _)) => t <= self.now,
+            None => false,
+        }
+    }
+
+    /// Removes and returns the next due event.
+    ///
+    /// # Returns
+    /// - `Some`: The event and the cycle it was due at,
+    ///   which might be some cycles in the past.
+    /// - `None`: No event is due yet.
+    pub fn pop_due(&mut self) -> Option<(u64, EventKind)> {
+        match self.events.last() {
+            Some(&(t, _)) if t <= self.now => self.events.pop(),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler { Scheduler::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,277 @@
+// License below.
+#![allow(missing_docs)]
+
+use std::thread;
+use byteorder::{ByteOrder, LittleEndian};
+use super::*;
+use super::memory::RawBytes;
+use super::scheduler::*;
+use super::ioregs::*;
+use super::dma::*;
+use test::*;
+
+// mov r1, #0x02000000; loop: add r0, r0, #1; str r0, [r1];
+// ldr r2, [r1]; b loop
+const COUNTER_LOOP: &'static [u32] = &[
+    0xE3A01402, 0xE2800001, 0xE5810000, 0xE5912000, 0xEAFFFFFB,
+];
+
+fn gba_running(prog: &[u32]) -> Gba {
+    let mut gba = Gba::new();
+    gba.bios_mut().load_replacement();
+    for (i, &w) in prog.iter().enumerate() {
+        LittleEndian::write_u32(gba.game_pak_mut().rom_mut().bytes_mut(i as u32 * 4), w);
+    }
+    gba.set_bios_skipped(true);
+    gba.reset().unwrap();
+    gba
+}
+
+#[test]
+fn gba_runs_on_worker_thread() {
+    let gba = gba_running(COUNTER_LOOP);
+    let gba = thread::spawn(move || {
+        let mut gba = gba;
+        gba.run_frame().unwrap();
+        gba
+    }).join().unwrap();
+
+    let count = gba.bus().load_word(0x02000000).unwrap();
+    assert!(count > 0);
+    assert_eq!(count, gba.cpu_arm7tdmi().gpr(0));
+}
+
+#[test]
+fn misaligned_bus_accesses() {
+    let mut gba = Gba::new();
+    let bus = gba.bus_mut();
+    bus.store_word(0x02000003, 0x44338211).unwrap();
+    assert_eq!(bus.load_word(0x02000000).unwrap(), 0x44338211);
+    assert_eq!(bus.load_word(0x02000001).unwrap() as u32, 0x11443382);
+    assert_eq!(bus.load_halfword(0x02000002).unwrap(), 0x4433);
+    assert_eq!(bus.load_halfword(0x02000003).unwrap(), 0x33000044);
+
+    bus.store_halfword(0x02000001, 0xCCDD).unwrap();
+    assert_eq!(bus.load_word(0x02000000).unwrap(), 0x4433CCDD);
+}
+
+#[test]
+fn on_chip_wram_is_mirrored() {
+    let mut gba = Gba::new();
+    let bus = gba.bus_mut();
+    bus.store_word(0x03FFFFFC, 0x08000000).unwrap();
+    assert_eq!(bus.load_word(0x03007FFC).unwrap(), 0x08000000);
+    assert_eq!(bus.load_word(0x0300FFFC).unwrap(), 0x08000000);
+}
+
+#[test]
+fn scheduler_hands_out_events_in_order() {
+    let mut sched = Scheduler::new();
+    sched.schedule(EventKind::ApuSample, 10);
+    sched.schedule(EventKind::TimerOverflow(0), 5);
+    sched.schedule_at(EventKind::TimerOverflow(1), 10);
+    assert_eq!(sched.next_event_at(), Some(5));
+
+    sched.advance(4);
+    assert!(!sched.is_event_due());
+    assert_eq!(sched.pop_due(), None);
+    sched.advance(8);
+    assert!(sched.is_event_due());
+    assert_eq!(sched.pop_due(), Some((5, EventKind::TimerOverflow(0))));
+    assert_eq!(sched.pop_due(), Some((10, EventKind::ApuSample)));
+    assert_eq!(sched.pop_due(), Some((10, EventKind::TimerOverflow(1))));
+    assert_eq!(sched.pop_due(), None);
+    assert!(!sched.is_event_due());
+    assert_eq!(sched.now(), 12);
+}
+
+#[test]
+fn scheduler_reschedules_and_cancels() {
+    let mut sched = Scheduler::new();
+    sched.schedule(EventKind::ApuFrameSequencer, 100);
+    sched.schedule(EventKind::TimerOverflow(2), 50);
+    sched.cancel(EventKind::TimerOverflow(2));
+    assert!(!sched.is_scheduled(EventKind::TimerOverflow(2)));
+    sched.schedule(EventKind::TimerOverflow(2), 150);
+
+    // Late events are rescheduled relative to the cycle they were due at.
+    sched.advance(120);
+    let (t, kind) = sched.pop_due().unwrap();
+    assert_eq!((t, kind), (100, EventKind::ApuFrameSequencer));
+    sched.schedule_at(kind, t + 100);
+    assert_eq!(sched.next_event_at(), Some(150));
+    sched.advance(80);
+    assert_eq!(sched.pop_due(), Some((150, EventKind::TimerOverflow(2))));
+    assert_eq!(sched.pop_due(), Some((200, EventKind::ApuFrameSequencer)));
+
+    sched.schedule(EventKind::ApuSample, 1);
+    sched.reset();
+    assert_eq!((sched.now(), sched.next_event_at()), (0, None));
+}
+
+#[test]
+fn timers_prescale_and_cascade() {
+    let mut gba = Gba::new();
+    let bus = gba.bus_mut();
+    bus.store_halfword(0x04000100, 0xFFF0).unwrap();
+    bus.store_word(0x04000104, 0x00C4FFFE).unwrap();
+    bus.store_halfword(0x04000102, 0x0080).unwrap();
+    bus.store_word(0x04000108, 0x00810000).unwrap();
+    let counters = |bus: &Bus| (0..3).map(|n| bus.load_halfword(0x04000100 + n * 4).unwrap() as u16).collect::<Vec<_>>();
+
+    bus.tick(8);
+    assert_eq!(counters(bus), vec![0xFFF8, 0xFFFE, 0]);
+    bus.tick(8);
+    assert_eq!(counters(bus), vec![0xFFF0, 0xFFFF, 0]);
+    assert_eq!(bus.load_halfword(0x04000202).unwrap(), 0);
+
+    // Timer 1 reloads when timer 0 overflows again.
+    bus.tick(16);
+    assert_eq!(counters(bus), vec![0xFFF0, 0xFFFE, 0]);
+    assert_eq!(bus.load_halfword(0x04000202).unwrap() as u16, Interrupt::Timer1.mask());
+    bus.tick(40);
+    assert_eq!(counters(bus), vec![0xFFF8, 0xFFFE, 1]);
+}
+
+#[test]
+fn timers_reload_on_overflow() {
+    let mut gba = Gba::new();
+    let bus = gba.bus_mut();
+    bus.store_word(0x04000100, 0x0082FFFE).unwrap();
+    bus.tick(511);
+    assert_eq!(bus.load_halfword(0x04000100).unwrap(), 0xFFFF);
+
+    // Writing the reload value leaves the running counter alone.
+    bus.store_halfword(0x04000100, 0xFFFD).unwrap();
+    assert_eq!(bus.load_halfword(0x04000100).unwrap(), 0xFFFF);
+    bus.tick(1);
+    assert_eq!(bus.load_halfword(0x04000100).unwrap(), 0xFFFD);
+
+    // Switching the prescaler keeps the counter.
+    bus.tick(256);
+    bus.store_halfword(0x04000102, 0x0080).unwrap();
+    bus.tick(1);
+    assert_eq!(bus.load_halfword(0x04000100).unwrap(), 0xFFFF);
+    bus.tick(1);
+    assert_eq!(bus.load_halfword(0x04000100).unwrap(), 0xFFFD);
+}
+
+#[test]
+fn dma_transfers_immediately_with_address_control() {
+    let mut gba = Gba::new();
+    let bus = gba.bus_mut();
+    for i in 0..4 { bus.store_word(0x02000000 + i * 4, i as i32 + 1).unwrap(); }
+    let dest = |bus: &Bus| (0..4).map(|i| bus.load_word(0x02000100 + i * 4).unwrap()).collect::<Vec<_>>();
+
+    // Incrementing word transfer.
+    bus.store_word(0x040000D4, 0x02000000).unwrap();
+    bus.store_word(0x040000D8, 0x02000100).unwrap();
+    bus.store_word(0x040000DC, 0x84000004_u32 as i32).unwrap();
+    assert_eq!(dest(bus), vec![1, 2, 3, 4]);
+    assert_eq!(bus.load_halfword(0x040000DE).unwrap(), 0x0400);
+
+    // Decrementing destination.
+    bus.store_word(0x040000D8, 0x0200010C).unwrap();
+    bus.store_word(0x040000DC, 0x84200004_u32 as i32).unwrap();
+    assert_eq!(dest(bus), vec![4, 3, 2, 1]);
+
+    // Fixed source, halfwords.
+    bus.store_word(0x040000D8, 0x02000100).unwrap();
+    bus.store_word(0x040000DC, 0x81000008_u32 as i32).unwrap();
+    assert_eq!(dest(bus), vec![0x00010001; 4]);
+}
+
+#[test]
+fn dma_waits_for_its_start_timing() {
+    let mut gba = Gba::new();
+    let bus = gba.bus_mut();
+    bus.store_word(0x02000000, 42).unwrap();
+    bus.store_word(0x040000D4, 0x02000000).unwrap();
+    bus.store_word(0x040000D8, 0x02000100).unwrap();
+    // VBlank, repeat, words.
+    bus.store_word(0x040000DC, 0x96000001_u32 as i32).unwrap();
+    bus.tick(280896);
+    assert_eq!(bus.load_word(0x02000100).unwrap(), 0);
+    assert_eq!(bus.load_halfword(0x040000DE).unwrap() as u16, 0x9600);
+}
+
+#[test]
+fn dma_repeats_and_reloads_destination() {
+    let mut dma = Dma::new();
+    let mut regs = IoRegisters::new();
+    // DMA 0 at HBlank, repeat, increment and reload destination.
+    let writes = &[(REG_DMA0SAD, 0x02000000_u32), (REG_DMA0SAD + 4, 0x06000000), (REG_DMA0SAD + 8, 0xA2600008)];
+    let mut started = None;
+    for &(offs, x) in writes {
+        for i in 0..4 { started = started.or(dma.write_register(offs + i, (x >> (8 * i)) as u8, &mut regs)); }
+    }
+    assert_eq!(started, None);
+
+    let t = dma.transfer(0, &regs);
+    assert_eq!((t.source, t.destination, t.units, t.words), (0x02000000, 0x06000000, 8, false));
+    assert_eq!(t.destination_control, AddressControl::IncrementReload);
+    assert!(!dma.finish(&t, 0x02000010, 0x06000010, &mut regs));
+    assert_eq!(dma.destination(0), 0x06000000);
+    assert_eq!(dma.transfer(0, &regs).source, 0x02000010);
+    assert_eq!(Dma::read_register(REG_DMA0SAD + 11, &regs), 0xA2);
+}
+
+#[test]
+fn timer_overflows_refill_sound_fifo() {
+    let mut gba = Gba::new();
+    let bus = gba.bus_mut();
+    for i in 0..16 { bus.store_word(0x02000000 + i * 4, (0x03020100 + i * 0x04040404) as i32).unwrap(); }
+
+    // FIFO A on both speakers at full volume, driven by timer 0.
+    bus.store_halfword(0x04000084, 0x0080).unwrap();
+    bus.store_halfword(0x04000082, 0x0B04).unwrap();
+    // DMA 1 at special timing, repeat, words, with an IRQ.
+    bus.store_word(0x040000BC, 0x02000000).unwrap();
+    bus.store_word(0x040000C0, 0x040000A0).unwrap();
+    bus.store_word(0x040000C4, 0xF6000000_u32 as i32).unwrap();
+    assert_eq!(bus.apu().fifo_a().len(), 0);
+
+    // Timer 0 overflows every cycle.
+    bus.store_word(0x04000100, 0x0080FFFF).unwrap();
+    let mut played = vec![];
+    for _ in 0..4 {
+        bus.tick(1);
+        played.push((bus.apu().fifo_a().output(), bus.apu().fifo_a().len()));
+    }
+    assert_eq!(played, vec![(0, 16), (0, 31), (1, 30), (2, 29)]);
+    assert_eq!(bus.load_halfword(0x04000202).unwrap() as u16, Interrupt::Dma1.mask());
+    assert_eq!(bus.load_halfword(0x040000C6).unwrap() as u16, 0xF600);
+}
+
+#[bench]
+pub fn frame_emulation_speed(b: &mut Bencher) {
+    let mut gba = gba_running(COUNTER_LOOP);
+    b.iter(|| gba.run_frame().unwrap());
+}
+
+#[bench]
+pub fn frame_emulation_speed_with_block_cache(b: &mut Bencher) {
+    let mut gba = gba_running(COUNTER_LOOP);
+    gba.cpu_arm7tdmi_mut().set_block_cache_enabled(true);
+    b.iter(|| gba.run_frame().unwrap());
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
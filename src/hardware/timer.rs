@@ -0,0 +1,188 @@
+// License below.
+//! Implements the GBA's four 16-bit timers.
+//!
+//! Timers count up at a prescaled CPU clock, or on each overflow
+//! of the previous timer. On overflow, a timer reloads its
+//! counter, may request an interrupt, and may drive the Direct
+//! Sound channels in case of timers 0 and 1.
+//!
+//! Running timers are not ticked each cycle. Instead, their
+//! counters are derived from the scheduler's timeline and
+//! their overflows are scheduled as events.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use super::ioregs::REG_TM0CNT_L;
+use super::scheduler::*;
+
+/// Number of timers.
+pub const TIMERS: usize = 4;
+
+/// Prescaler selections of `TMxCNT_H` bits 0-1, as shifts.
+const PRESCALER_SHIFTS: [u32; 4] = [0, 6, 8, 10];
+
+const CNT_COUNT_UP: u16 = 1 << 2;
+const CNT_IRQ: u16 = 1 << 6;
+const CNT_ENABLE: u16 = 1 << 7;
+
+
+#[derive(Clone, Copy)]
+struct Timer {
+    reload: u16,
+    counter: u16,
+    control: u16,
+    // The point in time `counter` was valid at.
+    synced_at: u64,
+}
+
+impl Timer {
+    fn is_running(&self) -> bool { 0 != (self.control & CNT_ENABLE) }
+
+    // Count-up timers only tick when the previous timer overflows.
+    // Timer 0 has no previous timer and ignores count-up mode.
+    fn is_cascading(&self, n: usize) -> bool { (n > 0) & (0 != (self.control & CNT_COUNT_UP)) }
+
+    fn shift(&self) -> u32 { PRESCALER_SHIFTS[(self.control & 0b11) as usize] }
+
+    fn counter_at(&self, n: usize, now: u64) -> u16 {
+        if !self.is_running() | self.is_cascading(n) { return self.counter; }
+        let ticks = (now - self.synced_at) >> self.shift();
+        (self.counter as u64 + ticks) as u16
+    }
+
+    fn cycles_to_overflow(&self) -> u64 {
+        (0x10000 - self.counter as u64) << self.shift()
+    }
+}
+
+
+/// Emulates all four timers.
+pub struct Timers {
+    timers: [Timer; TIMERS],
+}
+
+impl Timers {
+    /// Creates new stopped timers.
+    pub fn new() -> Timers {
+        Timers {
+            timers: [Timer { reload: 0, counter: 0, control: 0, synced_at: 0 }; TIMERS],
+        }
+    }
+
+    /// Stops and clears all timers.
+    pub fn reset(&mut self) { *self = Timers::new(); }
+
+    /// Checks whether an IO register offset belongs to the timers.
+    pub fn is_timer_register(offs: u32) -> bool {
+        (REG_TM0CNT_L <= offs) & (offs < REG_TM0CNT_L + (TIMERS as u32) * 4)
+    }
+
+    /// Gets a timer's current counter value.
+    pub fn counter(&self, n: usize, now: u64) -> u16 { self.timers[n].counter_at(n, now) }
+
+    /// Checks whether a timer requests an interrupt on overflow.
+    pub fn irq_enabled(&self, n: usize) -> bool { 0 != (self.timers[n].control & CNT_IRQ) }
+
+    /// Reads a byte from a timer register.
+    ///
+    /// Reading `TMxCNT_L` returns the current counter value
+    /// rather than the reload value last written.
+    pub fn read_register(&self, offs: u32, now: u64) -> u8 {
+        let rel = offs - REG_TM0CNT_L;
+        let n = (rel / 4) as usize;
+        let x = match rel % 4 {
+            0 | 1 => self.counter(n, now),
+            _     => self.timers[n].control & 0x00C7,
+        };
+        if 0 == (rel & 1) { x as u8 } else { (x >> 8) as u8 }
+    }
+
+    /// Writes a byte to a timer register and applies its effects.
+    ///
+    /// # Params
+    /// - `offs`: The register's offset in IO memory.
+    /// - `b`: The byte to write.
+    /// - `sched`: The scheduler to update the timer's overflow event with.
+    pub fn write_register(&mut self, offs: u32, b: u8, sched: &mut Scheduler) {
+        let rel = offs - REG_TM0CNT_L;
+        let n = (rel / 4) as usize;
+        let now = sched.now();
+        let t = &mut self.timers[n];
+        match rel % 4 {
+            0 => t.reload = (t.reload & 0xFF00) | (b as u16),
+            1 => t.reload = (t.reload & 0x00FF) | ((b as u16) << 8),
+            2 => {
+                // Catch up before switching prescaler or mode.
+                let was_running = t.is_running();
+                t.counter = t.counter_at(n, now);
+                t.synced_at = now;
+                t.control = b as u16;
+                if !was_running & t.is_running() { t.counter = t.reload; }
+                sched.cancel(EventKind::TimerOverflow(n));
+                if t.is_running() & !t.is_cascading(n) {
+                    sched.schedule(EventKind::TimerOverflow(n), t.cycles_to_overflow());
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Handles a scheduled overflow of a timer.
+    ///
+    /// Count-up timers depending on this timer are
+    /// incremented and might overflow as well.
+    ///
+    /// # Params
+    /// - `n`: The overflowing timer.
+    /// - `at`: The point in time the overflow was due at.
+    /// - `sched`: The scheduler to reschedule the timer with.
+    ///
+    /// # Returns
+    /// A bit mask of all timers that overflowed.
+    pub fn overflow(&mut self, n: usize, at: u64, sched: &mut Scheduler) -> u8 {
+        {
+            let t = &mut self.timers[n];
+            t.counter = t.reload;
+            t.synced_at = at;
+            sched.schedule_at(EventKind::TimerOverflow(n), at + t.cycles_to_overflow());
+        }
+        let mut mask = 1 << n;
+
+        // Cascade into count-up timers.
+        for i in (n + 1)..TIMERS {
+            let t = &mut self.timers[i];
+            if !t.is_running() | !t.is_cascading(i) { break; }
+            t.counter = t.counter.wrapping_add(1);
+            if t.counter != 0 { break; }
+            t.counter = t.reload;
+            mask |= 1 << i;
+        }
+        mask
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Timers { Timers::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
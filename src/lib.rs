@@ -0,0 +1,54 @@
+// License below.
+//! # GBArs
+//!
+//! The emulation core of the GBArs GameBoy Advance emulator.
+//!
+//! Besides the emulator itself, this library is meant for tools
+//! working on GBA ROMs. For example, `hardware::hle::decompress`
+//! unpacks the BIOS compression formats from plain byte slices:
+//!
+//! ```ignore
+//! extern crate gbars;
+//! use gbars::hardware::hle::decompress;
+//!
+//! let tiles = decompress(&rom[offs..]).unwrap();
+//! ```
+#![cfg_attr(feature="clippy", feature(plugin))]
+#![cfg_attr(feature="clippy", plugin(clippy))]
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![cfg_attr(feature="clippy", allow(doc_markdown))] // Too many false positives, gettin' on my nerves.
+#![cfg_attr(test, feature(test))]
+#![warn(missing_docs, trivial_casts, trivial_numeric_casts)]
+
+#[macro_use]
+extern crate log;
+extern crate byteorder;
+extern crate term_painter;
+
+#[cfg(test)]
+extern crate test;
+
+pub mod hardware;
+pub mod video;
+pub mod audio;
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
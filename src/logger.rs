@@ -82,14 +82,14 @@ impl Log for ConsoleFileLogger {
 pub fn init_with(file: &Path, verbose: bool, colour: bool) -> Result<(), SetLoggerError> {
     set_logger(|max_log_level| {
         max_log_level.set(LogLevelFilter::Trace);
-        box ConsoleFileLogger {
+        Box::new(ConsoleFileLogger {
             file: match OpenOptions::new().write(true).truncate(true).open(file) {
                 Ok(f)  => Some(Mutex::new(RefCell::new(f))),
                 Err(_) => None,
             },
             verbose: verbose,
             colour: colour,
-        }
+        })
     })
 }
 
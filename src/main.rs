@@ -20,26 +20,22 @@
 #![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
 #![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
 #![cfg_attr(feature="clippy", allow(doc_markdown))] // Too many false positives, gettin' on my nerves.
-#![feature(box_syntax, associated_consts, test)] // `test` is sometimes "unknown" an causes other times errors if missing.
 #![warn(missing_docs, trivial_casts, trivial_numeric_casts)]
 
 #[macro_use]
 extern crate log;
 extern crate argparse;
-extern crate byteorder;
 extern crate term_painter;
+extern crate gbars;
 
-#[cfg(test)]
-extern crate test;
-
-use argparse::{ArgumentParser, Print, Parse, ParseOption, StoreTrue, StoreFalse, StoreOption};
+use argparse::{ArgumentParser, Print, Parse, ParseOption, Store, StoreTrue, StoreFalse, StoreOption};
 use std::path::PathBuf;
 use std::ops::Range;
 use std::process;
+use gbars::{hardware, video, audio};
 
 pub mod repl;
 pub mod logger;
-pub mod hardware;
 
 /// Set of values configurable by the command line.
 ///
@@ -58,6 +54,12 @@ pub struct CmdLineArgs {
     /// initialising the emulator.
     pub rom_file_path: Option<PathBuf>,
 
+    /// Accepts `--symbols FILE`.
+    ///
+    /// Labels of this symbol file are used in
+    /// disassembly, e.g. by `--dasm-bios-arm`.
+    pub symbols_file_path: Option<PathBuf>,
+
     /// Accepts `--log FILE`, defaults to `"./GBArs.log"`.
     pub log_file_path: PathBuf,
 
@@ -85,6 +87,12 @@ pub struct CmdLineArgs {
     /// area and logs the result.
     pub disasm_bios_thumb: Option<String>,
 
+    /// Accepts `--dasm-rom FILE`.
+    ///
+    /// Disassembles the whole loaded ROM into an
+    /// assembly file written for GNU `as`.
+    pub disasm_rom_file_path: Option<PathBuf>,
+
     /// Accepts `-v` or `--verbose` as `true`.
     ///
     /// Also accepts `-q` or `--quiet` as `false`, which is the default value.
@@ -119,6 +127,34 @@ pub struct CmdLineArgs {
     /// code.
     pub optimise_swi: bool,
 
+    /// Accepts `--skip-bios` as `true`.
+    ///
+    /// If `true`, the CPU and IO registers are set up like
+    /// the BIOS leaves them, and execution starts directly
+    /// at the GamePak ROM's entry point.
+    pub skip_bios: bool,
+
+    /// Accepts `--block-cache` as `true`.
+    ///
+    /// If `true`, the CPU decodes runs of instructions once
+    /// and executes them from a cache, instead of fetching and
+    /// decoding every instruction again.
+    pub block_cache: bool,
+
+    /// Accepts `--skip-idle-loops` as `true`.
+    ///
+    /// If `true`, the CPU detects loops polling memory until
+    /// it changes, and skips ahead to the next timed event.
+    /// The detected loops are reported after `--run-frames`.
+    pub skip_idle_loops: bool,
+
+    /// Accepts `--unpredictable POLICY`, defaults to `warn`.
+    ///
+    /// How the CPU handles instructions whose behaviour the
+    /// ARM7TDMI manual calls unpredictable. See
+    /// `UnpredictablePolicy`.
+    pub unpredictable: hardware::cpu::UnpredictablePolicy,
+
     /// Accepts `-l` or `--load-sram` as `true`.
     ///
     /// If `true`, the `--rom` flag must be given. GBArs
@@ -131,6 +167,76 @@ pub struct CmdLineArgs {
     /// If `true`, runs the emulator in a REPL-style
     /// debug loop.
     pub run_repl: bool,
+
+    /// Accepts `--run-frames N`.
+    ///
+    /// Runs the emulator for `N` frames without
+    /// any debug REPL.
+    pub run_frames: Option<u32>,
+
+    /// Accepts `--term-video` as `true`.
+    ///
+    /// If `true`, each frame emulated by `--run-frames`
+    /// will be drawn into the terminal.
+    pub term_video: bool,
+
+    /// Accepts `--term-scale N`, defaults to `2`.
+    ///
+    /// By how much frames drawn into the terminal
+    /// should be downscaled.
+    pub term_scale: usize,
+
+    /// Accepts `--colour-correction MODE`, defaults to `raw`.
+    ///
+    /// How presented frames' colours are corrected to
+    /// look like on a real GBA. See `ColourCorrection`.
+    pub colour_correction: video::ColourCorrection,
+
+    /// Accepts `--frame-blending` as `true`.
+    ///
+    /// If `true`, presented frames are blended with
+    /// the previous frame to emulate LCD ghosting.
+    pub frame_blending: bool,
+
+    /// Accepts `--record-audio PATH`.
+    ///
+    /// Records the audio of `--run-frames` into
+    /// a 16-bit stereo WAVE file.
+    pub record_audio: Option<PathBuf>,
+
+    /// Accepts `--audio-rate HZ`, defaults to `44100`.
+    ///
+    /// The sample rate recorded audio is resampled to.
+    pub audio_rate: u32,
+
+    /// Accepts `--dump-tiles BLOCK:FMT`.
+    ///
+    /// Exports a char block as a tile sheet after
+    /// running the emulator. See `parse_tile_sheet_spec`.
+    pub dump_tiles: Option<String>,
+
+    /// Accepts `--dump-tilemaps` as `true`.
+    ///
+    /// If `true`, exports each displayed BG's tilemap
+    /// after running the emulator.
+    pub dump_tilemaps: bool,
+
+    /// Accepts `--dump-palette` as `true`.
+    ///
+    /// If `true`, exports the palette as a swatch
+    /// image after running the emulator.
+    pub dump_palette: bool,
+
+    /// Accepts `--dump-oam` as `true`.
+    ///
+    /// If `true`, logs a table of all sprites
+    /// after running the emulator.
+    pub dump_oam: bool,
+
+    /// Accepts `--dump-dir DIR`, defaults to `"."`.
+    ///
+    /// Where images exported by `--dump-*` are saved.
+    pub dump_dir: PathBuf,
 }
 
 impl Default for CmdLineArgs {
@@ -138,17 +244,35 @@ impl Default for CmdLineArgs {
         CmdLineArgs {
             bios_file_path: None,
             rom_file_path: None,
+            symbols_file_path: None,
             log_file_path: PathBuf::from("./GBArs.log"),
             single_disasm_arm: None,
             single_disasm_thumb: None,
             disasm_bios_arm: None,
             disasm_bios_thumb: None,
+            disasm_rom_file_path: None,
             verbose: cfg!(debug_assertions), // Default to `true` while testing.
             colour: true,
             exit: false,
             optimise_swi: false,
+            skip_bios: false,
+            block_cache: false,
+            skip_idle_loops: false,
+            unpredictable: hardware::cpu::UnpredictablePolicy::Warn,
             load_sram: false,
             run_repl: false,
+            run_frames: None,
+            term_video: false,
+            term_scale: 2,
+            colour_correction: video::ColourCorrection::Raw,
+            frame_blending: false,
+            record_audio: None,
+            audio_rate: audio::SAMPLE_RATE_44100,
+            dump_tiles: None,
+            dump_tilemaps: false,
+            dump_palette: false,
+            dump_oam: false,
+            dump_dir: PathBuf::from("."),
         }
     }
 }
@@ -178,26 +302,61 @@ pub fn parse_hex_range(x: &str, default_begin: u32, default_end: u32) -> Option<
 }
 
 
+/// Parses a tile sheet specification `BLOCK:FMT`.
+///
+/// `BLOCK` is a char block index `0..6`, where blocks
+/// 4 and 5 hold OBJ tiles. `FMT` is either `8bpp` or a
+/// 4bpp palette bank `0..16`, e.g. `4:15` or `0:8bpp`.
+pub fn parse_tile_sheet_spec(x: &str) -> Option<(usize, video::TileFormat)> {
+    let mut s = x.split(':');
+    let b = if let Some(b) = s.next() { b } else { return None; };
+    let f = if let Some(f) = s.next() { f } else { return None; };
+    if let Some(_) = s.next() { return None; } // Invalid spec syntax!
+    let block = match usize::from_str_radix(b, 10) {
+        Ok(i) if i < video::CHAR_BLOCKS => i,
+        Ok(i)  => { error!("Char block {} out of range 0..{}.", i, video::CHAR_BLOCKS); return None; },
+        Err(e) => { error!("{}", e); return None; },
+    };
+    match f.parse() {
+        Ok(fmt) => Some((block, fmt)),
+        Err(e)  => { error!("{}", e); None },
+    }
+}
+
+
 fn main() {
     // Build command line parser.
     let mut args = CmdLineArgs::default();
     parse_command_line(&mut args);
+    validate_command_line(&args);
     configure_logging(&args);
 
     // Prepare the GBA and handle oneshot commands.
     let mut gba = hardware::Gba::new();
     configure_gba_from_command_line(&mut gba, &args);
-    handle_oneshot_commands(&args, &gba);
+    let symbols = load_symbols(&args);
+    handle_oneshot_commands(&args, &gba, &symbols);
 
     // Run REPL?
     if args.run_repl {
         if let Err(e) = repl::GbaRepl::new()
+                        .with_symbols(symbols)
                         .with_colour(args.colour)
+                        .with_term_scale(args.term_scale)
+                        .with_colour_correction(args.colour_correction)
                         .run(&mut gba) {
             error!("{}", e);
         }
     }
 
+    // Run without REPL?
+    if let Some(n) = args.run_frames {
+        if let Err(e) = run_headless(&mut gba, n, &args) { error!("{}", e); }
+    }
+
+    // Export video memory?
+    dump_video_memory(&args, &gba);
+
     // Exit early?
     if args.exit { trace!("Exiting early."); process::exit(0); }
 }
@@ -215,6 +374,10 @@ fn parse_command_line(args: &mut CmdLineArgs) {
     parser.refer(&mut args.rom_file_path)
           .add_option(&["--rom"], ParseOption, "Path to a ROM file to load.")
           .metavar("PATH");
+    parser.refer(&mut args.symbols_file_path)
+          .add_option(&["--symbols"], ParseOption, "Path to a symbol file of `ADDRESS NAME` lines, \
+                                                    e.g. a no$gba `.sym` file, to label disassembly.")
+          .metavar("PATH");
     parser.refer(&mut args.log_file_path)
           .add_option(&["--log"], Parse, "Custom path for the log file.")
           .metavar("PATH");
@@ -248,6 +411,12 @@ fn parse_command_line(args: &mut CmdLineArgs) {
                        address on the right is given, e.g. `00C4..`, it will be set to \
                        `4000` (16KiB).")
           .metavar("RANGE");
+    parser.refer(&mut args.disasm_rom_file_path)
+          .add_option(&["--dasm-rom"], ParseOption,
+                      "Disassembles the loaded ROM into an assembly file at PATH. \
+                       Code is found by following the control flow from the ROM \
+                       header's entry point, everything else is written as data.")
+          .metavar("PATH");
     parser.refer(&mut args.verbose)
           .add_option(&["-v","--verbose"], StoreTrue, "Log extra messages and information.")
           .add_option(&["-q","--quiet"], StoreFalse, "Log with less messages and information. (default)");
@@ -259,14 +428,76 @@ fn parse_command_line(args: &mut CmdLineArgs) {
     parser.refer(&mut args.optimise_swi)
           .add_option(&["-S","--optimise-swi"], StoreTrue, "Enable optimised BIOS functions.")
           .add_option(&["-s","--emulate-swi"], StoreFalse, "Disable optimised BIOS functions. (default)");
+    parser.refer(&mut args.skip_bios)
+          .add_option(&["--skip-bios"], StoreTrue, "Skip the BIOS boot sequence and start the game directly.");
+    parser.refer(&mut args.block_cache)
+          .add_option(&["--block-cache"], StoreTrue, "Execute instructions from a cache of decoded blocks.");
+    parser.refer(&mut args.skip_idle_loops)
+          .add_option(&["--skip-idle-loops"], StoreTrue, "Detect busy-waiting loops and skip ahead to the next event.");
+    parser.refer(&mut args.unpredictable)
+          .add_option(&["--unpredictable"], Store, "Handles unpredictable instructions. POLICY is one of `strict` \
+                                                     to stop with an error, `warn` to log and emulate the hardware, \
+                                                     or `silent` to just emulate it. (default `warn`)")
+          .metavar("POLICY");
     parser.refer(&mut args.load_sram)
           .add_option(&["-l", "--load-sram"], StoreTrue, "Tries loading an SRAM file corresponding to a given `--rom`.");
     parser.refer(&mut args.run_repl)
           .add_option(&["-D", "--debug-repl"], StoreTrue, "Enters a debug loop where each \
                                                            instruction is emulated step by step.");
+    parser.refer(&mut args.run_frames)
+          .add_option(&["--run-frames"], StoreOption, "Runs the emulator for N frames without a debug REPL.")
+          .metavar("N");
+    parser.refer(&mut args.term_video)
+          .add_option(&["--term-video"], StoreTrue, "Draws each frame run by `--run-frames` into the terminal. \
+                                                     The terminal must support 24-bit ANSI colours.");
+    parser.refer(&mut args.term_scale)
+          .add_option(&["--term-scale"], Store, "Downscales frames drawn into the terminal by N. (default 2)")
+          .metavar("N");
+    parser.refer(&mut args.colour_correction)
+          .add_option(&["--colour-correction"], Store,
+                      "Corrects the colours of presented frames. MODE is one of `raw` for \
+                       uncorrected colours, `lcd` for the original GBA LCD, or `sp` for \
+                       the backlit GBA SP LCD. (default `raw`)")
+          .metavar("MODE");
+    parser.refer(&mut args.frame_blending)
+          .add_option(&["--frame-blending"], StoreTrue, "Blends each presented frame with the previous one \
+                                                         to emulate the ghosting of the GBA LCD.");
+    parser.refer(&mut args.record_audio)
+          .add_option(&["--record-audio"], StoreOption, "Records the audio of `--run-frames` into a WAV file.")
+          .metavar("PATH");
+    parser.refer(&mut args.audio_rate)
+          .add_option(&["--audio-rate"], Store, "The sample rate of recorded audio, from 8000 to 192000, \
+                                                    e.g. 48000. (default 44100)")
+          .metavar("HZ");
+    parser.refer(&mut args.dump_tiles)
+          .add_option(&["--dump-tiles"], StoreOption,
+                      "Exports a char block as a BMP tile sheet after running. The BLOCK \
+                       is a char block index from 0 to 5, where 4 and 5 hold OBJ tiles. \
+                       The FMT is either `8bpp` or a 4bpp palette bank from 0 to 15, \
+                       e.g. `4:15`.")
+          .metavar("BLOCK:FMT");
+    parser.refer(&mut args.dump_tilemaps)
+          .add_option(&["--dump-tilemaps"], StoreTrue, "Exports each displayed BG's tilemap as BMP after running.");
+    parser.refer(&mut args.dump_palette)
+          .add_option(&["--dump-palette"], StoreTrue, "Exports the palette as BMP swatches after running.");
+    parser.refer(&mut args.dump_oam)
+          .add_option(&["--dump-oam"], StoreTrue, "Logs a table of all sprites after running.");
+    parser.refer(&mut args.dump_dir)
+          .add_option(&["--dump-dir"], Parse, "Where to save images exported by `--dump-*`. (default `.`)")
+          .metavar("PATH");
     parser.parse_args_or_exit();
 }
 
+// Rejects values the parser accepts but the emulator can't use.
+fn validate_command_line(args: &CmdLineArgs) {
+    use std::io::{self, Write};
+    if !audio::is_valid_sample_rate(args.audio_rate) {
+        let _ = writeln!(io::stderr(), "--audio-rate: {}Hz is not supported. Use {}Hz to {}Hz.",
+                         args.audio_rate, audio::SAMPLE_RATE_MIN, audio::SAMPLE_RATE_MAX);
+        process::exit(2);
+    }
+}
+
 
 fn configure_logging(args: &CmdLineArgs) {
     let p = args.log_file_path.as_path();
@@ -275,14 +506,27 @@ fn configure_logging(args: &CmdLineArgs) {
 }
 
 
-fn handle_oneshot_commands(args: &CmdLineArgs, gba: &hardware::Gba) {
+fn load_symbols(args: &CmdLineArgs) -> hardware::cpu::SymbolTable {
+    let mut symbols = hardware::cpu::SymbolTable::new();
+    if let Some(ref fp) = args.symbols_file_path {
+        if let Err(e) = symbols.load_from_file(fp.as_path()) {
+            error!("Failed loading the symbol file:\n{}", e);
+        } else {
+            info!("Loaded {} symbols.", symbols.len());
+        }
+    }
+    symbols
+}
+
+fn handle_oneshot_commands(args: &CmdLineArgs, gba: &hardware::Gba, symbols: &hardware::cpu::SymbolTable) {
     // Single instructions to disassemble?
     if let Some(ref x) = args.single_disasm_arm   { disasm_arm(x.as_str()); }
     if let Some(ref x) = args.single_disasm_thumb { disasm_thumb(x.as_str()); }
 
     // ROM sections to disassemble?
-    if let Some(ref x) = args.disasm_bios_arm   { disasm_bios_arm(  x.as_str(), gba); }
-    if let Some(ref x) = args.disasm_bios_thumb { disasm_bios_thumb(x.as_str(), gba); }
+    if let Some(ref x) = args.disasm_bios_arm   { disasm_bios(x.as_str(), gba, symbols, hardware::cpu::State::ARM);   }
+    if let Some(ref x) = args.disasm_bios_thumb { disasm_bios(x.as_str(), gba, symbols, hardware::cpu::State::THUMB); }
+    if let Some(ref fp) = args.disasm_rom_file_path { disasm_rom(fp, gba, symbols); }
 }
 
 fn disasm_arm(x: &str) {
@@ -305,49 +549,108 @@ fn disasm_thumb(x: &str) {
     }
 }
 
-fn disasm_bios_arm(x: &str, gba: &hardware::Gba) {
-    use hardware::memory::Rom32;
-    use std::fmt::Write;
+fn disasm_bios(x: &str, gba: &hardware::Gba, symbols: &hardware::cpu::SymbolTable, state: hardware::cpu::State) {
+    use hardware::memory::RawBytes;
+    use hardware::cpu::{CodeImage, Disassembler};
     let r = if let Some(r) = parse_hex_range(x, 0, hardware::memory::BIOS_ROM_LEN as u32) { r } else { return; };
-    let bios = gba.bios();
-    let mut msg = "Disassembling BIOS ROM section:\n\nOffset   Data      \tInstruction\n".to_owned();
-    let mut i = r.start & 0xFFFFFFFC;
-    while i < r.end {
-        let w = bios.read_word(i);
-        let e = if let Ok(inst) = hardware::cpu::ArmInstruction::decode(w) { write!(msg, "{:06X} - {}\n", i, inst) }
-                else { write!(msg, "{:06X} - {:08X}\n", i, w) };
-        if let Err(e) = e { error!("{}", e); break; }
-        i += 4;
+    let image = CodeImage::new(0, gba.bios().bytes(0));
+    info!("Disassembling BIOS ROM section:\n\n{}", Disassembler::new(&image).with_symbols(symbols).listing(r, state));
+}
+
+fn disasm_rom(fp: &PathBuf, gba: &hardware::Gba, symbols: &hardware::cpu::SymbolTable) {
+    use std::io::Write;
+    use std::fs::File;
+    use hardware::memory::RawBytes;
+    let rom = gba.game_pak().rom();
+    let bytes = &rom.bytes(0)[..rom.header().rom_size()];
+    if bytes.is_empty() { error!("DASM ROM: No ROM loaded. Pass one with `--rom PATH`."); return; }
+    let listing = hardware::cpu::RomDisassembler::new(bytes).with_symbols(symbols).listing();
+    match File::create(fp).and_then(|mut f| f.write_all(listing.as_bytes())) {
+        Ok(_)  => info!("Disassembled the ROM into `{}`.", fp.display()),
+        Err(e) => error!("Failed writing `{}`:\n{}", fp.display(), e),
     }
-    info!("{}", msg);
 }
 
-fn disasm_bios_thumb(x: &str, gba: &hardware::Gba) {
-    use hardware::memory::Rom16;
-    use std::fmt::Write;
-    let r = if let Some(r) = parse_hex_range(x, 0, hardware::memory::BIOS_ROM_LEN as u32) { r } else { return; };
-    let bios = gba.bios();
-    let mut msg = "Disassembling BIOS ROM section:\n\nOffset   Data  \tInstruction\n".to_owned();
-    let mut i = r.start & 0xFFFFFFFE;
-    while i < r.end {
-        let h = bios.read_halfword(i);
-        let e = if let Ok(inst) = hardware::cpu::ThumbInstruction::decode(h) { write!(msg, "{:06X} - {}\n", i, inst) }
-                else { write!(msg, "{:06X} - {:#06X}\n", i, h) };
-        if let Err(e) = e { error!("{}", e); break; }
-        i += 2;
+
+fn run_headless(gba: &mut hardware::Gba, frames: u32, args: &CmdLineArgs) -> Result<(), hardware::GbaError> {
+    use std::io;
+    let mut renderer = video::TerminalRenderer::new();
+    renderer.with_downscale(args.term_scale)
+            .with_live_display(true)
+            .with_colour_correction(args.colour_correction)
+            .with_frame_blending(args.frame_blending);
+    let stdout = io::stdout();
+
+    let mut recorder = args.record_audio.as_ref().and_then(|fp| {
+        match audio::WavWriter::create(fp.as_path(), args.audio_rate) {
+            Ok(w)  => Some(audio::AudioPipeline::new(w)),
+            Err(e) => { error!("Failed creating `{}`:\n{}", fp.display(), e); None },
+        }
+    });
+
+    info!("Running {} frames.", frames);
+    for _ in 0..frames {
+        try!(gba.run_frame());
+        if args.term_video {
+            if let Err(e) = renderer.present(gba.frame_buffer(), &mut stdout.lock()) { error!("{}", e); }
+        }
+        let chunks = gba.take_audio();
+        if let Some(ref mut r) = recorder {
+            if let Err(e) = r.push(&chunks[..]) { error!("{}", e); }
+        }
+    }
+
+    if let Some(r) = recorder {
+        match r.finish() {
+            Ok(w)  => info!("Recorded {} audio samples.", w.frames()),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    for l in gba.cpu_arm7tdmi().idle_loops() {
+        info!("Idle loop {:#010X}...{:#010X}: skipped {} times, {} cycles in total.",
+              l.first, l.branch, l.skips, l.skipped_cycles);
+    }
+    Ok(())
+}
+
+
+fn dump_video_memory(args: &CmdLineArgs, gba: &hardware::Gba) {
+    let bus = gba.bus();
+    let viewer = video::VramViewer::new(bus);
+    let save = |img: video::Image, name: String| {
+        let fp = args.dump_dir.join(name);
+        match img.save_bmp(fp.as_path()) {
+            Ok(_)  => info!("Saved `{}`.", fp.display()),
+            Err(e) => error!("{}", e),
+        }
+    };
+
+    if let Some((block, fmt)) = args.dump_tiles.as_ref().and_then(|x| parse_tile_sheet_spec(x.as_str())) {
+        if let Some(img) = viewer.tile_sheet(block, fmt) { save(img, format!("tiles-{}-{}.bmp", block, fmt)); }
+    }
+    if args.dump_tilemaps {
+        for bg in 0..4 {
+            if let Some(img) = viewer.tilemap(bg) { save(img, format!("tilemap-bg{}.bmp", bg)); }
+        }
     }
-    info!("{}", msg);
+    if args.dump_palette { save(viewer.palette(), "palette.bmp".to_string()); }
+    if args.dump_oam { info!("OAM:\n{}", viewer.oam_table()); }
 }
 
 
 fn configure_gba_from_command_line(gba: &mut hardware::Gba, args: &CmdLineArgs) {
     // If a BIOS file is given, load it into the BIOS ROM area.
+    // Otherwise, fall back to the bundled replacement BIOS.
     if let Some(ref fp) = args.bios_file_path {
         if let Err(e) = gba.bios_mut().load_from_file(fp.as_path()) {
             error!("Failed loading the BIOS file:\n{}", e);
         } else {
             info!("Loaded the BIOS ROM from file.");
         }
+    } else {
+        gba.bios_mut().load_replacement();
+        info!("Loaded the replacement BIOS ROM.");
     }
 
     // Load ROM now if a path is given.
@@ -373,8 +676,13 @@ fn configure_gba_from_command_line(gba: &mut hardware::Gba, args: &CmdLineArgs)
         }
     }
 
-    // Configure the CPU.
+    // Configure the CPU and put it into its initial state.
     gba.cpu_arm7tdmi_mut().set_swi_optimised(args.optimise_swi);
+    gba.set_bios_skipped(args.skip_bios);
+    gba.cpu_arm7tdmi_mut().set_block_cache_enabled(args.block_cache);
+    gba.cpu_arm7tdmi_mut().set_idle_loop_detection_enabled(args.skip_idle_loops);
+    gba.cpu_arm7tdmi_mut().set_unpredictable_policy(args.unpredictable);
+    if let Err(e) = gba.reset() { error!("Failed resetting the GBA:\n{}", e); }
 }
 
 
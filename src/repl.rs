@@ -6,6 +6,7 @@
 #![warn(missing_docs)]
 
 use super::hardware;
+use super::video::{TerminalRenderer, VramViewer, Image, ColourCorrection};
 use super::term_painter::ToStyle;
 use super::term_painter::Color::*;
 use super::term_painter::Attr::Plain;
@@ -13,6 +14,7 @@ use std::u32;
 use std::io;
 use std::io::Write;
 use std::str::SplitWhitespace;
+use std::path::Path;
 
 /// Implements a debug REPL for the GBA emulator.
 ///
@@ -23,6 +25,9 @@ pub struct GbaRepl {
     diff_arm7tdmi: hardware::cpu::Arm7TdmiDiff,
     colour: bool,
     show_arm7tdmi: bool,
+    term_scale: usize,
+    colour_correction: ColourCorrection,
+    symbols: hardware::cpu::SymbolTable,
 }
 
 impl GbaRepl {
@@ -32,17 +37,29 @@ impl GbaRepl {
             diff_arm7tdmi: hardware::cpu::Arm7TdmiDiff::new(),
             colour: true,
             show_arm7tdmi: true,
+            term_scale: 2,
+            colour_correction: ColourCorrection::Raw,
+            symbols: hardware::cpu::SymbolTable::new(),
         }
     }
 
     /// Configure whether terminal outputs should be colourised.
     pub fn with_colour(&mut self, c: bool) -> &mut GbaRepl { self.colour = c; self }
 
+    /// Configure the default downscale factor of the `show` command.
+    pub fn with_term_scale(&mut self, n: usize) -> &mut GbaRepl { self.term_scale = n; self }
+
+    /// Configure the colour correction of the `show` command.
+    pub fn with_colour_correction(&mut self, cc: ColourCorrection) -> &mut GbaRepl { self.colour_correction = cc; self }
+
+    /// Configure the labels the `dasm` command prints.
+    pub fn with_symbols(&mut self, symbols: hardware::cpu::SymbolTable) -> &mut GbaRepl { self.symbols = symbols; self }
+
     /// Runs the REPL until the user quits, an error occurred,
     /// or until the emulated program ends.
     pub fn run(&mut self, gba: &mut hardware::Gba) -> Result<(), hardware::GbaError> {
         // Prepare everything we need.
-        gba.cpu_arm7tdmi_mut().reset();
+        try!(gba.reset());
         self.diff_arm7tdmi.diff(gba.cpu_arm7tdmi());
         self.print_emu();
         let mut input = String::new();
@@ -57,8 +74,15 @@ impl GbaRepl {
                 Some("x") => break,
                 Some("p") => self.print_emu(),
                 Some("hex") => if let Some(r) = s.next() { GbaRepl::hexdump(r, gba); },
+                Some("dasm") => self.disassemble(gba, s.next()),
+                Some("asm") => if let Some(a) = s.next() { GbaRepl::assemble(gba, a, &s.collect::<Vec<_>>().join(" ")); },
                 Some("run") => if let Some(n) = s.next() { try!(self.run_n_steps_str(gba, n)); },
                 Some("toggle") => if let Some(cpu) = s.next() { self.toggle_cpu(cpu); },
+                Some("show") => self.show_frame(gba, s.next()),
+                Some("tiles") => GbaRepl::dump_tiles(gba, s.next(), s.next()),
+                Some("tilemap") => GbaRepl::dump_tilemap(gba, s.next(), s.next()),
+                Some("palette") => if let Some(fp) = s.next() { GbaRepl::save_image(VramViewer::new(gba.bus()).palette(), fp); },
+                Some("oam") => print!("{}\n", VramViewer::new(gba.bus()).oam_table()),
                 Some("") | None => try!(self.run_n_steps(gba, 1)),
                 _ => print!("\t\t<What?>\n\n"),
             }
@@ -67,7 +91,7 @@ impl GbaRepl {
     }
 
     fn input_prompt(&self, input: &mut String) -> io::Result<()> {
-        print!("\t{}\n\t> ", Black.bg(White).paint("[? = Help, x = Exit, p, hex A..B, run N, toggle CPU]"));
+        print!("\t{}\n\t> ", Black.bg(White).paint("[? = Help, x = Exit, p, hex A..B, dasm A..B, asm A CODE, run N, toggle CPU, show, tiles, tilemap, palette, oam]"));
         io::stdout().flush().unwrap();
         input.clear();
         try!(io::stdin().read_line(input));
@@ -81,14 +105,29 @@ impl GbaRepl {
             x          - Exit the debug REPL.\n\t\
             p          - Print the current CPU state again.\n\t\
             hex RANGE  - Hexdump a region of memory defined by RANGE.\n\t\
+            dasm [RANGE] - Disassemble RANGE in the current CPU state. By\n\t               \
+                           default, RANGE starts at the current instruction\n\t               \
+                           and ends 0x20 bytes later.\n\t\
+            asm A CODE - Assemble CODE in the current CPU state and write\n\t             \
+                         it to address A, even into ROM. Separate multiple\n\t             \
+                         instructions by `;`, e.g. `asm 8000000 nop; b .`.\n\t\
             run N      - Run N pipeline steps, where N is a positive integer.\n\t\
             toggle CPU - Show/hide the current state of CPU.\n\t\
+            show [N]   - Draw the current frame, optionally downscaled by N.\n\t\
+            tiles SHEET FILE - Save a char block as a tile sheet BMP.\n\t\
+            tilemap BG FILE  - Save the whole tilemap of BG 0 to 3 as BMP.\n\t\
+            palette FILE     - Save all 512 colours as a BMP of swatches.\n\t\
+            oam        - Print a table of all sprites.\n\t\
             [ENTER]    - Just hit the enter key to run a single pipeline step.\n\t\
             \n\t{}\n\t\
+            A     - A baseless hexadecimal address, e.g. `8000000`.\n\t\
             RANGE - A pair of baseless hexadecimal values, e.g. `A..B`.\n\t        \
                     The default range is `0..80` and any omitted value\n\t        \
                     will be interpreted as the default value. Thus, `..B`\n\t        \
                     will be interpreted as `0..B`.\n\t\
+            SHEET - A char block and tile format, e.g. `4:15`. Blocks\n\t        \
+                    0 to 5 are valid, where 4 and 5 hold OBJ tiles. The\n\t        \
+                    format is `8bpp` or a 4bpp palette bank 0 to 15.\n\t\
             CPU   - A CPU name. The possible values are:\n\t        \
                     - all\n\t        \
                     - Arm7Tdmi\n\t",
@@ -100,8 +139,11 @@ impl GbaRepl {
         if self.show_arm7tdmi { self.diff_arm7tdmi.print(); }
     }
 
+    // Like `Gba::run_frame`, treats a pipeline step as a single cycle.
     fn emu_step(&self, gba: &mut hardware::Gba) -> Result<(), hardware::GbaError> {
-        gba.cpu_arm7tdmi_mut().pipeline_step()
+        try!(gba.cpu_arm7tdmi_mut().pipeline_step());
+        gba.bus_mut().tick(1);
+        Ok(())
     }
 
     fn diff(&mut self, gba: &hardware::Gba) {
@@ -110,6 +152,8 @@ impl GbaRepl {
 
     fn run_n_steps(&mut self, gba: &mut hardware::Gba, n: u32) -> Result<(), hardware::GbaError> {
         for _ in 0..n { try!(self.emu_step(gba)); }
+        // There is no audio output, so don't let samples pile up.
+        gba.take_audio();
         self.diff(gba);
         self.print_emu();
         Ok(())
@@ -138,6 +182,73 @@ impl GbaRepl {
         }
     }
 
+    fn disassemble(&self, gba: &hardware::Gba, range: Option<&str>) {
+        let cpu = gba.cpu_arm7tdmi();
+        let pc = cpu.current_instruction_address();
+        let end = pc.saturating_add(0x20); // Don't wrap around into an empty range.
+        let r = match range {
+            Some(r) => if let Some(r) = super::parse_hex_range(r, pc, end) { r } else { return; },
+            None => pc..end,
+        };
+        let listing = hardware::cpu::Disassembler::new(gba.bus()).with_symbols(&self.symbols).listing(r, cpu.state());
+        for line in listing.lines() { print!("\t\t{}\n", line); }
+        print!("\n");
+    }
+
+    fn assemble(gba: &mut hardware::Gba, addr: &str, source: &str) {
+        let addr = match u32::from_str_radix(addr, 16) {
+            Ok(a)  => a,
+            Err(e) => { error!("{}", e); return; },
+        };
+        let state = gba.cpu_arm7tdmi().state();
+        let asm = match hardware::cpu::Assembler::new(addr).with_state(state).assemble(source) {
+            Ok(a)  => a,
+            Err(e) => { error!("{}", e); return; },
+        };
+        for (i, &b) in asm.bytes().iter().enumerate() {
+            if let Err(e) = gba.bus_mut().patch_byte(addr.wrapping_add(i as u32), b) { error!("{}", e); return; }
+        }
+        gba.cpu_arm7tdmi_mut().invalidate_code(addr, asm.len() as u32);
+        print!("\t\t<Assembled {} bytes at {:#010X}.>\n\n", asm.len(), addr);
+    }
+
+    fn show_frame(&self, gba: &hardware::Gba, n: Option<&str>) {
+        let n = match n.map(|n| usize::from_str_radix(n, 10)) {
+            Some(Ok(n))  => n,
+            Some(Err(e)) => { error!("{}", e); return; },
+            None         => self.term_scale,
+        };
+        print!("{}\n", TerminalRenderer::new()
+            .with_downscale(n)
+            .with_colour_correction(self.colour_correction)
+            .render(gba.frame_buffer()));
+    }
+
+    fn dump_tiles(gba: &hardware::Gba, spec: Option<&str>, fp: Option<&str>) {
+        let (spec, fp) = match (spec, fp) { (Some(s), Some(f)) => (s, f), _ => return };
+        if let Some((block, fmt)) = super::parse_tile_sheet_spec(spec) {
+            if let Some(img) = VramViewer::new(gba.bus()).tile_sheet(block, fmt) { GbaRepl::save_image(img, fp); }
+        }
+    }
+
+    fn dump_tilemap(gba: &hardware::Gba, bg: Option<&str>, fp: Option<&str>) {
+        let (bg, fp) = match (bg, fp) { (Some(b), Some(f)) => (b, f), _ => return };
+        match usize::from_str_radix(bg, 10) {
+            Ok(bg) => match VramViewer::new(gba.bus()).tilemap(bg) {
+                Some(img) => GbaRepl::save_image(img, fp),
+                None      => print!("\t\t<BG {} is not a tilemap in the current BG mode.>\n\n", bg),
+            },
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    fn save_image(img: Image, fp: &str) {
+        match img.save_bmp(Path::new(fp)) {
+            Ok(_)  => print!("\t\t<Saved {}x{} image `{}`.>\n\n", img.width(), img.height(), fp),
+            Err(e) => error!("{}", e),
+        }
+    }
+
     fn toggle_cpu(&mut self, cpu: &str) {
         match cpu {
             "Arm7Tdmi" => { self.show_arm7tdmi = !self.show_arm7tdmi; },
@@ -0,0 +1,243 @@
+// License below.
+//! Implements colour transforms and filters applied to frames before presenting them.
+//!
+//! The GBA's LCD is neither backlit nor calibrated, so raw BGR555
+//! colours expanded to RGB888 look much more saturated and brighter
+//! on modern screens than they did on real hardware. Games were
+//! designed for the real LCD, so we optionally emulate its response.
+//!
+//! The LCD is also slow to change its pixels, such that some games
+//! flicker sprites each frame to fake transparency. Interframe
+//! blending emulates this ghosting by mixing two consecutive frames.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::fmt;
+use std::str::FromStr;
+use super::super::hardware::framebuffer::*;
+
+/// Number of distinct BGR555 colours.
+const COLOURS: usize = 0x8000;
+
+
+/// Selects how BGR555 colours are converted to RGB888 colours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColourCorrection {
+    #[doc = "Expand each channel without any correction."]
+    Raw,
+    #[doc = "Emulate the dark, washed out, non-backlit GBA LCD."]
+    GbaLcd,
+    #[doc = "Emulate the slightly desaturated backlit GBA SP LCD."]
+    GbaSp,
+}
+
+impl ColourCorrection {
+    /// Converts a single BGR555 colour to a corrected RGB888 colour.
+    ///
+    /// This is slow. Use an `OutputFilter` for whole frames.
+    pub fn apply(self, c: u16) -> (u8, u8, u8) {
+        let r = ( c        & 0x1F) as f64 / 31.0;
+        let g = ((c >>  5) & 0x1F) as f64 / 31.0;
+        let b = ((c >> 10) & 0x1F) as f64 / 31.0;
+
+        match self {
+            ColourCorrection::Raw => bgr555_to_rgb888(c),
+            ColourCorrection::GbaLcd => {
+                // The LCD's response has a gamma of about 4 and its
+                // colour channels bleed into each other. The result
+                // is scaled such that white ends up slightly grey.
+                let (lr, lg, lb) = (r.powf(4.0), g.powf(4.0), b.powf(4.0));
+                let scale = 255.0 / 280.0;
+                (
+                    gamma_to_u8((              50.0 * lg + 255.0 * lr) / 255.0, scale),
+                    gamma_to_u8(( 30.0 * lb + 230.0 * lg +  10.0 * lr) / 255.0, scale),
+                    gamma_to_u8((220.0 * lb +  10.0 * lg +  50.0 * lr) / 255.0, scale),
+                )
+            },
+            ColourCorrection::GbaSp => {
+                // The backlit LCD has about the same gamma as modern
+                // screens, but mixes neighbouring channels a bit.
+                let (lr, lg, lb) = (r.powf(2.2), g.powf(2.2), b.powf(2.2));
+                (
+                    gamma_to_u8(0.92 * lr + 0.08 * lg,             1.0),
+                    gamma_to_u8(0.03 * lr + 0.90 * lg + 0.07 * lb, 1.0),
+                    gamma_to_u8(            0.06 * lg + 0.94 * lb, 1.0),
+                )
+            },
+        }
+    }
+}
+
+impl FromStr for ColourCorrection {
+    type Err = String;
+
+    /// Parses one of `raw`, `lcd` or `sp`.
+    fn from_str(s: &str) -> Result<ColourCorrection, String> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(ColourCorrection::Raw),
+            "lcd" => Ok(ColourCorrection::GbaLcd),
+            "sp"  => Ok(ColourCorrection::GbaSp),
+            _     => Err(format!("Unknown colour correction `{}`.", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColourCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            ColourCorrection::Raw    => "raw",
+            ColourCorrection::GbaLcd => "lcd",
+            ColourCorrection::GbaSp  => "sp",
+        })
+    }
+}
+
+impl Default for ColourCorrection {
+    fn default() -> ColourCorrection { ColourCorrection::Raw }
+}
+
+// Encodes a linear intensity with a gamma of 2.2 and clamps it to 8 bits.
+fn gamma_to_u8(x: f64, scale: f64) -> u8 {
+    let y = x.max(0.0).powf(1.0 / 2.2) * scale * 255.0;
+    if y >= 255.0 { 255 } else { (y + 0.5) as u8 }
+}
+
+
+/// A 240x160 pixels picture in RGB888 format, ready to be presented.
+///
+/// Pixels are stored row by row, starting at the
+/// top left corner of the screen.
+pub struct RgbFrame(Vec<(u8, u8, u8)>);
+
+impl RgbFrame {
+    /// Creates a new black frame.
+    pub fn new() -> RgbFrame { RgbFrame(vec![(0, 0, 0); LCD_PIXELS]) }
+
+    /// Gets the colour `(r, g, b)` of a single pixel.
+    ///
+    /// # Panics
+    /// Panics if `x` or `y` are out of bounds.
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        debug_assert!((x < LCD_WIDTH) & (y < LCD_HEIGHT));
+        self.0[y * LCD_WIDTH + x]
+    }
+
+    /// Gets all pixels row by row.
+    pub fn pixels(&self) -> &[(u8, u8, u8)] { &self.0[..] }
+}
+
+impl Default for RgbFrame {
+    fn default() -> RgbFrame { RgbFrame::new() }
+}
+
+
+/// Converts framebuffers to presentable RGB888 frames.
+///
+/// The colour correction is precomputed for all 32768 colours.
+/// With interframe blending enabled, each output frame is the
+/// average of the current and the previous corrected frame.
+pub struct OutputFilter {
+    correction: ColourCorrection,
+    lut: Vec<(u8, u8, u8)>,
+    blending: bool,
+    previous: Option<RgbFrame>,
+}
+
+impl OutputFilter {
+    /// Creates a new filter without colour correction and blending.
+    pub fn new() -> OutputFilter {
+        let mut f = OutputFilter {
+            correction: ColourCorrection::Raw,
+            lut: Vec::with_capacity(COLOURS),
+            blending: false,
+            previous: None,
+        };
+        f.build_lut();
+        f
+    }
+
+    /// Configure the colour correction to apply.
+    pub fn with_colour_correction(&mut self, cc: ColourCorrection) -> &mut OutputFilter {
+        if cc != self.correction {
+            self.correction = cc;
+            self.build_lut();
+            self.previous = None;
+        }
+        self
+    }
+
+    /// Configure whether consecutive frames should be blended.
+    pub fn with_frame_blending(&mut self, b: bool) -> &mut OutputFilter {
+        self.blending = b;
+        self.previous = None;
+        self
+    }
+
+    /// Get the current colour correction.
+    pub fn colour_correction(&self) -> ColourCorrection { self.correction }
+
+    /// Get whether consecutive frames are blended.
+    pub fn frame_blending(&self) -> bool { self.blending }
+
+    /// Converts a single BGR555 colour without any blending.
+    pub fn convert(&self, c: u16) -> (u8, u8, u8) { self.lut[(c & 0x7FFF) as usize] }
+
+    /// Converts a framebuffer without any blending and without
+    /// affecting the history used for blending.
+    pub fn convert_frame(&self, fb: &FrameBuffer) -> RgbFrame {
+        RgbFrame(fb.pixels().iter().map(|&c| self.convert(c)).collect())
+    }
+
+    /// Converts the next framebuffer to present.
+    ///
+    /// If blending is enabled, the result is mixed with the
+    /// previously processed frame, which is then replaced by
+    /// the unblended result for this frame.
+    pub fn process(&mut self, fb: &FrameBuffer) -> RgbFrame {
+        let current = self.convert_frame(fb);
+        if !self.blending { return current; }
+
+        let blended = match self.previous {
+            Some(ref prev) => RgbFrame(current.0.iter().zip(prev.0.iter()).map(|(&c, &p)| (
+                ((c.0 as u16 + p.0 as u16 + 1) / 2) as u8,
+                ((c.1 as u16 + p.1 as u16 + 1) / 2) as u8,
+                ((c.2 as u16 + p.2 as u16 + 1) / 2) as u8,
+            )).collect()),
+            None => RgbFrame(current.0.clone()),
+        };
+        self.previous = Some(current);
+        blended
+    }
+
+    fn build_lut(&mut self) {
+        let cc = self.correction;
+        self.lut.clear();
+        self.lut.extend((0..COLOURS).map(|c| cc.apply(c as u16)));
+    }
+}
+
+impl Default for OutputFilter {
+    fn default() -> OutputFilter { OutputFilter::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
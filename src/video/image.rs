@@ -0,0 +1,151 @@
+// License below.
+//! Implements simple images used for exporting debug views.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::io::{self, Write};
+use std::fs::File;
+use std::path::Path;
+use byteorder::{LittleEndian, WriteBytesExt};
+use super::super::hardware::framebuffer::*;
+
+const BMP_FILE_HEADER_LEN: u32 = 14;
+const BMP_INFO_HEADER_LEN: u32 = 40;
+
+
+/// A picture of arbitrary size in BGR555 format.
+///
+/// Pixels are stored row by row, starting at the
+/// top left corner of the image.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u16>,
+}
+
+impl Image {
+    /// Creates a new black image.
+    pub fn new(width: usize, height: usize) -> Image {
+        Image {
+            width: width,
+            height: height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Creates a copy of the framebuffer's current picture.
+    pub fn from_frame_buffer(fb: &FrameBuffer) -> Image {
+        Image {
+            width: LCD_WIDTH,
+            height: LCD_HEIGHT,
+            pixels: fb.pixels().to_vec(),
+        }
+    }
+
+    /// Get the image's width in pixels.
+    pub fn width(&self) -> usize { self.width }
+
+    /// Get the image's height in pixels.
+    pub fn height(&self) -> usize { self.height }
+
+    /// Gets the colour of a single pixel.
+    ///
+    /// # Panics
+    /// Panics if `x` or `y` are out of bounds.
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        debug_assert!((x < self.width) & (y < self.height));
+        self.pixels[y * self.width + x]
+    }
+
+    /// Sets the colour of a single pixel.
+    ///
+    /// # Panics
+    /// Panics if `x` or `y` are out of bounds.
+    pub fn set_pixel(&mut self, x: usize, y: usize, c: u16) {
+        debug_assert!((x < self.width) & (y < self.height));
+        self.pixels[y * self.width + x] = c;
+    }
+
+    /// Fills a rectangle with a single colour.
+    ///
+    /// Parts of the rectangle outside of the image are clipped.
+    pub fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, c: u16) {
+        for j in y..::std::cmp::min(y + h, self.height) {
+            for i in x..::std::cmp::min(x + w, self.width) {
+                self.pixels[j * self.width + i] = c;
+            }
+        }
+    }
+
+    /// Gets all pixels row by row.
+    pub fn pixels(&self) -> &[u16] { &self.pixels[..] }
+
+    /// Writes the image as an uncompressed 24-bit BMP file.
+    pub fn write_bmp<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        // Each row of pixels is padded to a multiple of 4 bytes.
+        let stride = (self.width * 3 + 3) & !3;
+        let data_len = (stride * self.height) as u32;
+        let data_offs = BMP_FILE_HEADER_LEN + BMP_INFO_HEADER_LEN;
+
+        try!(out.write_all(b"BM"));
+        try!(out.write_u32::<LittleEndian>(data_offs + data_len));
+        try!(out.write_u32::<LittleEndian>(0));
+        try!(out.write_u32::<LittleEndian>(data_offs));
+
+        try!(out.write_u32::<LittleEndian>(BMP_INFO_HEADER_LEN));
+        try!(out.write_i32::<LittleEndian>(self.width as i32));
+        try!(out.write_i32::<LittleEndian>(self.height as i32)); // Positive, i.e. bottom-up.
+        try!(out.write_u16::<LittleEndian>(1));  // Colour planes.
+        try!(out.write_u16::<LittleEndian>(24)); // Bits per pixel.
+        try!(out.write_u32::<LittleEndian>(0));  // No compression.
+        try!(out.write_u32::<LittleEndian>(data_len));
+        try!(out.write_i32::<LittleEndian>(2835)); // 72 DPI.
+        try!(out.write_i32::<LittleEndian>(2835));
+        try!(out.write_u32::<LittleEndian>(0));
+        try!(out.write_u32::<LittleEndian>(0));
+
+        let mut row = vec![0_u8; stride];
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let (r, g, b) = bgr555_to_rgb888(self.pixel(x, y));
+                row[x * 3]     = b;
+                row[x * 3 + 1] = g;
+                row[x * 3 + 2] = r;
+            }
+            try!(out.write_all(&row[..]));
+        }
+        out.flush()
+    }
+
+    /// Saves the image as an uncompressed 24-bit BMP file.
+    ///
+    /// # Params
+    /// - `fp`: Path to the file to create or overwrite.
+    pub fn save_bmp(&self, fp: &Path) -> io::Result<()> {
+        trace!("Saving BMP image `{}`.", fp.display());
+        let mut f = try!(File::create(fp));
+        self.write_bmp(&mut f)
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,186 @@
+// License below.
+//! Implements a renderer drawing the framebuffer into a terminal.
+//!
+//! Each character cell shows two vertically stacked pixels
+//! using the Unicode upper half block `▀`. The upper pixel
+//! is the cell's foreground colour and the lower pixel is
+//! the cell's background colour. Both colours are set with
+//! 24-bit ANSI escape codes, so the terminal in use must
+//! support true colours.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::io;
+use std::fmt::Write as FmtWrite;
+use super::super::hardware::framebuffer::*;
+use super::filter::*;
+
+const UPPER_HALF_BLOCK: char = '\u{2580}';
+const RESET_ATTRIBUTES: &'static str = "\x1B[0m";
+const CURSOR_HOME: &'static str = "\x1B[H";
+const CLEAR_SCREEN: &'static str = "\x1B[2J";
+
+/// Draws the framebuffer with Unicode half blocks and ANSI true colours.
+///
+/// With a downscale factor of 1, a frame takes 240x80 characters.
+/// Larger factors shrink the picture by averaging blocks of pixels,
+/// e.g. a factor of 2 makes a frame take 120x40 characters.
+pub struct TerminalRenderer {
+    downscale: usize,
+    live: bool,
+    presented: bool,
+    filter: OutputFilter,
+}
+
+impl TerminalRenderer {
+    /// Creates a new renderer drawing at half the LCD's size.
+    pub fn new() -> TerminalRenderer {
+        TerminalRenderer {
+            downscale: 2,
+            live: false,
+            presented: false,
+            filter: OutputFilter::new(),
+        }
+    }
+
+    /// Configure by how much frames should be downscaled.
+    ///
+    /// A factor of 0 will be treated as 1.
+    pub fn with_downscale(&mut self, n: usize) -> &mut TerminalRenderer {
+        self.downscale = if n == 0 { 1 } else { n };
+        self
+    }
+
+    /// Configure whether presented frames should overdraw the previous one.
+    ///
+    /// If `true`, the terminal will be cleared once and each
+    /// presented frame moves the cursor back to the top left
+    /// corner, such that frames show up as a live display.
+    /// Otherwise frames are just printed one after another.
+    pub fn with_live_display(&mut self, live: bool) -> &mut TerminalRenderer {
+        self.live = live;
+        self
+    }
+
+    /// Configure the colour correction applied to rendered frames.
+    pub fn with_colour_correction(&mut self, cc: ColourCorrection) -> &mut TerminalRenderer {
+        self.filter.with_colour_correction(cc);
+        self
+    }
+
+    /// Configure whether presented frames should be blended with the previous one.
+    ///
+    /// Frames rendered with `render` are never blended.
+    pub fn with_frame_blending(&mut self, b: bool) -> &mut TerminalRenderer {
+        self.filter.with_frame_blending(b);
+        self
+    }
+
+    /// Get the current downscale factor.
+    pub fn downscale(&self) -> usize { self.downscale }
+
+    /// Get the number of characters per rendered line.
+    pub fn columns(&self) -> usize { LCD_WIDTH / self.downscale }
+
+    /// Get the number of rendered lines.
+    pub fn rows(&self) -> usize { (LCD_HEIGHT / self.downscale + 1) / 2 }
+
+    /// Renders a frame into a string of ANSI escaped half blocks.
+    ///
+    /// Each line ends with resetting all text attributes,
+    /// such that the terminal's colours are left untouched.
+    pub fn render(&self, fb: &FrameBuffer) -> String {
+        self.render_rgb(&self.filter.convert_frame(fb))
+    }
+
+    /// Renders an already filtered frame into a string of ANSI escaped half blocks.
+    pub fn render_rgb(&self, frame: &RgbFrame) -> String {
+        let w = LCD_WIDTH  / self.downscale;
+        let h = LCD_HEIGHT / self.downscale;
+        let mut s = String::with_capacity(self.rows() * (w * 40 + 8));
+
+        for row in 0..self.rows() {
+            let mut last_top = None;
+            let mut last_bot = None;
+            for col in 0..w {
+                let top = self.sample(frame, col, 2 * row);
+                let bot = if (2 * row + 1) < h { Some(self.sample(frame, col, 2 * row + 1)) } else { None };
+
+                // Only emit escape codes for colours that actually changed.
+                if last_top != Some(top) {
+                    write!(s, "\x1B[38;2;{};{};{}m", top.0, top.1, top.2).unwrap();
+                    last_top = Some(top);
+                }
+                if last_bot != Some(bot) {
+                    match bot {
+                        Some(c) => write!(s, "\x1B[48;2;{};{};{}m", c.0, c.1, c.2).unwrap(),
+                        None    => s.push_str("\x1B[49m"), // Odd height, keep the terminal's background.
+                    }
+                    last_bot = Some(bot);
+                }
+                s.push(UPPER_HALF_BLOCK);
+            }
+            s.push_str(RESET_ATTRIBUTES);
+            s.push('\n');
+        }
+        s
+    }
+
+    /// Renders a frame and writes it to a terminal.
+    ///
+    /// In live display mode, the previously presented
+    /// frame will be overdrawn. With frame blending enabled,
+    /// the frame is mixed with the previously presented one.
+    pub fn present<W: io::Write>(&mut self, fb: &FrameBuffer, out: &mut W) -> io::Result<()> {
+        if self.live {
+            if !self.presented { try!(out.write_all(CLEAR_SCREEN.as_bytes())); }
+            try!(out.write_all(CURSOR_HOME.as_bytes()));
+        }
+        self.presented = true;
+        let frame = self.filter.process(fb);
+        try!(out.write_all(self.render_rgb(&frame).as_bytes()));
+        out.flush()
+    }
+
+    // Averages a block of `downscale * downscale` pixels.
+    fn sample(&self, frame: &RgbFrame, x: usize, y: usize) -> (u8, u8, u8) {
+        let n = self.downscale;
+        let (mut r, mut g, mut b) = (0_usize, 0_usize, 0_usize);
+        for j in (y * n)..((y + 1) * n) {
+            for i in (x * n)..((x + 1) * n) {
+                let c = frame.pixel(i, j);
+                r += c.0 as usize;
+                g += c.1 as usize;
+                b += c.2 as usize;
+            }
+        }
+        let d = n * n;
+        ((r / d) as u8, (g / d) as u8, (b / d) as u8)
+    }
+}
+
+impl Default for TerminalRenderer {
+    fn default() -> TerminalRenderer { TerminalRenderer::new() }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
@@ -0,0 +1,234 @@
+// License below.
+#![allow(missing_docs)]
+
+use super::*;
+use super::super::hardware::Gba;
+use super::super::hardware::framebuffer::*;
+
+const RED: u16 = 0x001F;
+const BLUE: u16 = 0x7C00;
+const GREEN: u16 = 0x03E0;
+const WHITE: u16 = 0x7FFF;
+
+// Fills the rows `y0..y1` of a framebuffer with a colour.
+fn fill_rows(fb: &mut FrameBuffer, y0: usize, y1: usize, c: u16) {
+    for p in &mut fb.pixels_mut()[(y0 * LCD_WIDTH)..(y1 * LCD_WIDTH)] { *p = c; }
+}
+
+#[test]
+fn bgr555_expands_by_repeating_upper_bits() {
+    assert_eq!(bgr555_to_rgb888(0x0000), (0x00, 0x00, 0x00));
+    assert_eq!(bgr555_to_rgb888(0x7FFF), (0xFF, 0xFF, 0xFF));
+    assert_eq!(bgr555_to_rgb888(0b00001_10000_01111), (0x7B, 0x84, 0x08));
+}
+
+#[test]
+fn terminal_renders_half_blocks() {
+    let mut fb = FrameBuffer::new();
+    fill_rows(&mut fb, 0, 8, RED);
+    fill_rows(&mut fb, 8, 16, BLUE);
+    let mut r = TerminalRenderer::new();
+    r.with_downscale(8);
+    assert_eq!((r.columns(), r.rows()), (30, 10));
+
+    let text = r.render(&fb);
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), 10);
+    // Colours are only set when they change.
+    let blocks: String = (0..30).map(|_| '\u{2580}').collect();
+    assert_eq!(lines[0], format!("\x1B[38;2;255;0;0m\x1B[48;2;0;0;255m{}\x1B[0m", blocks));
+    assert_eq!(lines[1], format!("\x1B[38;2;0;0;0m\x1B[48;2;0;0;0m{}\x1B[0m", blocks));
+}
+
+#[test]
+fn terminal_downscales_by_averaging() {
+    let mut fb = FrameBuffer::new();
+    fb.set_pixel(0, 0, WHITE);
+    fb.set_pixel(1, 1, WHITE);
+    let text = TerminalRenderer::new().with_downscale(2).render(&fb);
+    assert!(text.starts_with("\x1B[38;2;127;127;127m\x1B[48;2;0;0;0m\u{2580}\x1B[38;2;0;0;0m"), "{:?}", &text[..40]);
+}
+
+#[test]
+fn terminal_keeps_background_below_odd_heights() {
+    // 160 / 3 = 53 lines of pixels, thus a half empty last row.
+    let mut r = TerminalRenderer::new();
+    r.with_downscale(3);
+    let text = r.render(&FrameBuffer::new());
+    let last = text.lines().last().unwrap();
+    assert_eq!(text.lines().count(), 27);
+    assert!(last.starts_with("\x1B[38;2;0;0;0m\x1B[49m\u{2580}"), "{:?}", last);
+}
+
+#[test]
+fn terminal_live_display_overdraws() {
+    let fb = FrameBuffer::new();
+    let mut out = Vec::new();
+    let mut r = TerminalRenderer::new();
+    r.with_live_display(true);
+    r.present(&fb, &mut out).unwrap();
+    r.present(&fb, &mut out).unwrap();
+
+    let frame = r.render(&fb);
+    let expected = format!("\x1B[2J\x1B[H{}\x1B[H{}", frame, frame);
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+}
+
+// Two tiles of 4bpp BG pixels, where only the first one of the
+// second tile is set, and a matching BG and OBJ palette.
+fn gba_with_tiles() -> Gba {
+    let mut gba = Gba::new();
+    {
+        let bus = gba.bus_mut();
+        bus.store_halfword(0x0500_0000, WHITE as i32).unwrap(); // Backdrop.
+        bus.store_halfword(0x0500_0002, RED as i32).unwrap();
+        bus.store_halfword(0x0500_0022, BLUE as i32).unwrap();
+        bus.store_halfword(0x0500_0202, GREEN as i32).unwrap();
+        bus.store_halfword(0x0600_0020, 0x0001).unwrap();
+        bus.store_halfword(0x0601_0000, 0x0001).unwrap();
+    }
+    gba
+}
+
+#[test]
+fn obj_entries_decode() {
+    // Affine, double size, blended, 8bpp, horizontal 32x16 at (-16, 32).
+    let obj = ObjEntry::decode(3, [0b01_1_0_01_1_1_00100000, 0b10_00101_111110000, 0b0111_10_0100100011]);
+    assert_eq!(obj, ObjEntry {
+        index: 3, x: -16, y: 32, width: 32, height: 16, tile: 0x123, priority: 2,
+        palette: None, affine_group: Some(5), double_size: true, hflip: false, vflip: false,
+        mosaic: false, mode: ObjMode::SemiTransparent,
+    });
+
+    // Regular, flipped, mosaic, 4bpp, vertical 8x32.
+    let obj = ObjEntry::decode(0, [0b10_0_1_00_0_0_00000000, 0b01_11_000_000000001, 0b1111_00_0000000000]);
+    assert_eq!((obj.x, obj.width, obj.height), (1, 8, 32));
+    assert_eq!((obj.palette, obj.affine_group), (Some(15), None));
+    assert!(obj.hflip & obj.vflip & obj.mosaic);
+    assert_eq!(obj.mode, ObjMode::Normal);
+
+    assert_eq!(ObjEntry::decode(0, [1 << 9, 0, 0]).mode, ObjMode::Disabled);
+    assert_eq!(ObjEntry::decode(0, [3 << 14, 0, 0]).width, 0);
+}
+
+#[test]
+fn tile_sheets_use_palette_banks() {
+    let gba = gba_with_tiles();
+    let viewer = VramViewer::new(gba.bus());
+
+    let sheet = viewer.tile_sheet(0, TileFormat::Bpp4(0)).unwrap();
+    assert_eq!((sheet.width(), sheet.height()), (256, 128));
+    assert_eq!((sheet.pixel(8, 0), sheet.pixel(9, 0), sheet.pixel(0, 0)), (RED, WHITE, WHITE));
+    assert_eq!(viewer.tile_sheet(0, TileFormat::Bpp4(1)).unwrap().pixel(8, 0), BLUE);
+
+    // In 8bpp, the same bytes are the fifth row of the first tile.
+    let sheet = viewer.tile_sheet(0, TileFormat::Bpp8).unwrap();
+    assert_eq!((sheet.width(), sheet.height()), (128, 128));
+    assert_eq!(sheet.pixel(0, 4), RED);
+
+    assert_eq!(viewer.tile_sheet(4, TileFormat::Bpp4(0)).unwrap().pixel(0, 0), GREEN);
+    assert!(viewer.tile_sheet(CHAR_BLOCKS, TileFormat::Bpp8).is_none());
+    assert!(viewer.tile_sheet(0, TileFormat::Bpp4(16)).is_none());
+}
+
+#[test]
+fn tilemaps_flip_tiles() {
+    let mut gba = gba_with_tiles();
+    {
+        let bus = gba.bus_mut();
+        bus.store_halfword(0x0400_0008, 1 << 8).unwrap(); // BG0CNT, screen block 1.
+        bus.store_halfword(0x0600_0800, 0x1401).unwrap(); // Tile 1, HFLIP, bank 1.
+    }
+    let map = VramViewer::new(gba.bus()).tilemap(0).unwrap();
+    assert_eq!((map.width(), map.height()), (256, 256));
+    assert_eq!((map.pixel(7, 0), map.pixel(0, 0)), (BLUE, WHITE));
+    assert!(VramViewer::new(gba.bus()).tilemap(4).is_none());
+}
+
+// A 2x2 image of red, white, blue and black pixels.
+const BMP_FILE: &'static [u8] = &[
+    b'B', b'M', 70, 0, 0, 0, 0, 0, 0, 0, 54, 0, 0, 0,
+    40, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 24, 0, 0, 0, 0, 0,
+    16, 0, 0, 0, 0x13, 0x0B, 0, 0, 0x13, 0x0B, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0, 0, // Blue, black.
+    0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, // Red, white.
+];
+
+#[test]
+fn bmp_files_are_bottom_up_and_padded() {
+    let mut img = Image::new(2, 2);
+    img.set_pixel(0, 0, RED);
+    img.set_pixel(1, 0, WHITE);
+    img.set_pixel(0, 1, BLUE);
+    let mut bmp = Vec::new();
+    img.write_bmp(&mut bmp).unwrap();
+    assert_eq!(&bmp[..], &BMP_FILE[..]);
+}
+
+
+#[test]
+fn colour_corrections_parse_and_print() {
+    for cc in &[ColourCorrection::Raw, ColourCorrection::GbaLcd, ColourCorrection::GbaSp] {
+        assert_eq!(format!("{}", cc).parse::<ColourCorrection>(), Ok(*cc));
+    }
+    assert_eq!("LCD".parse::<ColourCorrection>(), Ok(ColourCorrection::GbaLcd));
+    assert!("crt".parse::<ColourCorrection>().is_err());
+}
+
+#[test]
+fn colour_corrections_mix_channels() {
+    assert_eq!(ColourCorrection::Raw.apply(0b00001_10000_01111), bgr555_to_rgb888(0b00001_10000_01111));
+    assert_eq!(ColourCorrection::GbaSp.apply(WHITE), (255, 255, 255));
+    for cc in &[ColourCorrection::Raw, ColourCorrection::GbaLcd, ColourCorrection::GbaSp] {
+        assert_eq!(cc.apply(0), (0, 0, 0), "{}", cc);
+    }
+
+    // The LCD never gets fully white, and its red bleeds into blue.
+    let (r, g, b) = ColourCorrection::GbaLcd.apply(WHITE);
+    assert!((r < 255) & (g < 255) & (b < 255));
+    let (r, _, b) = ColourCorrection::GbaLcd.apply(RED);
+    assert!((r > b) & (b > 0));
+
+    let mut filter = OutputFilter::new();
+    filter.with_colour_correction(ColourCorrection::GbaLcd);
+    for c in 0..0x8000_u16 { assert_eq!(filter.convert(c), ColourCorrection::GbaLcd.apply(c)); }
+}
+
+#[test]
+fn frame_blending_mixes_consecutive_frames() {
+    let (mut red, mut blue) = (FrameBuffer::new(), FrameBuffer::new());
+    fill_rows(&mut red, 0, LCD_HEIGHT, RED);
+    fill_rows(&mut blue, 0, LCD_HEIGHT, BLUE);
+    let mut filter = OutputFilter::new();
+    filter.with_frame_blending(true);
+
+    // Neither the first frame nor `convert_frame` blend anything.
+    assert_eq!(filter.process(&red).pixel(0, 0), (255, 0, 0));
+    assert_eq!(filter.convert_frame(&blue).pixel(0, 0), (0, 0, 255));
+    assert_eq!(filter.process(&blue).pixel(0, 0), (128, 0, 128));
+    assert_eq!(filter.process(&blue).pixel(LCD_WIDTH - 1, LCD_HEIGHT - 1), (0, 0, 255));
+
+    // Changing the colour correction starts over.
+    filter.with_colour_correction(ColourCorrection::GbaSp);
+    assert_eq!(filter.process(&red).pixel(0, 0), ColourCorrection::GbaSp.apply(RED));
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/
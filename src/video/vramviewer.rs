@@ -0,0 +1,385 @@
+// License below.
+//! Implements debug views of the graphics data stored in VRAM, palette RAM and OAM.
+//!
+//! These views decode the raw video memory the same way the LCD
+//! would, but without any layering, scrolling or special effects,
+//! such that graphics glitches can be traced back to the data
+//! that causes them.
+#![cfg_attr(feature="clippy", warn(result_unwrap_used, option_unwrap_used, print_stdout))]
+#![cfg_attr(feature="clippy", warn(single_match_else, string_add, string_add_assign))]
+#![cfg_attr(feature="clippy", warn(wrong_pub_self_convention))]
+#![warn(missing_docs)]
+
+use std::fmt;
+use std::str::FromStr;
+use std::fmt::Write as FmtWrite;
+use super::image::Image;
+use super::super::hardware::bus::Bus;
+use super::super::hardware::ioregs::*;
+use super::super::hardware::memory::*;
+
+/// Number of character blocks in VRAM. Blocks 4 and 5 hold OBJ tiles.
+pub const CHAR_BLOCKS: usize = 6;
+
+/// Index of the first colour of the OBJ palette.
+const OBJ_PALETTE_FIRST: usize = 256;
+
+/// Size of a swatch in the palette image, in pixels.
+const SWATCH_SIZE: usize = 8;
+
+/// Sprite sizes in tiles, indexed by shape and size.
+const OBJ_SIZES: [[(u8, u8); 4]; 3] = [
+    [(1, 1), (2, 2), (4, 4), (8, 8)], // Square.
+    [(2, 1), (4, 1), (4, 2), (8, 4)], // Horizontal.
+    [(1, 2), (1, 4), (2, 4), (4, 8)], // Vertical.
+];
+
+
+/// Selects how tile data should be interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileFormat {
+    #[doc = "16 colours per tile, using the given palette bank `0..16`."]
+    Bpp4(u8),
+    #[doc = "256 colours per tile, using the whole palette."]
+    Bpp8,
+}
+
+impl FromStr for TileFormat {
+    type Err = String;
+
+    /// Parses either `8bpp` or a decimal 4bpp palette bank, e.g. `15`.
+    fn from_str(s: &str) -> Result<TileFormat, String> {
+        if s.to_lowercase() == "8bpp" { return Ok(TileFormat::Bpp8); }
+        match u8::from_str_radix(s, 10) {
+            Ok(b) if b < 16 => Ok(TileFormat::Bpp4(b)),
+            Ok(b)  => Err(format!("Palette bank {} out of range 0..16.", b)),
+            Err(e) => Err(format!("Invalid tile format `{}`: {}", s, e)),
+        }
+    }
+}
+
+impl fmt::Display for TileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TileFormat::Bpp4(b) => write!(f, "{}", b),
+            TileFormat::Bpp8    => write!(f, "8bpp"),
+        }
+    }
+}
+
+
+/// The display mode of a single sprite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjMode {
+    #[doc = "The sprite is not displayed."]
+    Disabled,
+    #[doc = "A regular sprite."]
+    Normal,
+    #[doc = "The sprite is alpha blended."]
+    SemiTransparent,
+    #[doc = "The sprite is used as an OBJ window."]
+    Window,
+    #[doc = "The reserved mode 3."]
+    Prohibited,
+}
+
+
+/// A decoded OAM entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjEntry {
+    /// The sprite's index in OAM.
+    pub index: usize,
+    /// Horizontal position of the sprite's left edge.
+    pub x: i16,
+    /// Vertical position of the sprite's top edge.
+    pub y: u8,
+    /// Width in pixels, `0` for the prohibited shape.
+    pub width: u8,
+    /// Height in pixels, `0` for the prohibited shape.
+    pub height: u8,
+    /// Index of the first tile in the OBJ char blocks.
+    pub tile: u16,
+    /// Drawing priority, where `0` is the highest.
+    pub priority: u8,
+    /// The palette bank of 4bpp sprites, or `None` for 8bpp sprites.
+    pub palette: Option<u8>,
+    /// The affine group of rotated or scaled sprites.
+    pub affine_group: Option<u8>,
+    /// Whether an affine sprite's drawing area is doubled.
+    pub double_size: bool,
+    /// Whether the sprite is mirrored horizontally.
+    pub hflip: bool,
+    /// Whether the sprite is mirrored vertically.
+    pub vflip: bool,
+    /// Whether the sprite is mosaic filtered.
+    pub mosaic: bool,
+    /// The sprite's display mode.
+    pub mode: ObjMode,
+}
+
+impl ObjEntry {
+    /// Decodes the three attribute halfwords of a sprite.
+    pub fn decode(index: usize, attr: [u16; 3]) -> ObjEntry {
+        let affine = 0 != (attr[0] & (1 << 8));
+        let bit9   = 0 != (attr[0] & (1 << 9));
+        let shape  = ((attr[0] >> 14) & 0b11) as usize;
+        let size   = ((attr[1] >> 14) & 0b11) as usize;
+        let (w, h) = if shape < 3 { OBJ_SIZES[shape][size] } else { (0, 0) };
+        let bpp8   = 0 != (attr[0] & (1 << 13));
+
+        let mode = if !affine & bit9 { ObjMode::Disabled } else {
+            match (attr[0] >> 10) & 0b11 {
+                0 => ObjMode::Normal,
+                1 => ObjMode::SemiTransparent,
+                2 => ObjMode::Window,
+                _ => ObjMode::Prohibited,
+            }
+        };
+
+        ObjEntry {
+            index: index,
+            x: (((attr[1] & 0x1FF) << 7) as i16) >> 7, // Sign-extend 9 bits.
+            y: (attr[0] & 0xFF) as u8,
+            width: w * 8,
+            height: h * 8,
+            tile: attr[2] & 0x3FF,
+            priority: ((attr[2] >> 10) & 0b11) as u8,
+            palette: if bpp8 { None } else { Some((attr[2] >> 12) as u8) },
+            affine_group: if affine { Some(((attr[1] >> 9) & 0x1F) as u8) } else { None },
+            double_size: affine & bit9,
+            hflip: !affine & (0 != (attr[1] & (1 << 12))),
+            vflip: !affine & (0 != (attr[1] & (1 << 13))),
+            mosaic: 0 != (attr[0] & (1 << 12)),
+            mode: mode,
+        }
+    }
+}
+
+impl fmt::Display for ObjEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self.mode {
+            ObjMode::Disabled        => "off",
+            ObjMode::Normal          => "normal",
+            ObjMode::SemiTransparent => "blend",
+            ObjMode::Window          => "window",
+            ObjMode::Prohibited      => "invalid",
+        };
+        let pal = match self.palette { Some(p) => format!("{:>4}", p), None => "8bpp".to_string() };
+        let aff = match self.affine_group {
+            Some(g) => format!("{:>2}{}", g, if self.double_size { "x2" } else { "  " }),
+            None    => format!("  {}{}", if self.hflip { 'h' } else { '-' }, if self.vflip { 'v' } else { '-' }),
+        };
+        write!(f, "{:>3} {:>4} {:>4} {:>2}x{:<2} {:>4} {:>3} {} {} {:>3} {}",
+               self.index, self.x, self.y, self.width, self.height, self.tile,
+               self.priority, pal, aff, if self.mosaic { "yes" } else { "no" }, mode)
+    }
+}
+
+
+/// Renders debug views of the video memory attached to a bus.
+pub struct VramViewer<'a> {
+    bus: &'a Bus,
+}
+
+impl<'a> VramViewer<'a> {
+    /// Creates a new viewer inspecting the given bus' video memory.
+    pub fn new(bus: &'a Bus) -> VramViewer<'a> {
+        VramViewer { bus: bus }
+    }
+
+    /// Renders all tiles of a character block as a tile sheet.
+    ///
+    /// 4bpp blocks are rendered as 32x16 tiles, 8bpp blocks as
+    /// 16x16 tiles. Blocks 4 and 5 use the OBJ palette.
+    ///
+    /// # Params
+    /// - `block`: The character block's index, `0..6`.
+    /// - `fmt`: How the tile data should be interpreted.
+    ///
+    /// # Returns
+    /// - `Some`: The tile sheet.
+    /// - `None`: The block or palette bank is out of range.
+    pub fn tile_sheet(&self, block: usize, fmt: TileFormat) -> Option<Image> {
+        if block >= CHAR_BLOCKS { return None; }
+        if let TileFormat::Bpp4(bank) = fmt { if bank >= 16 { return None; } }
+
+        let pal_first = if block >= 4 { OBJ_PALETTE_FIRST } else { 0 };
+        let tiles_per_row = match fmt { TileFormat::Bpp4(_) => 32, TileFormat::Bpp8 => 16 };
+        let tile_len = match fmt { TileFormat::Bpp4(_) => 32, TileFormat::Bpp8 => 64 };
+        let tiles = VideoRam::CHAR_BLOCK_LEN / tile_len;
+        let mut img = Image::new(tiles_per_row * 8, (tiles / tiles_per_row) * 8);
+
+        let base = block * VideoRam::CHAR_BLOCK_LEN;
+        for t in 0..tiles {
+            let (tx, ty) = ((t % tiles_per_row) * 8, (t / tiles_per_row) * 8);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let c = self.tile_pixel(base + t * tile_len, fmt, pal_first, x, y);
+                    img.set_pixel(tx + x, ty + y, c);
+                }
+            }
+        }
+        Some(img)
+    }
+
+    /// Renders a background's whole tilemap, ignoring scrolling.
+    ///
+    /// Text BGs are 256 or 512 pixels wide and high. Affine BGs are
+    /// between 128x128 and 1024x1024 pixels and always use 8bpp tiles.
+    ///
+    /// # Params
+    /// - `bg`: The background's index, `0..4`.
+    ///
+    /// # Returns
+    /// - `Some`: The tilemap.
+    /// - `None`: The BG index is out of range or the current
+    ///   BG mode doesn't display this BG as a tilemap.
+    pub fn tilemap(&self, bg: usize) -> Option<Image> {
+        if bg >= 4 { return None; }
+        let dispcnt = self.bus.ioregs().read_halfword(REG_DISPCNT);
+        let bgcnt = self.bus.ioregs().read_halfword(reg_bgcnt(bg));
+        let char_base = (((bgcnt >> 2) & 0b11) as usize) * VideoRam::CHAR_BLOCK_LEN;
+        let screen_base = (((bgcnt >> 8) & 0x1F) as usize) * VideoRam::SCREEN_BLOCK_LEN;
+        let size = ((bgcnt >> 14) & 0b11) as usize;
+
+        match (dispcnt & 0b111, bg) {
+            (0, _) | (1, 0) | (1, 1) => {
+                let bpp8 = 0 != (bgcnt & (1 << 7));
+                Some(self.text_tilemap(char_base, screen_base, size, bpp8))
+            },
+            (1, 2) | (2, 2) | (2, 3) => Some(self.affine_tilemap(char_base, screen_base, size)),
+            _ => None,
+        }
+    }
+
+    /// Renders all 512 colours as swatches.
+    ///
+    /// The BG palette is shown left, the OBJ palette right,
+    /// each as 16 rows of 16 colours. A row is a 4bpp bank.
+    pub fn palette(&self) -> Image {
+        let pal = self.bus.palette();
+        let mut img = Image::new(32 * SWATCH_SIZE, 16 * SWATCH_SIZE);
+        for i in 0..PaletteRam::COLOURS {
+            let x = ((i / 256) * 16 + (i % 16)) * SWATCH_SIZE;
+            let y = ((i % 256) / 16) * SWATCH_SIZE;
+            img.fill_rect(x, y, SWATCH_SIZE, SWATCH_SIZE, pal.colour(i));
+        }
+        img
+    }
+
+    /// Decodes all 128 OAM entries.
+    pub fn objects(&self) -> Vec<ObjEntry> {
+        let oam = self.bus.oam();
+        (0..ObjAttributeMemory::OBJECTS).map(|i| ObjEntry::decode(i, oam.attributes(i))).collect()
+    }
+
+    /// Formats a table of all displayed sprites and the affine groups they use.
+    pub fn oam_table(&self) -> String {
+        let objs = self.objects();
+        let mut s = String::new();
+        let mut groups = [false; ObjAttributeMemory::AFFINE_GROUPS];
+
+        s.push_str("  # xpos ypos  size tile pri  pal  aff mos mode\n");
+        for obj in objs.iter().filter(|o| o.mode != ObjMode::Disabled) {
+            writeln!(s, "{}", obj).unwrap();
+            if let Some(g) = obj.affine_group { groups[g as usize] = true; }
+        }
+        let hidden = objs.iter().filter(|o| o.mode == ObjMode::Disabled).count();
+        writeln!(s, "{} of {} sprites disabled.", hidden, objs.len()).unwrap();
+
+        for (g, _) in groups.iter().enumerate().filter(|&(_, used)| *used) {
+            let p = self.bus.oam().affine_parameters(g);
+            writeln!(s, "Affine group {:>2}: pa={:#06X} pb={:#06X} pc={:#06X} pd={:#06X}",
+                     g, p[0] as u16, p[1] as u16, p[2] as u16, p[3] as u16).unwrap();
+        }
+        s
+    }
+
+    fn text_tilemap(&self, char_base: usize, screen_base: usize, size: usize, bpp8: bool) -> Image {
+        let (blocks_x, blocks_y) = match size { 0 => (1, 1), 1 => (2, 1), 2 => (1, 2), _ => (2, 2) };
+        let mut img = Image::new(blocks_x * 256, blocks_y * 256);
+        let tile_len = if bpp8 { 64 } else { 32 };
+
+        for ty in 0..(blocks_y * 32) {
+            for tx in 0..(blocks_x * 32) {
+                // Screen blocks are laid out row by row, each holding 32x32 entries.
+                let sb = (ty / 32) * blocks_x + (tx / 32);
+                let offs = screen_base + sb * VideoRam::SCREEN_BLOCK_LEN + ((ty % 32) * 32 + (tx % 32)) * 2;
+                let entry = self.vram_halfword(offs);
+                let tile = (entry & 0x3FF) as usize;
+                let hflip = 0 != (entry & (1 << 10));
+                let vflip = 0 != (entry & (1 << 11));
+                let fmt = if bpp8 { TileFormat::Bpp8 } else { TileFormat::Bpp4((entry >> 12) as u8) };
+
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let sx = if hflip { 7 - x } else { x };
+                        let sy = if vflip { 7 - y } else { y };
+                        let c = self.tile_pixel(char_base + tile * tile_len, fmt, 0, sx, sy);
+                        img.set_pixel(tx * 8 + x, ty * 8 + y, c);
+                    }
+                }
+            }
+        }
+        img
+    }
+
+    fn affine_tilemap(&self, char_base: usize, screen_base: usize, size: usize) -> Image {
+        let tiles = 16 << size;
+        let mut img = Image::new(tiles * 8, tiles * 8);
+        for ty in 0..tiles {
+            for tx in 0..tiles {
+                let tile = self.vram_byte(screen_base + ty * tiles + tx) as usize;
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let c = self.tile_pixel(char_base + tile * 64, TileFormat::Bpp8, 0, x, y);
+                        img.set_pixel(tx * 8 + x, ty * 8 + y, c);
+                    }
+                }
+            }
+        }
+        img
+    }
+
+    fn tile_pixel(&self, tile_offs: usize, fmt: TileFormat, pal_first: usize, x: usize, y: usize) -> u16 {
+        let index = match fmt {
+            TileFormat::Bpp4(bank) => {
+                let b = self.vram_byte(tile_offs + y * 4 + x / 2);
+                let i = (if 0 == (x & 1) { b & 0x0F } else { b >> 4 }) as usize;
+                if i == 0 { 0 } else { (bank as usize) * 16 + i }
+            },
+            TileFormat::Bpp8 => self.vram_byte(tile_offs + y * 8 + x) as usize,
+        };
+        // Colour 0 is transparent, so show the backdrop colour instead.
+        if index == 0 { self.bus.palette().colour(0) }
+        else { self.bus.palette().colour(pal_first + index) }
+    }
+
+    fn vram_byte(&self, offs: usize) -> u8 {
+        if offs < (VRAM_LEN as usize) { self.bus.vram().read_byte(offs as u32) } else { 0 }
+    }
+
+    fn vram_halfword(&self, offs: usize) -> u16 {
+        if offs < (VRAM_LEN as usize) { self.bus.vram().read_halfword(offs as u32) } else { 0 }
+    }
+}
+
+
+/*
+Licensed to the Apache Software Foundation (ASF) under one
+or more contributor license agreements.  See the NOTICE file
+distributed with this work for additional information
+regarding copyright ownership.  The ASF licenses this file
+to you under the Apache License, Version 2.0 (the
+"License"); you may not use this file except in compliance
+with the License.  You may obtain a copy of the License at
+
+  http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing,
+software distributed under the License is distributed on an
+"AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+KIND, either express or implied.  See the License for the
+specific language governing permissions and limitations
+under the License.
+*/